@@ -0,0 +1,98 @@
+use db::cipher::Cipher;
+use db::codec::Codec;
+use db::compression;
+use db::Database;
+use match_engine::archive;
+use match_engine::canonical::{self, Duplicates};
+use match_engine::quarantine;
+use match_engine::shard::{self, Shards, Strategy};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+mod routes;
+
+use routes::AppState;
+
+const EXPIRY_SWEEP: Duration = Duration::from_secs(1);
+
+#[tokio::main]
+async fn main() {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let db = Arc::new(Mutex::new(open_database("order_book.db")));
+    let shards = Shards::new(
+        db.clone(),
+        shard::paths_from_env()
+            .iter()
+            .map(|path| Arc::new(Mutex::new(open_database(path))))
+            .collect(),
+        Strategy::from_env().expect("Invalid FTX_SHARD_STRATEGY"),
+    )
+    .expect("Invalid FTX_SHARDS");
+    let duplicates = Duplicates::from_env().expect("Invalid FTX_DUPLICATE_PAIRS");
+    for shard in shards.all() {
+        let guard = shard.lock().expect("could not get db lock");
+        let migration =
+            canonical::migrate(&guard, duplicates).expect("could not migrate legacy pair keys");
+        if !migration.is_empty() {
+            println!(
+                "Migrated legacy pairs: {} renamed, {} dropped, {} rewritten",
+                migration.renamed.len(),
+                migration.dropped.len(),
+                migration.rewritten
+            );
+        }
+        quarantine::scan(&guard).expect("could not scan persisted pairs");
+    }
+
+    let mut state = AppState::new(db).with_shards(shards);
+    if let Some(store) = archive::from_env().expect("Invalid trade archive") {
+        state = state.with_archive(Arc::from(store));
+    }
+    if let Some(feed_addr) = env::args().nth(2) {
+        let (event_sender, events) = crossbeam_channel::unbounded();
+        state = state.with_event_sender(event_sender);
+        let feed_listener = tokio::net::TcpListener::bind(&feed_addr)
+            .await
+            .unwrap_or_else(|e| panic!("could not bind {feed_addr}: {e}"));
+        println!("Streaming market data on ws://{feed_addr}/<base>/<quote>");
+        tokio::spawn(async move {
+            feed::serve(feed_listener, events)
+                .await
+                .expect("market-data feed stopped");
+        });
+    }
+
+    // expires good-til-date orders, the books are shared with the routes
+    let sweeper = state.clone();
+    thread::spawn(move || loop {
+        thread::sleep(EXPIRY_SWEEP);
+        if let Err(e) = sweeper.expire_orders() {
+            eprintln!("could not expire orders: {e}");
+        }
+    });
+
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .unwrap_or_else(|e| panic!("could not bind {addr}: {e}"));
+    println!("Serving order books on http://{addr}");
+    axum::serve(listener, routes::router(state))
+        .await
+        .expect("api server stopped");
+}
+
+fn open_database(path: &str) -> Database {
+    let codec = Codec::from_env().expect("Invalid FTX_DB_CODEC");
+    let mut database = Database::new(Some(path.to_string()), codec)
+        .unwrap_or_else(|_| panic!("could not open {path}"));
+    if let Some(cipher) = Cipher::from_env().expect("Invalid FTX_DB_KEY") {
+        database = database.with_cipher(cipher);
+    }
+    for tree in compression::trees_from_env() {
+        database = database.with_compression(&tree);
+    }
+    database
+}
@@ -0,0 +1,850 @@
+mod ticker;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use crossbeam_channel::Sender;
+use db::Database;
+use match_engine::access::{self, UserRole};
+use match_engine::accounts::{self, Account, AccountId};
+use match_engine::archive::{self, ObjectStore};
+use match_engine::error::{self, EngineError, ErrorKind};
+use match_engine::events::OrderBookEvent;
+use match_engine::exchange::{Exchange, PairOverview};
+use match_engine::fees::{self, RebateReport};
+use match_engine::idempotency::{self, RecentKeys};
+use match_engine::order::tag::Tag;
+use match_engine::order::time_in_force::TimeInForce;
+use match_engine::order::{Execution, Order, OrderType};
+use match_engine::order_book::ack::OrderAck;
+use match_engine::order_book::filter::CancelFilter;
+use match_engine::order_book::{Item, OrderBook};
+use match_engine::secrets;
+use match_engine::shard::Shards;
+use match_engine::symbol::Symbol;
+use match_engine::telemetry;
+use match_engine::trade::{self, LoggedTrade};
+use match_engine::version::VersionInfo;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+pub use ticker::{Ticker, TickerCache};
+
+// `<name>:<secret>` of a key made with `keys create <name> api`, the name is
+// the actor roles and account owners are matched against.
+pub const API_KEY_HEADER: &str = "x-api-key";
+pub use access::ANONYMOUS;
+// a POST /orders sent again with the same key gets the first response back
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+// One exchange for every request, so requests for the same pair always see
+// each other's orders instead of racing on stale copies.
+#[derive(Clone)]
+pub struct AppState {
+    shards: Shards,
+    exchange: Arc<Mutex<Exchange>>,
+    archive: Option<Arc<dyn ObjectStore>>,
+    event_sender: Option<Sender<OrderBookEvent>>,
+    tickers: Arc<TickerCache>,
+    recent: Arc<Mutex<RecentKeys>>,
+}
+
+// Body of POST /orders, an order without a price is a market order.
+#[derive(Debug, Deserialize)]
+pub struct NewOrder {
+    pub pair: String,
+    pub side: OrderType,
+    #[serde(default)]
+    pub price: Option<i32>,
+    #[serde(default = "one")]
+    pub quantity: i32,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    // reserves the order on the account's balance
+    #[serde(default)]
+    pub account: Option<AccountId>,
+    // groups the order for DELETE /orders, see BulkCancel
+    #[serde(default)]
+    pub tag: Option<Tag>,
+    // Sweep or BestLevel, for market orders
+    #[serde(default)]
+    pub execution: Execution,
+    // the least the order's first match must fill, what it leaves below
+    // that is cancelled
+    #[serde(default)]
+    pub min_qty: Option<i32>,
+    // rests until a fill for all of it comes along
+    #[serde(default)]
+    pub all_or_none: bool,
+}
+
+// Query of DELETE /orders, the filter is spelled like the CLI's, e.g.
+// pair=BTC/USD&filter=side=buy,tag=mm,account=7
+#[derive(Debug, Deserialize)]
+pub struct BulkCancel {
+    pub pair: String,
+    #[serde(default)]
+    pub filter: String,
+}
+
+// Body of PATCH /orders/{id}, quantity is the new total including what filled.
+#[derive(Debug, Deserialize)]
+pub struct Amendment {
+    pub price: i32,
+    pub quantity: i32,
+}
+
+fn one() -> i32 {
+    1
+}
+
+// Who a request acts for, see AppState::authorize.
+struct Caller {
+    actor: String,
+    // operators and above, or anyone while no role is assigned
+    unrestricted: bool,
+}
+
+impl Caller {
+    // Traders act for the accounts they own. Orders without an account
+    // cannot be told apart, so once placed only operators touch them.
+    fn ensure_acts_for(
+        &self,
+        state: &AppState,
+        account: Option<AccountId>,
+    ) -> Result<(), ApiError> {
+        if self.unrestricted {
+            return Ok(());
+        }
+        let db = state.shards.home().lock().expect("could not get db lock");
+        match account {
+            Some(account) => Ok(accounts::ensure_owner(&db, account, &self.actor)?),
+            None => Err(error::forbidden(format!(
+                "{} may only act for accounts it owns",
+                self.actor
+            ))
+            .into()),
+        }
+    }
+}
+
+// The status follows the error's kind, the body carries both.
+#[derive(Debug)]
+pub struct ApiError(EngineError);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.0.kind.http_status())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = json!({ "error": self.0.message, "kind": self.0.kind });
+        (status, Json(body)).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self(EngineError::from_anyhow(&e))
+    }
+}
+
+impl AppState {
+    pub fn new(db: Arc<Mutex<Database>>) -> Self {
+        let (shards, tickers) = (Shards::home_only(db), Arc::<TickerCache>::default());
+        Self {
+            exchange: new_exchange(&shards, None, &tickers),
+            shards,
+            archive: None,
+            event_sender: None,
+            tickers,
+            recent: Arc::new(Mutex::new(RecentKeys::new(
+                idempotency::DEFAULT_TTL,
+                idempotency::PERSIST_EVERY,
+            ))),
+        }
+    }
+
+    // Where old trades were archived to, GET /trades reads them back from it.
+    pub fn with_archive(mut self, archive: Arc<dyn ObjectStore>) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    // Books live on their pair's shard, control records on the home one. Set before
+    // serving, books opened earlier are dropped.
+    pub fn with_shards(mut self, shards: Shards) -> Self {
+        self.exchange = new_exchange(&shards, self.event_sender.clone(), &self.tickers);
+        self.shards = shards;
+        self
+    }
+
+    // Books push their updates here, e.g. for the market-data feed. Set before
+    // serving, books opened earlier are dropped.
+    pub fn with_event_sender(mut self, event_sender: Sender<OrderBookEvent>) -> Self {
+        self.exchange = new_exchange(&self.shards, Some(event_sender.clone()), &self.tickers);
+        self.event_sender = Some(event_sender);
+        self
+    }
+
+    // Run by the expiry sweep, see Exchange::expire.
+    pub fn expire_orders(&self) -> anyhow::Result<Vec<Order>> {
+        self.exchange().expire(telemetry::now_millis())
+    }
+
+    // Requests without a key are refused once roles are assigned, like
+    // requests with a key that does not verify.
+    fn authorize(&self, headers: &HeaderMap, required: UserRole) -> Result<Caller, ApiError> {
+        let db = self.shards.home().lock().expect("could not get db lock");
+        let enforced = access::enforced(&db)?;
+        let actor = match headers.get(API_KEY_HEADER) {
+            Some(value) => {
+                let (name, secret) = value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.split_once(':'))
+                    .ok_or_else(|| {
+                        error::forbidden(format!(
+                            "Invalid {}, expected <name>:<secret>",
+                            API_KEY_HEADER
+                        ))
+                    })?;
+                if !secrets::authenticate(&db, name, secret)? {
+                    return Err(error::forbidden(format!("Invalid API key {}", name)).into());
+                }
+                name.to_string()
+            }
+            None if enforced => {
+                return Err(error::forbidden(format!("{} is required", API_KEY_HEADER)).into())
+            }
+            None => ANONYMOUS.to_string(),
+        };
+        access::authorize(&db, &actor, required)?;
+        let unrestricted = !enforced || access::role_of(&db, &actor)? >= Some(UserRole::Operator);
+        Ok(Caller {
+            actor,
+            unrestricted,
+        })
+    }
+
+    // Balances and fills are private, a key is required even while no role
+    // is assigned.
+    fn authorize_key(&self, headers: &HeaderMap) -> Result<Caller, ApiError> {
+        if !headers.contains_key(API_KEY_HEADER) {
+            return Err(error::forbidden(format!("{} is required", API_KEY_HEADER)).into());
+        }
+        self.authorize(headers, UserRole::Trader)
+    }
+
+    fn recent(&self) -> MutexGuard<'_, RecentKeys> {
+        self.recent.lock().expect("could not get idempotency lock")
+    }
+
+    fn exchange(&self) -> MutexGuard<'_, Exchange> {
+        self.exchange.lock().expect("could not get exchange lock")
+    }
+
+    fn symbol(&self, raw: &str) -> Result<Symbol, ApiError> {
+        Ok(self.exchange().resolve(raw)?)
+    }
+
+    // From every shard, oldest first.
+    fn trades_of_account(&self, id: AccountId) -> anyhow::Result<Vec<LoggedTrade>> {
+        let mut trades = Vec::new();
+        for shard in self.shards.all() {
+            trades.extend(trade::trades_of_account(
+                &shard.lock().expect("could not get db lock"),
+                id,
+            )?);
+        }
+        trades.sort_by_key(|logged| (logged.trade.timestamp, logged.sequence));
+        Ok(trades)
+    }
+}
+
+// Every book drops its cached ticker on each of its events.
+fn new_exchange(
+    shards: &Shards,
+    event_sender: Option<Sender<OrderBookEvent>>,
+    tickers: &Arc<TickerCache>,
+) -> Arc<Mutex<Exchange>> {
+    let tickers = tickers.clone();
+    let exchange = Exchange::with_shards(shards.clone(), move || {
+        let mut order_book_builder = OrderBook::default();
+        if let Some(event_sender) = &event_sender {
+            order_book_builder.set_event_sender(event_sender.clone());
+        }
+        let tickers = tickers.clone();
+        order_book_builder.add_listener(move |event| tickers.invalidate(event));
+        order_book_builder
+    });
+    Arc::new(Mutex::new(exchange))
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/orders", post(place_order).delete(cancel_orders))
+        .route("/orders/{id}", delete(cancel_order).patch(amend_order))
+        .route("/orders/{id}/trades", get(order_trades))
+        .route("/accounts/{id}", get(account))
+        .route("/accounts/{id}/trades", get(account_trades))
+        .route("/accounts/{id}/rebates", get(account_rebates))
+        .route("/book/{*pair}", get(book))
+        .route("/ticker/{*pair}", get(ticker))
+        .route("/overview", get(overview))
+        .route("/trades/{*pair}", get(trades))
+        .route("/info", get(info))
+        .with_state(state)
+}
+
+// Responds with the OrderAck. A rejected order is acked too, with the status
+// of its error's kind; only internal failures answer with a plain error.
+// Keys are scoped like the CLI's, per account or else per caller.
+async fn place_order(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(new_order): Json<NewOrder>,
+) -> Result<(StatusCode, Json<OrderAck>), ApiError> {
+    let caller = state.authorize(&headers, UserRole::Trader)?;
+    if new_order.account.is_some() {
+        caller.ensure_acts_for(&state, new_order.account)?;
+    }
+    let mut order = match new_order.price {
+        Some(price) => Order::new(new_order.quantity, price, new_order.side),
+        None => Order::market(new_order.quantity, new_order.side),
+    };
+    order.update_hidden(new_order.hidden);
+    order.update_time_in_force(new_order.time_in_force);
+    order.update_account(new_order.account);
+    order.update_tag(new_order.tag);
+    order.update_execution(new_order.execution);
+    order.update_min_qty(new_order.min_qty);
+    order.update_all_or_none(new_order.all_or_none);
+
+    let idempotency_key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) => {
+            let key = key
+                .to_str()
+                .map_err(|_| error::validation(format!("Invalid {}", IDEMPOTENCY_KEY_HEADER)))?;
+            let scope = new_order
+                .account
+                .map_or_else(|| caller.actor.clone(), |id| format!("account-{id}"));
+            Some(format!("{}:{}", scope, key))
+        }
+        None => None,
+    };
+    // placements wait on each other, so a key sent twice at once places once
+    let mut exchange = state.exchange();
+    if let Some(key) = &idempotency_key {
+        let replayed = state.recent().replay::<(u16, OrderAck)>(
+            &state.shards.home().lock().expect("could not get db lock"),
+            key,
+        )?;
+        if let Some((status, ack)) = replayed {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::CREATED);
+            return Ok((status, Json(ack)));
+        }
+    }
+
+    let (status, ack) = match exchange.submit(&new_order.pair, order) {
+        Ok(ack) => (StatusCode::CREATED, ack),
+        Err(e) if ErrorKind::of(&e) == ErrorKind::Internal => return Err(e.into()),
+        Err(e) => {
+            let ack = OrderAck::rejected(order, &e);
+            let status = StatusCode::from_u16(ErrorKind::of(&e).http_status())
+                .unwrap_or(StatusCode::BAD_REQUEST);
+            (status, ack)
+        }
+    };
+    if let Some(key) = &idempotency_key {
+        state.recent().remember(
+            &state.shards.home().lock().expect("could not get db lock"),
+            key,
+            &(status.as_u16(), &ack),
+        )?;
+    }
+    Ok((status, Json(ack)))
+}
+
+// Order ids are unique across pairs, so every pair is searched.
+async fn amend_order(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(amendment): Json<Amendment>,
+) -> Result<Json<OrderAck>, ApiError> {
+    let caller = state.authorize(&headers, UserRole::Trader)?;
+    let mut exchange = state.exchange();
+    for pair in exchange.list_pairs()? {
+        let order_book = exchange.book(&pair)?;
+        let found = order_book
+            .join_active_orders()
+            .into_iter()
+            .find(|o| o.id == id);
+        if let Some(order) = found {
+            caller.ensure_acts_for(&state, order.account)?;
+            return Ok(Json(order_book.amend_order(
+                id,
+                amendment.price,
+                amendment.quantity,
+            )?));
+        }
+    }
+    Err(ApiError(EngineError::new(
+        ErrorKind::NotFound,
+        format!("No open order {}", id),
+    )))
+}
+
+async fn cancel_order(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<Order>, ApiError> {
+    let caller = state.authorize(&headers, UserRole::Trader)?;
+    let mut exchange = state.exchange();
+    for pair in exchange.list_pairs()? {
+        let order_book = exchange.book(&pair)?;
+        let found = order_book
+            .join_active_orders()
+            .into_iter()
+            .find(|o| o.id == id);
+        if let Some(order) = found {
+            caller.ensure_acts_for(&state, order.account)?;
+            return Ok(Json(order_book.cancel_order(id)?));
+        }
+    }
+    Err(ApiError(EngineError::new(
+        ErrorKind::NotFound,
+        format!("No open order {}", id),
+    )))
+}
+
+// Traders name one of their accounts in the filter, only its orders are
+// cancelled.
+async fn cancel_orders(
+    State(state): State<AppState>,
+    Query(bulk): Query<BulkCancel>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Order>>, ApiError> {
+    let caller = state.authorize(&headers, UserRole::Trader)?;
+    let filter = CancelFilter::parse(&bulk.filter)
+        .map_err(|e| error::validation(format!("Invalid filter: {e}")))?;
+    caller.ensure_acts_for(&state, filter.account)?;
+    let pair = state.symbol(&bulk.pair)?;
+    let mut exchange = state.exchange();
+    Ok(Json(exchange.book(&pair)?.cancel_where(&filter)?))
+}
+
+// The public view, hidden orders stay out of it.
+async fn book(
+    State(state): State<AppState>,
+    Path(pair): Path<String>,
+) -> Result<Json<Item>, ApiError> {
+    let pair = state.symbol(&pair)?;
+    let mut exchange = state.exchange();
+    let order_book = exchange.book(&pair)?;
+    Ok(Json(order_book.snapshot().public_view()))
+}
+
+// Served from TickerCache while the book is quiet. A pair given by alias
+// resolves on a miss, like every other route.
+async fn ticker(
+    State(state): State<AppState>,
+    Path(pair): Path<String>,
+) -> Result<Response, ApiError> {
+    let cached = Symbol::parse(&pair)
+        .ok()
+        .and_then(|symbol| state.tickers.get(symbol.as_str()));
+    let json = match cached {
+        Some(json) => json,
+        None => {
+            let pair = state.symbol(&pair)?;
+            let mut exchange = state.exchange();
+            let ticker = Ticker::of(exchange.book(&pair)?);
+            let json = Bytes::from(serde_json::to_vec(&ticker).map_err(anyhow::Error::from)?);
+            state.tickers.insert(pair.as_str(), json.clone());
+            json
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, "application/json")], json).into_response())
+}
+
+// Every pair in one response, see Exchange::overview.
+async fn overview(State(state): State<AppState>) -> Result<Json<Vec<PairOverview>>, ApiError> {
+    Ok(Json(state.exchange().overview(telemetry::now_millis())?))
+}
+
+async fn trades(
+    State(state): State<AppState>,
+    Path(pair): Path<String>,
+) -> Result<Json<Vec<LoggedTrade>>, ApiError> {
+    let pair = state.symbol(&pair)?;
+    let trades = archive::history(
+        &state
+            .shards
+            .db_for(&pair)
+            .lock()
+            .expect("could not get db lock"),
+        state.archive.as_deref(),
+        Some(pair.as_str()),
+        0,
+        u64::MAX,
+    )?;
+    Ok(Json(trades))
+}
+
+// The order's account is the one its fills name on its side.
+async fn order_trades(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<LoggedTrade>>, ApiError> {
+    let caller = state.authorize_key(&headers)?;
+    let mut trades = Vec::new();
+    for shard in state.shards.all() {
+        trades.extend(trade::trades_of_order(
+            &shard.lock().expect("could not get db lock"),
+            id,
+        )?);
+    }
+    if let Some(logged) = trades.first() {
+        let account = match logged.trade.buy_order_id == id {
+            true => logged.trade.buy_account,
+            false => logged.trade.sell_account,
+        };
+        caller.ensure_acts_for(&state, account)?;
+    }
+    Ok(Json(trades))
+}
+
+// Balances are kept on the home database, see accounts.
+async fn account(
+    State(state): State<AppState>,
+    Path(id): Path<AccountId>,
+    headers: HeaderMap,
+) -> Result<Json<Account>, ApiError> {
+    state
+        .authorize_key(&headers)?
+        .ensure_acts_for(&state, Some(id))?;
+    let account = accounts::get(
+        &state.shards.home().lock().expect("could not get db lock"),
+        id,
+    )?;
+    account.map(Json).ok_or_else(|| {
+        ApiError(EngineError::new(
+            ErrorKind::NotFound,
+            format!("No account {}", id),
+        ))
+    })
+}
+
+async fn account_trades(
+    State(state): State<AppState>,
+    Path(id): Path<AccountId>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<LoggedTrade>>, ApiError> {
+    state
+        .authorize_key(&headers)?
+        .ensure_acts_for(&state, Some(id))?;
+    Ok(Json(state.trades_of_account(id)?))
+}
+
+// Maker rebates the account accrued, see fees::rebates.
+async fn account_rebates(
+    State(state): State<AppState>,
+    Path(id): Path<AccountId>,
+    headers: HeaderMap,
+) -> Result<Json<RebateReport>, ApiError> {
+    state
+        .authorize_key(&headers)?
+        .ensure_acts_for(&state, Some(id))?;
+    Ok(Json(fees::rebates(id, &state.trades_of_account(id)?)?))
+}
+
+async fn info(State(state): State<AppState>) -> Json<VersionInfo> {
+    Json(state.exchange().version_info())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use match_engine::order::OrderStatus;
+    use serde::de::DeserializeOwned;
+    use test_utils::shared_temp_db;
+    use tower::ServiceExt;
+
+    async fn send<T: DeserializeOwned>(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, T) {
+        send_with(router, method, uri, body, &[]).await
+    }
+
+    async fn send_with<T: DeserializeOwned>(
+        router: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, T) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request
+            .body(body.map_or(Body::empty(), |b| Body::from(b.to_string())))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn orders_trade_and_cancel_over_http() {
+        let db = shared_temp_db();
+        accounts::deposit(&db.lock().unwrap(), 7, "BTC", 3).unwrap();
+        let secret =
+            secrets::create(&db.lock().unwrap(), "alice", secrets::SecretKind::ApiKey).unwrap();
+        let key = format!("alice:{secret}");
+        let headers = [(API_KEY_HEADER, key.as_str())];
+        let router = router(AppState::new(db));
+
+        let (status, ask): (_, OrderAck) = send(
+            &router,
+            "POST",
+            "/orders",
+            Some(json!({ "pair": "btc/usd", "side": "Sell", "price": 10, "quantity": 3, "account": 7 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(matches!(ask, OrderAck::Rested { .. }));
+        let ask = *ask.order();
+        let (_, bid): (_, OrderAck) = send(
+            &router,
+            "POST",
+            "/orders",
+            Some(json!({ "pair": "BTC/USD", "side": "Buy" })),
+        )
+        .await;
+        assert_eq!(bid.order().order_status, OrderStatus::Filled);
+        assert_eq!(bid.fills()[0].sell_order_id, ask.id);
+
+        let (_, trades): (_, Vec<LoggedTrade>) =
+            send(&router, "GET", "/trades/btc/usd", None).await;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].trade.sell_order_id, ask.id);
+        let uri = format!("/orders/{}/trades", bid.order().id);
+        let (status, _): (_, serde_json::Value) = send(&router, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (_, fills): (_, Vec<LoggedTrade>) =
+            send_with(&router, "GET", &uri, None, &headers).await;
+        assert_eq!(fills, trades);
+        let (status, _): (_, serde_json::Value) = send(&router, "GET", "/accounts/7", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (_, account): (_, Account) =
+            send_with(&router, "GET", "/accounts/7", None, &headers).await;
+        assert_eq!(account.balance("USD").available, 10);
+        assert_eq!(account.balance("BTC").reserved, 2);
+        let (_, fills): (_, Vec<LoggedTrade>) =
+            send_with(&router, "GET", "/accounts/7/trades", None, &headers).await;
+        assert_eq!(fills, trades);
+
+        let uri = format!("/orders/{}", ask.id);
+        let (status, cancelled): (_, Order) = send(&router, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cancelled.order_status, OrderStatus::Cancelled);
+        let (status, _): (_, serde_json::Value) = send(&router, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, item): (_, Item) = send(&router, "GET", "/book/btc/usd", None).await;
+        assert!(item.active_orders.is_empty());
+        assert_eq!(item.fulfilled_orders.len(), 2);
+
+        let (status, error): (_, serde_json::Value) = send(
+            &router,
+            "POST",
+            "/orders",
+            Some(json!({ "pair": "btc", "side": "Buy", "price": 1 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["outcome"], "rejected");
+        assert_eq!(error["kind"], "validation");
+
+        // nothing left to buy from
+        let (status, error): (_, serde_json::Value) = send(
+            &router,
+            "POST",
+            "/orders",
+            Some(json!({ "pair": "btc/usd", "side": "Buy" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["kind"], "risk");
+
+        let (_, info): (_, VersionInfo) = send(&router, "GET", "/info", None).await;
+        assert_eq!(info, VersionInfo::current());
+    }
+
+    #[tokio::test]
+    async fn tickers_are_cached_until_the_book_changes() {
+        let state = AppState::new(shared_temp_db());
+        let router = router(state.clone());
+        let order = |side, price| json!({ "pair": "BTC/USD", "side": side, "price": price });
+
+        send::<OrderAck>(&router, "POST", "/orders", Some(order("Buy", 9))).await;
+        let (status, ticker): (_, Ticker) = send(&router, "GET", "/ticker/btc/usd", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((ticker.bid, ticker.ask), (Some(9), None));
+        assert!(state.tickers.get("BTC/USD").is_some());
+
+        send::<OrderAck>(&router, "POST", "/orders", Some(order("Sell", 11))).await;
+        assert!(state.tickers.get("BTC/USD").is_none());
+        let (_, ticker): (_, Ticker) = send(&router, "GET", "/ticker/BTC/USD", None).await;
+        assert_eq!((ticker.spread, ticker.mid), (Some(2), Some(10)));
+
+        let (_, overview): (_, Vec<PairOverview>) = send(&router, "GET", "/overview", None).await;
+        assert_eq!(overview.len(), 1);
+        assert_eq!((overview[0].bid, overview[0].ask), (Some(9), Some(11)));
+    }
+
+    #[tokio::test]
+    async fn roles_and_account_owners_are_enforced() {
+        let db = shared_temp_db();
+        let key = |name: &str| {
+            let db = db.lock().unwrap();
+            let secret = secrets::create(&db, name, secrets::SecretKind::ApiKey).unwrap();
+            format!("{name}:{secret}")
+        };
+        let (alice, bob, ops) = (key("alice"), key("bob"), key("ops"));
+        {
+            let db = db.lock().unwrap();
+            access::assign(&db, "ops", UserRole::Operator).unwrap();
+            accounts::deposit(&db, 7, "BTC", 2).unwrap();
+            accounts::set_owner(&db, 7, "alice").unwrap();
+        }
+        let router = router(AppState::new(db));
+        let ask = json!({ "pair": "BTC/USD", "side": "Sell", "price": 10, "account": 7 });
+        let send_as = |key: &str, method: &'static str, uri: String, body| {
+            let (router, headers) = (router.clone(), [(API_KEY_HEADER, key.to_string())]);
+            async move {
+                let headers = [(headers[0].0, headers[0].1.as_str())];
+                send_with::<serde_json::Value>(&router, method, &uri, body, &headers).await
+            }
+        };
+
+        let (status, error) =
+            send::<serde_json::Value>(&router, "POST", "/orders", Some(ask.clone())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(error["kind"], "forbidden");
+        let place = |key| send_as(key, "POST", "/orders".to_string(), Some(ask.clone()));
+        assert_eq!(place("alice:wrong").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(place(&bob).await.0, StatusCode::FORBIDDEN);
+        let (status, placed) = place(&alice).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // only alice and operators may change alice's order
+        let uri = format!("/orders/{}", placed["order"]["id"].as_str().unwrap());
+        let amend = json!({ "price": 11, "quantity": 1 });
+        let (status, _) = send_as(&bob, "PATCH", uri.clone(), Some(amend)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_as(&bob, "DELETE", uri.clone(), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_as(&ops, "DELETE", uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // fills of alice's order are alice's to read
+        let (_, placed) = place(&alice).await;
+        let bid = json!({ "pair": "BTC/USD", "side": "Buy", "price": 10 });
+        send_as(&ops, "POST", "/orders".to_string(), Some(bid)).await;
+        let fills = format!("/orders/{}/trades", placed["order"]["id"].as_str().unwrap());
+        let (status, _) = send::<serde_json::Value>(&router, "GET", &fills, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            send_as(&bob, "GET", fills.clone(), None).await.0,
+            StatusCode::FORBIDDEN
+        );
+        let (status, read) = send_as(&alice, "GET", fills, None).await;
+        assert_eq!(
+            (status, read.as_array().map(Vec::len)),
+            (StatusCode::OK, Some(1))
+        );
+
+        // and only they read the account
+        for uri in ["/accounts/7", "/accounts/7/trades"] {
+            let (status, _) = send::<serde_json::Value>(&router, "GET", uri, None).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert_eq!(
+                send_as(&bob, "GET", uri.to_string(), None).await.0,
+                StatusCode::FORBIDDEN
+            );
+            assert_eq!(
+                send_as(&alice, "GET", uri.to_string(), None).await.0,
+                StatusCode::OK
+            );
+            assert_eq!(
+                send_as(&ops, "GET", uri.to_string(), None).await.0,
+                StatusCode::OK
+            );
+        }
+
+        // bulk cancels name an account the caller owns
+        let quote =
+            json!({ "pair": "BTC/USD", "side": "Sell", "price": 12, "account": 7, "tag": "mm" });
+        send_as(&alice, "POST", "/orders".to_string(), Some(quote)).await;
+        let cancel = |key, filter: &str| {
+            send_as(
+                key,
+                "DELETE",
+                format!("/orders?pair=BTC/USD&filter={filter}"),
+                None,
+            )
+        };
+        assert_eq!(cancel(&alice, "tag=mm").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(
+            cancel(&bob, "tag=mm,account=7").await.0,
+            StatusCode::FORBIDDEN
+        );
+        let (status, cancelled) = cancel(&alice, "tag=mm,account=7").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(cancelled[0]["tag"], "mm");
+        assert_eq!(cancelled.as_array().map(Vec::len), Some(1));
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_replay_the_first_response() {
+        let router = router(AppState::new(shared_temp_db()));
+        let bid = Some(json!({ "pair": "BTC/USD", "side": "Buy", "price": 10 }));
+        let place = |key| {
+            let (router, bid) = (router.clone(), bid.clone());
+            async move {
+                let headers = [(IDEMPOTENCY_KEY_HEADER, key)];
+                send_with::<OrderAck>(&router, "POST", "/orders", bid, &headers).await
+            }
+        };
+
+        let (status, first) = place("order-1").await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(place("order-1").await, (StatusCode::CREATED, first.clone()));
+        assert_ne!(place("order-2").await.1.order().id, first.order().id);
+
+        let (_, item): (_, Item) = send(&router, "GET", "/book/btc/usd", None).await;
+        assert_eq!(item.active_orders.len(), 2);
+    }
+}
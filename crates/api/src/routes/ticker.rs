@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use axum::body::Bytes;
+
+use match_engine::events::OrderBookEvent;
+use match_engine::order_book::OrderBook;
+use serde::{Deserialize, Serialize};
+
+// Top of book as served by GET /ticker, hidden orders are not quoted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ticker {
+    pub pair: String,
+    pub bid: Option<i32>,
+    pub ask: Option<i32>,
+    pub spread: Option<i32>,
+    pub mid: Option<i32>,
+}
+
+impl Ticker {
+    pub fn of(order_book: &OrderBook) -> Self {
+        Self {
+            pair: order_book.get_pair().to_string(),
+            bid: order_book.best_bid().map(|o| o.price),
+            ask: order_book.best_ask().map(|o| o.price),
+            spread: order_book.spread(),
+            mid: order_book.mid_price(),
+        }
+    }
+}
+
+// Serialized tickers by canonical pair. Every event of a book drops its
+// pair's entry, so polling a quiet book neither locks it nor serializes.
+#[derive(Default)]
+pub struct TickerCache {
+    tickers: RwLock<HashMap<String, Bytes>>,
+}
+
+impl TickerCache {
+    pub fn get(&self, pair: &str) -> Option<Bytes> {
+        self.read().get(pair).cloned()
+    }
+
+    // Callers hold the exchange lock while they build the ticker, so no
+    // event can slip in between and leave a stale entry.
+    pub fn insert(&self, pair: &str, json: Bytes) {
+        self.write().insert(pair.to_string(), json);
+    }
+
+    pub fn invalidate(&self, event: &OrderBookEvent) {
+        if self.read().contains_key(event.pair()) {
+            self.write().remove(event.pair());
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Bytes>> {
+        self.tickers.read().expect("could not get ticker lock")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Bytes>> {
+        self.tickers.write().expect("could not get ticker lock")
+    }
+}
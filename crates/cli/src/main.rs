@@ -1,44 +1,189 @@
+use db::cipher::Cipher;
+use db::codec::Codec;
+use db::compression;
 use db::Database;
-use match_engine::order::{Order, OrderStatus, OrderType};
+use match_engine::access::{self, UserRole};
+use match_engine::accounts::{self, Account};
+use match_engine::archive;
+use match_engine::audit;
+use match_engine::calendar::{self, MarketState, TradingCalendar};
+use match_engine::canonical::{self, Duplicates};
+use match_engine::clock::{self, Clock};
+use match_engine::error::{self, ErrorKind};
+use match_engine::event_log::{Event, EventLog};
+use match_engine::exchange::Exchange;
+use match_engine::fees::{self, FeeSchedule};
+use match_engine::handoff::{export_shards, import_shards, StateExport};
+use match_engine::health;
+use match_engine::idempotency::{self, RecentKeys};
+use match_engine::ingest::{self, market_data};
+use match_engine::instrument::{self, Instrument};
+use match_engine::l3::{self, Anonymizer};
+use match_engine::latency::{self, LatencyBudget};
+use match_engine::order::id::{IdGenerator, RandomIdGenerator, SnowflakeIdGenerator};
+use match_engine::order::peg::Peg;
+use match_engine::order::tag::Tag;
+use match_engine::order::time_in_force::TimeInForce;
+use match_engine::order::{Execution, Order, OrderKind, OrderType};
+use match_engine::order_book::adjust::PriceAdjustment;
+use match_engine::order_book::chart;
+use match_engine::order_book::depth::{self, DepthLevel};
+use match_engine::order_book::filter::CancelFilter;
+use match_engine::order_book::journal::{self, JournalStats};
+use match_engine::order_book::page::{Cursor, SortKey};
 use match_engine::order_book::{Item, OrderBook};
+use match_engine::quarantine;
+use match_engine::query::Query;
+use match_engine::replica::{self, Role};
+use match_engine::secrets::{self, SecretKind};
+use match_engine::shard::{self, Shards, Strategy};
+use match_engine::supervision;
+use match_engine::symbol::{self, Symbol};
+use match_engine::telemetry;
+use match_engine::trade::{self, fix};
+use match_engine::writer::{self, AckMode, Writer};
 use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+mod output;
+
+use output::Output;
 
 fn main() {
-    let print = env::args()
-        .nth(2)
-        .map(|arg| arg == "print".to_string())
-        .unwrap_or(false);
-    let commands: [String; 3] = [
+    let commands: [String; 38] = [
         "print".to_string(),
         "create_order".to_string(),
         "list_order".to_string(),
+        "export".to_string(),
+        "import".to_string(),
+        "replicate".to_string(),
+        "telemetry".to_string(),
+        "halted".to_string(),
+        "restart".to_string(),
+        "health".to_string(),
+        "halt".to_string(),
+        "audit".to_string(),
+        "roles".to_string(),
+        "keys".to_string(),
+        "alias".to_string(),
+        "cancel".to_string(),
+        "recover".to_string(),
+        "book_at".to_string(),
+        "verify".to_string(),
+        "ingest".to_string(),
+        "orders".to_string(),
+        "calendar".to_string(),
+        "adjust".to_string(),
+        "convert".to_string(),
+        "l3".to_string(),
+        "pairs".to_string(),
+        "overview".to_string(),
+        "version".to_string(),
+        "expire".to_string(),
+        "instrument".to_string(),
+        "journal".to_string(),
+        "shards".to_string(),
+        "archive".to_string(),
+        "amend".to_string(),
+        "query".to_string(),
+        "trades".to_string(),
+        "accounts".to_string(),
+        "fees".to_string(),
     ];
-    let db = Arc::new(Mutex::new(Database::new(Some("order_book.db".to_string()))));
-    let mut order_book_builder = OrderBook::default();
-    order_book_builder.set_db(db.clone());
+    if let Some(clock) = Clock::from_env().or_fail("Invalid FTX_CLOCK") {
+        clock::install(clock);
+    }
+    let db = Arc::new(Mutex::new(open_database("order_book.db")));
+    let mut shards = Shards::new(
+        db.clone(),
+        shard::paths_from_env()
+            .iter()
+            .map(|path| Arc::new(Mutex::new(open_database(path))))
+            .collect(),
+        Strategy::from_env().or_fail("Invalid FTX_SHARD_STRATEGY"),
+    )
+    .or_fail("Invalid FTX_SHARDS");
+    let duplicates = Duplicates::from_env().or_fail("Invalid FTX_DUPLICATE_PAIRS");
+    for shard in shards.all() {
+        let guard = shard.lock().expect("could not get db lock");
+        let migration =
+            canonical::migrate(&guard, duplicates).or_fail("could not migrate legacy pair keys");
+        if !migration.is_empty() {
+            println!(
+                "Migrated legacy pairs: {} renamed, {} dropped, {} rewritten",
+                migration.renamed.len(),
+                migration.dropped.len(),
+                migration.rewritten
+            );
+        }
+        quarantine::scan(&guard).or_fail("could not scan persisted pairs");
+    }
+    // dropped at the end of main, which drains queued writes before exiting
+    if let Ok(mode) = env::var(writer::ACK_MODE_ENV) {
+        shards.spawn_writers(AckMode::parse(&mode).or_fail("Invalid FTX_ACK_MODE"));
+    }
+    let writer = shards.writer(0).cloned();
+    let event_log = EventLog::from_env()
+        .or_fail("Invalid FTX_EVENT_LOG or FTX_EVENT_LOG_ROTATE")
+        .map(Arc::new);
+    let mut order_book_builder = new_order_book_builder(&db, writer.as_ref(), event_log.as_ref());
+
+    let mut output = Output::from_args();
 
-    match env::args().nth(2) {
+    match args().nth(2) {
         Some(arg) => match arg.as_str() {
             "print" => {
-                let pair = env::args()
+                let pair = args()
                     .nth(3)
-                    .expect("Pair is required. Example: print btc/usd");
-                let json = db.clone().lock().expect("could not get db lock").get(&pair);
-                let item: Item = serde_json::from_str(
-                    &json
-                        .expect("could not get fetch orders")
-                        .expect("sam bankman took the money"),
-                )
-                .expect(format!("Could not deserialize {}", pair).as_str());
+                    .map(|p| symbol(&db, p))
+                    .expect("Pair is required. Example: print btc/usd 127.0.0.1:7879 [[read replica address]] (optional) --levels=10 [[price levels per side]] (optional) --chart [[cumulative depth as bars]] (optional)");
+                let levels = args()
+                    .skip(4)
+                    .find_map(|a| a.strip_prefix("--levels=").map(str::to_string))
+                    .map_or(depth::DEFAULT_LEVELS, |l| {
+                        l.parse().or_fail("Invalid levels")
+                    });
+                let replica_addr = args().skip(4).find(|a| !a.starts_with("--"));
+                let item: Item = match replica_addr {
+                    Some(replica_addr) => replica::fetch_book(&replica_addr, pair.as_str())
+                        .or_fail("could not query read replica"),
+                    None => journal::current(
+                        &shards.db_for(&pair).lock().expect("could not get db lock"),
+                        pair.as_str(),
+                    )
+                    .or_fail(&format!("Could not read {}", pair)),
+                }
+                .unwrap_or_else(|| fail(error::not_found(format!("No order book for {}", pair))));
 
-                println!("Active orders={:?}", item.active_orders);
-                println!("Fulfilled orders={:?}", item.fulfilled_orders);
+                let depth = item.depth(levels);
+                let level = |l: &DepthLevel| {
+                    format!(
+                        "{} @ {} ({} orders)",
+                        l.total_quantity, l.price, l.order_count
+                    )
+                };
+                let text = match args().skip(4).any(|a| a == "--chart") {
+                    true => chart::render(&depth, chart::DEFAULT_WIDTH),
+                    false => format!(
+                        "Bids={:?}\nAsks={:?}",
+                        depth.bids.iter().map(level).collect::<Vec<_>>(),
+                        depth.asks.iter().map(level).collect::<Vec<_>>()
+                    ),
+                };
+                output.field("depth", &depth, text);
+                output.finish();
             }
             "order" => {
-                let err_msg = "Invalid usage! Example: order btc/usd [[represents pair]] buy [[or sell]] 10 [[price]] 3 [[quantity]] (default: 1)";
-                let pair = env::args().nth(3).expect(err_msg);
-                let order_type = env::args()
+                authorize(&db, UserRole::Trader);
+                let err_msg = "Invalid usage! Example: order btc/usd [[represents pair]] buy [[or sell]] 10 [[price, or market / peg:bid+1 / peg:ask-1 / peg:mid]] 3 [[quantity]] (default: 1) hidden [[optional, keeps the order out of the public book]] ioc [[optional time in force: gtc (default), ioc, fok or gtd:<expiry millis>]] account:7 [[optional, reserves the order on the account's balance]] tag:mm [[optional, groups orders for cancel tag=mm]] best [[optional, a market order only fills at the best price level]] min:2 [[optional, the order's first match fills at least 2 or it is cancelled]] aon [[optional, all or none, rests until it can fill entirely]]";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let order_type = args()
                     .nth(4)
                     .map(|a| {
                         if a == "sell" {
@@ -48,28 +193,1326 @@ fn main() {
                         }
                     })
                     .expect(err_msg);
-                let price = env::args()
-                    .nth(5)
-                    .map(|p| p.parse::<i32>().expect("Please provide a number"))
-                    .expect(err_msg);
-                let quantity = env::args()
+                let price_arg = args().nth(5).expect(err_msg);
+                let market = price_arg == "market";
+                let (price, peg) = match price_arg.strip_prefix("peg:") {
+                    _ if market => (0, None),
+                    Some(spec) => (
+                        0,
+                        Some(Peg::parse(spec).or_fail("Invalid peg, e.g. peg:bid+1")),
+                    ),
+                    None => (
+                        price_arg.parse::<i32>().or_fail("Please provide a number"),
+                        None,
+                    ),
+                };
+                let quantity = args()
                     .nth(6)
-                    .map(|q| q.parse::<i32>().expect("Please provide a number"))
+                    .map(|q| q.parse::<i32>().or_fail("Please provide a number"))
                     .unwrap_or(1);
+                let options = args().skip(7).collect::<Vec<_>>();
+                let hidden = options.iter().any(|o| o == "hidden");
+                let all_or_none = options.iter().any(|o| o == "aon");
+                let execution = match options.iter().any(|o| o == "best") {
+                    true => Execution::BestLevel,
+                    false => Execution::Sweep,
+                };
+                let account = options
+                    .iter()
+                    .find_map(|o| o.strip_prefix("account:"))
+                    .map(|id| id.parse().or_fail("Invalid account, e.g. account:7"));
+                let tag = options
+                    .iter()
+                    .find_map(|o| o.strip_prefix("tag:"))
+                    .map(|tag| Tag::parse(tag).or_fail("Invalid tag, e.g. tag:mm"));
+                let min_qty = options
+                    .iter()
+                    .find_map(|o| o.strip_prefix("min:"))
+                    .map(|min| min.parse().or_fail("Invalid min quantity, e.g. min:2"));
+                let time_in_force = options
+                    .iter()
+                    .find(|o| {
+                        !["hidden", "best", "aon"].contains(&o.as_str())
+                            && !o.starts_with("account:")
+                            && !o.starts_with("tag:")
+                            && !o.starts_with("min:")
+                    })
+                    .map(|tif| TimeInForce::parse(tif).or_fail("Invalid time in force, e.g. ioc"))
+                    .unwrap_or_default();
+                // client order ids are unique per account, keys of orders
+                // without one are scoped per actor so clients cannot replay
+                // each other's
+                let idempotency_key = env::var("FTX_IDEMPOTENCY_KEY").ok().map(|key| {
+                    let scope = account.map_or_else(|| actor(&db), |id| format!("account-{id}"));
+                    format!("{}:{}", scope, key)
+                });
+                let mut recent =
+                    RecentKeys::new(idempotency::DEFAULT_TTL, idempotency::PERSIST_EVERY);
+                let replayed = idempotency_key.as_ref().and_then(|key| {
+                    recent
+                        .replay::<Order>(&db.lock().expect("could not get db lock"), key)
+                        .or_fail("could not read idempotency key")
+                });
+                if let Some(order) = replayed {
+                    println!("Replayed Order={:?}", order);
+                } else {
+                    shards
+                        .configure(&mut order_book_builder, &pair)
+                        .unwrap_or_else(fail);
+                    order_book_builder.set_pair(pair.clone());
+                    let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+                    order_book.load().or_fail("could not load order book");
+
+                    let mut order =
+                        Order::with_generator(quantity, price, order_type, id_generator().as_ref());
+                    if market {
+                        order.update_kind(OrderKind::Market);
+                    }
+                    order.update_hidden(hidden);
+                    order.update_peg(peg);
+                    order.update_time_in_force(time_in_force);
+                    order.update_account(account);
+                    order.update_tag(tag);
+                    order.update_execution(execution);
+                    order.update_min_qty(min_qty);
+                    order.update_all_or_none(all_or_none);
+                    let ack = if order_type == OrderType::Buy {
+                        order_book.append_buy_order(order).unwrap_or_else(fail)
+                    } else {
+                        order_book.append_sell_order(order).unwrap_or_else(fail)
+                    };
+                    if let Some(key) = &idempotency_key {
+                        recent
+                            .remember(&db.lock().expect("could not get db lock"), key, &order)
+                            .or_fail("could not store idempotency key");
+                    }
+                    println!("{ack}");
+                    println!("Orders={:?}", order_book.join_active_orders());
+                }
+            }
+            "export" => {
+                authorize(&db, UserRole::Operator);
+                let err_msg = "File is required. Example: export state.json --format fix [[or state]] (default: state, fix writes trades as execution reports)";
+                let mut rest = args().skip(3).collect::<Vec<_>>();
+                let format = match rest.iter().position(|a| a == "--format") {
+                    Some(index) => {
+                        let format = rest.get(index + 1).cloned().expect(err_msg);
+                        rest.drain(index..=index + 1);
+                        format
+                    }
+                    None => "state".to_string(),
+                };
+                let path = rest.first().cloned().expect(err_msg);
+                match format.as_str() {
+                    "state" => {
+                        let state = export_shards(&shards).or_fail("could not export state");
+                        fs::write(
+                            &path,
+                            serde_json::to_string(&state).or_fail("could not serialize state"),
+                        )
+                        .or_fail("could not write state file");
+
+                        println!(
+                            "Exported {} pairs and {} accounts, state_hash={}",
+                            state.books.len(),
+                            state.accounts.len(),
+                            state.state_hash
+                        );
+                    }
+                    "fix" => {
+                        let mut trades = Vec::new();
+                        for shard in shards.all() {
+                            trades.extend(
+                                trade::trades(&shard.lock().expect("could not get db lock"), None)
+                                    .or_fail("could not read trades"),
+                            );
+                        }
+                        trades.sort_by_key(|logged| (logged.trade.timestamp, logged.sequence));
+                        let reports = trades
+                            .iter()
+                            .flat_map(fix::execution_reports)
+                            .collect::<Vec<_>>();
+                        fs::write(&path, reports.join("\n") + "\n")
+                            .or_fail("could not write execution reports");
+
+                        println!("Exported {} trades to {path}", trades.len());
+                    }
+                    _ => fail(error::validation(format!(
+                        "Unknown export format {format}, expected state or fix"
+                    ))),
+                }
+            }
+            "import" => {
+                authorize(&db, UserRole::Admin);
+                let path = args()
+                    .nth(3)
+                    .expect("File is required. Example: import state.json");
+                let json = fs::read_to_string(&path).or_fail("could not read state file");
+                let state: StateExport =
+                    serde_json::from_str(&json).or_fail("could not deserialize state");
+                import_shards(&shards, &state).or_fail("could not import state");
+                audit(
+                    &db,
+                    "import",
+                    &[("file", &path), ("state_hash", &state.state_hash)],
+                );
+
+                println!(
+                    "Imported {} pairs and {} accounts, state_hash={}",
+                    state.books.len(),
+                    state.accounts.len(),
+                    state.state_hash
+                );
+            }
+            "replicate" => {
+                authorize(&db, UserRole::Operator);
+                let err_msg = "Invalid usage! Example: replicate serve [[or follow, read-replica, promote]] 127.0.0.1:7878 [[primary address]] 1000 [[follow interval ms]] (default: 1000)";
+                let action = args().nth(3).expect(err_msg);
+                match action.as_str() {
+                    "serve" => {
+                        let addr = args().nth(4).expect(err_msg);
+                        let listener =
+                            TcpListener::bind(&addr).or_fail(&format!("Could not bind {}", addr));
+                        println!("Serving snapshots on {addr}");
+                        replica::serve(listener, db.clone()).or_fail("replication server failed");
+                    }
+                    "follow" => {
+                        let addr = args().nth(4).expect(err_msg);
+                        let interval = args()
+                            .nth(5)
+                            .map(|i| i.parse::<u64>().or_fail("Please provide a number"))
+                            .unwrap_or(1000);
+                        let guard = db.lock().expect("could not get db lock");
+                        replica::set_role(&guard, Role::Standby).or_fail("could not set role");
+
+                        loop {
+                            match replica::sync_from(&guard, &addr) {
+                                Ok(hash) => println!("Synced state_hash={hash}"),
+                                Err(e) => eprintln!("Sync failed: {e}"),
+                            }
+                            thread::sleep(Duration::from_millis(interval));
+                        }
+                    }
+                    "read-replica" => {
+                        let err_msg = "Invalid usage! Example: replicate read-replica 127.0.0.1:7878 [[primary address]] 127.0.0.1:7879 [[serve address]] 1000 [[follow interval ms]] (default: 1000)";
+                        let addr = args().nth(4).expect(err_msg);
+                        let serve_addr = args().nth(5).expect(err_msg);
+                        let interval = args()
+                            .nth(6)
+                            .map(|i| i.parse::<u64>().or_fail("Please provide a number"))
+                            .unwrap_or(1000);
+                        replica::set_role(
+                            &db.lock().expect("could not get db lock"),
+                            Role::ReadReplica,
+                        )
+                        .or_fail("could not set role");
+
+                        let listener = TcpListener::bind(&serve_addr)
+                            .or_fail(&format!("Could not bind {}", serve_addr));
+                        let serving = db.clone();
+                        thread::spawn(move || replica::serve(listener, serving));
+                        println!("Serving market data on {serve_addr}");
+
+                        loop {
+                            let result = replica::sync_from(
+                                &db.lock().expect("could not get db lock"),
+                                &addr,
+                            );
+                            match result {
+                                Ok(hash) => println!("Synced state_hash={hash}"),
+                                Err(e) => eprintln!("Sync failed: {e}"),
+                            }
+                            thread::sleep(Duration::from_millis(interval));
+                        }
+                    }
+                    "promote" => {
+                        replica::promote(&db.lock().expect("could not get db lock"))
+                            .or_fail("could not promote standby");
+                        audit(&db, "promote", &[]);
+                        println!("Standby promoted to primary");
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "telemetry" => {
+                let err_msg = "Invalid usage! Example: telemetry show [[or slow]] btc/usd [[pair]] (optional)";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "show" => {
+                        let pair = args().nth(4).map(|p| symbol(&db, p));
+                        let samples = telemetry::history(
+                            &db.lock().expect("could not get db lock"),
+                            pair.as_ref().map(Symbol::as_str),
+                        )
+                        .or_fail("could not read telemetry");
+
+                        output.list("samples", &samples, |s| format!("{:?}", s));
+                        let summary = telemetry::summarize(&samples);
+                        output.field("summary", &summary, format!("Summary={:?}", summary));
+                        output.finish();
+                    }
+                    "slow" => {
+                        let pair = args().nth(4).map(|p| symbol(&db, p));
+                        let reports = latency::slow_paths(
+                            &db.lock().expect("could not get db lock"),
+                            pair.as_ref().map(Symbol::as_str),
+                        )
+                        .or_fail("could not read slow path reports");
+
+                        output.list("reports", &reports, |r| format!("{:?}", r));
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "halted" => {
+                let halted = supervision::halted_pairs(&db.lock().expect("could not get db lock"))
+                    .or_fail("could not read halted pairs");
+                output.list("halted", &halted, |h| format!("Halted={:?}", h));
+                output.finish();
+            }
+            "restart" => {
+                authorize(&db, UserRole::Operator);
+                let pair = args()
+                    .nth(3)
+                    .map(|p| symbol(&db, p))
+                    .expect("Pair is required. Example: restart btc/usd");
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
                 order_book_builder.set_pair(pair.clone());
-                let mut order_book = order_book_builder.build();
-                order_book.load();
+                let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+                order_book.restart().unwrap_or_else(fail);
+                audit(&db, "restart", &[("pair", pair.as_str())]);
 
-                if order_type == OrderType::Buy {
-                    order_book
-                        .append_buy_order(Order::new(quantity, price, order_type))
-                        .expect("Invalid Order arguments");
-                } else {
-                    order_book
-                        .append_sell_order(Order::new(quantity, price, order_type))
-                        .expect("Invalid Order arguments");
+                println!(
+                    "Restarted {pair}, Orders={:?}",
+                    order_book.join_active_orders()
+                );
+            }
+            "recover" => {
+                authorize(&db, UserRole::Operator);
+                let pair = args()
+                    .nth(3)
+                    .map(|p| symbol(&db, p))
+                    .expect("Pair is required. Example: recover btc/usd");
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
+                order_book_builder.set_pair(pair.clone());
+                let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+                let replayed = order_book.recover().unwrap_or_else(fail);
+                audit(
+                    &db,
+                    "recover",
+                    &[("pair", pair.as_str()), ("commands", &replayed.to_string())],
+                );
+
+                println!(
+                    "Recovered {pair} from {replayed} commands, Orders={:?}",
+                    order_book.join_active_orders()
+                );
+            }
+            "book_at" => {
+                authorize(&db, UserRole::Operator);
+                let err_msg = "Invalid usage! Example: book_at btc/usd [[represents pair]] 1700000000000 [[unix timestamp in milliseconds]]";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let timestamp = args()
+                    .nth(4)
+                    .map(|t| t.parse::<u64>().or_fail("Please provide a number"))
+                    .expect(err_msg);
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
+                order_book_builder.set_pair(pair);
+                let order_book = order_book_builder.build().unwrap_or_else(fail);
+                let item = order_book
+                    .book_at(timestamp)
+                    .or_fail("could not reconstruct book");
+
+                print_item(&mut output, &item);
+                output.finish();
+            }
+            "ingest" => {
+                authorize(&db, UserRole::Trader);
+                let err_msg = "Invalid usage! Example: ingest ./orders [[directory of .csv or .fix order files]] 1000 [[poll interval ms]] (default: 1000)";
+                let dir = args().nth(3).expect(err_msg);
+                let interval = args()
+                    .nth(4)
+                    .map(|i| i.parse::<u64>().or_fail("Please provide a number"))
+                    .unwrap_or(1000);
+                let (builder_db, builder_writer, builder_event_log) =
+                    (db.clone(), writer.clone(), event_log.clone());
+                let mut exchange = Exchange::with_shards(shards.clone(), move || {
+                    new_order_book_builder(
+                        &builder_db,
+                        builder_writer.as_ref(),
+                        builder_event_log.as_ref(),
+                    )
+                });
+                println!("Watching {dir} for order files");
+
+                loop {
+                    let pending = ingest::pending(Path::new(&dir))
+                        .or_fail(&format!("could not read {}", dir));
+                    for path in pending {
+                        match ingest::process_file(&db, &path, |pair, order| {
+                            exchange.submit(pair, order).map(|ack| *ack.order())
+                        }) {
+                            Ok(result) => {
+                                audit(&db, "ingest", &[("file", &path.display().to_string())]);
+                                println!(
+                                    "Ingested {}, Results={}",
+                                    path.display(),
+                                    result.display()
+                                )
+                            }
+                            Err(e) => eprintln!("Ingest of {} failed: {e}", path.display()),
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(interval));
+                }
+            }
+            "convert" => {
+                let err_msg = "Invalid usage! Example: convert binance [[or kraken]] btc/usd [[pair]] trades.csv [[public trade dump]] ./orders/trades.csv [[order file for ingest]] 100 [[price scale]] (default: 100) 1000 [[quantity scale]] (default: 1000)";
+                let format = args()
+                    .nth(3)
+                    .map(|f| market_data::Format::parse(&f).or_fail("Invalid format"))
+                    .expect(err_msg);
+                let pair = args().nth(4).expect(err_msg);
+                let source = args().nth(5).expect(err_msg);
+                let destination = args().nth(6).expect(err_msg);
+                let scale = |n: usize, default: u32| {
+                    args()
+                        .nth(n)
+                        .map(|s| s.parse::<u32>().or_fail("Please provide a number"))
+                        .unwrap_or(default)
+                };
+                let scale = market_data::Scale {
+                    price: scale(7, 100),
+                    quantity: scale(8, 1000),
+                };
+                let contents = fs::read_to_string(&source).or_fail("could not read trade dump");
+
+                let lines =
+                    market_data::convert(format, &pair, &contents, scale).unwrap_or_else(fail);
+                fs::write(&destination, lines.join("\n") + "\n")
+                    .or_fail("could not write order file");
+
+                println!("Converted {} trades to {destination}", lines.len() / 2);
+            }
+            "l3" => {
+                let err_msg = "Invalid usage! Example: l3 events.ndjson [[event log, see FTX_EVENT_LOG]] btc/usd [[pair]] (optional)";
+                let path = args().nth(3).expect(err_msg);
+                let pair = args().nth(4).map(|p| symbol(&db, p));
+                let anonymizer = Anonymizer::from_env();
+                let log = fs::read_to_string(&path).or_fail("could not read event log");
+
+                for line in log.lines().filter(|l| !l.trim().is_empty()) {
+                    let event: Event = serde_json::from_str(line).or_fail("could not parse event");
+                    if pair.as_ref().is_some_and(|p| p.as_str() != event.pair) {
+                        continue;
+                    }
+                    if let Some(message) = l3::from_event(&event, &anonymizer) {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&message).or_fail("could not serialize update")
+                        );
+                    }
+                }
+            }
+            "pairs" => {
+                let pairs = Exchange::with_shards(shards.clone(), OrderBook::default)
+                    .list_pairs()
+                    .or_fail("could not list pairs");
+                output.list("pairs", &pairs, |p| format!("Pair={p}"));
+                output.finish();
+            }
+            "overview" => {
+                let overview = Exchange::with_shards(shards.clone(), OrderBook::default)
+                    .overview(telemetry::now_millis())
+                    .or_fail("could not read the overview");
+                output.list("pairs", &overview, |p| {
+                    format!(
+                        "Pair={} Bid={:?} Ask={:?} Last={:?} Volume24h={} Bids={} Asks={}",
+                        p.pair, p.bid, p.ask, p.last_price, p.volume_24h, p.open_bids, p.open_asks
+                    )
+                });
+                output.finish();
+            }
+            "version" => {
+                let info = Exchange::new(db.clone()).version_info();
+                match args().nth(3).as_deref() {
+                    Some("--verbose") => {
+                        output.field("crates", &info.crates, format!("Crates={:?}", info.crates));
+                        output.field(
+                            "git_hash",
+                            &info.git_hash,
+                            format!("Git hash={}", info.git_hash),
+                        );
+                        output.field(
+                            "schema_version",
+                            &info.schema_version,
+                            format!("Schema version={}", info.schema_version),
+                        );
+                        output.field(
+                            "features",
+                            &info.features,
+                            format!("Features={:?}", info.features),
+                        );
+                    }
+                    _ => output.field("version", &info.to_string(), info.to_string()),
+                }
+                output.finish();
+            }
+            "verify" => {
+                let err_msg = "Invalid usage! Example: verify btc/usd [[pair]] 60000 [[re-check interval ms]] (optional, runs once by default)";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let interval = args()
+                    .nth(4)
+                    .map(|i| i.parse::<u64>().or_fail("Please provide a number"));
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
+                order_book_builder.set_pair(pair.clone());
+                let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+
+                loop {
+                    // load keeps the previous sides when the pair has no snapshot
+                    order_book.load_bulk(Vec::new());
+                    order_book.load().or_fail("could not load order book");
+                    let violations = order_book.verify();
+                    // one report per check, so a watch prints one json line each time
+                    let mut report = Output::from_args();
+                    report.list("violations", &violations, |v| format!("Violation={v}"));
+                    let consistent = violations.is_empty();
+                    let text = if consistent {
+                        format!("Consistent {pair}")
+                    } else {
+                        String::new()
+                    };
+                    report.field("consistent", &consistent, text);
+                    match interval {
+                        Some(interval) => {
+                            report.finish();
+                            thread::sleep(Duration::from_millis(interval));
+                        }
+                        None if consistent => {
+                            report.finish();
+                            break;
+                        }
+                        None => report.fail(),
+                    }
+                }
+            }
+            "health" => {
+                let report = health::check(&db.lock().expect("could not get db lock"))
+                    .or_fail("could not run health check");
+                output.list("halted", &report.halted, |h| format!("Halted={:?}", h));
+                output.list("quarantined", &report.quarantined, |q| {
+                    format!("Quarantined={:?}", q)
+                });
+
+                if !report.is_healthy() {
+                    output.field("healthy", &false, String::new());
+                    output.fail();
+                }
+                output.field("healthy", &true, "Healthy".to_string());
+                output.finish();
+            }
+            "halt" => {
+                authorize(&db, UserRole::Operator);
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(
+                    "Pair is required. Example: halt btc/usd maintenance [[reason]] (optional)",
+                );
+                let reason = args()
+                    .nth(4)
+                    .unwrap_or_else(|| "halted by operator".to_string());
+                supervision::halt(
+                    &db.lock().expect("could not get db lock"),
+                    pair.as_str(),
+                    &reason,
+                    None,
+                )
+                .or_fail("could not halt pair");
+                audit(&db, "halt", &[("pair", pair.as_str()), ("reason", &reason)]);
+
+                println!("Halted {pair}");
+            }
+            "audit" => {
+                authorize(&db, UserRole::Operator);
+                let err_msg = "Invalid usage! Example: audit list halt [[action]] (optional)";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "list" => {
+                        let action = args().nth(4);
+                        let entries = audit::list(
+                            &db.lock().expect("could not get db lock"),
+                            action.as_deref(),
+                        )
+                        .or_fail("could not read audit log");
+                        output.list("entries", &entries, |e| format!("{:?}", e));
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "roles" => {
+                let err_msg = "Invalid usage! Example: roles assign [[or list]] alice [[actor]] operator [[trader, operator or admin]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "assign" => {
+                        authorize(&db, UserRole::Admin);
+                        let user = args().nth(4).expect(err_msg);
+                        let role = args()
+                            .nth(5)
+                            .map(|r| UserRole::parse(&r).or_fail("Invalid role"))
+                            .expect(err_msg);
+                        access::assign(&db.lock().expect("could not get db lock"), &user, role)
+                            .or_fail("could not assign role");
+                        audit(
+                            &db,
+                            "assign_role",
+                            &[("user", &user), ("role", &format!("{:?}", role))],
+                        );
+
+                        println!("Assigned {:?} to {user}", role);
+                    }
+                    "list" => {
+                        let assignments =
+                            access::assignments(&db.lock().expect("could not get db lock"))
+                                .or_fail("could not read roles");
+                        output.list("roles", &assignments, |(user, role)| {
+                            format!("{user}={:?}", role)
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "keys" => {
+                authorize(&db, UserRole::Admin);
+                let err_msg = "Invalid usage! Example: keys create [[or rotate, list]] trading-bot [[name]] api [[or webhook]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "create" => {
+                        let name = args().nth(4).expect(err_msg);
+                        let kind = args()
+                            .nth(5)
+                            .map(|k| SecretKind::parse(&k).or_fail("Invalid key kind"))
+                            .expect(err_msg);
+                        let secret = secrets::create(
+                            &db.lock().expect("could not get db lock"),
+                            &name,
+                            kind,
+                        )
+                        .or_fail("could not create key");
+                        audit(&db, "create_key", &[("name", &name)]);
+
+                        println!("Created {name}, secret={secret} (it will not be shown again)");
+                    }
+                    "rotate" => {
+                        let name = args().nth(4).expect(err_msg);
+                        let secret =
+                            secrets::rotate(&db.lock().expect("could not get db lock"), &name)
+                                .or_fail("could not rotate key");
+                        audit(&db, "rotate_key", &[("name", &name)]);
+
+                        println!("Rotated {name}, secret={secret} (it will not be shown again)");
+                    }
+                    "list" => {
+                        let stored = secrets::list(&db.lock().expect("could not get db lock"))
+                            .or_fail("could not read keys");
+                        output.list("keys", &stored, |key| {
+                            format!(
+                                "{} kind={:?} version={} created_at={} rotated_at={:?}",
+                                key.name, key.kind, key.version, key.created_at, key.rotated_at
+                            )
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "alias" => {
+                let err_msg = "Invalid usage! Example: alias add [[or remove, list]] xbt/usd [[alias]] btc/usd [[canonical pair]]";
+                let parse = |raw: String| Symbol::parse(&raw).unwrap_or_else(fail);
+                match args().nth(3).expect(err_msg).as_str() {
+                    "add" => {
+                        authorize(&db, UserRole::Operator);
+                        let alias = args().nth(4).map(parse).expect(err_msg);
+                        let canonical = args().nth(5).map(parse).expect(err_msg);
+                        symbol::add_alias(
+                            &db.lock().expect("could not get db lock"),
+                            &alias,
+                            &canonical,
+                        )
+                        .or_fail("could not add alias");
+                        audit(
+                            &db,
+                            "add_alias",
+                            &[("alias", alias.as_str()), ("canonical", canonical.as_str())],
+                        );
+
+                        println!("{alias} -> {canonical}");
+                    }
+                    "remove" => {
+                        authorize(&db, UserRole::Operator);
+                        let alias = args().nth(4).map(parse).expect(err_msg);
+                        symbol::remove_alias(&db.lock().expect("could not get db lock"), &alias)
+                            .or_fail("could not remove alias");
+                        audit(&db, "remove_alias", &[("alias", alias.as_str())]);
+
+                        println!("Removed {alias}");
+                    }
+                    "list" => {
+                        let aliases = symbol::aliases(&db.lock().expect("could not get db lock"))
+                            .or_fail("could not read aliases");
+                        output.list("aliases", &aliases, |(alias, canonical)| {
+                            format!("{alias} -> {canonical}")
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "calendar" => {
+                let err_msg = "Invalid usage! Example: calendar set [[or override, status, remove]] btc/usd [[pair]] mon-fri=09:00-17:00,holiday=2026-12-25 [[calendar, UTC]] / open [[override: open, closed or clear]]";
+                let command = args().nth(3).expect(err_msg);
+                let pair = args().nth(4).map(|p| symbol(&db, p)).expect(err_msg);
+                match command.as_str() {
+                    "set" => {
+                        authorize(&db, UserRole::Operator);
+                        let spec = args().nth(5).expect(err_msg);
+                        let trading_calendar =
+                            TradingCalendar::parse(&spec).or_fail("Invalid calendar");
+                        calendar::set(
+                            &db.lock().expect("could not get db lock"),
+                            pair.as_str(),
+                            trading_calendar,
+                        )
+                        .or_fail("could not set calendar");
+                        audit(
+                            &db,
+                            "set_calendar",
+                            &[("pair", pair.as_str()), ("calendar", &spec)],
+                        );
+
+                        println!("Calendar for {pair} set");
+                    }
+                    "override" => {
+                        authorize(&db, UserRole::Operator);
+                        let state = match args().nth(5).expect(err_msg).as_str() {
+                            "open" => Some(MarketState::Open),
+                            "closed" => Some(MarketState::Closed),
+                            "clear" => None,
+                            _ => panic!("{}", err_msg),
+                        };
+                        calendar::force(
+                            &db.lock().expect("could not get db lock"),
+                            pair.as_str(),
+                            state,
+                        )
+                        .or_fail("could not override market state");
+                        audit(
+                            &db,
+                            "override_market_state",
+                            &[
+                                ("pair", pair.as_str()),
+                                ("state", &args().nth(5).unwrap_or_default()),
+                            ],
+                        );
+
+                        println!("Override for {pair}: {:?}", state);
+                    }
+                    "status" => {
+                        let state = calendar::observe(
+                            &db.lock().expect("could not get db lock"),
+                            pair.as_str(),
+                        )
+                        .or_fail("could not read market state");
+                        output.field("state", &state, format!("{pair} is {:?}", state));
+                        output.finish();
+                    }
+                    "remove" => {
+                        authorize(&db, UserRole::Operator);
+                        calendar::remove(&db.lock().expect("could not get db lock"), pair.as_str())
+                            .or_fail("could not remove calendar");
+                        audit(&db, "remove_calendar", &[("pair", pair.as_str())]);
+
+                        println!("Removed calendar for {pair}");
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "cancel" => {
+                authorize(&db, UserRole::Trader);
+                let err_msg = "Invalid usage! Example: cancel btc/usd [[pair]] side=buy,min_price=10,max_price=20,older_than=60 [[filter, older_than in seconds, also tag=mm and account=7]]";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let filter = args()
+                    .nth(4)
+                    .map(|f| CancelFilter::parse(&f).or_fail("Invalid filter"))
+                    .expect(err_msg);
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
+                order_book_builder.set_pair(pair.clone());
+                let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+                order_book.load().or_fail("could not load order book");
+
+                let cancelled = order_book.cancel_where(&filter).unwrap_or_else(fail);
+                audit(
+                    &db,
+                    "cancel_where",
+                    &[
+                        ("pair", pair.as_str()),
+                        ("filter", &args().nth(4).unwrap_or_default()),
+                        ("cancelled", &cancelled.len().to_string()),
+                    ],
+                );
+
+                println!("Cancelled={:?}", cancelled);
+            }
+            "query" => {
+                let err_msg = "Invalid usage! Example: query \"pair=BTC/USD and price>100 and time>2024-01-01\" [[fields pair, side, price, quantity, time]]";
+                let query = Query::parse(&args().nth(3).expect(err_msg)).unwrap_or_else(fail);
+                let store = archive::from_env().or_fail("Invalid trade archive");
+                let dbs = match query.pair() {
+                    Some(pair) => vec![shards.db_for(pair).clone()],
+                    None => shards.all().to_vec(),
+                };
+                let mut trades = Vec::new();
+                for db in dbs {
+                    trades.extend(
+                        query
+                            .run(&db.lock().expect("could not get db lock"), store.as_deref())
+                            .unwrap_or_else(fail),
+                    );
+                }
+                trades.sort_by_key(|logged| (logged.trade.timestamp, logged.sequence));
+                output.list("trades", &trades, |logged| {
+                    format!("{} {:?}", logged.pair, logged.trade)
+                });
+                output.finish();
+            }
+            "trades" => {
+                let err_msg = "Invalid usage! Example: trades order 8b0c2a4e-... [[trades of an order]] [[or account 7, trades of an account, or reindex, indexes trades recorded before the index existed]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "order" => {
+                        let id = args()
+                            .nth(4)
+                            .map(|id| id.parse().or_fail("Invalid order id"))
+                            .expect(err_msg);
+                        let mut trades = Vec::new();
+                        for shard in shards.all() {
+                            trades.extend(
+                                trade::trades_of_order(
+                                    &shard.lock().expect("could not get db lock"),
+                                    id,
+                                )
+                                .or_fail("could not read trades"),
+                            );
+                        }
+                        output.list("trades", &trades, |logged| {
+                            format!("{} {:?}", logged.pair, logged.trade)
+                        });
+                        output.finish();
+                    }
+                    "account" => {
+                        let id = args()
+                            .nth(4)
+                            .map(|id| id.parse().or_fail("Invalid account"))
+                            .expect(err_msg);
+                        let mut trades = Vec::new();
+                        for shard in shards.all() {
+                            trades.extend(
+                                trade::trades_of_account(
+                                    &shard.lock().expect("could not get db lock"),
+                                    id,
+                                )
+                                .or_fail("could not read trades"),
+                            );
+                        }
+                        trades.sort_by_key(|logged| (logged.trade.timestamp, logged.sequence));
+                        output.list("trades", &trades, |logged| {
+                            format!("{} {:?}", logged.pair, logged.trade)
+                        });
+                        output.finish();
+                    }
+                    "reindex" => {
+                        authorize(&db, UserRole::Operator);
+                        let mut indexed = 0;
+                        for shard in shards.all() {
+                            indexed +=
+                                trade::reindex(&shard.lock().expect("could not get db lock"))
+                                    .or_fail("could not index trades");
+                        }
+                        audit(&db, "reindex_trades", &[("indexed", &indexed.to_string())]);
+
+                        println!("Added {indexed} trade index entries");
+                    }
+                    _ => panic!("{}", err_msg),
                 }
-                println!("Orders={:?}", order_book.join_active_orders());
+            }
+            "fees" => {
+                let err_msg = "Invalid usage! Example: fees set [[or remove, list]] btc/usd [[pair]] 10 [[maker basis points, negative for a rebate]] 20 [[taker basis points]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "set" => {
+                        authorize(&db, UserRole::Operator);
+                        let pair = args().nth(4).map(|p| symbol(&db, p)).expect(err_msg);
+                        let bps = |n: usize| -> i32 {
+                            args().nth(n).expect(err_msg).parse().or_fail("Invalid fee")
+                        };
+                        let schedule =
+                            FeeSchedule::new(pair.clone(), bps(5), bps(6)).unwrap_or_else(fail);
+                        fees::set(&db.lock().expect("could not get db lock"), &schedule)
+                            .or_fail("could not set fees");
+                        audit(
+                            &db,
+                            "set_fees",
+                            &[
+                                ("pair", pair.as_str()),
+                                ("maker_bps", &schedule.maker_bps.to_string()),
+                                ("taker_bps", &schedule.taker_bps.to_string()),
+                            ],
+                        );
+
+                        println!("Fees for {pair} set");
+                    }
+                    "remove" => {
+                        authorize(&db, UserRole::Operator);
+                        let pair = args().nth(4).map(|p| symbol(&db, p)).expect(err_msg);
+                        fees::remove(&db.lock().expect("could not get db lock"), pair.as_str())
+                            .or_fail("could not remove fees");
+                        audit(&db, "remove_fees", &[("pair", pair.as_str())]);
+
+                        println!("Removed fees for {pair}");
+                    }
+                    "list" => {
+                        let schedules = fees::schedules(&db.lock().expect("could not get db lock"))
+                            .or_fail("could not read fees");
+                        output.list("fees", &schedules, |f| {
+                            format!("{} maker={} taker={}", f.symbol, f.maker_bps, f.taker_bps)
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "accounts" => {
+                let err_msg = "Invalid usage! Example: accounts deposit [[or withdraw]] 7 [[account]] usd [[asset]] 1000 [[amount]] [[or owner 7 bot, the API key trading for it; or show 7, rebates 7, or list; fees are paid into account 0 and maker rebates out of it]]";
+                let id = || -> u64 {
+                    args()
+                        .nth(4)
+                        .expect(err_msg)
+                        .parse()
+                        .or_fail("Invalid account")
+                };
+                let show = |account: &Account| {
+                    account
+                        .balances
+                        .iter()
+                        .map(|(asset, b)| format!("{}={}+{}", asset, b.available, b.reserved))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                match args().nth(3).expect(err_msg).as_str() {
+                    action @ ("deposit" | "withdraw") => {
+                        authorize(&db, UserRole::Operator);
+                        let (id, asset) = (id(), args().nth(5).expect(err_msg));
+                        let amount = args()
+                            .nth(6)
+                            .expect(err_msg)
+                            .parse::<i64>()
+                            .or_fail("Invalid amount");
+                        let account = {
+                            let db = db.lock().expect("could not get db lock");
+                            match action {
+                                "deposit" => accounts::deposit(&db, id, &asset, amount),
+                                _ => accounts::withdraw(&db, id, &asset, amount),
+                            }
+                        }
+                        .unwrap_or_else(fail);
+                        audit(
+                            &db,
+                            action,
+                            &[
+                                ("account", &id.to_string()),
+                                ("asset", &asset.to_uppercase()),
+                                ("amount", &amount.to_string()),
+                            ],
+                        );
+
+                        println!("Account {id} {}", show(&account));
+                    }
+                    "owner" => {
+                        authorize(&db, UserRole::Operator);
+                        let (id, owner) = (id(), args().nth(5).expect(err_msg));
+                        accounts::set_owner(&db.lock().expect("could not get db lock"), id, &owner)
+                            .unwrap_or_else(fail);
+                        audit(
+                            &db,
+                            "account_owner",
+                            &[("account", &id.to_string()), ("owner", &owner)],
+                        );
+
+                        println!("Account {id} is traded by {owner}");
+                    }
+                    "show" => {
+                        let id = id();
+                        let account = accounts::get(&db.lock().expect("could not get db lock"), id)
+                            .or_fail("could not read account")
+                            .ok_or_else(|| error::not_found(format!("No account {id}")))
+                            .unwrap_or_else(fail);
+                        output.field(
+                            "account",
+                            &account,
+                            format!("Account {id} {}", show(&account)),
+                        );
+                        output.finish();
+                    }
+                    "rebates" => {
+                        let id = id();
+                        let mut trades = Vec::new();
+                        for shard in shards.all() {
+                            trades.extend(
+                                trade::trades_of_account(
+                                    &shard.lock().expect("could not get db lock"),
+                                    id,
+                                )
+                                .or_fail("could not read trades"),
+                            );
+                        }
+                        let report = fees::rebates(id, &trades).unwrap_or_else(fail);
+                        output.field(
+                            "rebates",
+                            &report,
+                            format!(
+                                "Account {id} rebates {:?} over {} trade(s)",
+                                report.rebates, report.trades
+                            ),
+                        );
+                        output.finish();
+                    }
+                    "list" => {
+                        let accounts =
+                            accounts::accounts(&db.lock().expect("could not get db lock"))
+                                .or_fail("could not read accounts");
+                        output.list("accounts", &accounts, |a| {
+                            format!("Account {} {}", a.id, show(a))
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "amend" => {
+                authorize(&db, UserRole::Trader);
+                let err_msg = "Invalid usage! Example: amend btc/usd [[pair]] 8b0c2a4e-... [[order id]] 20 [[price]] 5 [[total quantity]]";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let id = args()
+                    .nth(4)
+                    .map(|id| id.parse().or_fail("Invalid order id"))
+                    .expect(err_msg);
+                let number = |n| args().nth(n).expect(err_msg).parse::<i32>().expect(err_msg);
+                let (price, quantity) = (number(5), number(6));
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
+                order_book_builder.set_pair(pair.clone());
+                let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+                order_book.load().or_fail("could not load order book");
+
+                let ack = order_book
+                    .amend_order(id, price, quantity)
+                    .unwrap_or_else(fail);
+                audit(
+                    &db,
+                    "amend",
+                    &[
+                        ("pair", pair.as_str()),
+                        ("order", &id.to_string()),
+                        ("price", &price.to_string()),
+                        ("quantity", &quantity.to_string()),
+                    ],
+                );
+
+                println!("{ack}");
+            }
+            "expire" => {
+                authorize(&db, UserRole::Operator);
+                let (book_db, writer, event_log) = (db.clone(), writer.clone(), event_log.clone());
+                let mut exchange = Exchange::with_shards(shards.clone(), move || {
+                    new_order_book_builder(&book_db, writer.as_ref(), event_log.as_ref())
+                });
+                let expired = exchange
+                    .expire(telemetry::now_millis())
+                    .unwrap_or_else(fail);
+                audit(&db, "expire", &[("expired", &expired.len().to_string())]);
+
+                output.list("expired", &expired, |o| format!("Expired={:?}", o));
+                output.finish();
+            }
+            "instrument" => {
+                let err_msg = "Invalid usage! Example: instrument set [[or remove, list]] btc/usd [[pair]] 5 [[tick size]] 10 [[min quantity]] 10 [[lot size]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "set" => {
+                        authorize(&db, UserRole::Operator);
+                        let pair = args().nth(4).map(|p| symbol(&db, p)).expect(err_msg);
+                        let size = |n: usize| -> i32 {
+                            args()
+                                .nth(n)
+                                .expect(err_msg)
+                                .parse()
+                                .or_fail("Invalid size")
+                        };
+                        let instrument = Instrument::new(pair.clone(), size(5), size(6), size(7))
+                            .unwrap_or_else(fail);
+                        instrument::register(
+                            &db.lock().expect("could not get db lock"),
+                            &instrument,
+                        )
+                        .or_fail("could not register instrument");
+                        audit(
+                            &db,
+                            "set_instrument",
+                            &[
+                                ("pair", pair.as_str()),
+                                ("tick_size", &instrument.tick_size.to_string()),
+                                ("min_quantity", &instrument.min_quantity.to_string()),
+                                ("lot_size", &instrument.lot_size.to_string()),
+                            ],
+                        );
+
+                        println!("Instrument for {pair} set");
+                    }
+                    "remove" => {
+                        authorize(&db, UserRole::Operator);
+                        let pair = args().nth(4).map(|p| symbol(&db, p)).expect(err_msg);
+                        instrument::remove(
+                            &db.lock().expect("could not get db lock"),
+                            pair.as_str(),
+                        )
+                        .or_fail("could not remove instrument");
+                        audit(&db, "remove_instrument", &[("pair", pair.as_str())]);
+
+                        println!("Removed instrument for {pair}");
+                    }
+                    "list" => {
+                        let instruments =
+                            instrument::instruments(&db.lock().expect("could not get db lock"))
+                                .or_fail("could not read instruments");
+                        output.list("instruments", &instruments, |i| {
+                            format!(
+                                "{} tick={} min={} lot={}",
+                                i.symbol, i.tick_size, i.min_quantity, i.lot_size
+                            )
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "journal" => {
+                let err_msg = "Invalid usage! Example: journal compact [[or stats]] btc/usd [[pair, all pairs when omitted]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "compact" => {
+                        authorize(&db, UserRole::Operator);
+                        let retention = journal::retention_from_env()
+                            .or_fail("Invalid FTX_JOURNAL_RETENTION")
+                            .unwrap_or(journal::DEFAULT_RETENTION);
+                        let pairs = match args().nth(4).map(|p| symbol(&db, p)) {
+                            Some(pair) => vec![pair],
+                            None => shards
+                                .pairs()
+                                .or_fail("could not read pairs")
+                                .into_iter()
+                                .map(|(pair, _)| pair)
+                                .collect(),
+                        };
+                        let mut truncated = 0;
+                        for pair in &pairs {
+                            truncated += journal::compact(
+                                &shards.db_for(pair).lock().expect("could not get db lock"),
+                                pair.as_str(),
+                                retention,
+                            )
+                            .or_fail("could not compact journal");
+                        }
+                        let pairs = pairs.iter().map(Symbol::as_str).collect::<Vec<_>>();
+                        audit(
+                            &db,
+                            "compact_journal",
+                            &[
+                                ("pairs", &pairs.join(",")),
+                                ("truncated", &truncated.to_string()),
+                            ],
+                        );
+
+                        println!("Truncated {truncated} journalled commands");
+                    }
+                    "stats" => {
+                        let mut stats = JournalStats::default();
+                        for shard in shards.all() {
+                            stats.add(
+                                journal::stats(&shard.lock().expect("could not get db lock"))
+                                    .or_fail("could not read journal"),
+                            );
+                        }
+                        output.field("journal", &stats, format!("Journal={:?}", stats));
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "shards" => {
+                let err_msg = "Invalid usage! Example: shards list [[or rebalance, moves pairs to the shard FTX_SHARDS and FTX_SHARD_STRATEGY give them]]";
+                match args().nth(3).expect(err_msg).as_str() {
+                    "list" => {
+                        let pairs = shards.pairs().or_fail("could not read pairs");
+                        output.list("pairs", &pairs, |(pair, shard)| {
+                            format!("Pair={pair} shard={shard}")
+                        });
+                        output.finish();
+                    }
+                    "rebalance" => {
+                        authorize(&db, UserRole::Operator);
+                        let rebalance = shards.rebalance().unwrap_or_else(fail);
+                        audit(
+                            &db,
+                            "rebalance_shards",
+                            &[
+                                ("moved", &rebalance.moved.len().to_string()),
+                                ("records", &rebalance.records.to_string()),
+                            ],
+                        );
+
+                        output.list("moved", &rebalance.moved, |(pair, from, to)| {
+                            format!("Moved {pair} from shard {from} to {to}")
+                        });
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "archive" => {
+                let err_msg = "Invalid usage! Example: archive run 86400 [[archives trades older than this many seconds]] [[or trades btc/usd 0 1700000000000 [[pair, optional from and to in ms]]]]";
+                let store = archive::from_env().or_fail("Invalid trade archive");
+                match args().nth(3).expect(err_msg).as_str() {
+                    "run" => {
+                        authorize(&db, UserRole::Operator);
+                        let store = store.unwrap_or_else(|| {
+                            fail(error::validation(format!(
+                                "No archive configured, set {} or {}",
+                                archive::ARCHIVE_DIR_ENV,
+                                archive::ARCHIVE_S3_ENV
+                            )))
+                        });
+                        let age = args().nth(4).expect(err_msg).parse::<u64>().expect(err_msg);
+                        let before = telemetry::now_millis().saturating_sub(age * 1000);
+                        let mut batches = Vec::new();
+                        for shard in shards.all() {
+                            batches.extend(
+                                archive::archive(
+                                    &shard.lock().expect("could not get db lock"),
+                                    store.as_ref(),
+                                    before,
+                                )
+                                .unwrap_or_else(fail),
+                            );
+                        }
+                        let trades = batches.iter().map(|b| b.trades).sum::<usize>();
+                        audit(
+                            &db,
+                            "archive_trades",
+                            &[
+                                ("batches", &batches.len().to_string()),
+                                ("trades", &trades.to_string()),
+                            ],
+                        );
+
+                        output.list("batches", &batches, |batch| {
+                            format!(
+                                "Archived {} trades of {} to {}",
+                                batch.trades, batch.pair, batch.object
+                            )
+                        });
+                        output.finish();
+                    }
+                    "trades" => {
+                        let pair = args().nth(4).map(|p| symbol(&db, p)).expect(err_msg);
+                        let bound = |n, default| {
+                            args()
+                                .nth(n)
+                                .map_or(default, |raw| raw.parse().expect(err_msg))
+                        };
+                        let trades = archive::history(
+                            &shards.db_for(&pair).lock().expect("could not get db lock"),
+                            store.as_deref(),
+                            Some(pair.as_str()),
+                            bound(5, 0),
+                            bound(6, u64::MAX),
+                        )
+                        .unwrap_or_else(fail);
+                        output.list("trades", &trades, |logged| format!("{:?}", logged.trade));
+                        output.finish();
+                    }
+                    _ => panic!("{}", err_msg),
+                }
+            }
+            "adjust" => {
+                authorize(&db, UserRole::Operator);
+                let err_msg = "Invalid usage! Example: adjust btc/usd [[pair]] 1/100 [[price factor, e.g. 1/100 drops two zeros]]";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let adjustment = args()
+                    .nth(4)
+                    .map(|f| PriceAdjustment::parse(&f).or_fail("Invalid price factor"))
+                    .expect(err_msg);
+                shards
+                    .configure(&mut order_book_builder, &pair)
+                    .unwrap_or_else(fail);
+                order_book_builder.set_pair(pair.clone());
+                let mut order_book = order_book_builder.build().unwrap_or_else(fail);
+                order_book.load().or_fail("could not load order book");
+
+                let repriced = order_book.adjust_prices(adjustment).unwrap_or_else(fail);
+                audit(
+                    &db,
+                    "price_adjustment",
+                    &[
+                        ("pair", pair.as_str()),
+                        ("factor", &adjustment.to_string()),
+                        ("repriced", &repriced.len().to_string()),
+                    ],
+                );
+
+                println!("Adjusted {pair} by {adjustment}, Orders={:?}", repriced);
+            }
+            "orders" => {
+                let err_msg = "Invalid usage! Example: orders btc/usd [[pair]] price [[price, time or size]] 50 [[limit]] 15:<id> [[cursor]] (optional)";
+                let pair = args().nth(3).map(|p| symbol(&db, p)).expect(err_msg);
+                let sort = args()
+                    .nth(4)
+                    .map(|s| SortKey::parse(&s).or_fail("Invalid sort"))
+                    .unwrap_or(SortKey::Time);
+                let limit = args()
+                    .nth(5)
+                    .map(|l| l.parse().expect(err_msg))
+                    .unwrap_or(50);
+                let cursor = args()
+                    .nth(6)
+                    .map(|c| Cursor::parse(&c).or_fail("Invalid cursor"));
+                let item = journal::current(
+                    &shards.db_for(&pair).lock().expect("could not get db lock"),
+                    pair.as_str(),
+                )
+                .or_fail(&format!("Could not read {}", pair))
+                .unwrap_or_else(|| fail(error::not_found(format!("No order book for {}", pair))));
+
+                let page = item.public_view().page(sort, cursor.as_ref(), limit);
+                output.list("orders", &page.orders, |o| format!("Order={:?}", o));
+                output.field(
+                    "next",
+                    &page.next.map(|c| c.to_string()),
+                    page.next.map(|c| format!("Next={c}")).unwrap_or_default(),
+                );
+                output.finish();
             }
             _ => {}
         },
@@ -80,3 +1523,140 @@ fn main() {
         }
     }
 }
+
+// `<name>:<secret>` of a key made with `keys create <name> api`
+const API_KEY_ENV: &str = "FTX_API_KEY";
+const API_KEY_FLAG: &str = "--api-key=";
+
+// Positional arguments, the output and key flags may appear anywhere.
+fn args() -> impl Iterator<Item = String> {
+    env::args().filter(|a| !output::is_flag(a) && !a.starts_with(API_KEY_FLAG))
+}
+
+fn open_database(path: &str) -> Database {
+    let codec = Codec::from_env().or_fail("Invalid FTX_DB_CODEC");
+    let mut database =
+        Database::new(Some(path.to_string()), codec).or_fail(&format!("could not open {path}"));
+    if let Some(cipher) = Cipher::from_env().or_fail("Invalid FTX_DB_KEY") {
+        database = database.with_cipher(cipher);
+    }
+    for tree in compression::trees_from_env() {
+        database = database.with_compression(&tree);
+    }
+    database
+}
+
+fn new_order_book_builder(
+    db: &Arc<Mutex<Database>>,
+    writer: Option<&Arc<Writer>>,
+    event_log: Option<&Arc<EventLog>>,
+) -> OrderBook {
+    let mut order_book_builder = OrderBook::default();
+    order_book_builder.set_db(db.clone());
+    if let Some(budget) = LatencyBudget::from_env().or_fail("Invalid FTX_LATENCY_BUDGET") {
+        order_book_builder.set_latency_budget(budget);
+    }
+    if let Some(interval) = journal::interval_from_env().or_fail("Invalid FTX_SNAPSHOT_INTERVAL") {
+        order_book_builder.set_snapshot_interval(interval);
+    }
+    if let Some(retention) = journal::retention_from_env().or_fail("Invalid FTX_JOURNAL_RETENTION")
+    {
+        order_book_builder.set_journal_retention(retention);
+    }
+    if let Some(writer) = writer {
+        order_book_builder.set_writer(writer.clone());
+    }
+    if let Some(event_log) = event_log {
+        order_book_builder.set_event_log(event_log.clone());
+    }
+    order_book_builder
+}
+
+fn print_item(output: &mut Output, item: &Item) {
+    output.field(
+        "active_orders",
+        &item.active_orders,
+        format!("Active orders={:?}", item.active_orders),
+    );
+    output.field(
+        "fulfilled_orders",
+        &item.fulfilled_orders,
+        format!("Fulfilled orders={:?}", item.fulfilled_orders),
+    );
+}
+
+fn symbol(db: &Arc<Mutex<Database>>, raw: String) -> Symbol {
+    symbol::resolve(&db.lock().expect("could not get db lock"), &raw).unwrap_or_else(fail)
+}
+
+// Exits with the code of the error's kind, e.g. 2 for a validation error.
+fn fail<T>(e: anyhow::Error) -> T {
+    let kind = ErrorKind::of(&e);
+    eprintln!("Error ({kind}): {e:#}");
+    process::exit(kind.exit_code());
+}
+
+// Fails with what could not be done in front of the cause, e.g.
+// "could not read trades: ...", keeping the cause's exit code.
+trait OrFail<T> {
+    fn or_fail(self, what: &str) -> T;
+}
+
+impl<T, E: Into<anyhow::Error>> OrFail<T> for Result<T, E> {
+    fn or_fail(self, what: &str) -> T {
+        self.unwrap_or_else(|e| fail(e.into().context(what.to_string())))
+    }
+}
+
+fn id_generator() -> Box<dyn IdGenerator> {
+    match env::var("FTX_ID_SCHEME").as_deref() {
+        Ok("snowflake") => {
+            let node = env::var("FTX_NODE_ID")
+                .map(|n| n.parse::<u16>().or_fail("FTX_NODE_ID must be a number"))
+                .unwrap_or(0);
+            Box::new(SnowflakeIdGenerator::new(node))
+        }
+        _ => Box::new(RandomIdGenerator),
+    }
+}
+
+// The name of the API key given as `<name>:<secret>`, like the API's
+// x-api-key. A key that does not verify fails, without one the caller is
+// anonymous.
+fn actor(db: &Arc<Mutex<Database>>) -> String {
+    let key = env::args()
+        .find_map(|a| a.strip_prefix(API_KEY_FLAG).map(str::to_string))
+        .or_else(|| env::var(API_KEY_ENV).ok());
+    let Some(key) = key else {
+        return access::ANONYMOUS.to_string();
+    };
+    let (name, secret) = key.split_once(':').unwrap_or_else(|| {
+        fail(error::forbidden(format!(
+            "Invalid {}, expected <name>:<secret>",
+            API_KEY_ENV
+        )))
+    });
+    if !secrets::authenticate(&db.lock().expect("could not get db lock"), name, secret)
+        .or_fail("could not verify API key")
+    {
+        fail(error::forbidden(format!("Invalid API key {}", name)))
+    }
+    name.to_string()
+}
+
+fn authorize(db: &Arc<Mutex<Database>>, required: UserRole) {
+    let actor = actor(db);
+    access::authorize(&db.lock().expect("could not get db lock"), &actor, required)
+        .unwrap_or_else(fail);
+}
+
+fn audit(db: &Arc<Mutex<Database>>, action: &str, params: &[(&str, &str)]) {
+    let actor = actor(db);
+    audit::record(
+        &db.lock().expect("could not get db lock"),
+        &actor,
+        action,
+        params,
+    )
+    .or_fail("could not write audit log");
+}
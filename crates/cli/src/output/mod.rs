@@ -0,0 +1,108 @@
+use std::env;
+use std::process;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+pub const JSON_FLAG: &str = "--json";
+pub const QUIET_FLAG: &str = "--quiet";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Quiet,
+}
+
+// Query results are recorded as named fields. Text prints each one as it is
+// recorded (fields with empty text are json only), json prints a single
+// object on finish, quiet prints nothing and leaves only the exit code.
+pub struct Output {
+    format: Format,
+    fields: Map<String, Value>,
+}
+
+pub fn is_flag(arg: &str) -> bool {
+    arg == JSON_FLAG || arg == QUIET_FLAG
+}
+
+impl Output {
+    pub fn from_args() -> Self {
+        let args = env::args().collect::<Vec<_>>();
+        let format = if args.iter().any(|a| a == QUIET_FLAG) {
+            Format::Quiet
+        } else if args.iter().any(|a| a == JSON_FLAG) {
+            Format::Json
+        } else {
+            Format::Text
+        };
+        Self {
+            format,
+            fields: Map::new(),
+        }
+    }
+
+    pub fn field<T: Serialize>(&mut self, name: &str, value: &T, text: String) {
+        match self.format {
+            Format::Text if !text.is_empty() => println!("{text}"),
+            Format::Text => {}
+            Format::Json => {
+                let value = serde_json::to_value(value).expect("could not serialize output");
+                self.fields.insert(name.to_string(), value);
+            }
+            Format::Quiet => {}
+        }
+    }
+
+    // Text prints a line per item, json records the items as one array.
+    pub fn list<T, F>(&mut self, name: &str, items: &[T], text: F)
+    where
+        T: Serialize,
+        F: Fn(&T) -> String,
+    {
+        match self.format {
+            Format::Text => items.iter().for_each(|item| println!("{}", text(item))),
+            _ => self.field(name, &items, String::new()),
+        }
+    }
+
+    pub fn finish(self) {
+        if self.format == Format::Json {
+            println!(
+                "{}",
+                serde_json::to_string(&self.fields).expect("could not serialize output")
+            );
+        }
+    }
+
+    pub fn fail(self) -> ! {
+        self.finish();
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_collects_fields_and_quiet_drops_them() {
+        let mut json = Output {
+            format: Format::Json,
+            fields: Map::new(),
+        };
+        json.field("pair", &"BTC/USD", "Pair=BTC/USD".to_string());
+        json.list("prices", &[1, 2], |p| p.to_string());
+        assert_eq!(
+            Value::Object(json.fields),
+            serde_json::json!({"pair": "BTC/USD", "prices": [1, 2]})
+        );
+
+        let mut quiet = Output {
+            format: Format::Quiet,
+            fields: Map::new(),
+        };
+        quiet.list("prices", &[1, 2], |p| p.to_string());
+        assert!(quiet.fields.is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::anyhow;
+use rand::RngCore;
+
+use crate::error::{DbError, Result};
+
+const NONCE_LEN: usize = 12;
+
+pub const KEY_ENV: &str = "FTX_DB_KEY";
+
+#[derive(Clone)]
+pub struct Cipher {
+    inner: Aes256Gcm,
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cipher(AES-256-GCM)")
+    }
+}
+
+impl Cipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            inner: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        }
+    }
+
+    // digits only, slicing a multi-byte char would panic and
+    // from_str_radix takes a leading '+'
+    pub fn from_hex(hex: &str) -> anyhow::Result<Self> {
+        if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!(
+                "Encryption key must be 64 hex characters (32 bytes)"
+            ));
+        }
+
+        let mut key = [0u8; 32];
+        for (index, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)?;
+        }
+        Ok(Self::new(key))
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var(KEY_ENV) {
+            Ok(hex) => Ok(Some(Self::from_hex(&hex)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    // stored as nonce || ciphertext
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .inner
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| DbError::Encrypt)?;
+        Ok(nonce.iter().copied().chain(ciphertext).collect())
+    }
+
+    // too short to hold a nonce counts as corrupt
+    pub fn decrypt(&self, stored: &[u8]) -> Result<Vec<u8>> {
+        if stored.len() < NONCE_LEN {
+            return Err(DbError::Decrypt);
+        }
+
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        self.inner
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DbError::Decrypt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_roundtrip() {
+        let cipher = Cipher::new([7; 32]);
+        let stored = cipher.encrypt(b"secret").unwrap();
+
+        assert_ne!(&stored[NONCE_LEN..], b"secret");
+        assert_eq!(cipher.decrypt(&stored).unwrap(), b"secret");
+        assert!(Cipher::new([8; 32]).decrypt(&stored).is_err());
+    }
+
+    #[test]
+    fn key_from_hex() {
+        assert!(Cipher::from_hex(&"ab".repeat(32)).is_ok());
+        assert!(Cipher::from_hex("abcd").is_err());
+        assert!(Cipher::from_hex(&"zz".repeat(32)).is_err());
+        assert!(Cipher::from_hex(&"+a".repeat(32)).is_err());
+        assert!(Cipher::from_hex(&format!("a{}a", "é".repeat(31))).is_err());
+    }
+}
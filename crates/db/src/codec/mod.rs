@@ -0,0 +1,142 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{DbError, Result};
+
+// 0xc1 is never used by MessagePack and never starts UTF-8, so it cannot be
+// mistaken for a JSON value written before the codec was switched.
+const MESSAGE_PACK: u8 = 0xc1;
+
+pub const CODEC_ENV: &str = "FTX_DB_CODEC";
+
+// How values are written. Either codec reads both, so switching needs no
+// migration. MessagePack keeps field names like JSON does, so fields added
+// later with #[serde(default)] still load from older values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Codec {
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        match raw.trim().to_lowercase().as_str() {
+            "json" => Ok(Codec::Json),
+            "msgpack" | "messagepack" => Ok(Codec::MessagePack),
+            _ => Err(anyhow::anyhow!(
+                "Invalid codec {}, expected json or msgpack",
+                raw
+            )),
+        }
+    }
+
+    // e.g. FTX_DB_CODEC=msgpack, JSON when unset
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var(CODEC_ENV) {
+            Ok(raw) => Self::parse(&raw),
+            Err(_) => Ok(Codec::Json),
+        }
+    }
+
+    pub fn encode<T>(self, key: &str, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Codec::Json => serde_json::to_vec(value).map_err(|source| DbError::Serialize {
+                key: key.to_string(),
+                source,
+            }),
+            Codec::MessagePack => {
+                let mut bytes = vec![MESSAGE_PACK];
+                let mut serializer = rmp_serde::Serializer::new(&mut bytes)
+                    .with_struct_map()
+                    .with_human_readable();
+                value
+                    .serialize(&mut serializer)
+                    .map_err(|source| DbError::Encode {
+                        key: key.to_string(),
+                        source,
+                    })?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+pub fn decode<T: DeserializeOwned>(key: &str, bytes: &[u8]) -> Result<T> {
+    match bytes.split_first() {
+        Some((&MESSAGE_PACK, packed)) => {
+            let mut deserializer = rmp_serde::Deserializer::new(packed).with_human_readable();
+            T::deserialize(&mut deserializer).map_err(|source| DbError::Decode {
+                key: key.to_string(),
+                source,
+            })
+        }
+        _ => serde_json::from_slice(bytes).map_err(|source| DbError::Deserialize {
+            key: key.to_string(),
+            source,
+        }),
+    }
+}
+
+// The value as JSON whatever it was written with, for the raw accessors.
+// Object keys of MessagePack values come back sorted.
+pub fn to_json(key: &str, bytes: Vec<u8>) -> Result<String> {
+    if !is_message_pack(&bytes) {
+        return Ok(String::from_utf8(bytes)?);
+    }
+    let value = decode::<serde_json::Value>(key, &bytes)?;
+    serde_json::to_string(&value).map_err(|source| DbError::Serialize {
+        key: key.to_string(),
+        source,
+    })
+}
+
+pub fn is_message_pack(stored: &[u8]) -> bool {
+    stored.first() == Some(&MESSAGE_PACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: String,
+        side: Side,
+        price: i32,
+        peg: Option<u32>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Side {
+        Buy,
+    }
+
+    #[test]
+    fn message_pack_reads_back_typed_and_as_json() {
+        let order = Order {
+            id: "2b1d".to_string(),
+            side: Side::Buy,
+            price: 10,
+            peg: None,
+        };
+        let json = Codec::Json.encode("o", &order).unwrap();
+        let packed = Codec::MessagePack.encode("o", &order).unwrap();
+
+        assert!(is_message_pack(&packed) && !is_message_pack(&json));
+        assert!(packed.len() < json.len());
+        assert_eq!(decode::<Order>("o", &packed).unwrap(), order);
+        assert_eq!(decode::<Order>("o", &json).unwrap(), order);
+        // same value, keys come back sorted
+        let transcoded = to_json("o", packed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&transcoded).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(&json).unwrap()
+        );
+        assert!(Codec::parse("bincode").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::error::{DbError, Result};
+
+// zstd frame magic number, never a valid first byte sequence of JSON
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const LEVEL: i32 = 3;
+
+pub const TREES_ENV: &str = "FTX_DB_COMPRESSED_TREES";
+
+pub fn compress(value: &[u8]) -> Result<Vec<u8>> {
+    zstd::encode_all(value, LEVEL).map_err(DbError::Compress)
+}
+
+// Values written before compression was enabled are passed through as is.
+pub fn decompress(stored: &[u8]) -> Result<Vec<u8>> {
+    if !is_compressed(stored) {
+        return Ok(stored.to_vec());
+    }
+    zstd::decode_all(stored).map_err(DbError::Decompress)
+}
+
+pub fn is_compressed(stored: &[u8]) -> bool {
+    stored.starts_with(&MAGIC)
+}
+
+// e.g. FTX_DB_COMPRESSED_TREES=default,commands ("default" is the pair tree)
+pub fn trees_from_env() -> Vec<String> {
+    std::env::var(TREES_ENV)
+        .map(|trees| {
+            trees
+                .split(',')
+                .map(str::trim)
+                .filter(|tree| !tree.is_empty())
+                .map(|tree| match tree {
+                    "default" => crate::DEFAULT_TREE.to_string(),
+                    tree => tree.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_decompress_roundtrip() {
+        let value = "{\"active_orders\":[]}".repeat(100);
+        let compressed = compress(value.as_bytes()).unwrap();
+
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < value.len());
+        assert_eq!(decompress(&compressed).unwrap(), value.as_bytes());
+    }
+
+    #[test]
+    fn plain_values_pass_through() {
+        assert_eq!(decompress(b"{\"a\":1}").unwrap(), b"{\"a\":1}");
+    }
+}
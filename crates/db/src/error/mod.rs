@@ -0,0 +1,43 @@
+use std::string::FromUtf8Error;
+
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, DbError>;
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("Failed to open {path}: {source}")]
+    Open { path: String, source: sled::Error },
+    #[error("Storage error: {0}")]
+    Storage(#[from] sled::Error),
+    #[error("Failed to serialize value for {key}: {source}")]
+    Serialize {
+        key: String,
+        source: serde_json::Error,
+    },
+    #[error("Failed to deserialize {key}: {source}")]
+    Deserialize {
+        key: String,
+        source: serde_json::Error,
+    },
+    #[error("Failed to encode value for {key}: {source}")]
+    Encode {
+        key: String,
+        source: rmp_serde::encode::Error,
+    },
+    #[error("Failed to decode {key}: {source}")]
+    Decode {
+        key: String,
+        source: rmp_serde::decode::Error,
+    },
+    #[error("Failed to encrypt value")]
+    Encrypt,
+    #[error("Failed to decrypt value, wrong key or corrupt data")]
+    Decrypt,
+    #[error("Failed to compress value: {0}")]
+    Compress(std::io::Error),
+    #[error("Failed to decompress value: {0}")]
+    Decompress(std::io::Error),
+    #[error("Stored key or value is not UTF-8: {0}")]
+    Utf8(#[from] FromUtf8Error),
+}
@@ -1,54 +1,314 @@
-use anyhow::anyhow;
-use sled::{Db, IVec};
+use std::collections::HashSet;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, IVec, Transactional, Tree};
+
+pub mod cipher;
+pub mod codec;
+pub mod compression;
+pub mod error;
+pub mod subscription;
+
+use cipher::Cipher;
+use codec::Codec;
+pub use error::{DbError, Result};
+use subscription::Subscription;
+
+// sled's name for the tree behind Database::set/get
+pub const DEFAULT_TREE: &str = "__sled__default";
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug, Clone)]
 pub struct Database {
     inner: Db,
+    cipher: Option<Cipher>,
+    compressed_trees: HashSet<String>,
+    codec: Codec,
 }
 
 impl Database {
-    pub fn new(name: Option<String>) -> Self {
-        match name {
-            Some(name) => Self {
-                inner: sled::open(name.clone())
-                    .expect(format!("Failed to connect to {}", name).as_str()),
-            },
-            None => Self {
-                inner: sled::open("order_book.db").expect("Failed to connect to order_book.db"),
-            },
+    // The codec only changes how values are written, see Codec.
+    pub fn new(name: Option<String>, codec: Codec) -> Result<Self> {
+        let path = name.unwrap_or_else(|| "order_book.db".to_string());
+        let inner = sled::open(&path).map_err(|source| DbError::Open {
+            path: path.clone(),
+            source,
+        })?;
+        Ok(Self::from_sled(inner, codec))
+    }
+
+    // Opens a fresh directory that sled deletes once the last handle drops.
+    pub fn temporary(codec: Codec) -> Result<Self> {
+        let inner = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(|source| DbError::Open {
+                path: "temporary database".to_string(),
+                source,
+            })?;
+        Ok(Self::from_sled(inner, codec))
+    }
+
+    fn from_sled(inner: Db, codec: Codec) -> Self {
+        Self {
+            inner,
+            cipher: None,
+            compressed_trees: HashSet::new(),
+            codec,
+        }
+    }
+
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    // None when values are stored in the clear.
+    pub fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_ref()
+    }
+
+    pub fn with_compression(mut self, tree: &str) -> Self {
+        self.compressed_trees.insert(tree.to_string());
+        self
+    }
+
+    // compressed before encryption, ciphertext does not compress
+    fn encode<T>(&self, tree: &str, key: &str, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize + ?Sized,
+    {
+        let encoded = self.codec.encode(key, value)?;
+        let bytes = if self.compressed_trees.contains(tree) {
+            compression::compress(&encoded)?
+        } else {
+            encoded
+        };
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&bytes),
+            None => Ok(bytes),
         }
     }
 
-    pub fn set<T>(&self, key: &String, value: &T) -> sled::Result<Option<IVec>>
+    fn open(&self, stored: &IVec) -> Result<Vec<u8>> {
+        let bytes = match &self.cipher {
+            Some(cipher) => cipher.decrypt(stored)?,
+            None => stored.to_vec(),
+        };
+        compression::decompress(&bytes)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, key: &str, stored: Option<IVec>) -> Result<Option<T>> {
+        stored
+            .map(|stored| codec::decode(key, &self.open(&stored)?))
+            .transpose()
+    }
+
+    fn decode_json(&self, key: &str, stored: IVec) -> Result<String> {
+        codec::to_json(key, self.open(&stored)?)
+    }
+
+    fn tree(&self, tree: &str) -> Result<Tree> {
+        Ok(self.inner.open_tree(tree)?)
+    }
+
+    pub fn set<T>(&self, key: &str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.inner
+            .insert(key, self.encode(DEFAULT_TREE, key, value)?)?;
+        Ok(())
+    }
+
+    // None when the key is missing.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.decode(key, self.inner.get(key)?)
+    }
+
+    // The stored value as JSON, e.g. to keep a value that no longer parses.
+    pub fn get_raw(&self, key: &str) -> Result<Option<String>> {
+        self.inner
+            .get(key)?
+            .map(|stored| self.decode_json(key, stored))
+            .transpose()
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        self.inner.remove(key)?;
+        Ok(())
+    }
+
+    pub fn set_in<T>(&self, tree: &str, key: &str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.tree(tree)?
+            .insert(key, self.encode(tree, key, value)?)?;
+        Ok(())
+    }
+
+    pub fn get_in<T: DeserializeOwned>(&self, tree: &str, key: &str) -> Result<Option<T>> {
+        self.decode(key, self.tree(tree)?.get(key)?)
+    }
+
+    pub fn get_raw_in(&self, tree: &str, key: &str) -> Result<Option<String>> {
+        self.tree(tree)?
+            .get(key)?
+            .map(|stored| self.decode_json(key, stored))
+            .transpose()
+    }
+
+    // Raw JSON values in key order.
+    pub fn entries_in(&self, tree: &str) -> Result<Vec<(String, String)>> {
+        self.entries_in_from(tree, "")
+    }
+
+    // Like entries_in, starting at the first key not before `from`.
+    pub fn entries_in_from(&self, tree: &str, from: &str) -> Result<Vec<(String, String)>> {
+        self.decode_entries(self.tree(tree)?.range(from..))
+    }
+
+    // Like entries_in, only keys from `from` through `to`.
+    pub fn entries_in_range(
+        &self,
+        tree: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<(String, String)>> {
+        self.decode_entries(self.tree(tree)?.range(from..=to))
+    }
+
+    fn decode_entries(&self, entries: sled::Iter) -> Result<Vec<(String, String)>> {
+        entries
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key = String::from_utf8(key.to_vec())?;
+                let json = self.decode_json(&key, value)?;
+                Ok((key, json))
+            })
+            .collect()
+    }
+
+    pub fn remove_in(&self, tree: &str, key: &str) -> Result<()> {
+        self.tree(tree)?.remove(key)?;
+        Ok(())
+    }
+
+    pub fn batch(&self) -> Batch<'_> {
+        Batch {
+            db: self,
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn generate_id(&self) -> Result<u64> {
+        Ok(self.inner.generate_id()?)
+    }
+
+    // Hands out ids until the next one is past `id`, e.g. after records
+    // numbered by another database were copied in.
+    pub fn advance_ids(&self, id: u64) -> Result<()> {
+        while self.inner.generate_id()? < id {}
+        Ok(())
+    }
+
+    // Changes to keys of the default tree starting with prefix, "" follows all of them.
+    pub fn subscribe(&self, prefix: &str) -> Subscription {
+        Subscription::new(self.inner.watch_prefix(prefix), self.clone())
+    }
+
+    pub fn subscribe_in(&self, tree: &str, prefix: &str) -> Result<Subscription> {
+        Ok(Subscription::new(
+            self.tree(tree)?.watch_prefix(prefix),
+            self.clone(),
+        ))
+    }
+
+    pub fn flush(&self) -> Result<usize> {
+        Ok(self.inner.flush()?)
+    }
+
+    pub fn keys(&self) -> Result<Vec<String>> {
+        self.inner
+            .iter()
+            .keys()
+            .map(|key| Ok(String::from_utf8(key?.to_vec())?))
+            .collect()
+    }
+}
+
+// Writes to any number of trees that land together or not at all, e.g. a
+// record and the index entries pointing at it.
+pub struct Batch<'a> {
+    db: &'a Database,
+    // tree, key and the encoded value, None removes the key
+    writes: Vec<(String, String, Option<Vec<u8>>)>,
+}
+
+impl Batch<'_> {
+    pub fn set_in<T>(&mut self, tree: &str, key: &str, value: &T) -> Result<()>
     where
-        T: Sized + serde::Serialize,
+        T: Serialize + ?Sized,
     {
-        let stringify = serde_json::to_string(&value).expect("Failed to stringify");
-        self.inner.insert(key, stringify.as_bytes())
-    }
-
-    pub fn get(&self, key: &String) -> anyhow::Result<Option<String>> {
-        match self.inner.get(&key) {
-            Ok(value) => match value {
-                Some(result) => Ok(Some(
-                    String::from_utf8(result.to_vec())
-                        .expect("Could not convert Vec<u8> to String"),
-                )),
-                None => Ok(None),
-            },
-            Err(_) => Ok(None),
+        let encoded = self.db.encode(tree, key, value)?;
+        self.writes
+            .push((tree.to_string(), key.to_string(), Some(encoded)));
+        Ok(())
+    }
+
+    pub fn remove_in(&mut self, tree: &str, key: &str) {
+        self.writes.push((tree.to_string(), key.to_string(), None));
+    }
+
+    pub fn commit(self) -> Result<()> {
+        // sled cannot open a transaction over no trees
+        if self.writes.is_empty() {
+            return Ok(());
         }
+        let mut names = self
+            .writes
+            .iter()
+            .map(|(tree, ..)| tree.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let trees = names
+            .iter()
+            .map(|name| self.db.tree(name))
+            .collect::<Result<Vec<_>>>()?;
+        trees
+            .as_slice()
+            .transaction(|views| {
+                for (tree, key, value) in &self.writes {
+                    let view = &views[names.binary_search(tree).expect("opened above")];
+                    match value {
+                        Some(value) => view.insert(key.as_bytes(), value.as_slice())?,
+                        None => view.remove(key.as_bytes())?,
+                    };
+                }
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Storage(e) => DbError::Storage(e),
+                TransactionError::Abort(()) => unreachable!("batches never abort"),
+            })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::cipher::Cipher;
+    use crate::subscription::Change;
     use crate::Database;
     use rand::prelude::*;
     use serde::{Deserialize, Serialize};
-    use std::path::Path;
+
+    use std::thread;
     use std::time::Duration;
-    use std::{fs, thread};
 
     #[derive(Debug, Serialize, Deserialize)]
     struct Complex {
@@ -58,17 +318,11 @@ mod tests {
     }
 
     fn create_mock_db() -> Database {
-        Database::new(Some("mock.db".to_string()))
-    }
-
-    fn cleanup() {
-        if Path::new("mock.db").exists() {
-            fs::remove_dir_all("mock.db").expect("could not delete mock.db")
-        }
+        Database::temporary(Codec::default()).unwrap()
     }
 
     fn gen_rnd_complex_obj(num: usize) -> Vec<Complex> {
-        let mut rng = thread_rng();
+        let mut rng = StdRng::seed_from_u64(num as u64);
         let mut objs: Vec<Complex> = Vec::with_capacity(num);
 
         while objs.len() < num {
@@ -96,31 +350,201 @@ mod tests {
         let key = "BTC/USD".to_string();
         db.set(&key, &complex).expect("failed to insert");
 
-        let stringified = db.get(&key).unwrap().unwrap();
-        let converted: Complex =
-            serde_json::from_str(&*stringified).expect("failed to deserialize");
+        let stringified = db.get_raw(&key).unwrap().unwrap();
+        let converted: Complex = serde_json::from_str(&stringified).expect("failed to deserialize");
 
         assert_eq!(&complex.id, &converted.id);
         assert_eq!(&complex.fulfilled_orders, &converted.fulfilled_orders);
         assert_eq!(&complex.active_orders, &converted.active_orders);
-        cleanup();
     }
 
     #[test]
     fn multiple_set_latest_get() {
-        thread::sleep(Duration::from_secs(1));
-
         let db = create_mock_db();
         let btc_usdc: Vec<Complex> = gen_rnd_complex_obj(10);
 
-        for index in 0..10 {
-            db.set(&"btc/usdc".to_string(), &btc_usdc[index]).unwrap();
+        for complex in &btc_usdc {
+            db.set("btc/usdc", complex).unwrap();
         }
 
         assert_eq!(
-            db.get(&"btc/usdc".to_string()).unwrap().unwrap(),
+            db.get_raw("btc/usdc").unwrap().unwrap(),
             serde_json::to_string(&btc_usdc[9]).unwrap()
         );
-        cleanup();
+    }
+
+    #[test]
+    fn typed_get_reports_missing_and_mismatched_values() {
+        let db = create_mock_db();
+        db.set("BTC/USD", &vec![1, 2]).unwrap();
+
+        assert_eq!(db.get::<Vec<u32>>("BTC/USD").unwrap(), Some(vec![1, 2]));
+        assert_eq!(db.get::<Vec<u32>>("ETH/USD").unwrap(), None);
+        assert!(matches!(
+            db.get::<String>("BTC/USD"),
+            Err(DbError::Deserialize { key, .. }) if key == "BTC/USD"
+        ));
+    }
+
+    #[test]
+    fn batches_write_to_several_trees_together() {
+        let db = create_mock_db();
+        db.set_in("trades", "old", &0).unwrap();
+
+        let mut batch = db.batch();
+        batch.set_in("trades", "new", &1).unwrap();
+        batch.set_in("index", "order-new", &"new").unwrap();
+        batch.remove_in("trades", "old");
+        batch.commit().unwrap();
+
+        assert_eq!(db.get_in::<u32>("trades", "new").unwrap(), Some(1));
+        assert_eq!(
+            db.get_in::<String>("index", "order-new")
+                .unwrap()
+                .as_deref(),
+            Some("new")
+        );
+        assert_eq!(db.get_in::<u32>("trades", "old").unwrap(), None);
+    }
+
+    #[test]
+    fn keys_test() {
+        let db = create_mock_db();
+        db.set("btc/usd", &1).unwrap();
+        db.set("eth/usd", &2).unwrap();
+
+        assert_eq!(
+            db.keys().unwrap(),
+            vec!["btc/usd".to_string(), "eth/usd".to_string()]
+        );
+    }
+
+    #[test]
+    fn named_tree_is_isolated_from_pairs() {
+        let db = create_mock_db();
+        db.set_in("meta", "role", &"standby").unwrap();
+
+        assert_eq!(
+            db.get_raw_in("meta", "role").unwrap().unwrap(),
+            "\"standby\""
+        );
+        assert!(db.keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn entries_in_are_sorted_and_removable() {
+        let db = create_mock_db();
+        db.set_in("history", "2", &"b").unwrap();
+        db.set_in("history", "1", &"a").unwrap();
+        db.remove_in("history", "2").unwrap();
+
+        assert_eq!(
+            db.entries_in("history").unwrap(),
+            vec![("1".to_string(), "\"a\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn encrypted_values_are_transparent_to_callers() {
+        let db = create_mock_db().with_cipher(Cipher::new([3; 32]));
+        let key = "BTC/USD".to_string();
+        db.set(&key, &vec![1, 2, 3]).unwrap();
+        db.set_in("meta", "role", &"standby").unwrap();
+
+        assert_eq!(db.get_raw(&key).unwrap().unwrap(), "[1,2,3]");
+        assert_eq!(
+            db.get_raw_in("meta", "role").unwrap().unwrap(),
+            "\"standby\""
+        );
+        assert_ne!(db.inner.get(&key).unwrap().unwrap().as_ref(), b"[1,2,3]");
+
+        let plain = Database {
+            inner: db.inner.clone(),
+            cipher: None,
+            compressed_trees: HashSet::new(),
+            codec: Codec::default(),
+        };
+        assert!(plain.get_raw(&key).is_err());
+    }
+
+    #[test]
+    fn values_are_written_with_the_codec_the_database_was_opened_with() {
+        let db = Database::temporary(Codec::MessagePack).unwrap();
+        db.set("btc/usd", &vec![1, 2, 3]).unwrap();
+
+        assert!(codec::is_message_pack(
+            &db.inner.get("btc/usd").unwrap().unwrap()
+        ));
+        assert_eq!(db.get::<Vec<u32>>("btc/usd").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(db.get_raw("btc/usd").unwrap().unwrap(), "[1,2,3]");
+    }
+
+    #[test]
+    fn compression_is_per_tree_and_transparent() {
+        let db = create_mock_db()
+            .with_compression(DEFAULT_TREE)
+            .with_cipher(Cipher::new([5; 32]));
+        let snapshot = vec![7u32; 1000];
+        db.set("BTC/USD", &snapshot).unwrap();
+        db.set_in("meta", "role", &snapshot).unwrap();
+
+        let stored = db.inner.get("BTC/USD").unwrap().unwrap();
+        let plain = db
+            .inner
+            .open_tree("meta")
+            .unwrap()
+            .get("role")
+            .unwrap()
+            .unwrap();
+        assert!(stored.len() < plain.len());
+        assert_eq!(
+            db.get_raw("BTC/USD").unwrap().unwrap(),
+            serde_json::to_string(&snapshot).unwrap()
+        );
+        assert_eq!(
+            db.get_raw_in("meta", "role").unwrap().unwrap(),
+            serde_json::to_string(&snapshot).unwrap()
+        );
+    }
+
+    #[test]
+    fn subscribers_see_decoded_changes_under_prefix() {
+        let db = create_mock_db()
+            .with_cipher(Cipher::new([9; 32]))
+            .with_compression("audit");
+        let mut books = db.subscribe("BTC/");
+        let mut audit = db.subscribe_in("audit", "").unwrap();
+
+        let writer = db.clone();
+        thread::spawn(move || {
+            writer.set("ETH/USD", &1).unwrap();
+            writer.set("BTC/USD", &2).unwrap();
+            writer.remove("BTC/USD").unwrap();
+            writer.set_in("audit", "1", &"restart").unwrap();
+        })
+        .join()
+        .unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            books.next_timeout(timeout).unwrap().unwrap(),
+            Change::Insert {
+                key: "BTC/USD".to_string(),
+                value: "2".to_string()
+            }
+        );
+        assert_eq!(
+            books.next_timeout(timeout).unwrap().unwrap(),
+            Change::Remove {
+                key: "BTC/USD".to_string()
+            }
+        );
+        assert_eq!(
+            audit.next_timeout(timeout).unwrap().unwrap(),
+            Change::Insert {
+                key: "1".to_string(),
+                value: "\"restart\"".to_string()
+            }
+        );
     }
 }
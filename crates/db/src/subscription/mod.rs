@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use sled::{Event, Subscriber};
+
+use crate::{Database, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Insert { key: String, value: String },
+    Remove { key: String },
+}
+
+// Values are decoded with the subscribing database's cipher, so followers
+// see the same JSON that get_raw/get_raw_in return.
+pub struct Subscription {
+    inner: Subscriber,
+    db: Database,
+}
+
+impl Subscription {
+    pub(crate) fn new(inner: Subscriber, db: Database) -> Self {
+        Self { inner, db }
+    }
+
+    fn change(&self, event: Event) -> Result<Change> {
+        match event {
+            Event::Insert { key, value } => {
+                let key = String::from_utf8(key.to_vec())?;
+                let value = self.db.decode_json(&key, value)?;
+                Ok(Change::Insert { key, value })
+            }
+            Event::Remove { key } => Ok(Change::Remove {
+                key: String::from_utf8(key.to_vec())?,
+            }),
+        }
+    }
+
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Change>> {
+        let event = self.inner.next_timeout(timeout).ok()?;
+        Some(self.change(event))
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Change>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.inner.next()?;
+        Some(self.change(event))
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, HashMap};
+
+use match_engine::error;
+use match_engine::events::OrderBookEvent;
+use match_engine::order::{Order, OrderKind, OrderType};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Window sizes a client can ask for with ?depth=N.
+pub const DEPTHS: [usize; 3] = [5, 10, 25];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Level {
+    pub price: i32,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DepthMessage {
+    // best first on both sides
+    Snapshot {
+        pair: String,
+        bids: Vec<Level>,
+        asks: Vec<Level>,
+    },
+    // a level inside the window changed, quantity 0 drops it from the window.
+    // Conflated when it stands for several changes that were not sent.
+    Level {
+        pair: String,
+        side: OrderType,
+        price: i32,
+        quantity: i32,
+        conflated: bool,
+    },
+}
+
+pub fn parse_depth(raw: &str) -> anyhow::Result<usize> {
+    raw.parse::<usize>()
+        .ok()
+        .filter(|depth| DEPTHS.contains(depth))
+        .ok_or_else(|| {
+            error::validation(format!(
+                "Invalid depth {}, expected one of 5, 10 or 25",
+                raw
+            ))
+        })
+}
+
+#[derive(Debug, Clone)]
+struct Resting {
+    pair: String,
+    side: OrderType,
+    price: i32,
+    remaining: i32,
+}
+
+#[derive(Debug, Default)]
+struct Sides {
+    bids: BTreeMap<i32, i32>,
+    asks: BTreeMap<i32, i32>,
+}
+
+// Visible quantity per price level of every pair, rebuilt from the event
+// stream. Only orders that rested after the feed started are known.
+#[derive(Debug, Default)]
+pub struct Depth {
+    resting: HashMap<Uuid, Resting>,
+    books: HashMap<String, Sides>,
+}
+
+impl Depth {
+    pub fn apply(&mut self, event: &OrderBookEvent) {
+        match event {
+            OrderBookEvent::OrderAccepted { pair, order } => self.rest(pair, order),
+            OrderBookEvent::OrderCancelled { order, .. } => self.remove(order.id),
+            OrderBookEvent::OrderAmended { pair, order } => {
+                self.remove(order.id);
+                self.rest(pair, order);
+            }
+            OrderBookEvent::TradeExecuted { trade, .. } => {
+                let maker = match trade.aggressor {
+                    OrderType::Buy => trade.sell_order_id,
+                    OrderType::Sell => trade.buy_order_id,
+                };
+                if let Some(resting) = self.resting.get_mut(&maker) {
+                    let quantity = trade.quantity.min(resting.remaining);
+                    resting.remaining -= quantity;
+                    let resting = resting.clone();
+                    self.change(&resting, -quantity);
+                    if resting.remaining == 0 {
+                        self.resting.remove(&maker);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn rest(&mut self, pair: &str, order: &Order) {
+        if order.is_open() && !order.hidden && order.kind == OrderKind::Limit {
+            let resting = Resting {
+                pair: pair.to_string(),
+                side: order.order_type,
+                price: order.price,
+                remaining: order.remaining(),
+            };
+            self.change(&resting, resting.remaining);
+            self.resting.insert(order.id, resting);
+        }
+    }
+
+    fn remove(&mut self, id: Uuid) {
+        if let Some(resting) = self.resting.remove(&id) {
+            self.change(&resting, -resting.remaining);
+        }
+    }
+
+    fn change(&mut self, resting: &Resting, quantity: i32) {
+        let sides = self.books.entry(resting.pair.clone()).or_default();
+        let levels = match resting.side {
+            OrderType::Buy => &mut sides.bids,
+            OrderType::Sell => &mut sides.asks,
+        };
+        let level = levels.entry(resting.price).or_default();
+        *level += quantity;
+        if *level <= 0 {
+            levels.remove(&resting.price);
+        }
+    }
+
+    pub fn top(&self, pair: &str, side: OrderType, depth: usize) -> Vec<Level> {
+        let level = |(price, quantity): (&i32, &i32)| Level {
+            price: *price,
+            quantity: *quantity,
+        };
+        match (self.books.get(pair), side) {
+            (Some(sides), OrderType::Buy) => {
+                sides.bids.iter().rev().take(depth).map(level).collect()
+            }
+            (Some(sides), OrderType::Sell) => sides.asks.iter().take(depth).map(level).collect(),
+            (None, _) => Vec::new(),
+        }
+    }
+}
+
+// What one depth subscriber has been sent so far, so only changes inside its
+// window go out. A level falling out of the window is sent with quantity 0,
+// the one moving in behind it with its quantity.
+#[derive(Debug)]
+pub struct Window {
+    pair: String,
+    depth: usize,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+impl Window {
+    pub fn new(pair: &str, depth: usize) -> Self {
+        Self {
+            pair: pair.to_string(),
+            depth,
+            bids: Vec::new(),
+            asks: Vec::new(),
+        }
+    }
+
+    pub fn snapshot(&mut self, depth: &Depth) -> DepthMessage {
+        self.bids = depth.top(&self.pair, OrderType::Buy, self.depth);
+        self.asks = depth.top(&self.pair, OrderType::Sell, self.depth);
+        DepthMessage::Snapshot {
+            pair: self.pair.clone(),
+            bids: self.bids.clone(),
+            asks: self.asks.clone(),
+        }
+    }
+
+    pub fn update(&mut self, depth: &Depth, conflated: bool) -> Vec<DepthMessage> {
+        let bids = depth.top(&self.pair, OrderType::Buy, self.depth);
+        let asks = depth.top(&self.pair, OrderType::Sell, self.depth);
+        let mut messages = self.diff(OrderType::Buy, &self.bids, &bids, conflated);
+        messages.extend(self.diff(OrderType::Sell, &self.asks, &asks, conflated));
+        self.bids = bids;
+        self.asks = asks;
+        messages
+    }
+
+    fn diff(
+        &self,
+        side: OrderType,
+        sent: &[Level],
+        current: &[Level],
+        conflated: bool,
+    ) -> Vec<DepthMessage> {
+        let message = |price, quantity| DepthMessage::Level {
+            pair: self.pair.clone(),
+            side,
+            price,
+            quantity,
+            conflated,
+        };
+        let removed = sent
+            .iter()
+            .filter(|old| current.iter().all(|new| new.price != old.price))
+            .map(|old| message(old.price, 0));
+        let changed = current
+            .iter()
+            .filter(|new| !sent.contains(new))
+            .map(|new| message(new.price, new.quantity));
+        removed.chain(changed).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use match_engine::trade::Trade;
+
+    fn accepted(order: Order) -> OrderBookEvent {
+        OrderBookEvent::OrderAccepted {
+            pair: "BTC/USD".to_string(),
+            order,
+        }
+    }
+
+    #[test]
+    fn windows_only_see_their_top_levels() {
+        let mut depth = Depth::default();
+        let asks = [10, 11, 12, 12].map(|price| Order::new(2, price, OrderType::Sell));
+        for ask in asks {
+            depth.apply(&accepted(ask));
+        }
+        depth.apply(&accepted(Order::new(1, 9, OrderType::Buy)));
+        let mut window = Window::new("BTC/USD", 2);
+
+        assert_eq!(
+            window.snapshot(&depth),
+            DepthMessage::Snapshot {
+                pair: "BTC/USD".to_string(),
+                bids: vec![Level {
+                    price: 9,
+                    quantity: 1
+                }],
+                asks: vec![
+                    Level {
+                        price: 10,
+                        quantity: 2
+                    },
+                    Level {
+                        price: 11,
+                        quantity: 2
+                    }
+                ],
+            }
+        );
+
+        // outside the window
+        depth.apply(&OrderBookEvent::OrderCancelled {
+            pair: "BTC/USD".to_string(),
+            order: asks[3],
+        });
+        assert!(window.update(&depth, false).is_empty());
+
+        // the best ask fills, 12 moves into the window
+        let bid = Order::new(2, 10, OrderType::Buy);
+        depth.apply(&OrderBookEvent::TradeExecuted {
+            pair: "BTC/USD".to_string(),
+            trade: Trade::between(&bid, &asks[0], 2, 0, Some(bid.id)),
+        });
+        let levels = window
+            .update(&depth, true)
+            .into_iter()
+            .map(|message| match message {
+                DepthMessage::Level {
+                    side,
+                    price,
+                    quantity,
+                    conflated,
+                    ..
+                } => (side, price, quantity, conflated),
+                other => panic!("expected a level update, got {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            levels,
+            vec![
+                (OrderType::Sell, 10, 0, true),
+                (OrderType::Sell, 12, 2, true)
+            ]
+        );
+        assert!(parse_depth("7").is_err());
+        assert_eq!(parse_depth("25").unwrap(), 25);
+    }
+}
@@ -0,0 +1,436 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::Receiver;
+use futures_util::{SinkExt, StreamExt};
+use match_engine::error::{self, EngineError, ErrorKind};
+use match_engine::events::OrderBookEvent;
+use match_engine::l3::Anonymizer;
+use match_engine::order::{OrderKind, OrderType};
+use match_engine::symbol::Symbol;
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::{self, Interval, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+pub mod depth;
+
+use depth::{Depth, Window};
+
+// Updates a slow client can fall behind by before it starts missing some.
+const BUFFER: usize = 1024;
+
+// One JSON text frame per update. Order ids are anonymized the same way as
+// the L3 view, hidden orders are never published.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedMessage {
+    // quantity is what is left to rest once the order matched
+    OrderAdded {
+        pair: String,
+        order_id: String,
+        side: OrderType,
+        price: i32,
+        quantity: i32,
+    },
+    OrderCancelled {
+        pair: String,
+        order_id: String,
+        side: OrderType,
+        price: i32,
+    },
+    // the order rests at a new price or quantity, 0 once the amendment filled it
+    OrderAmended {
+        pair: String,
+        order_id: String,
+        side: OrderType,
+        price: i32,
+        quantity: i32,
+    },
+    TradeExecuted {
+        pair: String,
+        price: i32,
+        quantity: i32,
+        aggressor: OrderType,
+        timestamp: u64,
+    },
+}
+
+// Sent instead of any update when a subscription is refused, the connection
+// is closed right after.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "error")]
+pub struct ErrorFrame {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl From<EngineError> for ErrorFrame {
+    fn from(e: EngineError) -> Self {
+        Self {
+            kind: e.kind,
+            message: e.message,
+        }
+    }
+}
+
+impl FeedMessage {
+    pub fn pair(&self) -> &str {
+        match self {
+            Self::OrderAdded { pair, .. }
+            | Self::OrderCancelled { pair, .. }
+            | Self::OrderAmended { pair, .. }
+            | Self::TradeExecuted { pair, .. } => pair,
+        }
+    }
+}
+
+// Orders that fill straight away, market orders and hidden orders never
+// rest, only their trades are published.
+pub fn from_event(event: &OrderBookEvent, anonymizer: &Anonymizer) -> Option<FeedMessage> {
+    match event {
+        OrderBookEvent::OrderAccepted { pair, order } => (order.is_open()
+            && !order.hidden
+            && order.kind == OrderKind::Limit)
+            .then(|| FeedMessage::OrderAdded {
+                pair: pair.clone(),
+                order_id: anonymizer.order_id(order.id),
+                side: order.order_type,
+                price: order.price,
+                quantity: order.remaining(),
+            }),
+        OrderBookEvent::OrderCancelled { pair, order } => {
+            (!order.hidden && order.kind == OrderKind::Limit).then(|| FeedMessage::OrderCancelled {
+                pair: pair.clone(),
+                order_id: anonymizer.order_id(order.id),
+                side: order.order_type,
+                price: order.price,
+            })
+        }
+        OrderBookEvent::OrderAmended { pair, order } => {
+            (!order.hidden && order.kind == OrderKind::Limit).then(|| FeedMessage::OrderAmended {
+                pair: pair.clone(),
+                order_id: anonymizer.order_id(order.id),
+                side: order.order_type,
+                price: order.price,
+                quantity: order.remaining(),
+            })
+        }
+        OrderBookEvent::TradeExecuted { pair, trade } => Some(FeedMessage::TradeExecuted {
+            pair: pair.clone(),
+            price: trade.price,
+            quantity: trade.quantity,
+            aggressor: trade.aggressor,
+            timestamp: trade.timestamp,
+        }),
+        // covered by the trades
+        _ => None,
+    }
+}
+
+// Clients subscribe to one pair by path, e.g. ws://127.0.0.1:8081/btc/usd,
+// and get the updates from the moment they connect. With ?depth=5, 10 or 25
+// they get the top levels instead, a snapshot then DepthMessage::Level
+// changes, optionally conflated. Order books push to the other end of `events`, see
+// OrderBook::set_event_sender.
+pub async fn serve(listener: TcpListener, events: Receiver<OrderBookEvent>) -> anyhow::Result<()> {
+    let (sender, _) = broadcast::channel(BUFFER);
+    let depth = Arc::new(Mutex::new(Depth::default()));
+    let forward = sender.clone();
+    let forward_depth = depth.clone();
+    thread::spawn(move || {
+        let anonymizer = Anonymizer::from_env();
+        for event in events {
+            forward_depth
+                .lock()
+                .expect("could not get depth lock")
+                .apply(&event);
+            if let Some(message) = from_event(&event, &anonymizer) {
+                // no client connected
+                let _ = forward.send(message);
+            }
+        }
+    });
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        // subscribed before the handshake so nothing sent after it is missed
+        let updates = sender.subscribe();
+        let depth = depth.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stream_updates(stream, updates, depth).await {
+                eprintln!("feed client dropped: {}", e);
+            }
+        });
+    }
+}
+
+// The handshake callback's error type is tungstenite's, not ours to shrink.
+#[allow(clippy::result_large_err)]
+async fn stream_updates(
+    stream: TcpStream,
+    mut updates: broadcast::Receiver<FeedMessage>,
+    depth: Arc<Mutex<Depth>>,
+) -> anyhow::Result<()> {
+    let mut uri = None;
+    let mut socket = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+        uri = Some(request.uri().clone());
+        Ok::<Response, _>(response)
+    })
+    .await?;
+    let subscription = uri
+        .ok_or_else(|| error::validation("Missing request uri"))
+        .and_then(|uri| Subscription::parse(uri.path(), uri.query()));
+    let Subscription {
+        pair,
+        mut window,
+        conflation,
+    } = match subscription {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            send(&mut socket, &ErrorFrame::from(EngineError::from_anyhow(&e))).await?;
+            return Ok(socket.close(None).await?);
+        }
+    };
+    if let Some(window) = window.as_mut() {
+        let snapshot = window.snapshot(&depth.lock().expect("could not get depth lock"));
+        send(&mut socket, &snapshot).await?;
+    }
+
+    let mut ticks = conflation.map(|period| {
+        let mut ticks = time::interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks
+    });
+    // updates for the window not sent yet, and whether some were lost
+    let mut pending = 0;
+    let mut lagged = false;
+    loop {
+        tokio::select! {
+            update = updates.recv() => {
+                let message = match update {
+                    Ok(message) if message.pair() != pair.as_str() => continue,
+                    Ok(message) => Some(message),
+                    Err(broadcast::error::RecvError::Lagged(_)) => None,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                };
+                match window.as_mut() {
+                    Some(window) => {
+                        pending += 1;
+                        lagged |= message.is_none();
+                        if ticks.is_none() {
+                            flush(&mut socket, window, &depth, lagged).await?;
+                            (pending, lagged) = (0, false);
+                        }
+                    }
+                    None => {
+                        if let Some(message) = message {
+                            send(&mut socket, &message).await?;
+                        }
+                    }
+                }
+            },
+            _ = tick(&mut ticks) => {
+                if let (Some(window), true) = (window.as_mut(), pending > 0) {
+                    flush(&mut socket, window, &depth, lagged || pending > 1).await?;
+                    (pending, lagged) = (0, false);
+                }
+            },
+            incoming = socket.next() => match incoming {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+        }
+    }
+}
+
+async fn send<T: Serialize>(
+    socket: &mut WebSocketStream<TcpStream>,
+    message: &T,
+) -> anyhow::Result<()> {
+    socket
+        .send(Message::text(serde_json::to_string(message)?))
+        .await?;
+    Ok(())
+}
+
+async fn flush(
+    socket: &mut WebSocketStream<TcpStream>,
+    window: &mut Window,
+    depth: &Mutex<Depth>,
+    conflated: bool,
+) -> anyhow::Result<()> {
+    let levels = window.update(&depth.lock().expect("could not get depth lock"), conflated);
+    for level in levels {
+        send(socket, &level).await?;
+    }
+    Ok(())
+}
+
+// Never resolves without conflation.
+async fn tick(ticks: &mut Option<Interval>) {
+    match ticks {
+        Some(ticks) => {
+            ticks.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// "/btc/usd" with an optional query: depth=10 for the top levels only, and
+// conflate=250 to send those at most every 250ms. Conflated updates carry
+// the latest levels, marked as such when intermediate changes were skipped.
+#[derive(Debug)]
+struct Subscription {
+    pair: Symbol,
+    window: Option<Window>,
+    conflation: Option<Duration>,
+}
+
+impl Subscription {
+    fn parse(path: &str, query: Option<&str>) -> anyhow::Result<Self> {
+        let pair = Symbol::parse(path.trim_start_matches('/'))?;
+        let param = |name: &str| {
+            query
+                .unwrap_or_default()
+                .split('&')
+                .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+        };
+        let window = param("depth")
+            .map(depth::parse_depth)
+            .transpose()?
+            .map(|depth| Window::new(pair.as_str(), depth));
+        let conflation = param("conflate")
+            .map(|raw| {
+                raw.parse::<u64>()
+                    .ok()
+                    .filter(|millis| *millis > 0)
+                    .map(Duration::from_millis)
+                    .ok_or_else(|| {
+                        error::validation(format!(
+                            "Invalid conflate {}, expected milliseconds above 0",
+                            raw
+                        ))
+                    })
+            })
+            .transpose()?;
+        if conflation.is_some() && window.is_none() {
+            return Err(error::validation(
+                "conflate only applies to depth subscriptions",
+            ));
+        }
+        Ok(Self {
+            pair,
+            window,
+            conflation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use match_engine::order::Order;
+    use match_engine::order_book::OrderBook;
+    use test_utils::shared_temp_db;
+
+    async fn next_message<S>(client: &mut S) -> FeedMessage
+    where
+        S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        let frame = client.next().await.unwrap().unwrap();
+        serde_json::from_str(frame.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn clients_get_the_updates_of_their_pair() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (event_sender, events) = crossbeam_channel::unbounded();
+        tokio::spawn(serve(listener, events));
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/btc/usd", addr))
+            .await
+            .unwrap();
+
+        let db = shared_temp_db();
+        let mut books = ["ETH/USD", "BTC/USD"].map(|pair| {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_pair(Symbol::parse(pair).unwrap());
+            order_book_builder.set_db(db.clone());
+            order_book_builder.set_event_sender(event_sender.clone());
+            order_book_builder.build().unwrap()
+        });
+        books[0]
+            .append_sell_order(Order::new(1, 10, OrderType::Sell))
+            .unwrap();
+        let mut hidden = Order::new(1, 9, OrderType::Buy);
+        hidden.update_hidden(true);
+        books[1].append_buy_order(hidden).unwrap();
+        let ask = Order::new(3, 10, OrderType::Sell);
+        books[1].append_sell_order(ask).unwrap();
+        books[1]
+            .append_buy_order(Order::new(2, 11, OrderType::Buy))
+            .unwrap();
+        books[1].cancel_order(ask.id).unwrap();
+
+        let mut messages = Vec::new();
+        for _ in 0..3 {
+            messages.push(next_message(&mut client).await);
+        }
+
+        let ask_id = match &messages[0] {
+            FeedMessage::OrderAdded {
+                order_id, quantity, ..
+            } => {
+                assert_eq!(*quantity, 3);
+                order_id.clone()
+            }
+            other => panic!("expected the ask to be added, got {:?}", other),
+        };
+        assert!(matches!(
+            messages[1],
+            FeedMessage::TradeExecuted {
+                price: 10,
+                quantity: 2,
+                aggressor: OrderType::Buy,
+                ..
+            }
+        ));
+        assert_eq!(
+            messages[2],
+            FeedMessage::OrderCancelled {
+                pair: "BTC/USD".to_string(),
+                order_id: ask_id,
+                side: OrderType::Sell,
+                price: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn subscriptions_take_depth_and_conflation() {
+        let subscription = Subscription::parse("/eth/usd", Some("depth=10&conflate=250")).unwrap();
+        assert_eq!(subscription.pair.as_str(), "ETH/USD");
+        assert!(subscription.window.is_some());
+        assert_eq!(subscription.conflation, Some(Duration::from_millis(250)));
+
+        assert!(Subscription::parse("/eth/usd", None)
+            .unwrap()
+            .window
+            .is_none());
+        for query in ["depth=3", "depth=5&conflate=0", "conflate=100"] {
+            let e = Subscription::parse("/eth/usd", Some(query)).unwrap_err();
+            assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+        }
+        let frame = ErrorFrame::from(EngineError::new(ErrorKind::Validation, "bad depth"));
+        assert_eq!(
+            serde_json::to_string(&frame).unwrap(),
+            r#"{"type":"error","kind":"validation","message":"bad depth"}"#
+        );
+    }
+}
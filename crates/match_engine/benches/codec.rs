@@ -0,0 +1,69 @@
+// Size and round-trip latency of an order book snapshot per storage codec.
+// Run with `cargo bench -p match_engine --bench codec`.
+use std::hint;
+use std::time::{Duration, Instant};
+
+use db::codec::Codec;
+use db::Database;
+use match_engine::order::{Order, OrderStatus};
+use match_engine::order_book::Item;
+use test_utils::order;
+
+const ORDERS: [usize; 3] = [1_000, 5_000, 20_000];
+const RUNS: usize = 20;
+
+fn snapshot(orders: usize) -> Item {
+    let nth = |index: usize| -> Order {
+        let price = 100 + (index % 50) as i32;
+        match index.is_multiple_of(2) {
+            true => order().buy().price(price).build(),
+            false => order().sell().price(price + 50).build(),
+        }
+    };
+    let fulfilled = (0..orders / 4)
+        .map(|index| {
+            let mut filled = nth(index);
+            filled.update_order_status(OrderStatus::Filled);
+            filled
+        })
+        .collect();
+    Item {
+        active_orders: (0..orders).map(nth).collect(),
+        fulfilled_orders: fulfilled,
+        sequence: None,
+    }
+}
+
+fn median(mut samples: Vec<Duration>) -> u128 {
+    samples.sort();
+    samples[samples.len() / 2].as_micros()
+}
+
+fn main() {
+    for orders in ORDERS {
+        let item = snapshot(orders);
+        for codec in [Codec::Json, Codec::MessagePack] {
+            let db = Database::temporary(codec).unwrap();
+            let bytes = codec.encode("BTC/USD", &item).unwrap().len();
+            let (mut writes, mut reads) = (Vec::new(), Vec::new());
+            for _ in 0..RUNS {
+                let started = Instant::now();
+                db.set("BTC/USD", &item).unwrap();
+                writes.push(started.elapsed());
+
+                let started = Instant::now();
+                let read = db.get::<Item>("BTC/USD").unwrap();
+                reads.push(started.elapsed());
+                hint::black_box(read);
+            }
+            println!(
+                "{:<12} orders={:<6} bytes={:<9} set={}us get={}us",
+                format!("{:?}", codec),
+                orders + orders / 4,
+                bytes,
+                median(writes),
+                median(reads)
+            );
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Round-trip latency of one order at a time through the channel pipeline and
+// the busy-poll worker. Run with `cargo bench -p match_engine`.
+use std::hint;
+use std::time::{Duration, Instant};
+
+use match_engine::busy_poll::BusyPoll;
+use match_engine::order::Order;
+use match_engine::order_book::OrderBook;
+use match_engine::pipeline::Pipeline;
+use match_engine::symbol::Symbol;
+use test_utils::{order, shared_temp_db};
+
+const ORDERS: usize = 1_000;
+
+fn order_book() -> OrderBook {
+    let mut order_book_builder = OrderBook::default();
+    order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+    order_book_builder.set_db(shared_temp_db());
+    order_book_builder.build().unwrap()
+}
+
+fn nth_order(index: usize) -> Order {
+    // alternate sides around the same price so half of the orders match
+    if index.is_multiple_of(2) {
+        order().buy().price(100 + (index % 7) as i32).build()
+    } else {
+        order().sell().price(100 + (index % 5) as i32).build()
+    }
+}
+
+fn report(mode: &str, mut latencies: Vec<Duration>) {
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100].as_micros();
+    println!(
+        "{mode:<10} orders={} p50={}us p99={}us max={}us",
+        latencies.len(),
+        percentile(50),
+        percentile(99),
+        percentile(100)
+    );
+}
+
+fn main() {
+    let pipeline = Pipeline::spawn(order_book(), 1024);
+    let latencies = (0..ORDERS)
+        .map(|index| {
+            let started = Instant::now();
+            pipeline.submit(nth_order(index)).unwrap();
+            pipeline.outcomes().recv().unwrap();
+            started.elapsed()
+        })
+        .collect();
+    pipeline.shutdown();
+    report("pipeline", latencies);
+
+    let busy_poll = BusyPoll::spawn(order_book(), 1024, None);
+    let latencies = (0..ORDERS)
+        .map(|index| {
+            let started = Instant::now();
+            busy_poll.submit(nth_order(index));
+            while busy_poll.poll().is_none() {
+                hint::spin_loop();
+            }
+            started.elapsed()
+        })
+        .collect();
+    busy_poll.shutdown();
+    report("busy-poll", latencies);
+}
@@ -0,0 +1,98 @@
+use anyhow::anyhow;
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::key::{self, Key};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+pub enum UserRole {
+    Trader,
+    Operator,
+    Admin,
+}
+
+impl UserRole {
+    pub fn parse(role: &str) -> anyhow::Result<Self> {
+        match role.to_lowercase().as_str() {
+            "trader" => Ok(UserRole::Trader),
+            "operator" => Ok(UserRole::Operator),
+            "admin" => Ok(UserRole::Admin),
+            _ => Err(anyhow!(
+                "Unknown role {}, expected trader, operator or admin",
+                role
+            )),
+        }
+    }
+}
+
+// who callers without an API key act as, allowed only while no role is
+// assigned
+pub const ANONYMOUS: &str = "anonymous";
+
+pub fn assign(db: &Database, actor: &str, role: UserRole) -> anyhow::Result<()> {
+    Key::role(actor).set(db, &role)?;
+    Ok(())
+}
+
+pub fn role_of(db: &Database, actor: &str) -> anyhow::Result<Option<UserRole>> {
+    Key::role(actor)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+pub fn assignments(db: &Database) -> anyhow::Result<Vec<(String, UserRole)>> {
+    db.entries_in(key::ROLES)?
+        .into_iter()
+        .map(|(actor, json)| Ok((actor, serde_json::from_str(&json)?)))
+        .collect()
+}
+
+// Until the first role is assigned every actor is allowed, so a fresh
+// deployment can bootstrap its admin.
+pub fn enforced(db: &Database) -> anyhow::Result<bool> {
+    Ok(!assignments(db)?.is_empty())
+}
+
+pub fn authorize(db: &Database, actor: &str, required: UserRole) -> anyhow::Result<()> {
+    if !enforced(db)? {
+        return Ok(());
+    }
+
+    let role = role_of(db, actor)?.unwrap_or(UserRole::Trader);
+    if role < required {
+        return Err(error::forbidden(format!(
+            "{} has role {:?} but {:?} is required",
+            actor, role, required
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    #[test]
+    fn roles_are_enforced_once_assigned() {
+        let db = temp_db();
+        assert!(authorize(&db, "anyone", UserRole::Admin).is_ok());
+
+        assign(&db, "root", UserRole::Admin).unwrap();
+        assign(&db, "ops", UserRole::Operator).unwrap();
+
+        assert!(authorize(&db, "root", UserRole::Admin).is_ok());
+        assert!(authorize(&db, "ops", UserRole::Operator).is_ok());
+        assert!(authorize(&db, "ops", UserRole::Admin).is_err());
+        assert!(authorize(&db, "anyone", UserRole::Trader).is_ok());
+        assert!(authorize(&db, "anyone", UserRole::Operator).is_err());
+    }
+
+    #[test]
+    fn parse_role() {
+        assert_eq!(UserRole::parse("Operator").unwrap(), UserRole::Operator);
+        assert!(UserRole::parse("root").is_err());
+    }
+}
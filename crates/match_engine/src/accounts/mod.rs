@@ -0,0 +1,401 @@
+// Balances per asset. An order placed for an account moves what it could
+// spend from available to reserved: the quote amount of a buy at its limit
+// price, the base quantity of a sell. Funds are only checked before a command
+// is logged; what it reserves, and the trades it executed, are settled after
+// it is applied in one batch that also records the pair's settled command,
+// so a book loading after a crash settles what was logged past it and
+// nothing is held for a command that never was. Assets are shared between
+// pairs, so accounts live on the home database.
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::key::{self, Key};
+use crate::order::{Order, OrderType};
+use crate::symbol::Symbol;
+use crate::trade::Trade;
+
+pub type AccountId = u64;
+// the exchange's own account, fees are paid into it and maker rebates out of
+// it, so it can run negative in an asset
+pub const REVENUE: AccountId = 0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    pub available: i64,
+    // held by open orders
+    pub reserved: i64,
+}
+
+impl Balance {
+    pub fn total(&self) -> i64 {
+        self.available + self.reserved
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    pub id: AccountId,
+    pub balances: BTreeMap<String, Balance>,
+    // the actor trading for it through the API, see access
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl Account {
+    pub fn new(id: AccountId) -> Self {
+        Self {
+            id,
+            balances: BTreeMap::new(),
+            owner: None,
+        }
+    }
+
+    pub fn balance(&self, asset: &str) -> Balance {
+        self.balances.get(asset).copied().unwrap_or_default()
+    }
+}
+
+pub fn get(db: &Database, id: AccountId) -> anyhow::Result<Option<Account>> {
+    Key::account(id)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+pub fn accounts(db: &Database) -> anyhow::Result<Vec<Account>> {
+    db.entries_in(key::ACCOUNTS)?
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+// Opens the account on its first deposit.
+pub fn deposit(db: &Database, id: AccountId, asset: &str, amount: i64) -> anyhow::Result<Account> {
+    let asset = asset_name(asset, amount)?;
+    let mut account = get(db, id)?.unwrap_or_else(|| Account::new(id));
+    account.balances.entry(asset).or_default().available += amount;
+    Key::account(id).set(db, &account)?;
+    Ok(account)
+}
+
+// Only what no open order holds can be withdrawn.
+pub fn withdraw(db: &Database, id: AccountId, asset: &str, amount: i64) -> anyhow::Result<Account> {
+    let asset = asset_name(asset, amount)?;
+    let mut account = get(db, id)?.ok_or_else(|| error::not_found(format!("No account {}", id)))?;
+    let available = account.balance(&asset).available;
+    if available < amount {
+        return Err(error::risk(format!(
+            "Insufficient {} on account {}, {} requested and {} available",
+            asset, id, amount, available
+        )));
+    }
+    account.balances.entry(asset).or_default().available -= amount;
+    Key::account(id).set(db, &account)?;
+    Ok(account)
+}
+
+// Opens the account if it has to, like a deposit.
+pub fn set_owner(db: &Database, id: AccountId, owner: &str) -> anyhow::Result<Account> {
+    let mut account = get(db, id)?.unwrap_or_else(|| Account::new(id));
+    account.owner = Some(owner.to_string());
+    Key::account(id).set(db, &account)?;
+    Ok(account)
+}
+
+pub fn ensure_owner(db: &Database, id: AccountId, actor: &str) -> anyhow::Result<()> {
+    match get(db, id)?.and_then(|account| account.owner) {
+        Some(owner) if owner == actor => Ok(()),
+        _ => Err(error::forbidden(format!(
+            "{} does not own account {}",
+            actor, id
+        ))),
+    }
+}
+
+fn asset_name(asset: &str, amount: i64) -> anyhow::Result<String> {
+    if amount <= 0 {
+        return Err(error::validation(format!(
+            "Invalid amount {}, expected a positive number",
+            amount
+        )));
+    }
+    let asset = asset.trim().to_uppercase();
+    if asset.is_empty() || !asset.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(error::validation(format!("Invalid asset {}", asset)));
+    }
+    Ok(asset)
+}
+
+// What an open order of an account holds, closed orders hold nothing.
+pub fn reservation(pair: &Symbol, order: &Order) -> Option<(AccountId, String, i64)> {
+    let account = order.account.filter(|_| order.is_open())?;
+    Some(match order.order_type {
+        OrderType::Buy => (
+            account,
+            pair.quote().to_string(),
+            order.price as i64 * order.remaining() as i64,
+        ),
+        OrderType::Sell => (account, pair.base().to_string(), order.remaining() as i64),
+    })
+}
+
+// (available, reserved) changes per account and asset
+type Changes = BTreeMap<(AccountId, String), (i64, i64)>;
+
+fn reservation_changes(pair: &Symbol, from: &[Order], to: &[Order]) -> Changes {
+    let mut changes = Changes::new();
+    for (orders, sign) in [(from, -1), (to, 1)] {
+        for (account, asset, amount) in orders.iter().filter_map(|o| reservation(pair, o)) {
+            let change = changes.entry((account, asset)).or_default();
+            change.0 -= sign * amount;
+            change.1 += sign * amount;
+        }
+    }
+    changes
+}
+
+// Whether accounts can fund orders going from `from` to `to` before they
+// reach the book, e.g. nothing to a new order. Nothing is written, see
+// settle. Pegged orders cannot be placed for an account, their price is not
+// known up front.
+pub fn ensure_funded(
+    db: &Database,
+    pair: &Symbol,
+    from: &[Order],
+    to: &[Order],
+) -> anyhow::Result<()> {
+    if let Some(order) = to.iter().find(|o| o.account.is_some() && o.peg.is_some()) {
+        return Err(error::validation(format!(
+            "Order {} is pegged, accounts only fund orders with a fixed price",
+            order.id
+        )));
+    }
+    let changes = reservation_changes(pair, from, to);
+    for ((id, asset), (available, _)) in &changes {
+        if *available >= 0 {
+            continue;
+        }
+        let account =
+            get(db, *id)?.ok_or_else(|| error::not_found(format!("No account {}", id)))?;
+        let balance = account.balance(asset);
+        if balance.available + available < 0 {
+            return Err(error::risk(format!(
+                "Insufficient {} on account {}, {} needed and {} available",
+                asset, id, -available, balance.available
+            )));
+        }
+    }
+    Ok(())
+}
+
+// The last command of the pair whose balance changes were written, None
+// before any were.
+pub fn settled(db: &Database, pair: &str) -> anyhow::Result<Option<u64>> {
+    Key::settlement(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+// Follows the book from the orders accounts were last charged for to its
+// current ones, paying out the trades the command at `sequence` executed.
+// Fees come off what each side receives and go to REVENUE, for sides with
+// an account; a rebate is a negative fee REVENUE pays in the same batch.
+// Never fails for lack of balance, the command is already logged.
+pub fn settle(
+    db: &Database,
+    pair: &Symbol,
+    sequence: u64,
+    reserved: &[Order],
+    orders: &[Order],
+    trades: &[Trade],
+) -> anyhow::Result<()> {
+    let mut changes = reservation_changes(pair, reserved, orders);
+    let mut pay = |account: Option<AccountId>, asset: &str, amount: i64| {
+        if let Some(account) = account {
+            changes.entry((account, asset.to_string())).or_default().0 += amount;
+        }
+    };
+    for trade in trades {
+        let (quantity, cost) = (
+            trade.quantity as i64,
+            trade.price as i64 * trade.quantity as i64,
+        );
+        pay(trade.buy_account, pair.base(), quantity - trade.buy_fee);
+        pay(trade.buy_account, pair.quote(), -cost);
+        pay(trade.sell_account, pair.base(), -quantity);
+        pay(trade.sell_account, pair.quote(), cost - trade.sell_fee);
+        let revenue = |account: Option<AccountId>| account.map(|_| REVENUE);
+        pay(revenue(trade.buy_account), pair.base(), trade.buy_fee);
+        pay(revenue(trade.sell_account), pair.quote(), trade.sell_fee);
+    }
+    apply(db, changes, pair.as_str(), sequence)
+}
+
+// A command that changes no balance leaves the settled sequence behind,
+// settling it again changes nothing either.
+fn apply(db: &Database, changes: Changes, pair: &str, sequence: u64) -> anyhow::Result<()> {
+    let mut touched = BTreeMap::<AccountId, Account>::new();
+    for ((id, asset), (available, reserved)) in changes {
+        if (available, reserved) == (0, 0) {
+            continue;
+        }
+        let account = match touched.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(get(db, id)?.unwrap_or_else(|| Account::new(id))),
+        };
+        let balance = account.balances.entry(asset).or_default();
+        balance.available += available;
+        balance.reserved += reserved;
+    }
+    if touched.is_empty() {
+        return Ok(());
+    }
+    let mut batch = db.batch();
+    for account in touched.values() {
+        Key::account(account.id).set_in_batch(&mut batch, account)?;
+    }
+    Key::settlement(pair).set_in_batch(&mut batch, &sequence)?;
+    Ok(batch.commit()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_log::{self, Command};
+    use crate::error::ErrorKind;
+    use crate::order::peg::{Peg, PegReference};
+    use crate::order_book::OrderBook;
+    use crate::trade;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn orders_reserve_and_trades_settle_balances() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        let (alice, bob) = (1, 2);
+        deposit(&db.lock().unwrap(), alice, "btc", 5).unwrap();
+        deposit(&db.lock().unwrap(), bob, "USD", 1_000).unwrap();
+        let balance = |id, asset: &str| {
+            get(&db.lock().unwrap(), id)
+                .unwrap()
+                .unwrap()
+                .balance(asset)
+        };
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(pair.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        let funded = |quantity, price, order_type, account| {
+            let mut order = Order::new(quantity, price, order_type);
+            order.update_account(Some(account));
+            order
+        };
+
+        let e = order_book
+            .append_sell_order(funded(6, 100, OrderType::Sell, alice))
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Risk);
+        assert!(order_book.get_sell_orders().is_empty());
+
+        order_book
+            .append_sell_order(funded(3, 100, OrderType::Sell, alice))
+            .unwrap();
+        let mut pegged = funded(1, 100, OrderType::Sell, alice);
+        pegged.update_peg(Some(Peg::new(PegReference::BestAsk, 0)));
+        let e = order_book.append_sell_order(pegged).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+        assert_eq!(
+            balance(alice, "BTC"),
+            Balance {
+                available: 2,
+                reserved: 3
+            }
+        );
+        assert!(withdraw(&db.lock().unwrap(), alice, "BTC", 3).is_err());
+
+        // bob buys 3 of 5 at his limit of 110 and pays the resting 100
+        let bid = funded(5, 110, OrderType::Buy, bob);
+        order_book.append_buy_order(bid).unwrap();
+        assert_eq!(balance(alice, "BTC").reserved, 0);
+        assert_eq!(balance(alice, "USD").available, 300);
+        assert_eq!(balance(bob, "BTC").available, 3);
+        assert_eq!(
+            balance(bob, "USD"),
+            Balance {
+                available: 480,
+                reserved: 220
+            }
+        );
+
+        order_book.cancel_order(bid.id).unwrap();
+        assert_eq!(balance(bob, "USD").available, 700);
+        assert_eq!(
+            balance(bob, "USD").total() + balance(alice, "USD").total(),
+            1_000
+        );
+
+        let db = db.lock().unwrap();
+        assert_eq!(trade::trades_of_account(&db, alice).unwrap().len(), 1);
+        assert_eq!(
+            trade::trades_of_account(&db, bob).unwrap(),
+            trade::trades_of_order(&db, bid.id).unwrap()
+        );
+        assert!(trade::trades_of_account(&db, 3).unwrap().is_empty());
+        assert_eq!(accounts(&db).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn commands_logged_before_a_crash_settle_once_on_load() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        let (alice, bob) = (1, 2);
+        deposit(&db.lock().unwrap(), alice, "BTC", 5).unwrap();
+        deposit(&db.lock().unwrap(), bob, "USD", 1_000).unwrap();
+        let build = || {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_pair(pair.clone());
+            order_book_builder.set_db(db.clone());
+            let mut order_book = order_book_builder.build().unwrap();
+            order_book.load().unwrap();
+            order_book
+        };
+        let mut ask = Order::new(3, 100, OrderType::Sell);
+        ask.update_account(Some(alice));
+        build().append_sell_order(ask).unwrap();
+
+        // bob's bid is logged, then the process dies before it is reserved
+        let mut bid = Order::new(5, 110, OrderType::Buy);
+        bid.update_account(Some(bob));
+        ensure_funded(&db.lock().unwrap(), &pair, &[], &[bid]).unwrap();
+        assert_eq!(
+            get(&db.lock().unwrap(), bob)
+                .unwrap()
+                .unwrap()
+                .balance("USD")
+                .reserved,
+            0
+        );
+        command_log::append(&db.lock().unwrap(), pair.as_str(), &Command::Place(bid)).unwrap();
+
+        for _ in 0..2 {
+            build();
+            let db = db.lock().unwrap();
+            let balance = |id, asset| get(&db, id).unwrap().unwrap().balance(asset);
+            assert_eq!(balance(alice, "USD").available, 300);
+            assert_eq!(balance(bob, "BTC").available, 3);
+            assert_eq!(
+                balance(bob, "USD"),
+                Balance {
+                    available: 480,
+                    reserved: 220
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,223 @@
+// Old trades are moved out of the database into an object store in batches,
+// an index record per batch stays behind so history can still be read back
+// through `history` as if nothing had moved.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::PathBuf;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::key::{self, Key};
+use crate::telemetry;
+use crate::trade::{self, LoggedTrade};
+
+#[cfg(feature = "s3")]
+pub mod s3;
+
+// e.g. FTX_ARCHIVE_DIR=/mnt/archive
+pub const ARCHIVE_DIR_ENV: &str = "FTX_ARCHIVE_DIR";
+// e.g. FTX_ARCHIVE_S3=http://127.0.0.1:9000/trades, needs the s3 feature
+pub const ARCHIVE_S3_ENV: &str = "FTX_ARCHIVE_S3";
+pub const BATCH_SIZE: usize = 1000;
+
+pub trait ObjectStore: Send + Sync {
+    fn put(&self, key: &str, body: &[u8]) -> anyhow::Result<()>;
+    // None when nothing is stored under key
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+}
+
+// Objects as files under a directory, e.g. a mounted bucket.
+pub struct DirStore {
+    root: PathBuf,
+}
+
+impl DirStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ObjectStore for DirStore {
+    fn put(&self, key: &str, body: &[u8]) -> anyhow::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(fs::write(path, body)?)
+    }
+
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match fs::read(self.root.join(key)) {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == IoErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// None when no archive is configured.
+pub fn from_env() -> anyhow::Result<Option<Box<dyn ObjectStore>>> {
+    if let Ok(dir) = std::env::var(ARCHIVE_DIR_ENV) {
+        return Ok(Some(Box::new(DirStore::new(dir))));
+    }
+    match std::env::var(ARCHIVE_S3_ENV) {
+        #[cfg(feature = "s3")]
+        Ok(url) => Ok(Some(Box::new(s3::S3Store::from_env(&url)?))),
+        #[cfg(not(feature = "s3"))]
+        Ok(_) => Err(error::validation(format!(
+            "{} is set but this build has no s3 feature",
+            ARCHIVE_S3_ENV
+        ))),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedBatch {
+    pub pair: String,
+    pub object: String,
+    // timestamps of the first and last trade in the batch
+    pub from: u64,
+    pub to: u64,
+    pub trades: usize,
+    pub archived_at: u64,
+}
+
+// Moves trades executed before `before` to the store, BATCH_SIZE trades of
+// one pair per object. A batch is only removed from the database once its
+// object and index record are written.
+pub fn archive(
+    db: &Database,
+    store: &dyn ObjectStore,
+    before: u64,
+) -> anyhow::Result<Vec<ArchivedBatch>> {
+    let mut per_pair = BTreeMap::<String, Vec<LoggedTrade>>::new();
+    for logged in trade::trades(db, None)? {
+        if logged.trade.timestamp < before {
+            per_pair
+                .entry(logged.pair.clone())
+                .or_default()
+                .push(logged);
+        }
+    }
+
+    let mut archived = Vec::new();
+    for (pair, trades) in per_pair {
+        for batch in trades.chunks(BATCH_SIZE) {
+            let (first, last) = (&batch[0], &batch[batch.len() - 1]);
+            let object = format!(
+                "trades/{}/{:020}-{:020}.json",
+                pair.replace('/', "-"),
+                first.trade.timestamp,
+                first.sequence
+            );
+            store.put(&object, &serde_json::to_vec(batch)?)?;
+            let archived_batch = ArchivedBatch {
+                pair: pair.clone(),
+                object,
+                from: first.trade.timestamp,
+                to: last.trade.timestamp,
+                trades: batch.len(),
+                archived_at: telemetry::now_millis(),
+            };
+            Key::archive(&pair, first.trade.timestamp, first.sequence).set(db, &archived_batch)?;
+            db.flush()?;
+            for logged in batch {
+                trade::remove(db, logged)?;
+            }
+            archived.push(archived_batch);
+        }
+    }
+    db.flush()?;
+    Ok(archived)
+}
+
+// Batches of one pair are a range of their own, see key.
+pub fn batches(db: &Database, pair: Option<&str>) -> anyhow::Result<Vec<ArchivedBatch>> {
+    let entries = match pair {
+        Some(pair) => db.entries_in_range(
+            key::ARCHIVES,
+            Key::archive(pair, 0, 0).id(),
+            Key::archive(pair, u64::MAX, u64::MAX).id(),
+        )?,
+        None => db.entries_in(key::ARCHIVES)?,
+    };
+    entries
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+// Trades executed between from and to (inclusive), archived or not, in the
+// order they executed. Archived batches in range need the store.
+pub fn history(
+    db: &Database,
+    store: Option<&dyn ObjectStore>,
+    pair: Option<&str>,
+    from: u64,
+    to: u64,
+) -> anyhow::Result<Vec<LoggedTrade>> {
+    let in_range = |logged: &LoggedTrade| (from..=to).contains(&logged.trade.timestamp);
+    let mut trades = Vec::new();
+    for batch in batches(db, pair)? {
+        if batch.to < from || batch.from > to {
+            continue;
+        }
+        let store = store.ok_or_else(|| {
+            error::state(format!(
+                "Trades of {} up to {} are archived, set {} or {} to read them",
+                batch.pair, batch.to, ARCHIVE_DIR_ENV, ARCHIVE_S3_ENV
+            ))
+        })?;
+        let body = store.get(&batch.object)?.ok_or_else(|| {
+            error::not_found(format!("Archived batch {} is missing", batch.object))
+        })?;
+        let archived: Vec<LoggedTrade> = serde_json::from_slice(&body)?;
+        trades.extend(archived.into_iter().filter(in_range));
+    }
+    trades.extend(trade::trades_between(db, pair, from, to)?);
+    trades.sort_by_key(|logged| (logged.trade.timestamp, logged.sequence));
+    Ok(trades)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, OrderType};
+    use crate::trade::Trade;
+    use test_utils::temp_db;
+
+    #[test]
+    fn archived_trades_are_read_back_through_history() {
+        let db = temp_db();
+        let root = std::env::temp_dir().join(format!("ftx-archive-{}", uuid::Uuid::new_v4()));
+        let store = DirStore::new(&root);
+        let trade_at = |timestamp| {
+            let (bid, ask) = (
+                Order::new(1, 10, OrderType::Buy),
+                Order::new(1, 10, OrderType::Sell),
+            );
+            Trade::between(&bid, &ask, 1, timestamp, None)
+        };
+        trade::record(&db, "BTC/USD", &[trade_at(1_000), trade_at(2_000)]).unwrap();
+        trade::record(&db, "ETH/USD", &[trade_at(1_500), trade_at(3_000)]).unwrap();
+
+        let archived = archive(&db, &store, 2_500).unwrap();
+
+        assert_eq!(archived.len(), 2);
+        assert_eq!(trade::trades(&db, None).unwrap().len(), 1);
+        let btc = history(&db, Some(&store), Some("BTC/USD"), 0, u64::MAX).unwrap();
+        assert_eq!(
+            btc.iter().map(|t| t.trade.timestamp).collect::<Vec<_>>(),
+            vec![1_000, 2_000]
+        );
+        let all = history(&db, Some(&store), None, 1_200, 3_000).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(history(&db, None, None, 0, 1_000).is_err());
+        assert_eq!(history(&db, None, None, 2_600, 3_000).unwrap().len(), 1);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+// S3 compatible object storage (AWS, MinIO, ...) over path style URLs,
+// requests are signed with AWS Signature Version 4.
+use std::io::Read;
+
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+
+use super::ObjectStore;
+use crate::calendar;
+use crate::error;
+use crate::telemetry;
+
+pub const REGION_ENV: &str = "FTX_ARCHIVE_S3_REGION";
+pub const ACCESS_KEY_ENV: &str = "AWS_ACCESS_KEY_ID";
+pub const SECRET_KEY_ENV: &str = "AWS_SECRET_ACCESS_KEY";
+
+pub struct S3Store {
+    // scheme and host, e.g. http://127.0.0.1:9000
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Store {
+    // url is the bucket's, e.g. https://s3.eu-west-1.amazonaws.com/trades
+    pub fn new(
+        url: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> anyhow::Result<Self> {
+        let invalid = || {
+            error::validation(format!(
+                "Invalid bucket url {}, expected e.g. http://127.0.0.1:9000/trades",
+                url
+            ))
+        };
+        let (scheme, rest) = url.trim().split_once("://").ok_or_else(invalid)?;
+        let (host, bucket) = rest.split_once('/').ok_or_else(invalid)?;
+        let bucket = bucket.trim_end_matches('/');
+        if host.is_empty() || bucket.is_empty() || bucket.contains('/') {
+            return Err(invalid());
+        }
+        Ok(Self {
+            endpoint: format!("{scheme}://{host}"),
+            host: host.to_string(),
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+        })
+    }
+
+    // Credentials from AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, the
+    // region from FTX_ARCHIVE_S3_REGION (us-east-1 when unset).
+    pub fn from_env(url: &str) -> anyhow::Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| error::validation(format!("{} is not set", name)))
+        };
+        let region = std::env::var(REGION_ENV).unwrap_or_else(|_| "us-east-1".to_string());
+        Self::new(url, &region, &var(ACCESS_KEY_ENV)?, &var(SECRET_KEY_ENV)?)
+    }
+
+    fn request(&self, method: &str, key: &str, body: &[u8]) -> anyhow::Result<ureq::Request> {
+        if !key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./".contains(&b))
+        {
+            return Err(anyhow!("Object key {} needs escaping", key));
+        }
+        let path = format!("/{}/{}", self.bucket, key);
+        let headers = sign(
+            &Signature {
+                method,
+                host: &self.host,
+                path: &path,
+                region: &self.region,
+                access_key: &self.access_key,
+                secret_key: &self.secret_key,
+            },
+            body,
+            telemetry::now_millis() / 1000,
+        );
+        Ok(headers.into_iter().fold(
+            ureq::request(method, &format!("{}{}", self.endpoint, path)),
+            |request, (name, value)| request.set(name, &value),
+        ))
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn put(&self, key: &str, body: &[u8]) -> anyhow::Result<()> {
+        self.request("PUT", key, body)?.send_bytes(body)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.request("GET", key, &[])?.call() {
+            Ok(response) => {
+                let mut body = Vec::new();
+                response.into_reader().read_to_end(&mut body)?;
+                Ok(Some(body))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+struct Signature<'a> {
+    method: &'a str,
+    host: &'a str,
+    path: &'a str,
+    region: &'a str,
+    access_key: &'a str,
+    secret_key: &'a str,
+}
+
+// Headers to send besides host, see
+// https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-header-based-auth.html
+fn sign(signature: &Signature, body: &[u8], now_secs: u64) -> Vec<(&'static str, String)> {
+    let amz_date = amz_date(now_secs);
+    let date = &amz_date[..8];
+    let payload = hex(&Sha256::digest(body));
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical = format!(
+        "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        signature.method,
+        signature.path,
+        signature.host,
+        payload,
+        amz_date,
+        signed_headers,
+        payload
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, signature.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical.as_bytes()))
+    );
+    let key = [date, signature.region, "s3", "aws4_request"].iter().fold(
+        format!("AWS4{}", signature.secret_key).into_bytes(),
+        |key, part| hmac(&key, part.as_bytes()).to_vec(),
+    );
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        signature.access_key,
+        scope,
+        signed_headers,
+        hex(&hmac(&key, string_to_sign.as_bytes()))
+    );
+    vec![
+        ("x-amz-content-sha256", payload),
+        ("x-amz-date", amz_date),
+        ("authorization", authorization),
+    ]
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// e.g. 20130524T000000Z
+fn amz_date(secs: u64) -> String {
+    let (year, month, day) = calendar::civil_from_days((secs / 86_400) as i64);
+    let secs = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_signed_like_aws_does() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(amz_date(1_369_353_600), "20130524T000000Z");
+        assert_eq!(amz_date(951_782_400 + 3_661), "20000229T010101Z");
+
+        let headers = sign(
+            &Signature {
+                method: "GET",
+                host: "127.0.0.1:9000",
+                path: "/trades/a.json",
+                region: "us-east-1",
+                access_key: "AKIDEXAMPLE",
+                secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            },
+            &[],
+            1_369_353_600,
+        );
+        assert_eq!(
+            headers[0].1,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert!(headers[2].1.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20130524/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+        assert!(S3Store::new("127.0.0.1:9000/trades", "us-east-1", "a", "b").is_err());
+    }
+}
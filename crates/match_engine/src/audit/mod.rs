@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::key::{self, Key};
+use crate::telemetry;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub actor: String,
+    pub timestamp: u64,
+    pub action: String,
+    pub params: BTreeMap<String, String>,
+}
+
+pub fn record(
+    db: &Database,
+    actor: &str,
+    action: &str,
+    params: &[(&str, &str)],
+) -> anyhow::Result<AuditEntry> {
+    let entry = AuditEntry {
+        actor: actor.to_string(),
+        timestamp: telemetry::now_millis(),
+        action: action.to_string(),
+        params: params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    };
+    Key::audit(entry.timestamp, db.generate_id()?).set(db, &entry)?;
+    Ok(entry)
+}
+
+pub fn list(db: &Database, action: Option<&str>) -> anyhow::Result<Vec<AuditEntry>> {
+    let mut entries = Vec::new();
+    for (_, json) in db.entries_in(key::AUDIT)? {
+        let entry: AuditEntry = serde_json::from_str(&json)?;
+        if action.is_none_or(|action| action == entry.action) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    #[test]
+    fn record_and_list_in_order() {
+        let db = temp_db();
+
+        record(
+            &db,
+            "alice",
+            "halt",
+            &[("pair", "BTC/USD"), ("reason", "drill")],
+        )
+        .unwrap();
+        record(&db, "bob", "restart", &[("pair", "BTC/USD")]).unwrap();
+
+        let entries = list(&db, None).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.action.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["halt", "restart"]
+        );
+        assert_eq!(entries[0].actor, "alice");
+        assert_eq!(entries[0].params["reason"], "drill");
+
+        let restarts = list(&db, Some("restart")).unwrap();
+        assert_eq!(restarts.len(), 1);
+        assert_eq!(restarts[0].actor, "bob");
+    }
+}
@@ -0,0 +1,160 @@
+use std::hint;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use anyhow::anyhow;
+use crossbeam_queue::ArrayQueue;
+
+use crate::order::Order;
+use crate::order_book::OrderBook;
+use crate::pipeline::{self, Outcome};
+
+// One spinning thread validates, matches and emits outcomes. Orders come in
+// and outcomes go out over lock-free ring buffers, so nothing on the path
+// parks a thread; it trades a busy core for the lowest latency.
+pub struct BusyPoll {
+    inbound: Arc<ArrayQueue<Order>>,
+    outbound: Arc<ArrayQueue<Outcome>>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<OrderBook>>,
+}
+
+impl BusyPoll {
+    pub fn spawn(mut order_book: OrderBook, capacity: usize, core: Option<usize>) -> Self {
+        let inbound = Arc::new(ArrayQueue::new(capacity));
+        let outbound = Arc::new(ArrayQueue::new(capacity));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let (worker_inbound, worker_outbound, worker_running) =
+            (inbound.clone(), outbound.clone(), running.clone());
+        let worker = thread::spawn(move || {
+            if let Some(core) = core {
+                if let Err(e) = pin_to_core(core) {
+                    eprintln!("Busy-poll worker is not pinned: {}", e);
+                }
+            }
+            loop {
+                match worker_inbound.pop() {
+                    Some(order) => {
+                        let rejected = pipeline::validate(&order)
+                            .and_then(|_| pipeline::place(&mut order_book, order))
+                            .err()
+                            .map(|e| e.to_string());
+                        let mut outcome = Outcome { order, rejected };
+                        // after shutdown nobody polls anymore, outcomes are dropped
+                        while let Err(full) = worker_outbound.push(outcome) {
+                            if !worker_running.load(Ordering::Acquire) {
+                                break;
+                            }
+                            outcome = full;
+                            hint::spin_loop();
+                        }
+                    }
+                    None if !worker_running.load(Ordering::Acquire) => break,
+                    None => hint::spin_loop(),
+                }
+            }
+            order_book
+        });
+
+        Self {
+            inbound,
+            outbound,
+            running,
+            worker: Some(worker),
+        }
+    }
+
+    // Hands the order back when the ring is full.
+    pub fn try_submit(&self, order: Order) -> Result<(), Order> {
+        self.inbound.push(order)
+    }
+
+    pub fn submit(&self, mut order: Order) {
+        while let Err(full) = self.inbound.push(order) {
+            order = full;
+            hint::spin_loop();
+        }
+    }
+
+    pub fn poll(&self) -> Option<Outcome> {
+        self.outbound.pop()
+    }
+
+    // Processes every submitted order before handing the book back.
+    pub fn shutdown(mut self) -> OrderBook {
+        self.running.store(false, Ordering::Release);
+        self.worker
+            .take()
+            .expect("busy-poll worker is stopped")
+            .join()
+            .expect("busy-poll worker panicked")
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) -> anyhow::Result<()> {
+    // SAFETY: cpu_set_t is plain data and all zeroes is the empty set, the
+    // set outlives the call and its size is passed alongside it.
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(anyhow!(
+            "could not pin to core {}: {}",
+            core,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(core: usize) -> anyhow::Result<()> {
+    Err(anyhow!(
+        "pinning to core {} is only supported on linux",
+        core
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderType;
+    use crate::symbol::Symbol;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn single_thread_matches_and_emits_in_order() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+        order_book_builder.set_db(db.clone());
+        let busy_poll = BusyPoll::spawn(order_book_builder.build().unwrap(), 4, Some(0));
+
+        let buy = Order::new(1, 10, OrderType::Buy);
+        let invalid = Order::new(1, 0, OrderType::Sell);
+        let sell = Order::new(1, 9, OrderType::Sell);
+        let mut outcomes = Vec::new();
+        for order in [buy, invalid, sell] {
+            busy_poll.submit(order);
+            loop {
+                if let Some(outcome) = busy_poll.poll() {
+                    outcomes.push(outcome);
+                    break;
+                }
+                hint::spin_loop();
+            }
+        }
+
+        assert_eq!(
+            outcomes.iter().map(|o| o.order.id).collect::<Vec<_>>(),
+            vec![buy.id, invalid.id, sell.id]
+        );
+        assert!(outcomes[1].rejected.is_some());
+        assert_eq!(busy_poll.shutdown().join_filled_orders().len(), 2);
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::anyhow;
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::audit;
+use crate::key::Key;
+use crate::telemetry;
+
+// Actor recorded on the audit entries of scheduled transitions.
+pub const ACTOR: &str = "calendar";
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarketState {
+    Open,
+    Closed,
+}
+
+// Minutes after midnight UTC, the close is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub open: u16,
+    pub close: u16,
+}
+
+// Days without a session and holidays (UTC dates) are closed all day.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradingCalendar {
+    // Monday first
+    pub sessions: [Option<Session>; 7],
+    pub holidays: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub pair: String,
+    pub calendar: TradingCalendar,
+    // set by an operator, wins over the calendar until cleared
+    #[serde(default)]
+    pub forced: Option<MarketState>,
+    #[serde(default)]
+    pub last_state: Option<MarketState>,
+}
+
+impl TradingCalendar {
+    // e.g. "mon-fri=09:00-17:00,sat=10:00-14:00,holiday=2026-12-25"
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut calendar = TradingCalendar::default();
+        for clause in spec.split(',').filter(|c| !c.trim().is_empty()) {
+            let (key, value) = clause
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid calendar clause {}, expected key=value", clause))?;
+            match key.trim() {
+                "holiday" => {
+                    let date = value.trim();
+                    parse_date(date)?;
+                    calendar.holidays.push(date.to_string());
+                }
+                days => {
+                    let session = parse_session(value.trim())?;
+                    for day in parse_days(days)? {
+                        calendar.sessions[day] = Some(session);
+                    }
+                }
+            }
+        }
+        Ok(calendar)
+    }
+
+    pub fn state_at(&self, timestamp: u64) -> MarketState {
+        let days = timestamp / MILLIS_PER_DAY;
+        let minute = ((timestamp % MILLIS_PER_DAY) / 60_000) as u16;
+        // 1970-01-01 was a Thursday
+        let weekday = ((days + 3) % 7) as usize;
+        let (year, month, day) = civil_from_days(days as i64);
+        let date = format!("{:04}-{:02}-{:02}", year, month, day);
+
+        match self.sessions[weekday] {
+            Some(session)
+                if !self.holidays.contains(&date)
+                    && session.open <= minute
+                    && minute < session.close =>
+            {
+                MarketState::Open
+            }
+            _ => MarketState::Closed,
+        }
+    }
+}
+
+fn parse_days(days: &str) -> anyhow::Result<Vec<usize>> {
+    let day = |name: &str| {
+        DAYS.iter()
+            .position(|d| *d == name.trim())
+            .ok_or_else(|| anyhow!("Invalid day {}, expected one of {}", name, DAYS.join(", ")))
+    };
+    match days.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (day(first)?, day(last)?);
+            if first > last {
+                return Err(anyhow!("Invalid day range {}, ranges run mon to sun", days));
+            }
+            Ok((first..=last).collect())
+        }
+        None => Ok(vec![day(days)?]),
+    }
+}
+
+fn parse_session(hours: &str) -> anyhow::Result<Session> {
+    let minutes = |time: &str| -> anyhow::Result<u16> {
+        let (hour, minute) = time
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid time {}, expected HH:MM", time))?;
+        let (hour, minute): (u16, u16) = (hour.parse()?, minute.parse()?);
+        if hour > 24 || minute > 59 || hour * 60 + minute > 24 * 60 {
+            return Err(anyhow!("Invalid time {}, expected 00:00 to 24:00", time));
+        }
+        Ok(hour * 60 + minute)
+    };
+    let (open, close) = hours
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Invalid session {}, expected HH:MM-HH:MM", hours))?;
+    let session = Session {
+        open: minutes(open)?,
+        close: minutes(close)?,
+    };
+    if session.open >= session.close {
+        return Err(anyhow!(
+            "Invalid session {}, the open must be before the close",
+            hours
+        ));
+    }
+    Ok(session)
+}
+
+fn parse_date(date: &str) -> anyhow::Result<()> {
+    let parts = date.split('-').collect::<Vec<_>>();
+    match parts.as_slice() {
+        [year, month, day] if year.len() == 4 && month.len() == 2 && day.len() == 2 => {
+            let (month, day): (u32, u32) = (month.parse()?, day.parse()?);
+            year.parse::<u32>()?;
+            if (1..=12).contains(&month) && (1..=31).contains(&day) {
+                return Ok(());
+            }
+            Err(anyhow!("Invalid date {}", date))
+        }
+        _ => Err(anyhow!("Invalid date {}, expected YYYY-MM-DD", date)),
+    }
+}
+
+// Days since the unix epoch to a (year, month, day) date.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// The inverse of civil_from_days.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn schedule(db: &Database, pair: &str) -> anyhow::Result<Option<Schedule>> {
+    Key::calendar(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+// Replaces the calendar, an operator override stays in place.
+pub fn set(db: &Database, pair: &str, calendar: TradingCalendar) -> anyhow::Result<Schedule> {
+    let schedule = match self::schedule(db, pair)? {
+        Some(schedule) => Schedule {
+            calendar,
+            ..schedule
+        },
+        None => Schedule {
+            pair: pair.to_string(),
+            calendar,
+            forced: None,
+            last_state: None,
+        },
+    };
+    Key::calendar(pair).set(db, &schedule)?;
+    Ok(schedule)
+}
+
+pub fn force(db: &Database, pair: &str, state: Option<MarketState>) -> anyhow::Result<()> {
+    let mut schedule =
+        self::schedule(db, pair)?.ok_or_else(|| anyhow!("No trading calendar for {}", pair))?;
+    schedule.forced = state;
+    Ok(Key::calendar(pair).set(db, &schedule)?)
+}
+
+pub fn remove(db: &Database, pair: &str) -> anyhow::Result<()> {
+    Ok(Key::calendar(pair).remove(db)?)
+}
+
+// The pair's market state now. Pairs without a calendar are always open.
+// A change from the last observed state is persisted and recorded in the
+// audit log as market_open or market_closed.
+pub fn observe(db: &Database, pair: &str) -> anyhow::Result<MarketState> {
+    let mut schedule = match self::schedule(db, pair)? {
+        Some(schedule) => schedule,
+        None => return Ok(MarketState::Open),
+    };
+    let state = schedule
+        .forced
+        .unwrap_or_else(|| schedule.calendar.state_at(telemetry::now_millis()));
+    if schedule.last_state != Some(state) {
+        schedule.last_state = Some(state);
+        Key::calendar(pair).set(db, &schedule)?;
+        let action = match state {
+            MarketState::Open => "market_open",
+            MarketState::Closed => "market_closed",
+        };
+        let cause = match schedule.forced {
+            Some(_) => "override",
+            None => "schedule",
+        };
+        audit::record(db, ACTOR, action, &[("pair", pair), ("cause", cause)])?;
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{self, Clock};
+    use crate::order::{Order, OrderType};
+    use crate::order_book::OrderBook;
+    use crate::symbol::Symbol;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use test_utils::shared_temp_db;
+
+    // Friday 2026-01-02 00:00 UTC
+    const FRIDAY: u64 = 1_767_312_000_000;
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    #[test]
+    fn sessions_and_holidays() {
+        let calendar = TradingCalendar::parse("mon-fri=09:00-17:00,holiday=2026-01-05").unwrap();
+
+        assert_eq!(calendar.state_at(FRIDAY + 8 * HOUR), MarketState::Closed);
+        assert_eq!(calendar.state_at(FRIDAY + 9 * HOUR), MarketState::Open);
+        assert_eq!(calendar.state_at(FRIDAY + 17 * HOUR), MarketState::Closed);
+        // saturday, then the monday holiday, then tuesday
+        assert_eq!(calendar.state_at(FRIDAY + 34 * HOUR), MarketState::Closed);
+        assert_eq!(calendar.state_at(FRIDAY + 82 * HOUR), MarketState::Closed);
+        assert_eq!(calendar.state_at(FRIDAY + 106 * HOUR), MarketState::Open);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_calendars() {
+        assert!(TradingCalendar::parse("fri-mon=09:00-17:00").is_err());
+        assert!(TradingCalendar::parse("mon=17:00-09:00").is_err());
+        assert!(TradingCalendar::parse("mon=09:00").is_err());
+        assert!(TradingCalendar::parse("someday=09:00-17:00").is_err());
+        assert!(TradingCalendar::parse("holiday=25/12/2026").is_err());
+    }
+
+    #[test]
+    fn transitions_are_audited_once_and_block_orders() {
+        let db = shared_temp_db();
+        let calendar = TradingCalendar::parse("mon-fri=09:00-17:00").unwrap();
+        set(&db.lock().unwrap(), "BTC/USD", calendar).unwrap();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        let time = Arc::new(Clock::manual(FRIDAY + 8 * HOUR));
+
+        clock::with_clock(time.clone(), || {
+            assert!(order_book
+                .append_buy_order(Order::new(1, 10, OrderType::Buy))
+                .is_err());
+            time.advance(Duration::from_millis(HOUR));
+            order_book
+                .append_buy_order(Order::new(1, 10, OrderType::Buy))
+                .unwrap();
+            order_book
+                .append_buy_order(Order::new(1, 10, OrderType::Buy))
+                .unwrap();
+
+            force(&db.lock().unwrap(), "BTC/USD", Some(MarketState::Closed)).unwrap();
+            assert!(order_book
+                .append_buy_order(Order::new(1, 10, OrderType::Buy))
+                .is_err());
+        });
+
+        let actions = audit::list(&db.lock().unwrap(), None)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.action, e.params["cause"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                ("market_closed".to_string(), "schedule".to_string()),
+                ("market_open".to_string(), "schedule".to_string()),
+                ("market_closed".to_string(), "override".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::anyhow;
+use db::Database;
+use serde_json::Value;
+
+use crate::error;
+use crate::key;
+use crate::symbol::Symbol;
+use crate::trade::TradeRef;
+
+pub const DUPLICATES_ENV: &str = "FTX_DUPLICATE_PAIRS";
+
+// Trees keyed by pair, see key.
+const PAIR_KEYED: [&str; 10] = [
+    key::BOOKS,
+    key::HALTED,
+    key::CORRUPT,
+    key::CALENDARS,
+    key::ALIASES,
+    key::INSTRUMENTS,
+    key::FEES,
+    key::COMPACTIONS,
+    key::SETTLEMENTS,
+    key::RECORDED,
+];
+// Trees whose records name their pair in a `pair` field.
+const PAIR_FIELDS: [&str; 2] = [key::TELEMETRY, key::SLOW_PATH];
+// Like PAIR_FIELDS, also keyed BASE/QUOTE/<id>, see key.
+const PAIR_GROUPED: [&str; 3] = [key::COMMANDS, key::TRADES, key::ARCHIVES];
+// Index entries pointing at a trade key, see trade::TradeRef.
+const TRADE_REFS: [&str; 2] = [key::ORDER_TRADES, key::ACCOUNT_TRADES];
+
+// What to do when a legacy key, e.g. "btc/usd", and its canonical form
+// "BTC/USD" both hold a record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Duplicates {
+    // nothing is migrated until the duplicates are resolved by hand
+    #[default]
+    Fail,
+    KeepCanonical,
+    KeepLegacy,
+}
+
+impl Duplicates {
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        match raw.trim() {
+            "fail" => Ok(Duplicates::Fail),
+            "keep-canonical" => Ok(Duplicates::KeepCanonical),
+            "keep-legacy" => Ok(Duplicates::KeepLegacy),
+            _ => Err(anyhow!(
+                "Invalid duplicate policy {}, expected fail, keep-canonical or keep-legacy",
+                raw
+            )),
+        }
+    }
+
+    // e.g. FTX_DUPLICATE_PAIRS=keep-canonical, fail when unset
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var(DUPLICATES_ENV) {
+            Ok(raw) => Self::parse(&raw),
+            Err(_) => Ok(Duplicates::Fail),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Migration {
+    // (tree, legacy key, canonical key)
+    pub renamed: Vec<(String, String, String)>,
+    // legacy or canonical records given up for the other, (tree, key)
+    pub dropped: Vec<(String, String)>,
+    // records left under their key with their pair field rewritten
+    pub rewritten: usize,
+}
+
+impl Migration {
+    pub fn is_empty(&self) -> bool {
+        self.renamed.is_empty() && self.dropped.is_empty() && self.rewritten == 0
+    }
+}
+
+fn canonical(raw: &str) -> Option<String> {
+    Symbol::parse(raw)
+        .ok()
+        .map(|symbol| symbol.as_str().to_string())
+        .filter(|canonical| canonical != raw)
+}
+
+// Pair strings inside a record, the record itself for aliases.
+fn canonical_value(json: &str) -> anyhow::Result<Option<Value>> {
+    let mut value: Value = serde_json::from_str(json)?;
+    let pair = match &mut value {
+        Value::String(pair) => Some(pair),
+        Value::Object(fields) => match fields.get_mut("pair") {
+            Some(Value::String(pair)) => Some(pair),
+            _ => None,
+        },
+        _ => None,
+    };
+    match pair.and_then(|pair| canonical(pair).map(|c| (pair, c))) {
+        Some((pair, canonical)) => {
+            *pair = canonical;
+            Ok(Some(value))
+        }
+        None => Ok(None),
+    }
+}
+
+// A grouped key without its pair, e.g. {timestamp}-{sequence} of a trade.
+fn id_of(key: &str) -> &str {
+    key.rsplit('/').next().unwrap_or(key)
+}
+
+// Rewrites records stored under a pair in whatever casing it was typed,
+// from before pairs were normalized, into the canonical BASE/QUOTE form the
+// CLI and API look them up by. Safe to run again, canonical records are
+// left alone.
+pub fn migrate(db: &Database, duplicates: Duplicates) -> anyhow::Result<Migration> {
+    let mut legacy = Vec::new();
+    for tree in PAIR_KEYED {
+        for (key, json) in db.entries_in(tree)? {
+            if let Some(canonical) = canonical(&key) {
+                let taken = db.get_raw_in(tree, &canonical)?.is_some();
+                legacy.push((tree, key, canonical, json, taken));
+            }
+        }
+    }
+    let conflicts = legacy
+        .iter()
+        .filter(|(.., taken)| *taken)
+        .map(|(tree, key, canonical, ..)| format!("{tree}: {key} and {canonical}"))
+        .collect::<Vec<_>>();
+    if duplicates == Duplicates::Fail && !conflicts.is_empty() {
+        return Err(error::state(format!(
+            "Pairs stored twice, set {} to keep-canonical or keep-legacy: {}",
+            DUPLICATES_ENV,
+            conflicts.join(", ")
+        )));
+    }
+
+    // each key moves in one batch, a crash never loses the record
+    let mut migration = Migration::default();
+    for (tree, key, canonical, json, taken) in legacy {
+        let mut batch = db.batch();
+        batch.remove_in(tree, &key);
+        if taken && duplicates == Duplicates::KeepCanonical {
+            batch.commit()?;
+            migration.dropped.push((tree.to_string(), key));
+            continue;
+        }
+        if taken {
+            migration
+                .dropped
+                .push((tree.to_string(), canonical.clone()));
+        }
+        let value = match canonical_value(&json)? {
+            Some(value) => value,
+            None => serde_json::from_str(&json)?,
+        };
+        batch.set_in(tree, &canonical, &value)?;
+        batch.commit()?;
+        migration.renamed.push((tree.to_string(), key, canonical));
+    }
+    // older records were keyed by their id alone, without the pair
+    for tree in PAIR_GROUPED {
+        for (key, json) in db.entries_in(tree)? {
+            let value = match canonical_value(&json)? {
+                Some(value) => value,
+                None => serde_json::from_str(&json)?,
+            };
+            let pair = value
+                .get("pair")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow!("{} record {} names no pair", tree, key))?;
+            let regrouped = format!("{}/{}", pair, id_of(&key));
+            if regrouped == key {
+                continue;
+            }
+            let mut batch = db.batch();
+            batch.remove_in(tree, &key);
+            batch.set_in(tree, &regrouped, &value)?;
+            batch.commit()?;
+            migration.renamed.push((tree.to_string(), key, regrouped));
+        }
+    }
+    for tree in TRADE_REFS {
+        for (key, json) in db.entries_in(tree)? {
+            let mut trade_ref: TradeRef = serde_json::from_str(&json)?;
+            let pair = canonical(&trade_ref.pair).unwrap_or(trade_ref.pair.clone());
+            let trade = format!("{}/{}", pair, id_of(&trade_ref.trade));
+            if (&pair, &trade) == (&trade_ref.pair, &trade_ref.trade) {
+                continue;
+            }
+            (trade_ref.pair, trade_ref.trade) = (pair, trade);
+            db.set_in(tree, &key, &trade_ref)?;
+            migration.rewritten += 1;
+        }
+    }
+    for tree in PAIR_KEYED.into_iter().chain(PAIR_FIELDS) {
+        for (key, json) in db.entries_in(tree)? {
+            if let Some(value) = canonical_value(&json)? {
+                db.set_in(tree, &key, &value)?;
+                migration.rewritten += 1;
+            }
+        }
+    }
+    Ok(migration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_log::{self, Command, LoggedCommand};
+    use crate::error::ErrorKind;
+    use crate::key::Key;
+    use crate::order::{Order, OrderType};
+    use crate::order_book::Item;
+    use crate::trade::{self, LoggedTrade, Trade};
+    use test_utils::temp_db;
+
+    fn book(orders: usize) -> Item {
+        Item {
+            active_orders: (0..orders)
+                .map(|_| Order::new(1, 10, OrderType::Buy))
+                .collect(),
+            fulfilled_orders: Vec::new(),
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn legacy_pairs_move_to_their_canonical_keys() {
+        let db = temp_db();
+        db.set("btc/usd", &book(1)).unwrap();
+        db.set_in(
+            key::HALTED,
+            "btc/usd",
+            &serde_json::json!({ "pair": "btc/usd" }),
+        )
+        .unwrap();
+        db.set_in(key::ALIASES, "xbt/usd", &"btc/usd").unwrap();
+        let (bid, ask) = (
+            Order::new(1, 10, OrderType::Buy),
+            Order::new(1, 10, OrderType::Sell),
+        );
+        trade::record(&db, "btc/usd", &[Trade::between(&bid, &ask, 1, 10, None)]).unwrap();
+        let logged = LoggedCommand {
+            sequence: 7,
+            timestamp: 1,
+            pair: "btc/usd".to_string(),
+            command: Command::Place(bid),
+        };
+        db.set_in(key::COMMANDS, &format!("{:020}", 7), &logged)
+            .unwrap();
+        // a trade and its index entry from before trades were keyed by pair
+        let (id, older) = (
+            Key::chronological(key::TRADES, 9, 8).id().to_string(),
+            LoggedTrade {
+                sequence: 8,
+                pair: "BTC/USD".to_string(),
+                trade: Trade::between(&bid, &ask, 1, 9, None),
+            },
+        );
+        db.set_in(key::TRADES, &id, &older).unwrap();
+        let trade_ref = TradeRef {
+            pair: "BTC/USD".to_string(),
+            trade: id,
+        };
+        Key::order_trade(bid.id, 9, 8).set(&db, &trade_ref).unwrap();
+
+        let migration = migrate(&db, Duplicates::Fail).unwrap();
+
+        assert_eq!(migration.renamed.len(), 6);
+        assert_eq!(db.keys().unwrap(), vec!["BTC/USD".to_string()]);
+        assert_eq!(
+            db.get::<Item>("BTC/USD")
+                .unwrap()
+                .unwrap()
+                .active_orders
+                .len(),
+            1
+        );
+        assert_eq!(
+            db.get_in::<String>(key::ALIASES, "XBT/USD").unwrap(),
+            Some("BTC/USD".to_string())
+        );
+        assert_eq!(trade::trades(&db, Some("BTC/USD")).unwrap().len(), 2);
+        assert_eq!(trade::trades_of_order(&db, bid.id).unwrap().len(), 2);
+        let commands = command_log::commands(&db, Some("BTC/USD")).unwrap();
+        assert_eq!(commands[0].pair, "BTC/USD");
+        assert!(migrate(&db, Duplicates::Fail).unwrap().is_empty());
+    }
+
+    #[test]
+    fn duplicates_follow_the_policy() {
+        let db = temp_db();
+        db.set("eth/usd", &book(1)).unwrap();
+        db.set("ETH/USD", &book(2)).unwrap();
+
+        let e = migrate(&db, Duplicates::Fail).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::State);
+        assert_eq!(db.keys().unwrap().len(), 2);
+
+        let migration = migrate(&db, Duplicates::KeepLegacy).unwrap();
+        assert_eq!(
+            migration.dropped,
+            vec![(key::BOOKS.to_string(), "ETH/USD".to_string())]
+        );
+        assert_eq!(
+            db.get::<Item>("ETH/USD")
+                .unwrap()
+                .unwrap()
+                .active_orders
+                .len(),
+            1
+        );
+        assert_eq!(db.keys().unwrap(), vec!["ETH/USD".to_string()]);
+    }
+}
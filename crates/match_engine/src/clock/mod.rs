@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+
+pub const CLOCK_ENV: &str = "FTX_CLOCK";
+
+// Simulated time: `origin` plus wall time elapsed since `started`, scaled by
+// `factor`. A factor of 0 is a manual clock that only moves when advanced.
+#[derive(Debug)]
+pub struct Clock {
+    started: Instant,
+    origin: AtomicU64,
+    factor: u64,
+}
+
+static PROCESS: RwLock<Option<Arc<Clock>>> = RwLock::new(None);
+
+thread_local! {
+    static THREAD: RefCell<Option<Arc<Clock>>> = const { RefCell::new(None) };
+}
+
+impl Clock {
+    pub fn accelerated(factor: u64) -> Self {
+        Self::starting_at(system_millis(), factor)
+    }
+
+    pub fn manual(start: u64) -> Self {
+        Self::starting_at(start, 0)
+    }
+
+    fn starting_at(origin: u64, factor: u64) -> Self {
+        Self {
+            started: Instant::now(),
+            origin: AtomicU64::new(origin),
+            factor,
+        }
+    }
+
+    pub fn now_millis(&self) -> u64 {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.origin.load(Ordering::SeqCst) + elapsed * self.factor
+    }
+
+    // Steps the clock forward, for manual clocks this is the only way it moves.
+    pub fn advance(&self, by: Duration) {
+        self.origin
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    // e.g. "accelerated=60", "manual" or "manual=1700000000000" (unix millis)
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let (mode, value) = match spec.trim().split_once('=') {
+            Some((mode, value)) => (mode, Some(value.trim())),
+            None => (spec.trim(), None),
+        };
+        match (mode, value) {
+            ("accelerated", Some(factor)) => Ok(Self::accelerated(factor.parse()?)),
+            ("manual", Some(start)) => Ok(Self::manual(start.parse()?)),
+            ("manual", None) => Ok(Self::manual(system_millis())),
+            _ => Err(anyhow!(
+                "Invalid clock {}, expected accelerated=<factor> or manual[=<unix millis>]",
+                spec
+            )),
+        }
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var(CLOCK_ENV) {
+            Ok(spec) => Ok(Some(Self::parse(&spec)?)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+// Replaces the system clock for every thread, e.g. for a daemon started in
+// simulation mode. Keep the returned handle to step the clock.
+pub fn install(clock: Clock) -> Arc<Clock> {
+    let clock = Arc::new(clock);
+    *PROCESS.write().expect("could not get clock lock") = Some(clock.clone());
+    clock
+}
+
+// Overrides the clock on the current thread only while `f` runs, so tests
+// can fast-forward without affecting tests running in parallel.
+pub fn with_clock<T>(clock: Arc<Clock>, f: impl FnOnce() -> T) -> T {
+    let previous = THREAD.with(|c| c.replace(Some(clock)));
+    let result = f();
+    THREAD.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+pub fn now_millis() -> u64 {
+    if let Some(now) = THREAD.with(|c| c.borrow().as_ref().map(|c| c.now_millis())) {
+        return now;
+    }
+    match PROCESS.read().expect("could not get clock lock").as_ref() {
+        Some(clock) => clock.now_millis(),
+        None => system_millis(),
+    }
+}
+
+fn system_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before unix epoch")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = Arc::new(Clock::manual(1_000));
+
+        with_clock(clock.clone(), || {
+            thread::sleep(Duration::from_millis(5));
+            assert_eq!(now_millis(), 1_000);
+
+            clock.advance(Duration::from_secs(60));
+            assert_eq!(now_millis(), 61_000);
+        });
+        assert!(now_millis() > 61_000);
+    }
+
+    #[test]
+    fn accelerated_clock_runs_faster() {
+        let clock = Clock::parse("accelerated=1000").unwrap();
+        let start = clock.now_millis();
+        thread::sleep(Duration::from_millis(5));
+
+        assert!(clock.now_millis() - start >= 5_000);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_modes() {
+        assert_eq!(Clock::parse("manual=5").unwrap().now_millis(), 5);
+        assert!(Clock::parse("accelerated").is_err());
+        assert!(Clock::parse("realtime").is_err());
+    }
+}
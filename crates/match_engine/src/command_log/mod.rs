@@ -0,0 +1,102 @@
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::key::{self, Key};
+use crate::order::Order;
+use crate::order_book::adjust::PriceAdjustment;
+use crate::order_book::amend::Amendment;
+use crate::order_book::filter::CancelFilter;
+use crate::telemetry;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    Place(Order),
+    CancelWhere(CancelFilter),
+    Adjust(PriceAdjustment),
+    Amend(Amendment),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedCommand {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub pair: String,
+    pub command: Command,
+}
+
+// Flushed before returning so a command is durable before it is applied.
+pub fn append(db: &Database, pair: &str, command: &Command) -> anyhow::Result<LoggedCommand> {
+    let logged = LoggedCommand {
+        sequence: db.generate_id()?,
+        timestamp: telemetry::now_millis(),
+        pair: pair.to_string(),
+        command: command.clone(),
+    };
+    Key::command(pair, logged.sequence).set(db, &logged)?;
+    db.flush()?;
+    Ok(logged)
+}
+
+// Commands in the order they were accepted, optionally for a single pair.
+pub fn commands(db: &Database, pair: Option<&str>) -> anyhow::Result<Vec<LoggedCommand>> {
+    commands_after(db, pair, None)
+}
+
+// Like commands, skipping those up to and including sequence `after`. A
+// single pair is read from its own key range, all pairs are merged.
+pub fn commands_after(
+    db: &Database,
+    pair: Option<&str>,
+    after: Option<u64>,
+) -> anyhow::Result<Vec<LoggedCommand>> {
+    let from = after.map_or(0, |after| after + 1);
+    let entries = match pair {
+        Some(pair) => db.entries_in_range(
+            key::COMMANDS,
+            Key::command(pair, from).id(),
+            Key::command(pair, u64::MAX).id(),
+        )?,
+        None => db.entries_in(key::COMMANDS)?,
+    };
+    let mut logged = entries
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect::<anyhow::Result<Vec<LoggedCommand>>>()?;
+    if pair.is_none() {
+        logged.retain(|c| c.sequence >= from);
+        logged.sort_by_key(|c| c.sequence);
+    }
+    Ok(logged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderType;
+    use test_utils::temp_db;
+
+    #[test]
+    fn commands_are_sequenced_per_pair() {
+        let db = temp_db();
+        let order = Order::new(1, 10, OrderType::Buy);
+
+        append(&db, "BTC/USD", &Command::Place(order)).unwrap();
+        append(&db, "ETH/USD", &Command::Place(order)).unwrap();
+        append(
+            &db,
+            "BTC/USD",
+            &Command::CancelWhere(CancelFilter::default()),
+        )
+        .unwrap();
+
+        let btc = commands(&db, Some("BTC/USD")).unwrap();
+        assert_eq!(btc.len(), 2);
+        assert!(btc[0].sequence < btc[1].sequence);
+        assert_eq!(btc[0].command, Command::Place(order));
+        let all = commands(&db, None).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.windows(2).all(|w| w[0].sequence < w[1].sequence));
+        let after = commands_after(&db, Some("BTC/USD"), Some(btc[0].sequence)).unwrap();
+        assert_eq!(after, btc[1..]);
+    }
+}
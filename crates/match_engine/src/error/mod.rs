@@ -0,0 +1,146 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Every transport reports failures with one of these, so a client sees the
+// same kind whether it came through the CLI, HTTP or the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    // the request itself is malformed
+    Validation,
+    // well formed, but refused to protect the book, e.g. no liquidity
+    Risk,
+    NotFound,
+    // the book cannot take it right now, e.g. halted or closed
+    State,
+    // the caller's role or key does not allow it
+    Forbidden,
+    Internal,
+}
+
+impl ErrorKind {
+    // Errors nobody tagged are assumed to be ours, storage failures included.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<EngineError>())
+            .map(|e| e.kind)
+            .unwrap_or(ErrorKind::Internal)
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Internal => 1,
+            ErrorKind::Validation => 2,
+            ErrorKind::Risk => 3,
+            ErrorKind::NotFound => 4,
+            ErrorKind::State => 5,
+            ErrorKind::Forbidden => 6,
+        }
+    }
+
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorKind::Validation => 400,
+            ErrorKind::Forbidden => 403,
+            ErrorKind::NotFound => 404,
+            ErrorKind::State => 409,
+            ErrorKind::Risk => 422,
+            ErrorKind::Internal => 500,
+        }
+    }
+
+    // google.rpc.Code values
+    pub fn grpc_code(self) -> i32 {
+        match self {
+            ErrorKind::Validation => 3,
+            ErrorKind::NotFound => 5,
+            ErrorKind::Forbidden => 7,
+            ErrorKind::Risk => 8,
+            ErrorKind::State => 9,
+            ErrorKind::Internal => 13,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Validation => "validation",
+            ErrorKind::Risk => "risk",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::State => "state",
+            ErrorKind::Forbidden => "forbidden",
+            ErrorKind::Internal => "internal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct EngineError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl EngineError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    // The kind of any error, its message kept as is.
+    pub fn from_anyhow(error: &anyhow::Error) -> Self {
+        Self::new(ErrorKind::of(error), error.to_string())
+    }
+}
+
+pub fn validation(message: impl Into<String>) -> anyhow::Error {
+    EngineError::new(ErrorKind::Validation, message).into()
+}
+
+pub fn risk(message: impl Into<String>) -> anyhow::Error {
+    EngineError::new(ErrorKind::Risk, message).into()
+}
+
+pub fn not_found(message: impl Into<String>) -> anyhow::Error {
+    EngineError::new(ErrorKind::NotFound, message).into()
+}
+
+pub fn state(message: impl Into<String>) -> anyhow::Error {
+    EngineError::new(ErrorKind::State, message).into()
+}
+
+pub fn forbidden(message: impl Into<String>) -> anyhow::Error {
+    EngineError::new(ErrorKind::Forbidden, message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn kinds_survive_context_and_default_to_internal() {
+        let error = Err::<(), _>(not_found("No open order"))
+            .context("cancel failed")
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::NotFound);
+        assert_eq!(ErrorKind::of(&error).http_status(), 404);
+
+        let untagged = anyhow::anyhow!("writer thread is gone");
+        assert_eq!(
+            EngineError::from_anyhow(&untagged),
+            EngineError::new(ErrorKind::Internal, "writer thread is gone")
+        );
+        assert_eq!(
+            serde_json::to_string(&EngineError::new(ErrorKind::State, "halted")).unwrap(),
+            r#"{"kind":"state","message":"halted"}"#
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::order::Order;
+use crate::telemetry;
+
+pub const PATH_ENV: &str = "FTX_EVENT_LOG";
+pub const ROTATE_ENV: &str = "FTX_EVENT_LOG_ROTATE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Accepted,
+    Rejected,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Amended,
+}
+
+// One line of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub timestamp: u64,
+    pub pair: String,
+    pub kind: EventKind,
+    pub order: Order,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Event {
+    pub fn new(pair: &str, kind: EventKind, order: Order) -> Self {
+        Self {
+            timestamp: telemetry::now_millis(),
+            pair: pair.to_string(),
+            kind,
+            order,
+            reason: None,
+        }
+    }
+}
+
+// A limit that is not set never rotates the file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+}
+
+impl Rotation {
+    // e.g. "max_bytes=10485760,max_age=3600", max_age in seconds
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut rotation = Rotation::default();
+        for clause in spec.split(',').filter(|c| !c.trim().is_empty()) {
+            let (limit, value) = clause
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid rotation {}, expected limit=value", clause))?;
+            match limit.trim() {
+                "max_bytes" => rotation.max_bytes = Some(value.trim().parse()?),
+                "max_age" => rotation.max_age = Some(Duration::from_secs(value.trim().parse()?)),
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown limit {}, expected max_bytes or max_age",
+                        limit
+                    ))
+                }
+            }
+        }
+        Ok(rotation)
+    }
+}
+
+struct Current {
+    file: File,
+    bytes: u64,
+    created: SystemTime,
+}
+
+// Newline-delimited JSON events appended to path. A full file is renamed to
+// <stem>.<unix ms>.<extension> next to it and a fresh one is started.
+pub struct EventLog {
+    path: PathBuf,
+    rotation: Rotation,
+    current: Mutex<Current>,
+}
+
+impl EventLog {
+    pub fn open(path: &Path, rotation: Rotation) -> anyhow::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotation,
+            current: Mutex::new(Self::open_current(path)?),
+        })
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let path = match std::env::var(PATH_ENV) {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+        let rotation = match std::env::var(ROTATE_ENV) {
+            Ok(spec) => Rotation::parse(&spec)?,
+            Err(_) => Rotation::default(),
+        };
+        Ok(Some(Self::open(Path::new(&path), rotation)?))
+    }
+
+    pub fn append(&self, events: &[Event]) -> anyhow::Result<()> {
+        let mut current = self.current.lock().expect("could not get event log lock");
+        let full = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| current.bytes >= max);
+        let expired = self
+            .rotation
+            .max_age
+            .is_some_and(|max| current.created.elapsed().unwrap_or_default() >= max);
+        if current.bytes > 0 && (full || expired) {
+            fs::rename(&self.path, self.rotated_path())?;
+            *current = Self::open_current(&self.path)?;
+        }
+
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+        current.file.write_all(lines.as_bytes())?;
+        current.bytes += lines.len() as u64;
+        Ok(())
+    }
+
+    // Age counts from the file's creation so short-lived processes appending
+    // to the same log still rotate it, falling back to now where the
+    // filesystem does not record it.
+    fn open_current(path: &Path) -> anyhow::Result<Current> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        Ok(Current {
+            bytes: metadata.len(),
+            created: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+            file,
+        })
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = match self.path.extension() {
+            Some(extension) => format!(
+                "{}.{}.{}",
+                stem,
+                telemetry::now_millis(),
+                extension.to_string_lossy()
+            ),
+            None => format!("{}.{}", stem, telemetry::now_millis()),
+        };
+        self.path.with_file_name(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderType;
+
+    #[test]
+    fn rotates_once_the_file_is_full() {
+        let dir = Path::new("mock_event_log");
+        fs::create_dir_all(dir).expect("could not create mock_event_log");
+        let log = EventLog::open(
+            &dir.join("events.ndjson"),
+            Rotation::parse("max_bytes=1").unwrap(),
+        )
+        .unwrap();
+        let event = Event::new(
+            "BTC/USD",
+            EventKind::Accepted,
+            Order::new(1, 10, OrderType::Buy),
+        );
+
+        log.append(&[event.clone(), event.clone()]).unwrap();
+        log.append(std::slice::from_ref(&event)).unwrap();
+
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[1], "events.ndjson");
+        let current = fs::read_to_string(dir.join("events.ndjson")).unwrap();
+        let parsed: Event = serde_json::from_str(current.trim()).unwrap();
+        assert_eq!(parsed, event);
+        assert_eq!(
+            fs::read_to_string(dir.join(&names[0]))
+                .unwrap()
+                .lines()
+                .count(),
+            2
+        );
+        assert!(Rotation::parse("max_lines=1").is_err());
+
+        fs::remove_dir_all(dir).expect("could not delete mock_event_log");
+    }
+}
@@ -0,0 +1,304 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order::Order;
+use crate::trade::Trade;
+
+// Published by an OrderBook to its subscribers, in the order the changes were
+// applied. A placement publishes OrderAccepted, then its trades, then the
+// fills of every order involved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderBookEvent {
+    // the order as it stands once matched, it only rests while still open
+    OrderAccepted {
+        pair: String,
+        order: Order,
+    },
+    OrderRejected {
+        pair: String,
+        order: Order,
+        reason: String,
+    },
+    OrderFilled {
+        pair: String,
+        order: Order,
+    },
+    OrderPartiallyFilled {
+        pair: String,
+        order: Order,
+    },
+    OrderCancelled {
+        pair: String,
+        order: Order,
+    },
+    // the order as it stands once amended and matched again
+    OrderAmended {
+        pair: String,
+        order: Order,
+    },
+    TradeExecuted {
+        pair: String,
+        trade: Trade,
+    },
+}
+
+impl OrderBookEvent {
+    pub fn pair(&self) -> &str {
+        match self {
+            OrderBookEvent::OrderAccepted { pair, .. }
+            | OrderBookEvent::OrderRejected { pair, .. }
+            | OrderBookEvent::OrderFilled { pair, .. }
+            | OrderBookEvent::OrderPartiallyFilled { pair, .. }
+            | OrderBookEvent::OrderCancelled { pair, .. }
+            | OrderBookEvent::OrderAmended { pair, .. }
+            | OrderBookEvent::TradeExecuted { pair, .. } => pair,
+        }
+    }
+}
+
+pub type Listener = Arc<dyn Fn(&OrderBookEvent) + Send + Sync>;
+
+// What a bounded subscription does when its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Overflow {
+    // the oldest queued event makes room
+    DropOldest,
+    // the subscriber is cut off, its receiver ends once drained
+    Disconnect,
+    // queued events about the same order collapse into the latest one, the
+    // oldest make room if that is not enough. Trades are never merged.
+    Conflate,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriberStats {
+    pub published: u64,
+    pub dropped: u64,
+    pub conflated: u64,
+    // events queued but not received yet, now and at worst
+    pub lag: usize,
+    pub max_lag: usize,
+    pub disconnected: bool,
+}
+
+struct Subscriber {
+    sender: Option<Sender<OrderBookEvent>>,
+    // the bus's own end of a bounded queue, to make room in it
+    queue: Option<(Receiver<OrderBookEvent>, usize, Overflow)>,
+    stats: SubscriberStats,
+}
+
+impl Subscriber {
+    fn deliver(&mut self, event: OrderBookEvent) {
+        let Some(sender) = self.sender.clone() else {
+            return;
+        };
+        self.stats.published += 1;
+        match sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Disconnected(_)) => {
+                self.sender = None;
+                self.stats.disconnected = true;
+            }
+            // a caller's own channel, the new event is lost
+            Err(TrySendError::Full(_)) if self.queue.is_none() => self.stats.dropped += 1,
+            Err(TrySendError::Full(event)) => self.overflow(&sender, event),
+        }
+        self.stats.lag = sender.len();
+        self.stats.max_lag = self.stats.max_lag.max(self.stats.lag);
+    }
+
+    fn overflow(&mut self, sender: &Sender<OrderBookEvent>, event: OrderBookEvent) {
+        let Some((queue, capacity, overflow)) = &self.queue else {
+            return;
+        };
+        match overflow {
+            Overflow::DropOldest => {
+                if queue.try_recv().is_ok() {
+                    self.stats.dropped += 1;
+                }
+                let _ = sender.try_send(event);
+            }
+            Overflow::Disconnect => {
+                self.sender = None;
+                self.stats.dropped += 1;
+                self.stats.disconnected = true;
+            }
+            Overflow::Conflate => {
+                let mut queued = queue.try_iter().collect::<Vec<_>>();
+                queued.push(event);
+                let before = queued.len();
+                let queued = conflate(queued);
+                self.stats.conflated += (before - queued.len()) as u64;
+                let excess = queued.len().saturating_sub(*capacity);
+                self.stats.dropped += excess as u64;
+                for event in queued.into_iter().skip(excess) {
+                    let _ = sender.try_send(event);
+                }
+            }
+        }
+    }
+}
+
+fn order_id(event: &OrderBookEvent) -> Option<Uuid> {
+    match event {
+        OrderBookEvent::OrderAccepted { order, .. }
+        | OrderBookEvent::OrderFilled { order, .. }
+        | OrderBookEvent::OrderPartiallyFilled { order, .. }
+        | OrderBookEvent::OrderCancelled { order, .. }
+        | OrderBookEvent::OrderAmended { order, .. } => Some(order.id),
+        OrderBookEvent::OrderRejected { .. } | OrderBookEvent::TradeExecuted { .. } => None,
+    }
+}
+
+// Keeps the last event of each order where it was queued.
+fn conflate(events: Vec<OrderBookEvent>) -> Vec<OrderBookEvent> {
+    let ids = events.iter().map(order_id).collect::<Vec<_>>();
+    events
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| match ids[*index] {
+            Some(id) => !ids[index + 1..].contains(&Some(id)),
+            None => true,
+        })
+        .map(|(_, event)| event)
+        .collect()
+}
+
+// Listeners run on the thread that changed the book, before the channels are
+// sent to, so they should be quick. Sending never blocks: unbounded
+// subscriptions grow, bounded ones follow their Overflow policy.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Subscriber>>,
+    listeners: Vec<Listener>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self) -> Receiver<OrderBookEvent> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.add_sender(sender);
+        receiver
+    }
+
+    // The bus keeps a handle on the queue, so it stays at most `capacity`
+    // long even after the receiver is dropped.
+    pub fn subscribe_bounded(
+        &mut self,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> Receiver<OrderBookEvent> {
+        let (sender, receiver) = crossbeam_channel::bounded(capacity.max(1));
+        self.subscribers().push(Subscriber {
+            sender: Some(sender),
+            queue: Some((receiver.clone(), capacity.max(1), overflow)),
+            stats: SubscriberStats::default(),
+        });
+        receiver
+    }
+
+    // A full bounded sender drops the new event instead of blocking.
+    pub fn add_sender(&mut self, sender: Sender<OrderBookEvent>) {
+        self.subscribers().push(Subscriber {
+            sender: Some(sender),
+            queue: None,
+            stats: SubscriberStats::default(),
+        });
+    }
+
+    pub fn add_listener(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers().is_empty() && self.listeners.is_empty()
+    }
+
+    // In subscription order.
+    pub fn stats(&self) -> Vec<SubscriberStats> {
+        self.subscribers().iter().map(|s| s.stats).collect()
+    }
+
+    pub fn publish(&self, events: Vec<OrderBookEvent>) {
+        let mut subscribers = self.subscribers();
+        for event in events {
+            for listener in &self.listeners {
+                listener(&event);
+            }
+            for subscriber in subscribers.iter_mut() {
+                subscriber.deliver(event.clone());
+            }
+        }
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<Subscriber>> {
+        self.subscribers
+            .lock()
+            .expect("could not get subscribers lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderType;
+
+    fn events(order: Order) -> Vec<OrderBookEvent> {
+        let pair = "BTC/USD".to_string();
+        let mut filled = order;
+        filled.fill(order.quantity);
+        vec![
+            OrderBookEvent::OrderAccepted {
+                pair: pair.clone(),
+                order,
+            },
+            OrderBookEvent::TradeExecuted {
+                pair: pair.clone(),
+                trade: Trade::between(&order, &order, 1, 0, None),
+            },
+            OrderBookEvent::OrderFilled {
+                pair,
+                order: filled,
+            },
+        ]
+    }
+
+    #[test]
+    fn full_queues_follow_their_overflow_policy() {
+        let mut bus = EventBus::default();
+        let oldest_dropped = bus.subscribe_bounded(2, Overflow::DropOldest);
+        let disconnected = bus.subscribe_bounded(2, Overflow::Disconnect);
+        let conflated = bus.subscribe_bounded(2, Overflow::Conflate);
+        let unbounded = bus.subscribe();
+        let order = Order::new(1, 10, OrderType::Buy);
+
+        bus.publish(events(order));
+
+        let kinds = |receiver: &Receiver<OrderBookEvent>| {
+            receiver
+                .try_iter()
+                .map(|event| match event {
+                    OrderBookEvent::OrderAccepted { .. } => "accepted",
+                    OrderBookEvent::TradeExecuted { .. } => "trade",
+                    OrderBookEvent::OrderFilled { .. } => "filled",
+                    _ => "other",
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(&oldest_dropped), vec!["trade", "filled"]);
+        assert_eq!(kinds(&disconnected), vec!["accepted", "trade"]);
+        assert_eq!(kinds(&conflated), vec!["trade", "filled"]);
+        assert_eq!(kinds(&unbounded).len(), 3);
+
+        let stats = bus.stats();
+        assert_eq!((stats[0].dropped, stats[0].max_lag), (1, 2));
+        assert!(stats[1].disconnected);
+        assert_eq!((stats[2].dropped, stats[2].conflated), (0, 1));
+        assert_eq!(stats[3].lag, 3);
+        bus.publish(events(order));
+        assert_eq!(bus.stats()[1].published, 3);
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::order::{Order, OrderType};
+use crate::order_book::ack::OrderAck;
+use crate::order_book::OrderBook;
+use crate::shard::Shards;
+use crate::symbol::{self, Symbol};
+use crate::trade;
+use crate::version::VersionInfo;
+
+type NewBook = Box<dyn Fn() -> OrderBook + Send>;
+
+const VOLUME_WINDOW_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+// One pair's line of Exchange::overview. Hidden orders are left out, like
+// they are from the ticker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairOverview {
+    pub pair: Symbol,
+    pub bid: Option<i32>,
+    pub ask: Option<i32>,
+    pub last_price: Option<i32>,
+    // traded quantity over the 24 hours up to now
+    pub volume_24h: i64,
+    pub open_bids: usize,
+    pub open_asks: usize,
+}
+
+// Every market served by one process. Books are built and loaded from the
+// database the first time their pair is used and kept from then on, so
+// orders for the same pair always see each other.
+pub struct Exchange {
+    db: Arc<Mutex<Database>>,
+    books: BTreeMap<Symbol, OrderBook>,
+    new_book: NewBook,
+    shards: Option<Shards>,
+}
+
+impl Exchange {
+    pub fn new(db: Arc<Mutex<Database>>) -> Self {
+        let builder_db = db.clone();
+        Self::with_builder(db, move || {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_db(builder_db.clone());
+            order_book_builder
+        })
+    }
+
+    // `new_book` returns a builder with everything but the pair set, e.g. a
+    // writer or an event sender shared by all books.
+    pub fn with_builder<F>(db: Arc<Mutex<Database>>, new_book: F) -> Self
+    where
+        F: Fn() -> OrderBook + Send + 'static,
+    {
+        Self {
+            db,
+            books: BTreeMap::new(),
+            new_book: Box::new(new_book),
+            shards: None,
+        }
+    }
+
+    // Like with_builder, each book is pointed at its pair's shard.
+    pub fn with_shards<F>(shards: Shards, new_book: F) -> Self
+    where
+        F: Fn() -> OrderBook + Send + 'static,
+    {
+        Self {
+            shards: Some(shards.clone()),
+            ..Self::with_builder(shards.home().clone(), new_book)
+        }
+    }
+
+    pub fn resolve(&self, raw: &str) -> anyhow::Result<Symbol> {
+        symbol::resolve(&self.db.lock().expect("could not get db lock"), raw)
+    }
+
+    pub fn book(&mut self, pair: &Symbol) -> anyhow::Result<&mut OrderBook> {
+        if !self.books.contains_key(pair) {
+            let mut order_book_builder = (self.new_book)();
+            order_book_builder.set_pair(pair.clone());
+            if let Some(shards) = &self.shards {
+                shards.configure(&mut order_book_builder, pair)?;
+            }
+            let mut order_book = order_book_builder.build()?;
+            order_book.load()?;
+            self.books.insert(pair.clone(), order_book);
+        }
+        Ok(self.books.get_mut(pair).expect("book was just opened"))
+    }
+
+    // Routes the order to its pair's book, aliases resolve to the canonical
+    // pair.
+    pub fn submit(&mut self, raw_pair: &str, order: Order) -> anyhow::Result<OrderAck> {
+        let pair = self.resolve(raw_pair)?;
+        let order_book = self.book(&pair)?;
+        match order.order_type {
+            OrderType::Buy => order_book.append_buy_order(order),
+            OrderType::Sell => order_book.append_sell_order(order),
+        }
+    }
+
+    // Expires good-til-date orders on every writable pair, see
+    // OrderBook::expire.
+    pub fn expire(&mut self, now: u64) -> anyhow::Result<Vec<Order>> {
+        let mut expired = Vec::new();
+        for pair in self.list_pairs()? {
+            let order_book = self.book(&pair)?;
+            if order_book.is_halted() || order_book.is_read_only() {
+                continue;
+            }
+            expired.extend(order_book.expire(now)?);
+        }
+        Ok(expired)
+    }
+
+    // Top of book, last price, 24h volume and open orders of every pair in
+    // one go, read from the books and each pair's own trades.
+    pub fn overview(&mut self, now: u64) -> anyhow::Result<Vec<PairOverview>> {
+        let mut overview = Vec::new();
+        for pair in self.list_pairs()? {
+            let (last_price, volume_24h) = {
+                let db = match &self.shards {
+                    Some(shards) => shards.db_for(&pair),
+                    None => &self.db,
+                }
+                .lock()
+                .expect("could not get db lock");
+                let since = now.saturating_sub(VOLUME_WINDOW_MILLIS);
+                let recent = trade::trades_between(&db, Some(pair.as_str()), since, now)?;
+                let last = match recent.last() {
+                    Some(logged) => Some(logged.trade.price),
+                    // a quiet pair still has the price it last traded at
+                    None => trade::trades_between(&db, Some(pair.as_str()), 0, now)?
+                        .last()
+                        .map(|logged| logged.trade.price),
+                };
+                let volume = recent
+                    .iter()
+                    .map(|logged| logged.trade.quantity as i64)
+                    .sum();
+                (last, volume)
+            };
+            let order_book = self.book(&pair)?;
+            let public = |orders: Vec<Order>| orders.iter().filter(|o| !o.hidden).count();
+            overview.push(PairOverview {
+                bid: order_book.best_bid().map(|o| o.price),
+                ask: order_book.best_ask().map(|o| o.price),
+                last_price,
+                volume_24h,
+                open_bids: public(order_book.get_active_buy_orders()),
+                open_asks: public(order_book.get_active_sell_orders()),
+                pair,
+            });
+        }
+        Ok(overview)
+    }
+
+    pub fn version_info(&self) -> VersionInfo {
+        VersionInfo::current()
+    }
+
+    // Persisted pairs and the ones opened since, sorted.
+    pub fn list_pairs(&self) -> anyhow::Result<Vec<Symbol>> {
+        let persisted = match &self.shards {
+            Some(shards) => shards.pairs()?.into_iter().map(|(pair, _)| pair).collect(),
+            None => self
+                .db
+                .lock()
+                .expect("could not get db lock")
+                .keys()?
+                .iter()
+                .filter_map(|pair| Symbol::parse(pair).ok())
+                .collect::<Vec<_>>(),
+        };
+        let mut pairs = persisted
+            .into_iter()
+            .chain(self.books.keys().cloned())
+            .collect::<Vec<_>>();
+        pairs.sort();
+        pairs.dedup();
+        Ok(pairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderStatus;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn orders_are_routed_to_their_pair() {
+        let db = shared_temp_db();
+        symbol::add_alias(
+            &db.lock().unwrap(),
+            &Symbol::parse("XBT/USD").unwrap(),
+            &Symbol::parse("BTC/USD").unwrap(),
+        )
+        .unwrap();
+        let mut exchange = Exchange::new(db.clone());
+
+        exchange
+            .submit("btc/usd", Order::new(2, 10, OrderType::Sell))
+            .unwrap();
+        exchange
+            .submit("eth/usd", Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        let bid = exchange
+            .submit("xbt/usd", Order::new(2, 10, OrderType::Buy))
+            .unwrap();
+
+        assert_eq!(bid.order().order_status, OrderStatus::Filled);
+        let btc = Symbol::parse("BTC/USD").unwrap();
+        let eth = Symbol::parse("ETH/USD").unwrap();
+        assert_eq!(
+            exchange.list_pairs().unwrap(),
+            vec![btc.clone(), eth.clone()]
+        );
+        assert_eq!(exchange.book(&eth).unwrap().join_active_orders().len(), 1);
+        assert!(exchange
+            .submit("btc", Order::new(1, 10, OrderType::Buy))
+            .is_err());
+
+        // a fresh exchange picks the persisted books up again
+        let mut reopened = Exchange::new(db);
+        assert_eq!(reopened.list_pairs().unwrap(), vec![btc, eth.clone()]);
+        assert_eq!(reopened.book(&eth).unwrap().join_active_orders().len(), 1);
+    }
+
+    #[test]
+    fn overview_covers_every_pair() {
+        let mut exchange = Exchange::new(shared_temp_db());
+        exchange
+            .submit("btc/usd", Order::new(3, 10, OrderType::Sell))
+            .unwrap();
+        exchange
+            .submit("btc/usd", Order::new(2, 10, OrderType::Buy))
+            .unwrap();
+        exchange
+            .submit("btc/usd", Order::new(1, 8, OrderType::Buy))
+            .unwrap();
+        let mut hidden = Order::new(1, 7, OrderType::Buy);
+        hidden.update_hidden(true);
+        exchange.submit("btc/usd", hidden).unwrap();
+        exchange
+            .submit("eth/usd", Order::new(1, 5, OrderType::Buy))
+            .unwrap();
+
+        let now = crate::telemetry::now_millis();
+        let overview = exchange.overview(now).unwrap();
+        assert_eq!(
+            overview[0],
+            PairOverview {
+                pair: Symbol::parse("BTC/USD").unwrap(),
+                bid: Some(8),
+                ask: Some(10),
+                last_price: Some(10),
+                volume_24h: 2,
+                open_bids: 1,
+                open_asks: 1,
+            }
+        );
+        assert_eq!((overview[1].bid, overview[1].last_price), (Some(5), None));
+
+        // a day later the volume is gone, the last price is not
+        let later = exchange
+            .overview(now + VOLUME_WINDOW_MILLIS + 1_000)
+            .unwrap();
+        assert_eq!((later[0].last_price, later[0].volume_24h), (Some(10), 0));
+    }
+}
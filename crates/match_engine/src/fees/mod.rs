@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::accounts::AccountId;
+use crate::error;
+use crate::key::{self, Key};
+use crate::order::OrderType;
+use crate::symbol::Symbol;
+use crate::trade::{LoggedTrade, Trade};
+
+// Maker and taker rates of a pair in basis points. The taker is the trade's
+// aggressor, the resting side makes. A negative maker rate is a rebate, paid
+// out of REVENUE when the trade settles, see accounts::settle. Pairs without
+// a schedule trade free.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub symbol: Symbol,
+    pub maker_bps: i32,
+    pub taker_bps: i32,
+}
+
+impl FeeSchedule {
+    pub fn new(symbol: Symbol, maker_bps: i32, taker_bps: i32) -> anyhow::Result<Self> {
+        for (name, bps, least) in [("maker", maker_bps, -10_000), ("taker", taker_bps, 0)] {
+            if !(least..=10_000).contains(&bps) {
+                return Err(error::validation(format!(
+                    "Invalid {} fee {} for {}, expected {} to 10000 basis points",
+                    name, bps, symbol, least
+                )));
+            }
+        }
+        if -maker_bps > taker_bps {
+            return Err(error::validation(format!(
+                "Maker rebate of {} basis points for {} exceeds the taker fee of {}",
+                -maker_bps, symbol, taker_bps
+            )));
+        }
+        Ok(Self {
+            symbol,
+            maker_bps,
+            taker_bps,
+        })
+    }
+
+    // Fees are rounded toward zero, a fill too small to owe a whole unit is
+    // free and one too small to earn a whole rebate earns none.
+    pub fn charge(&self, trade: &mut Trade) {
+        let (buy_bps, sell_bps) = match trade.aggressor {
+            OrderType::Buy => (self.taker_bps, self.maker_bps),
+            OrderType::Sell => (self.maker_bps, self.taker_bps),
+        };
+        let quantity = trade.quantity as i64;
+        trade.buy_fee = quantity * buy_bps as i64 / 10_000;
+        trade.sell_fee = trade.price as i64 * quantity * sell_bps as i64 / 10_000;
+    }
+}
+
+pub fn get(db: &Database, pair: &str) -> anyhow::Result<Option<FeeSchedule>> {
+    Key::fees(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+// Sets or replaces the schedule, trades already executed keep their fees.
+pub fn set(db: &Database, schedule: &FeeSchedule) -> anyhow::Result<()> {
+    Ok(Key::fees(schedule.symbol.as_str()).set(db, schedule)?)
+}
+
+pub fn remove(db: &Database, pair: &str) -> anyhow::Result<()> {
+    Ok(Key::fees(pair).remove(db)?)
+}
+
+pub fn schedules(db: &Database) -> anyhow::Result<Vec<FeeSchedule>> {
+    db.entries_in(key::FEES)?
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+// Maker rebates an account accrued per asset, in the asset its side of each
+// trade received.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebateReport {
+    pub account: AccountId,
+    // trades that paid it a rebate
+    pub trades: usize,
+    pub rebates: BTreeMap<String, i64>,
+}
+
+// Over the account's trades, e.g. trade::trades_of_account on every shard.
+pub fn rebates(account: AccountId, trades: &[LoggedTrade]) -> anyhow::Result<RebateReport> {
+    let mut report = RebateReport {
+        account,
+        ..RebateReport::default()
+    };
+    for logged in trades {
+        let pair = Symbol::parse(&logged.pair)?;
+        let trade = &logged.trade;
+        let mut rebated = false;
+        for (side, fee, asset) in [
+            (trade.buy_account, trade.buy_fee, pair.base()),
+            (trade.sell_account, trade.sell_fee, pair.quote()),
+        ] {
+            if side == Some(account) && fee < 0 {
+                *report.rebates.entry(asset.to_string()).or_default() -= fee;
+                rebated = true;
+            }
+        }
+        report.trades += rebated as usize;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::{self, REVENUE};
+    use crate::order::Order;
+    use crate::order_book::OrderBook;
+    use crate::trade;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn trades_pay_maker_and_taker_fees_into_revenue() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        assert!(FeeSchedule::new(pair.clone(), 10, -1).is_err());
+        let schedule = FeeSchedule::new(pair.clone(), 10, 20).unwrap();
+        set(&db.lock().unwrap(), &schedule).unwrap();
+        assert_eq!(schedules(&db.lock().unwrap()).unwrap(), vec![schedule]);
+        let (alice, bob) = (1, 2);
+        accounts::deposit(&db.lock().unwrap(), alice, "BTC", 10_000).unwrap();
+        accounts::deposit(&db.lock().unwrap(), bob, "USD", 1_000_000).unwrap();
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(pair);
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        let mut ask = Order::new(10_000, 100, OrderType::Sell);
+        ask.update_account(Some(alice));
+        order_book.append_sell_order(ask).unwrap();
+        let mut bid = Order::new(10_000, 100, OrderType::Buy);
+        bid.update_account(Some(bob));
+        let ack = order_book.append_buy_order(bid).unwrap();
+
+        // bob takes and pays 20 bps of the BTC bought, alice makes and pays
+        // 10 bps of the USD
+        assert_eq!(
+            (ack.fills()[0].buy_fee, ack.fills()[0].sell_fee),
+            (20, 1_000)
+        );
+        let db = db.lock().unwrap();
+        let logged = trade::trades(&db, None).unwrap();
+        assert_eq!(logged[0].trade, ack.fills()[0]);
+        let balance = |id, asset| accounts::get(&db, id).unwrap().unwrap().balance(asset);
+        assert_eq!(balance(bob, "BTC").available, 9_980);
+        assert_eq!(balance(alice, "USD").available, 999_000);
+        assert_eq!(balance(REVENUE, "BTC").available, 20);
+        assert_eq!(balance(REVENUE, "USD").available, 1_000);
+    }
+
+    #[test]
+    fn maker_rebates_are_paid_out_of_revenue() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        assert!(FeeSchedule::new(pair.clone(), -30, 20).is_err());
+        let schedule = FeeSchedule::new(pair.clone(), -5, 20).unwrap();
+        set(&db.lock().unwrap(), &schedule).unwrap();
+        let (alice, bob) = (1, 2);
+        accounts::deposit(&db.lock().unwrap(), alice, "BTC", 10_000).unwrap();
+        accounts::deposit(&db.lock().unwrap(), bob, "USD", 1_000_000).unwrap();
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(pair);
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        let mut ask = Order::new(10_000, 100, OrderType::Sell);
+        ask.update_account(Some(alice));
+        order_book.append_sell_order(ask).unwrap();
+        let mut bid = Order::new(10_000, 100, OrderType::Buy);
+        bid.update_account(Some(bob));
+        let ack = order_book.append_buy_order(bid).unwrap();
+
+        // alice makes and earns 5 bps of the USD on top of the price
+        assert_eq!(
+            (ack.fills()[0].buy_fee, ack.fills()[0].sell_fee),
+            (20, -500)
+        );
+        let db = db.lock().unwrap();
+        let balance = |id, asset| accounts::get(&db, id).unwrap().unwrap().balance(asset);
+        assert_eq!(balance(alice, "USD").available, 1_000_500);
+        assert_eq!(balance(REVENUE, "USD").available, -500);
+        assert_eq!(balance(REVENUE, "BTC").available, 20);
+
+        let report = |id| rebates(id, &trade::trades_of_account(&db, id).unwrap()).unwrap();
+        assert_eq!(
+            report(alice),
+            RebateReport {
+                account: alice,
+                trades: 1,
+                rebates: BTreeMap::from([("USD".to_string(), 500)]),
+            }
+        );
+        assert!(report(bob).rebates.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use db::Database;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::accounts::{self, Account};
+use crate::key::Key;
+use crate::order_book::{journal, Item};
+use crate::shard::Shards;
+use crate::symbol::Symbol;
+
+/// Full live state of an engine instance, handed over from a draining instance to its successor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateExport {
+    pub books: BTreeMap<String, Item>,
+    // balances with what open orders hold of them
+    pub accounts: Vec<Account>,
+    // the highest journal sequence of the books, the successor logs its
+    // commands past it so they are not taken as folded into a snapshot
+    pub sequence: u64,
+    pub state_hash: String,
+}
+
+impl StateExport {
+    pub fn new(books: BTreeMap<String, Item>, accounts: Vec<Account>) -> Self {
+        let sequence = books
+            .values()
+            .filter_map(|item| item.sequence)
+            .max()
+            .unwrap_or(0);
+        let state_hash = state_hash(&books, &accounts, sequence);
+        Self {
+            books,
+            accounts,
+            sequence,
+            state_hash,
+        }
+    }
+
+    pub fn verify(&self) -> bool {
+        state_hash(&self.books, &self.accounts, self.sequence) == self.state_hash
+    }
+}
+
+pub fn state_hash(books: &BTreeMap<String, Item>, accounts: &[Account], sequence: u64) -> String {
+    let bytes = serde_json::to_vec(&(books, accounts, sequence)).expect("Failed to stringify");
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn books(db: &Database) -> anyhow::Result<BTreeMap<String, Item>> {
+    let mut books = BTreeMap::new();
+    for pair in db.keys()? {
+        if let Some(item) = journal::current(db, &pair)? {
+            books.insert(pair, item);
+        }
+    }
+    Ok(books)
+}
+
+pub fn export_state(db: &Database) -> anyhow::Result<StateExport> {
+    Ok(StateExport::new(books(db)?, accounts::accounts(db)?))
+}
+
+// Like export_state, with the books of every shard.
+pub fn export_shards(shards: &Shards) -> anyhow::Result<StateExport> {
+    let mut all = BTreeMap::new();
+    for shard in shards.all() {
+        all.extend(books(&shard.lock().expect("could not get db lock"))?);
+    }
+    let accounts = accounts::accounts(&shards.home().lock().expect("could not get db lock"))?;
+    Ok(StateExport::new(all, accounts))
+}
+
+pub fn import_state(db: &Database, state: &StateExport) -> anyhow::Result<()> {
+    ensure_verified(state)?;
+    write_books(db, state.books.iter(), state.sequence)?;
+    write_accounts(db, state)?;
+    ensure_imported(&export_state(db)?, state)
+}
+
+// Like import_state, each book goes to the shard of its pair.
+pub fn import_shards(shards: &Shards, state: &StateExport) -> anyhow::Result<()> {
+    ensure_verified(state)?;
+    for (index, shard) in shards.all().iter().enumerate() {
+        let mut books = Vec::new();
+        for (pair, item) in &state.books {
+            if shards.shard_of(&Symbol::parse(pair)?) == index {
+                books.push((pair, item));
+            }
+        }
+        let shard = shard.lock().expect("could not get db lock");
+        write_books(&shard, books.into_iter(), state.sequence)?;
+    }
+    write_accounts(&shards.home().lock().expect("could not get db lock"), state)?;
+    ensure_imported(&export_shards(shards)?, state)
+}
+
+fn ensure_verified(state: &StateExport) -> anyhow::Result<()> {
+    match state.verify() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "State hash mismatch, export is corrupt or was modified in transit"
+        )),
+    }
+}
+
+// Replaces the books in one batch, books the state does not have are
+// removed. Commands logged after the import number past the imported
+// journals.
+fn write_books<'a>(
+    db: &Database,
+    books: impl Iterator<Item = (&'a String, &'a Item)>,
+    sequence: u64,
+) -> anyhow::Result<()> {
+    let books = books.collect::<BTreeMap<_, _>>();
+    let mut batch = db.batch();
+    for pair in db.keys()? {
+        if !books.contains_key(&pair) {
+            Key::book(&pair).remove_in_batch(&mut batch);
+        }
+    }
+    for (pair, item) in books {
+        Key::book(pair).set_in_batch(&mut batch, item)?;
+    }
+    batch.commit()?;
+    Ok(db.advance_ids(sequence)?)
+}
+
+fn write_accounts(db: &Database, state: &StateExport) -> anyhow::Result<()> {
+    for account in &state.accounts {
+        Key::account(account.id).set(db, account)?;
+    }
+    Ok(())
+}
+
+fn ensure_imported(imported: &StateExport, state: &StateExport) -> anyhow::Result<()> {
+    if imported.state_hash != state.state_hash {
+        return Err(anyhow!(
+            "Imported state hash {} does not match exported state hash {}",
+            imported.state_hash,
+            state.state_hash
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_log::{self, Command};
+    use crate::order::{Order, OrderType};
+    use crate::shard::Strategy;
+    use test_utils::{shared_temp_db, temp_db};
+
+    fn seed(db: &Database) {
+        db.set(
+            "BTC/USD",
+            &Item {
+                active_orders: vec![
+                    Order::new(1, 10, OrderType::Buy),
+                    Order::new(2, 20, OrderType::Sell),
+                ],
+                fulfilled_orders: vec![],
+                sequence: Some(100),
+            },
+        )
+        .unwrap();
+        accounts::deposit(db, 7, "USD", 100).unwrap();
+    }
+
+    #[test]
+    fn export_import_roundtrip() {
+        let source = temp_db();
+        let target = temp_db();
+        seed(&source);
+
+        let stale = Item {
+            active_orders: vec![Order::new(1, 5, OrderType::Buy)],
+            fulfilled_orders: vec![],
+            sequence: None,
+        };
+        target.set("ETH/USD", &stale).unwrap();
+
+        let exported = export_state(&source).unwrap();
+        import_state(&target, &exported).unwrap();
+
+        assert_eq!(export_state(&target).unwrap(), exported);
+        assert_eq!(target.keys().unwrap(), vec!["BTC/USD".to_string()]);
+        assert_eq!(
+            accounts::get(&target, 7)
+                .unwrap()
+                .unwrap()
+                .balance("USD")
+                .available,
+            100
+        );
+        let logged = command_log::append(
+            &target,
+            "BTC/USD",
+            &Command::Place(Order::new(1, 10, OrderType::Buy)),
+        )
+        .unwrap();
+        assert!(logged.sequence > exported.sequence);
+    }
+
+    #[test]
+    fn import_rejects_tampered_state() {
+        let source = temp_db();
+        seed(&source);
+
+        let mut exported = export_state(&source).unwrap();
+        exported
+            .books
+            .get_mut("BTC/USD")
+            .unwrap()
+            .active_orders
+            .pop();
+
+        assert!(import_state(&source, &exported).is_err());
+
+        let mut exported = export_state(&source).unwrap();
+        exported.accounts[0]
+            .balances
+            .get_mut("USD")
+            .unwrap()
+            .available += 1;
+        assert!(import_state(&source, &exported).is_err());
+    }
+
+    #[test]
+    fn sharded_books_are_exported_and_imported_to_their_shard() {
+        let sharded = || {
+            let strategy = Strategy::parse("prefix:BTC=1").unwrap();
+            Shards::new(shared_temp_db(), vec![shared_temp_db()], strategy).unwrap()
+        };
+        let (source, target) = (sharded(), sharded());
+        seed(&source.all()[1].lock().unwrap());
+        let eth = Item {
+            active_orders: vec![Order::new(1, 5, OrderType::Buy)],
+            fulfilled_orders: vec![],
+            sequence: None,
+        };
+        source.home().lock().unwrap().set("ETH/USD", &eth).unwrap();
+
+        let exported = export_shards(&source).unwrap();
+        assert_eq!(exported.books.len(), 2);
+        import_shards(&target, &exported).unwrap();
+
+        assert_eq!(target.pairs().unwrap().len(), 2);
+        assert_eq!(
+            target.all()[1].lock().unwrap().keys().unwrap(),
+            vec!["BTC/USD".to_string()]
+        );
+        assert_eq!(export_shards(&target).unwrap(), exported);
+    }
+}
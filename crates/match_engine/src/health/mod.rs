@@ -0,0 +1,42 @@
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::quarantine::{self, Quarantined};
+use crate::supervision::{self, Halt};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub halted: Vec<Halt>,
+    pub quarantined: Vec<Quarantined>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.halted.is_empty() && self.quarantined.is_empty()
+    }
+}
+
+pub fn check(db: &Database) -> anyhow::Result<HealthReport> {
+    Ok(HealthReport {
+        halted: supervision::halted_pairs(db)?,
+        quarantined: quarantine::quarantined(db)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    #[test]
+    fn report_is_unhealthy_with_quarantined_pairs() {
+        let db = temp_db();
+        assert!(check(&db).unwrap().is_healthy());
+
+        quarantine::quarantine(&db, "BTC/USD", "{", "EOF while parsing").unwrap();
+        let report = check(&db).unwrap();
+
+        assert!(!report.is_healthy());
+        assert_eq!(report.quarantined[0].pair, "BTC/USD");
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// A set that answers "definitely not" or "maybe". Positions come from a
+// SHA-256 of the item so a persisted filter reads the same in every build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    pub items: usize,
+}
+
+impl BloomFilter {
+    // Sized so `capacity` items give roughly `false_positives` of maybes for
+    // items never inserted, more items raise it.
+    pub fn new(capacity: usize, false_positives: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity.max(1) as f64) * false_positives.ln() / (ln2 * ln2)).ceil();
+        let words = (bits as usize).div_ceil(64).max(1);
+        let hashes = ((words * 64) as f64 / capacity.max(1) as f64 * ln2).round();
+        Self {
+            bits: vec![0; words],
+            hashes: (hashes as u32).clamp(1, 16),
+            items: 0,
+        }
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        for position in self.positions(item) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+        self.items += 1;
+    }
+
+    pub fn may_contain(&self, item: &str) -> bool {
+        self.positions(item)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+
+    // Double hashing, h1 + i * h2, over the first two words of the digest.
+    fn positions(&self, item: &str) -> impl Iterator<Item = usize> {
+        let digest = Sha256::digest(item.as_bytes());
+        let word = |i: usize| u64::from_le_bytes(digest[i..i + 8].try_into().expect("8 bytes"));
+        let (h1, h2) = (word(0), word(8) | 1);
+        let len = (self.bits.len() * 64) as u64;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_items_are_never_missed() {
+        let mut filter = BloomFilter::new(1_000, 0.01);
+        for i in 0..1_000 {
+            filter.insert(&format!("alice:{i}"));
+        }
+        assert!((0..1_000).all(|i| filter.may_contain(&format!("alice:{i}"))));
+        let maybes = (0..10_000)
+            .filter(|i| filter.may_contain(&format!("bob:{i}")))
+            .count();
+        assert!(maybes < 300, "{maybes} false positives");
+
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(serde_json::from_str::<BloomFilter>(&json).unwrap(), filter);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use db::Database;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub mod bloom;
+
+use crate::key::{self, Key};
+use crate::telemetry;
+use bloom::BloomFilter;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// keys per filter generation before false positives climb past 1%
+pub const FILTER_CAPACITY: usize = 10_000;
+pub const PERSIST_EVERY: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub key: String,
+    pub timestamp: u64,
+    pub response: String,
+}
+
+pub fn remember<T>(db: &Database, key: &str, response: &T) -> anyhow::Result<()>
+where
+    T: Serialize,
+{
+    let cached = CachedResponse {
+        key: key.to_string(),
+        timestamp: telemetry::now_millis(),
+        response: serde_json::to_string(response)?,
+    };
+    Key::idempotency(key).set(db, &cached)?;
+    Ok(())
+}
+
+// The original response for a replayed key; expired entries are dropped on read.
+pub fn replay<T>(db: &Database, key: &str, ttl: Duration) -> anyhow::Result<Option<T>>
+where
+    T: DeserializeOwned,
+{
+    let cached: CachedResponse = match Key::idempotency(key).get(db)? {
+        Some(json) => serde_json::from_str(&json)?,
+        None => return Ok(None),
+    };
+    if cached.timestamp + ttl.as_millis() as u64 <= telemetry::now_millis() {
+        Key::idempotency(key).remove(db)?;
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&cached.response)?))
+}
+
+fn scope_of(key: &str) -> &str {
+    key.split_once(':').map_or(key, |(scope, _)| scope)
+}
+
+// Keys of one scope remembered within the last one to two TTLs, as two
+// generations that rotate once the current one is a TTL old. A key is only
+// dropped with its generation after it expired.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeFilter {
+    pub scope: String,
+    pub started_at: u64,
+    pub current: BloomFilter,
+    pub previous: BloomFilter,
+}
+
+impl ScopeFilter {
+    fn new(scope: &str, now: u64) -> Self {
+        Self {
+            scope: scope.to_string(),
+            started_at: now,
+            current: BloomFilter::new(FILTER_CAPACITY, 0.01),
+            previous: BloomFilter::new(FILTER_CAPACITY, 0.01),
+        }
+    }
+
+    fn rotate(&mut self, now: u64, ttl: Duration) -> bool {
+        let ttl = ttl.as_millis() as u64;
+        if now < self.started_at + ttl {
+            return false;
+        }
+        let current = std::mem::replace(&mut self.current, BloomFilter::new(FILTER_CAPACITY, 0.01));
+        self.previous = match now < self.started_at + 2 * ttl {
+            true => current,
+            false => BloomFilter::new(FILTER_CAPACITY, 0.01),
+        };
+        self.started_at = now;
+        true
+    }
+
+    pub fn may_contain(&self, key: &str) -> bool {
+        self.current.may_contain(key) || self.previous.may_contain(key)
+    }
+}
+
+// Fronts replay and remember with a bloom filter per scope, e.g. per actor
+// or account, so a key never seen is answered without reading the index.
+// Filters are persisted every PERSIST_EVERY remembered keys. Keys remembered
+// since are kept in the pending tree and folded back in when a filter loads.
+pub struct RecentKeys {
+    ttl: Duration,
+    persist_every: usize,
+    filters: BTreeMap<String, ScopeFilter>,
+    // pending entries and filter changes not yet persisted
+    pending: Vec<String>,
+    dirty: bool,
+}
+
+impl RecentKeys {
+    pub fn new(ttl: Duration, persist_every: usize) -> Self {
+        Self {
+            ttl,
+            persist_every,
+            filters: BTreeMap::new(),
+            pending: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    fn filter(&mut self, db: &Database, scope: &str) -> anyhow::Result<&mut ScopeFilter> {
+        let now = telemetry::now_millis();
+        if !self.filters.contains_key(scope) {
+            let mut filter = match Key::idempotency_filter(scope).get(db)? {
+                Some(json) => serde_json::from_str(&json)?,
+                // keys remembered before the scope had a filter
+                None => {
+                    let mut filter = ScopeFilter::new(scope, now);
+                    for (key, _) in db.entries_in_range(
+                        key::IDEMPOTENCY,
+                        &format!("{}:", scope),
+                        &format!("{};", scope),
+                    )? {
+                        filter.current.insert(&key);
+                        self.dirty = true;
+                    }
+                    filter
+                }
+            };
+            for (id, key) in db.entries_in_range(
+                key::IDEMPOTENCY_PENDING,
+                &format!("{}/", scope),
+                &format!("{}/~", scope),
+            )? {
+                let key: String = serde_json::from_str(&key)?;
+                if scope_of(&key) == scope {
+                    filter.current.insert(&key);
+                    self.pending.push(id);
+                }
+            }
+            self.filters.insert(scope.to_string(), filter);
+        }
+        let filter = self.filters.get_mut(scope).expect("loaded above");
+        self.dirty |= filter.rotate(now, self.ttl);
+        Ok(filter)
+    }
+
+    pub fn replay<T>(&mut self, db: &Database, key: &str) -> anyhow::Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        if !self.filter(db, scope_of(key))?.may_contain(key) {
+            return Ok(None);
+        }
+        replay(db, key, self.ttl)
+    }
+
+    // The response and its pending entry are written together, a key is
+    // never in the index without being in its filter once loaded.
+    pub fn remember<T>(&mut self, db: &Database, key: &str, response: &T) -> anyhow::Result<()>
+    where
+        T: Serialize,
+    {
+        let scope = scope_of(key);
+        self.filter(db, scope)?.current.insert(key);
+        let cached = CachedResponse {
+            key: key.to_string(),
+            timestamp: telemetry::now_millis(),
+            response: serde_json::to_string(response)?,
+        };
+        let pending = Key::idempotency_pending(scope, cached.timestamp, db.generate_id()?);
+        let mut batch = db.batch();
+        Key::idempotency(key).set_in_batch(&mut batch, &cached)?;
+        pending.set_in_batch(&mut batch, &key)?;
+        batch.commit()?;
+        self.pending.push(pending.id().to_string());
+        if self.pending.len() >= self.persist_every {
+            self.persist(db)?;
+        }
+        Ok(())
+    }
+
+    // Writes the loaded filters and drops the pending entries they now cover.
+    pub fn persist(&mut self, db: &Database) -> anyhow::Result<()> {
+        if self.pending.is_empty() && !self.dirty {
+            return Ok(());
+        }
+        let mut batch = db.batch();
+        for filter in self.filters.values() {
+            Key::idempotency_filter(&filter.scope).set_in_batch(&mut batch, filter)?;
+        }
+        for id in self.pending.drain(..) {
+            batch.remove_in(key::IDEMPOTENCY_PENDING, &id);
+        }
+        batch.commit()?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{self, Clock};
+    use crate::order::{Order, OrderType};
+    use std::sync::Arc;
+    use test_utils::temp_db;
+
+    #[test]
+    fn replays_until_ttl_expires() {
+        let db = temp_db();
+        let order = Order::new(1, 10, OrderType::Buy);
+
+        assert_eq!(replay::<Order>(&db, "alice:1", DEFAULT_TTL).unwrap(), None);
+        remember(&db, "alice:1", &order).unwrap();
+        assert_eq!(
+            replay::<Order>(&db, "alice:1", DEFAULT_TTL).unwrap(),
+            Some(order)
+        );
+        assert_eq!(replay::<Order>(&db, "bob:1", DEFAULT_TTL).unwrap(), None);
+
+        assert_eq!(
+            replay::<Order>(&db, "alice:1", Duration::ZERO).unwrap(),
+            None
+        );
+        assert_eq!(replay::<Order>(&db, "alice:1", DEFAULT_TTL).unwrap(), None);
+    }
+
+    #[test]
+    fn unseen_keys_are_answered_by_the_filter() {
+        let db = temp_db();
+        let mut recent = RecentKeys::new(DEFAULT_TTL, 2);
+        recent.remember(&db, "alice:1", &1).unwrap();
+        assert_eq!(recent.replay::<i32>(&db, "alice:1").unwrap(), Some(1));
+        // written behind the filter's back, only the index knows it
+        remember(&db, "alice:2", &2).unwrap();
+        assert_eq!(recent.replay::<i32>(&db, "alice:2").unwrap(), None);
+        // unless the scope had no filter yet, e.g. after an upgrade
+        remember(&db, "carol:1", &4).unwrap();
+        assert_eq!(recent.replay::<i32>(&db, "carol:1").unwrap(), Some(4));
+
+        // pending keys are folded in before the filter was ever persisted
+        let mut reloaded = RecentKeys::new(DEFAULT_TTL, 2);
+        assert_eq!(reloaded.replay::<i32>(&db, "alice:1").unwrap(), Some(1));
+        recent.remember(&db, "bob:1", &3).unwrap();
+        assert!(db.entries_in(key::IDEMPOTENCY_PENDING).unwrap().is_empty());
+        let mut reloaded = RecentKeys::new(DEFAULT_TTL, 2);
+        assert_eq!(reloaded.replay::<i32>(&db, "bob:1").unwrap(), Some(3));
+        assert_eq!(reloaded.replay::<i32>(&db, "alice:1").unwrap(), Some(1));
+    }
+
+    #[test]
+    fn expires_when_the_clock_is_fast_forwarded() {
+        let db = temp_db();
+        let clock = Arc::new(Clock::manual(1_700_000_000_000));
+
+        clock::with_clock(clock.clone(), || {
+            remember(&db, "alice:1", &1).unwrap();
+            clock.advance(DEFAULT_TTL - Duration::from_millis(1));
+            assert_eq!(replay::<i32>(&db, "alice:1", DEFAULT_TTL).unwrap(), Some(1));
+
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(replay::<i32>(&db, "alice:1", DEFAULT_TTL).unwrap(), None);
+        });
+    }
+}
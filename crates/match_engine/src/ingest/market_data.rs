@@ -0,0 +1,167 @@
+use anyhow::anyhow;
+
+use crate::order::OrderType;
+
+// Public trade dumps that can be turned into order files for `ingest`.
+//
+// Binance (data.binance.vision) trades: id,price,qty,quote_qty,time,is_buyer_maker,is_best_match
+// Binance aggTrades: agg_id,price,qty,first_id,last_id,time,is_buyer_maker,is_best_match
+// Kraken time and sales: timestamp,price,volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Binance,
+    Kraken,
+}
+
+// Decimal prices and quantities are multiplied by these and rounded, e.g. a
+// price scale of 100 keeps cents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    pub price: u32,
+    pub quantity: u32,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> anyhow::Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "binance" => Ok(Self::Binance),
+            "kraken" => Ok(Self::Kraken),
+            _ => Err(anyhow!(
+                "Unknown format {}, expected binance or kraken",
+                name
+            )),
+        }
+    }
+}
+
+// Each trade becomes two order lines in the ingest CSV format: the maker's
+// order rests first and the taker's order crosses it, so replaying the file
+// reproduces the trade. Kraken dumps carry no side, the taker is inferred
+// with the tick rule (an uptick is a buy, an unchanged price keeps the last
+// direction). Header lines are skipped.
+pub fn convert(
+    format: Format,
+    pair: &str,
+    contents: &str,
+    scale: Scale,
+) -> anyhow::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut last: Option<(i32, OrderType)> = None;
+    for (index, line) in contents.lines().enumerate() {
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        if fields[0].is_empty() || fields[0].parse::<f64>().is_err() {
+            continue;
+        }
+        let trade = match (format, fields.len()) {
+            (Format::Binance, 7) => Ok((fields[1], fields[2], Some(fields[5]))),
+            (Format::Binance, 8) => Ok((fields[1], fields[2], Some(fields[6]))),
+            (Format::Kraken, 3) => Ok((fields[1], fields[2], None)),
+            _ => Err(anyhow!("unexpected column count {}", fields.len())),
+        };
+        let (price, quantity, taker) = trade
+            .and_then(|(price, quantity, buyer_maker)| {
+                let price = scaled(price, scale.price)?;
+                let quantity = scaled(quantity, scale.quantity)?;
+                let taker = match buyer_maker.map(str::to_lowercase).as_deref() {
+                    Some("true") => OrderType::Sell,
+                    Some("false") => OrderType::Buy,
+                    Some(flag) => return Err(anyhow!("invalid is_buyer_maker {}", flag)),
+                    None => match last {
+                        Some((last_price, _)) if price > last_price => OrderType::Buy,
+                        Some((last_price, _)) if price < last_price => OrderType::Sell,
+                        Some((_, taker)) => taker,
+                        None => OrderType::Buy,
+                    },
+                };
+                Ok((price, quantity, taker))
+            })
+            .map_err(|e| anyhow!("Invalid trade on line {}: {}", index + 1, e))?;
+        last = Some((price, taker));
+
+        let maker = match taker {
+            OrderType::Buy => "sell",
+            OrderType::Sell => "buy",
+        };
+        let taker = match taker {
+            OrderType::Buy => "buy",
+            OrderType::Sell => "sell",
+        };
+        lines.push(format!("{},{},{},{}", pair, maker, price, quantity));
+        lines.push(format!("{},{},{},{}", pair, taker, price, quantity));
+    }
+    Ok(lines)
+}
+
+fn scaled(value: &str, scale: u32) -> anyhow::Result<i32> {
+    let scaled = (value.parse::<f64>()? * scale as f64).round();
+    if scaled < 1.0 || scaled > i32::MAX as f64 {
+        return Err(anyhow!(
+            "{} does not fit a positive integer at scale {}",
+            value,
+            scale
+        ));
+    }
+    Ok(scaled as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::parse_line;
+
+    const SCALE: Scale = Scale {
+        price: 100,
+        quantity: 1000,
+    };
+
+    #[test]
+    fn binance_trades_become_maker_then_taker() {
+        let dump = "id,price,qty,quote_qty,time,is_buyer_maker,is_best_match\n\
+                    1,42000.10,0.015,630.0015,1700000000000,True,True\n\
+                    7,42000.50,0.002,11,12,1700000000001,false,true\n";
+
+        let lines = convert(Format::Binance, "btc/usd", dump, SCALE).unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                "btc/usd,buy,4200010,15",
+                "btc/usd,sell,4200010,15",
+                "btc/usd,sell,4200050,2",
+                "btc/usd,buy,4200050,2",
+            ]
+        );
+        assert!(lines.iter().all(|line| parse_line(line).is_ok()));
+    }
+
+    #[test]
+    fn kraken_sides_follow_the_tick_rule() {
+        let dump =
+            "1700000000,100.00,1\n1700000001,99.50,1\n1700000002,99.50,2\n1700000003,101,1\n";
+
+        let lines = convert(Format::Kraken, "eth/usd", dump, SCALE).unwrap();
+        let takers = lines
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|line| parse_line(line).unwrap().1.order_type)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            takers,
+            vec![
+                OrderType::Buy,
+                OrderType::Sell,
+                OrderType::Sell,
+                OrderType::Buy
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_trades_that_do_not_fit() {
+        assert!(convert(Format::Kraken, "eth/usd", "1700000000,0.001,1\n", SCALE).is_err());
+        assert!(convert(Format::Binance, "eth/usd", "1,100,1\n", SCALE).is_err());
+        assert!(Format::parse("coinbase").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use db::Database;
+use sha2::{Digest, Sha256};
+
+pub mod market_data;
+
+use crate::idempotency::{self, RecentKeys};
+use crate::order::{Order, OrderType};
+
+pub const RESULT_EXTENSION: &str = "result";
+pub const DONE_EXTENSION: &str = "done";
+
+// One order per line, either CSV "btc/usd,buy,10,3[,hidden]" or FIX-like
+// "55=BTC/USD|54=1|44=10|38=3" (55 symbol, 54 side 1=buy 2=sell, 44 price,
+// 38 quantity). Returns the raw pair with the order.
+pub fn parse_line(line: &str) -> anyhow::Result<(String, Order)> {
+    if line.contains('=') {
+        return parse_fix(line);
+    }
+    let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+    let (pair, side, price, quantity) = match fields[..] {
+        [pair, side, price, quantity] | [pair, side, price, quantity, _] => {
+            (pair, side, price, quantity)
+        }
+        _ => {
+            return Err(anyhow!(
+                "Invalid line {}, expected pair,side,price,quantity[,hidden]",
+                line
+            ))
+        }
+    };
+    let side = match side {
+        "buy" => OrderType::Buy,
+        "sell" => OrderType::Sell,
+        _ => return Err(anyhow!("Invalid side {}, expected buy or sell", side)),
+    };
+    let mut order = Order::new(quantity.parse()?, price.parse()?, side);
+    match fields.get(4) {
+        Some(&"hidden") => order.update_hidden(true),
+        Some(flag) => return Err(anyhow!("Invalid flag {}, expected hidden", flag)),
+        None => {}
+    }
+    Ok((pair.to_string(), order))
+}
+
+fn parse_fix(line: &str) -> anyhow::Result<(String, Order)> {
+    let (mut pair, mut side, mut price, mut quantity) = (None, None, None, None);
+    for field in line.split(['|', '\x01']).filter(|f| !f.trim().is_empty()) {
+        let (tag, value) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid field {}, expected tag=value", field))?;
+        match tag.trim() {
+            "55" => pair = Some(value.trim().to_string()),
+            "54" => {
+                side = Some(match value.trim() {
+                    "1" => OrderType::Buy,
+                    "2" => OrderType::Sell,
+                    _ => return Err(anyhow!("Invalid side {}, expected 1 or 2", value)),
+                })
+            }
+            "44" => price = Some(value.trim().parse()?),
+            "38" => quantity = Some(value.trim().parse()?),
+            _ => {}
+        }
+    }
+    match (pair, side, price, quantity) {
+        (Some(pair), Some(side), Some(price), Some(quantity)) => {
+            Ok((pair, Order::new(quantity, price, side)))
+        }
+        _ => Err(anyhow!(
+            "Invalid line {}, tags 55, 54, 44 and 38 are required",
+            line
+        )),
+    }
+}
+
+// Order files waiting in dir, oldest name first.
+pub fn pending(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<PathBuf>>>()?
+        .into_iter()
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension().is_some_and(|e| e == "csv" || e == "fix"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+// Submits every order in path and writes <path>.result with a
+// "line,accepted,<id>" or "line,rejected,<reason>" row per order, then renames
+// the file to <path>.done. Each line is remembered under an idempotency key
+// of the file's name and contents, so a file picked up again after a crash
+// does not place its orders twice while a reused name with new orders does.
+pub fn process_file<F>(
+    db: &Arc<Mutex<Database>>,
+    path: &Path,
+    mut submit: F,
+) -> anyhow::Result<PathBuf>
+where
+    F: FnMut(&str, Order) -> anyhow::Result<Order>,
+{
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid order file {}", path.display()))?
+        .to_string_lossy();
+    let contents = fs::read_to_string(path)?;
+    let digest = format!("{:x}", Sha256::digest(&contents));
+    let mut results = Vec::new();
+    let mut recent = RecentKeys::new(idempotency::DEFAULT_TTL, idempotency::PERSIST_EVERY);
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("pair,") {
+            continue;
+        }
+        let number = index + 1;
+        let key = format!("ingest:{}:{}:{}", name, digest, number);
+        let replayed = recent.replay::<Order>(&db.lock().expect("could not get db lock"), &key)?;
+        let result = match replayed {
+            Some(order) => Ok(order),
+            None => parse_line(line).and_then(|(pair, order)| submit(&pair, order)),
+        };
+        match result {
+            Ok(order) => {
+                recent.remember(&db.lock().expect("could not get db lock"), &key, &order)?;
+                results.push(format!("{},accepted,{}", number, order.id));
+            }
+            Err(e) => results.push(format!("{},rejected,{}", number, e)),
+        }
+    }
+
+    recent.persist(&db.lock().expect("could not get db lock"))?;
+
+    let result_path = with_suffix(path, RESULT_EXTENSION);
+    fs::write(&result_path, results.join("\n") + "\n")?;
+    fs::rename(path, with_suffix(path, DONE_EXTENSION))?;
+    Ok(result_path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_book::OrderBook;
+    use crate::symbol::Symbol;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn parses_csv_and_fix_lines() {
+        let (pair, order) = parse_line("btc/usd, sell, 10, 3, hidden").unwrap();
+        assert_eq!(pair, "btc/usd");
+        assert_eq!(
+            (order.order_type, order.price, order.quantity, order.hidden),
+            (OrderType::Sell, 10, 3, true)
+        );
+
+        let (pair, order) = parse_line("55=ETH/USD|54=1|44=7|38=2|").unwrap();
+        assert_eq!(pair, "ETH/USD");
+        assert_eq!(
+            (order.order_type, order.price, order.quantity),
+            (OrderType::Buy, 7, 2)
+        );
+
+        assert!(parse_line("btc/usd,hold,10,3").is_err());
+        assert!(parse_line("55=ETH/USD|54=1|44=7").is_err());
+    }
+
+    #[test]
+    fn processes_a_file_once_and_reports_each_line() {
+        let dir = Path::new("mock_ingest");
+        fs::create_dir_all(dir).expect("could not create mock_ingest");
+        fs::write(
+            dir.join("batch.csv"),
+            "pair,side,price,quantity\nbtc/usd,buy,10,1\nbtc/usd,hold,10,1\n55=BTC/USD|54=2|44=10|38=1\n",
+        )
+        .unwrap();
+        let db = shared_temp_db();
+        let submit = |pair: &str, order: Order| {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_pair(Symbol::parse(pair)?);
+            order_book_builder.set_db(db.clone());
+            let mut order_book = order_book_builder.build().unwrap();
+            order_book.load()?;
+            match order.order_type {
+                OrderType::Buy => order_book.append_buy_order(order)?,
+                OrderType::Sell => order_book.append_sell_order(order)?,
+            };
+            Ok(order)
+        };
+
+        let pending_files = pending(dir).unwrap();
+        assert_eq!(pending_files, vec![dir.join("batch.csv")]);
+        let result_path = process_file(&db, &pending_files[0], submit).unwrap();
+
+        let results = fs::read_to_string(&result_path).unwrap();
+        let rows = results.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].starts_with("2,accepted,"));
+        assert!(rows[1].starts_with("3,rejected,"));
+        assert!(rows[2].starts_with("4,accepted,"));
+        assert!(pending(dir).unwrap().is_empty());
+        assert!(dir.join("batch.csv.done").exists());
+
+        fs::remove_dir_all(dir).expect("could not delete mock_ingest");
+    }
+}
@@ -0,0 +1,143 @@
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::key::{self, Key};
+use crate::order::{Order, OrderKind};
+use crate::symbol::Symbol;
+
+// Trading rules of a pair. Pairs without an instrument accept any positive
+// price and quantity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instrument {
+    pub symbol: Symbol,
+    pub base: String,
+    pub quote: String,
+    // prices are multiples of the tick size
+    pub tick_size: i32,
+    pub min_quantity: i32,
+    // quantities are multiples of the lot size
+    pub lot_size: i32,
+}
+
+impl Instrument {
+    pub fn new(
+        symbol: Symbol,
+        tick_size: i32,
+        min_quantity: i32,
+        lot_size: i32,
+    ) -> anyhow::Result<Self> {
+        for (name, value) in [
+            ("tick size", tick_size),
+            ("minimum quantity", min_quantity),
+            ("lot size", lot_size),
+        ] {
+            if value <= 0 {
+                return Err(error::validation(format!(
+                    "Invalid {} {} for {}, expected a positive number",
+                    name, value, symbol
+                )));
+            }
+        }
+        Ok(Self {
+            base: symbol.base().to_string(),
+            quote: symbol.quote().to_string(),
+            symbol,
+            tick_size,
+            min_quantity,
+            lot_size,
+        })
+    }
+
+    // Market orders are priced by the book and pegged ones by their
+    // reference, only the offset of a peg has to be on tick.
+    pub fn validate(&self, order: &Order) -> anyhow::Result<()> {
+        let price = match &order.peg {
+            Some(peg) => Some(peg.offset),
+            None if order.kind == OrderKind::Market => None,
+            None => Some(order.price),
+        };
+        if let Some(price) = price.filter(|price| price % self.tick_size != 0) {
+            return Err(error::validation(format!(
+                "Price {} is off tick for {}, prices move in steps of {}",
+                price, self.symbol, self.tick_size
+            )));
+        }
+        if order.quantity < self.min_quantity {
+            return Err(error::validation(format!(
+                "Quantity {} is below the minimum of {} for {}",
+                order.quantity, self.min_quantity, self.symbol
+            )));
+        }
+        if order.quantity % self.lot_size != 0 {
+            return Err(error::validation(format!(
+                "Quantity {} is not a whole number of lots of {} for {}",
+                order.quantity, self.lot_size, self.symbol
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn get(db: &Database, pair: &str) -> anyhow::Result<Option<Instrument>> {
+    Key::instrument(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+// Registers or replaces the instrument, resting orders are not revalidated.
+pub fn register(db: &Database, instrument: &Instrument) -> anyhow::Result<()> {
+    Ok(Key::instrument(instrument.symbol.as_str()).set(db, instrument)?)
+}
+
+pub fn remove(db: &Database, pair: &str) -> anyhow::Result<()> {
+    Ok(Key::instrument(pair).remove(db)?)
+}
+
+pub fn instruments(db: &Database) -> anyhow::Result<Vec<Instrument>> {
+    db.entries_in(key::INSTRUMENTS)?
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::order::OrderType;
+    use crate::order_book::OrderBook;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn orders_off_tick_or_lot_are_rejected() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        assert!(Instrument::new(pair.clone(), 0, 1, 1).is_err());
+        let instrument = Instrument::new(pair.clone(), 5, 10, 10).unwrap();
+        register(&db.lock().unwrap(), &instrument).unwrap();
+        assert_eq!(instruments(&db.lock().unwrap()).unwrap(), vec![instrument]);
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(pair);
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        for (quantity, price, reason) in [
+            (10, 12, "off tick"),
+            (5, 10, "below the minimum"),
+            (15, 10, "whole number of lots"),
+        ] {
+            let e = order_book
+                .append_buy_order(Order::new(quantity, price, OrderType::Buy))
+                .unwrap_err();
+            assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+            assert!(e.to_string().contains(reason), "{e}");
+        }
+        order_book
+            .append_buy_order(Order::new(20, 10, OrderType::Buy))
+            .unwrap();
+        assert_eq!(order_book.get_active_buy_orders().len(), 1);
+    }
+}
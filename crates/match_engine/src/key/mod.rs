@@ -0,0 +1,272 @@
+// Storage layout. Every record lives in a sled tree named after its kind,
+// the key inside the tree identifies the record:
+//
+// | tree                | key                                          | value                       |
+// |---------------------|----------------------------------------------|-----------------------------|
+// | (default)           | BASE/QUOTE                                   | order_book::Item            |
+// | halted              | BASE/QUOTE                                   | supervision::Halt           |
+// | corrupt             | BASE/QUOTE                                   | quarantine::Quarantined     |
+// | calendars           | BASE/QUOTE                                   | calendar::Schedule          |
+// | aliases             | ALIAS/QUOTE                                  | canonical pair              |
+// | instruments         | BASE/QUOTE                                   | instrument::Instrument      |
+// | fees                | BASE/QUOTE                                   | fees::FeeSchedule           |
+// | compactions         | BASE/QUOTE                                   | journal::Compaction         |
+// | roles               | actor                                        | access::UserRole            |
+// | secrets             | secret name                                  | secrets::StoredSecret       |
+// | replica             | role, applied_state_hash                     | replica metadata            |
+// | idempotency         | actor:key                                    | idempotency::CachedResponse |
+// | idempotency_filters | scope                                        | idempotency::ScopeFilter    |
+// | idempotency_pending | scope/{timestamp:020}-{sequence:020}         | idempotency key             |
+// | commands            | BASE/QUOTE/{sequence:020}                    | command_log::LoggedCommand  |
+// | audit               | {timestamp:020}-{sequence:020}               | audit::AuditEntry           |
+// | telemetry           | {timestamp:020}-{sequence:020}               | telemetry::TelemetrySample  |
+// | slow_path           | {timestamp:020}-{sequence:020}               | latency::SlowPathReport     |
+// | trades              | BASE/QUOTE/{timestamp:020}-{sequence:020}    | trade::LoggedTrade          |
+// | archives            | BASE/QUOTE/{timestamp:020}-{sequence:020}    | archive::ArchivedBatch      |
+// | order_trades        | order id/{timestamp:020}-{sequence:020}      | trade::TradeRef             |
+// | accounts            | {account:020}                                | accounts::Account           |
+// | settlements         | BASE/QUOTE                                   | last settled command        |
+// | account_trades      | {account:020}/{timestamp:020}-{sequence:020} | trade::TradeRef             |
+// | recorded            | BASE/QUOTE                                   | last command with trades    |
+//
+// Only books may be written to the default tree, export and quarantine scan
+// all of its keys as pairs. Numeric keys are zero padded so sled's byte
+// ordering is chronological. Pairs are stored in their canonical form,
+// canonical::migrate rewrites keys and records written before that.
+use db::{Batch, Database};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::accounts::AccountId;
+
+pub const BOOKS: &str = db::DEFAULT_TREE;
+pub const HALTED: &str = "halted";
+pub const CORRUPT: &str = "corrupt";
+pub const CALENDARS: &str = "calendars";
+pub const ALIASES: &str = "aliases";
+pub const INSTRUMENTS: &str = "instruments";
+pub const FEES: &str = "fees";
+pub const COMPACTIONS: &str = "compactions";
+pub const ROLES: &str = "roles";
+pub const SECRETS: &str = "secrets";
+pub const REPLICA: &str = "replica";
+pub const IDEMPOTENCY: &str = "idempotency";
+pub const IDEMPOTENCY_FILTERS: &str = "idempotency_filters";
+pub const IDEMPOTENCY_PENDING: &str = "idempotency_pending";
+pub const COMMANDS: &str = "commands";
+pub const AUDIT: &str = "audit";
+pub const TELEMETRY: &str = "telemetry";
+pub const SLOW_PATH: &str = "slow_path";
+pub const TRADES: &str = "trades";
+pub const ARCHIVES: &str = "archives";
+pub const ORDER_TRADES: &str = "order_trades";
+pub const ACCOUNTS: &str = "accounts";
+pub const SETTLEMENTS: &str = "settlements";
+pub const ACCOUNT_TRADES: &str = "account_trades";
+pub const RECORDED: &str = "recorded";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    tree: &'static str,
+    id: String,
+}
+
+impl Key {
+    fn new(tree: &'static str, id: String) -> Self {
+        Self { tree, id }
+    }
+
+    pub(crate) fn chronological(tree: &'static str, timestamp: u64, sequence: u64) -> Self {
+        Self::new(tree, format!("{:020}-{:020}", timestamp, sequence))
+    }
+
+    pub fn book(pair: &str) -> Self {
+        Self::new(BOOKS, pair.to_string())
+    }
+
+    pub fn halt(pair: &str) -> Self {
+        Self::new(HALTED, pair.to_string())
+    }
+
+    pub fn quarantined(pair: &str) -> Self {
+        Self::new(CORRUPT, pair.to_string())
+    }
+
+    pub fn calendar(pair: &str) -> Self {
+        Self::new(CALENDARS, pair.to_string())
+    }
+
+    pub fn alias(alias: &str) -> Self {
+        Self::new(ALIASES, alias.to_string())
+    }
+
+    pub fn instrument(pair: &str) -> Self {
+        Self::new(INSTRUMENTS, pair.to_string())
+    }
+
+    pub fn fees(pair: &str) -> Self {
+        Self::new(FEES, pair.to_string())
+    }
+
+    pub fn compaction(pair: &str) -> Self {
+        Self::new(COMPACTIONS, pair.to_string())
+    }
+
+    pub fn role(actor: &str) -> Self {
+        Self::new(ROLES, actor.to_string())
+    }
+
+    pub fn secret(name: &str) -> Self {
+        Self::new(SECRETS, name.to_string())
+    }
+
+    pub fn replica(name: &str) -> Self {
+        Self::new(REPLICA, name.to_string())
+    }
+
+    pub fn idempotency(key: &str) -> Self {
+        Self::new(IDEMPOTENCY, key.to_string())
+    }
+
+    // the part of an idempotency key before its first ':', e.g. the actor
+    pub fn idempotency_filter(scope: &str) -> Self {
+        Self::new(IDEMPOTENCY_FILTERS, scope.to_string())
+    }
+
+    // a key remembered since the scope's filter was last persisted
+    pub fn idempotency_pending(scope: &str, timestamp: u64, sequence: u64) -> Self {
+        Self::new(
+            IDEMPOTENCY_PENDING,
+            format!("{}/{:020}-{:020}", scope, timestamp, sequence),
+        )
+    }
+
+    // grouped by pair so a book's journal is one range scan
+    pub fn command(pair: &str, sequence: u64) -> Self {
+        Self::new(COMMANDS, format!("{}/{:020}", pair, sequence))
+    }
+
+    pub fn audit(timestamp: u64, sequence: u64) -> Self {
+        Self::chronological(AUDIT, timestamp, sequence)
+    }
+
+    pub fn telemetry(timestamp: u64, sequence: u64) -> Self {
+        Self::chronological(TELEMETRY, timestamp, sequence)
+    }
+
+    pub fn slow_path(timestamp: u64, sequence: u64) -> Self {
+        Self::chronological(SLOW_PATH, timestamp, sequence)
+    }
+
+    // Chronological within a group, e.g. a pair, so the group is one range.
+    pub(crate) fn grouped(tree: &'static str, group: &str, timestamp: u64, sequence: u64) -> Self {
+        Self::new(
+            tree,
+            format!(
+                "{}/{}",
+                group,
+                Self::chronological(tree, timestamp, sequence).id
+            ),
+        )
+    }
+
+    pub fn trade(pair: &str, timestamp: u64, sequence: u64) -> Self {
+        Self::grouped(TRADES, pair, timestamp, sequence)
+    }
+
+    // keyed by the first trade of the batch
+    pub fn archive(pair: &str, timestamp: u64, sequence: u64) -> Self {
+        Self::grouped(ARCHIVES, pair, timestamp, sequence)
+    }
+
+    // index entry of one side of a trade, see trade::trades_of_order
+    pub fn order_trade(order: Uuid, timestamp: u64, sequence: u64) -> Self {
+        Self::grouped(ORDER_TRADES, &order.to_string(), timestamp, sequence)
+    }
+
+    pub fn account(account: AccountId) -> Self {
+        Self::new(ACCOUNTS, format!("{:020}", account))
+    }
+
+    pub fn settlement(pair: &str) -> Self {
+        Self::new(SETTLEMENTS, pair.to_string())
+    }
+
+    // kept with the pair's trades, see trade::record_command
+    pub fn recorded(pair: &str) -> Self {
+        Self::new(RECORDED, pair.to_string())
+    }
+
+    // like order_trade, for the account behind an order
+    pub fn account_trade(account: AccountId, timestamp: u64, sequence: u64) -> Self {
+        Self::grouped(
+            ACCOUNT_TRADES,
+            &format!("{:020}", account),
+            timestamp,
+            sequence,
+        )
+    }
+
+    pub fn tree(&self) -> &'static str {
+        self.tree
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    // The stored JSON, callers parse it into the type of the tree.
+    pub fn get(&self, db: &Database) -> db::Result<Option<String>> {
+        db.get_raw_in(self.tree, &self.id)
+    }
+
+    pub fn set<T>(&self, db: &Database, value: &T) -> db::Result<()>
+    where
+        T: Serialize,
+    {
+        db.set_in(self.tree, &self.id, value)
+    }
+
+    pub fn remove(&self, db: &Database) -> db::Result<()> {
+        db.remove_in(self.tree, &self.id)
+    }
+
+    pub fn set_in_batch<T>(&self, batch: &mut Batch, value: &T) -> db::Result<()>
+    where
+        T: Serialize,
+    {
+        batch.set_in(self.tree, &self.id, value)
+    }
+
+    pub fn remove_in_batch(&self, batch: &mut Batch) {
+        batch.remove_in(self.tree, &self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    #[test]
+    fn chronological_keys_sort_by_time() {
+        assert!(Key::audit(9, 2).id() < Key::audit(10, 1).id());
+        assert!(Key::command("BTC/USD", 9).id() < Key::command("BTC/USD", 10).id());
+        assert!(Key::command("BTC/USD", 10).id() < Key::command("BTC/USDT", 1).id());
+        assert!(Key::trade("BTC/USD", 10, 1).id() < Key::trade("BTC/USDT", 9, 2).id());
+    }
+
+    #[test]
+    fn books_live_in_the_default_tree() {
+        let db = temp_db();
+        Key::book("BTC/USD").set(&db, &"book").unwrap();
+        Key::halt("BTC/USD").set(&db, &"halt").unwrap();
+
+        assert_eq!(db.keys().unwrap(), vec!["BTC/USD".to_string()]);
+        assert_eq!(db.get::<String>("BTC/USD").unwrap().unwrap(), "book");
+        assert_eq!(Key::halt("BTC/USD").get(&db).unwrap().unwrap(), "\"halt\"");
+
+        Key::book("BTC/USD").remove(&db).unwrap();
+        assert!(db.keys().unwrap().is_empty());
+    }
+}
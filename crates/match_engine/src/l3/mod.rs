@@ -0,0 +1,148 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::event_log::{Event, EventKind};
+use crate::order::{OrderKind, OrderType};
+
+pub const SALT_ENV: &str = "FTX_L3_SALT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum L3Action {
+    Add,
+    // the order rests with a smaller remaining quantity, or amended to a new
+    // price or quantity
+    Modify,
+    Delete,
+}
+
+// Market-by-order update. The order id is replaced by a salted hash, stable
+// for the life of the salt, so consumers can follow each order through the
+// queue without learning engine ids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L3Message {
+    pub timestamp: u64,
+    pub pair: String,
+    pub action: L3Action,
+    pub order_id: String,
+    pub side: OrderType,
+    pub price: i32,
+    pub quantity: i32,
+}
+
+pub struct Anonymizer {
+    salt: String,
+}
+
+impl Anonymizer {
+    pub fn new(salt: &str) -> Self {
+        Self {
+            salt: salt.to_string(),
+        }
+    }
+
+    // A fresh salt per process unless FTX_L3_SALT pins one, e.g. to keep ids
+    // stable across restarts of a publisher.
+    pub fn from_env() -> Self {
+        match std::env::var(SALT_ENV) {
+            Ok(salt) => Self::new(&salt),
+            Err(_) => {
+                let mut bytes = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut bytes);
+                Self::new(
+                    &bytes
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<String>(),
+                )
+            }
+        }
+    }
+
+    pub fn order_id(&self, id: Uuid) -> String {
+        let digest = Sha256::digest(format!("{}{}", self.salt, id));
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+// Hidden orders never leave the engine and market orders never rest, so
+// neither shows up. Rejections change nothing in the book.
+pub fn from_event(event: &Event, anonymizer: &Anonymizer) -> Option<L3Message> {
+    let order = &event.order;
+    if order.hidden || order.kind == OrderKind::Market {
+        return None;
+    }
+    let action = match event.kind {
+        EventKind::Accepted => L3Action::Add,
+        EventKind::PartiallyFilled => L3Action::Modify,
+        EventKind::Amended if order.is_open() => L3Action::Modify,
+        EventKind::Filled | EventKind::Cancelled | EventKind::Amended => L3Action::Delete,
+        EventKind::Rejected => return None,
+    };
+    Some(L3Message {
+        timestamp: event.timestamp,
+        pair: event.pair.clone(),
+        action,
+        order_id: anonymizer.order_id(order.id),
+        side: order.order_type,
+        price: order.price,
+        quantity: match action {
+            L3Action::Delete => 0,
+            _ => order.remaining(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+
+    #[test]
+    fn events_map_to_anonymized_l3_updates() {
+        let anonymizer = Anonymizer::new("salt");
+        let mut order = Order::new(5, 10, OrderType::Buy);
+        let added = from_event(
+            &Event::new("BTC/USD", EventKind::Accepted, order),
+            &anonymizer,
+        )
+        .unwrap();
+        order.fill(2);
+        let modified = from_event(
+            &Event::new("BTC/USD", EventKind::PartiallyFilled, order),
+            &anonymizer,
+        )
+        .unwrap();
+        order.fill(3);
+        let deleted = from_event(
+            &Event::new("BTC/USD", EventKind::Filled, order),
+            &anonymizer,
+        )
+        .unwrap();
+
+        assert_eq!(
+            [&added, &modified, &deleted].map(|m| (m.action, m.quantity)),
+            [
+                (L3Action::Add, 5),
+                (L3Action::Modify, 3),
+                (L3Action::Delete, 0)
+            ]
+        );
+        assert_eq!(added.order_id, deleted.order_id);
+        assert_ne!(Anonymizer::new("other").order_id(order.id), added.order_id);
+
+        let mut hidden = Order::new(1, 10, OrderType::Buy);
+        hidden.update_hidden(true);
+        let events = [
+            Event::new("BTC/USD", EventKind::Accepted, hidden),
+            Event::new(
+                "BTC/USD",
+                EventKind::Accepted,
+                Order::market(1, OrderType::Buy),
+            ),
+            Event::new("BTC/USD", EventKind::Rejected, order),
+        ];
+        assert!(events.iter().all(|e| from_event(e, &anonymizer).is_none()));
+    }
+}
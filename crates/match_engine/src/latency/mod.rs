@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use db::Database;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::key::{self, Key};
+use crate::telemetry;
+
+pub const BUDGET_ENV: &str = "FTX_LATENCY_BUDGET";
+
+// A stage without a budget is never reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyBudget {
+    pub validation: Option<Duration>,
+    pub matching: Option<Duration>,
+    pub persistence: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageTimings {
+    pub validation_micros: u64,
+    pub matching_micros: u64,
+    pub persistence_micros: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlowPathReport {
+    pub timestamp: u64,
+    pub pair: String,
+    pub order_id: Uuid,
+    pub timings: StageTimings,
+    pub exceeded: Vec<String>,
+}
+
+impl StageTimings {
+    pub fn new(validation: Duration, matching: Duration, persistence: Duration) -> Self {
+        Self {
+            validation_micros: validation.as_micros() as u64,
+            matching_micros: matching.as_micros() as u64,
+            persistence_micros: persistence.as_micros() as u64,
+        }
+    }
+}
+
+impl LatencyBudget {
+    // e.g. "validation=1,matching=5,persistence=50" in milliseconds
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut budget = LatencyBudget::default();
+        for clause in spec.split(',').filter(|c| !c.trim().is_empty()) {
+            let (stage, millis) = clause
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid budget {}, expected stage=milliseconds", clause))?;
+            let millis = Some(Duration::from_millis(millis.trim().parse()?));
+            match stage.trim() {
+                "validation" => budget.validation = millis,
+                "matching" => budget.matching = millis,
+                "persistence" => budget.persistence = millis,
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown stage {}, expected validation, matching or persistence",
+                        stage
+                    ))
+                }
+            }
+        }
+        Ok(budget)
+    }
+
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var(BUDGET_ENV) {
+            Ok(spec) => Ok(Some(Self::parse(&spec)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn exceeded(&self, timings: &StageTimings) -> Vec<String> {
+        [
+            ("validation", self.validation, timings.validation_micros),
+            ("matching", self.matching, timings.matching_micros),
+            ("persistence", self.persistence, timings.persistence_micros),
+        ]
+        .into_iter()
+        .filter(|(_, budget, micros)| budget.is_some_and(|b| *micros > b.as_micros() as u64))
+        .map(|(stage, _, _)| stage.to_string())
+        .collect()
+    }
+}
+
+pub fn record(db: &Database, report: &SlowPathReport) -> anyhow::Result<()> {
+    Ok(Key::slow_path(report.timestamp, db.generate_id()?).set(db, report)?)
+}
+
+pub fn slow_paths(db: &Database, pair: Option<&str>) -> anyhow::Result<Vec<SlowPathReport>> {
+    let mut reports = Vec::new();
+    for (_, json) in db.entries_in(key::SLOW_PATH)? {
+        let report: SlowPathReport = serde_json::from_str(&json)?;
+        if pair.is_none_or(|pair| pair == report.pair) {
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
+
+pub fn report(
+    pair: &str,
+    order_id: Uuid,
+    timings: StageTimings,
+    exceeded: Vec<String>,
+) -> SlowPathReport {
+    SlowPathReport {
+        timestamp: telemetry::now_millis(),
+        pair: pair.to_string(),
+        order_id,
+        timings,
+        exceeded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_budget() {
+        let budget = LatencyBudget::parse("matching=5,persistence=50").unwrap();
+        assert_eq!(budget.validation, None);
+        assert_eq!(budget.matching, Some(Duration::from_millis(5)));
+        assert!(LatencyBudget::parse("risk=1").is_err());
+    }
+
+    #[test]
+    fn only_budgeted_stages_are_exceeded() {
+        let budget = LatencyBudget::parse("matching=5,persistence=50").unwrap();
+        let timings = StageTimings::new(
+            Duration::from_secs(1),
+            Duration::from_millis(6),
+            Duration::from_millis(50),
+        );
+        assert_eq!(budget.exceeded(&timings), vec!["matching".to_string()]);
+    }
+}
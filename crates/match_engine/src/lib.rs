@@ -1,2 +1,38 @@
+pub mod access;
+pub mod accounts;
+pub mod archive;
+pub mod audit;
+pub mod busy_poll;
+pub mod calendar;
+pub mod canonical;
+pub mod clock;
+pub mod command_log;
+pub mod error;
+pub mod event_log;
+pub mod events;
+pub mod exchange;
+pub mod fees;
+pub mod handoff;
+pub mod health;
+pub mod idempotency;
+pub mod ingest;
+pub mod instrument;
+pub mod key;
+pub mod l3;
+pub mod latency;
 pub mod order;
-pub mod order_book;
\ No newline at end of file
+pub mod order_book;
+pub mod pipeline;
+pub mod quarantine;
+pub mod query;
+pub mod replica;
+pub mod scenario;
+pub mod secrets;
+pub mod shard;
+pub mod supervision;
+pub mod symbol;
+pub(crate) mod sync;
+pub mod telemetry;
+pub mod trade;
+pub mod version;
+pub mod writer;
@@ -1,5 +1,18 @@
-use std::cmp::Ordering;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+pub mod id;
+pub mod peg;
+pub mod tag;
+pub mod time_in_force;
+
+use crate::accounts::AccountId;
+use crate::telemetry;
+use id::{IdGenerator, RandomIdGenerator};
+use peg::Peg;
+use tag::Tag;
+use time_in_force::TimeInForce;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum OrderType {
@@ -11,23 +24,100 @@ pub enum OrderType {
 pub enum OrderStatus {
     Filled,
     Active,
+    // still resting with the remaining quantity
+    PartiallyFilled,
+    Cancelled,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum OrderKind {
+    #[default]
+    Limit,
+    // placed without a price, fills against the book and never rests
+    Market,
+}
+
+// How far a market order reaches into the book.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Execution {
+    // through as many price levels as its quantity takes
+    #[default]
+    Sweep,
+    // only at the best opposite price, the rest is cancelled
+    BestLevel,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Order {
+    #[serde(default)]
+    pub id: Uuid,
     pub price: i32,
     pub quantity: i32,
     pub order_type: OrderType,
     pub order_status: OrderStatus,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub peg: Option<Peg>,
+    #[serde(default)]
+    pub filled_quantity: i32,
+    #[serde(default)]
+    pub kind: OrderKind,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    // orders of an account reserve its balance, see accounts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<Tag>,
+    // market orders only
+    #[serde(default)]
+    pub execution: Execution,
+    // the least its first match on arrival must fill, see order_book::cross
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_qty: Option<i32>,
+    // only trades when it fills entirely, see order_book::fill_all_or_none
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_or_none: bool,
 }
 
 impl Order {
     pub fn new(quantity: i32, price: i32, order_type: OrderType) -> Self {
+        Self::with_generator(quantity, price, order_type, &RandomIdGenerator)
+    }
+
+    pub fn with_generator(
+        quantity: i32,
+        price: i32,
+        order_type: OrderType,
+        id_generator: &dyn IdGenerator,
+    ) -> Self {
         Self {
+            id: id_generator.next_id(),
             quantity,
             price,
             order_type,
             order_status: OrderStatus::Active,
+            created_at: telemetry::now_millis(),
+            hidden: false,
+            peg: None,
+            filled_quantity: 0,
+            kind: OrderKind::Limit,
+            time_in_force: TimeInForce::Gtc,
+            account: None,
+            tag: None,
+            execution: Execution::Sweep,
+            min_qty: None,
+            all_or_none: false,
+        }
+    }
+
+    pub fn market(quantity: i32, order_type: OrderType) -> Self {
+        Self {
+            kind: OrderKind::Market,
+            ..Self::new(quantity, 0, order_type)
         }
     }
 
@@ -38,6 +128,93 @@ impl Order {
     pub fn update_order_status(&mut self, new_order_status: OrderStatus) {
         self.order_status = new_order_status;
     }
+
+    pub fn update_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    pub fn update_peg(&mut self, peg: Option<Peg>) {
+        self.peg = peg;
+    }
+
+    pub fn update_kind(&mut self, kind: OrderKind) {
+        self.kind = kind;
+    }
+
+    pub fn update_time_in_force(&mut self, time_in_force: TimeInForce) {
+        self.time_in_force = time_in_force;
+    }
+
+    pub fn update_account(&mut self, account: Option<AccountId>) {
+        self.account = account;
+    }
+
+    pub fn update_tag(&mut self, tag: Option<Tag>) {
+        self.tag = tag;
+    }
+
+    pub fn update_execution(&mut self, execution: Execution) {
+        self.execution = execution;
+    }
+
+    pub fn update_min_qty(&mut self, min_qty: Option<i32>) {
+        self.min_qty = min_qty;
+    }
+
+    pub fn update_all_or_none(&mut self, all_or_none: bool) {
+        self.all_or_none = all_or_none;
+    }
+
+    pub fn remaining(&self) -> i32 {
+        self.quantity - self.filled_quantity
+    }
+
+    // Can still be matched or cancelled.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.order_status,
+            OrderStatus::Active | OrderStatus::PartiallyFilled
+        )
+    }
+
+    pub fn fill(&mut self, quantity: i32) {
+        self.filled_quantity += quantity;
+        if self.remaining() > 0 {
+            self.order_status = OrderStatus::PartiallyFilled;
+        } else {
+            self.order_status = OrderStatus::Filled;
+            // executed pegs keep the price they filled at
+            self.peg = None;
+        }
+    }
+
+    // Price priority first; at the same price displayed orders queue ahead of
+    // hidden ones, and within the same visibility the earlier created order
+    // stays ahead. Orders created in the same millisecond keep arrival order.
+    pub fn queues_ahead_of(&self, incoming: &Order) -> bool {
+        let better_price = match self.order_type {
+            OrderType::Buy => self.price > incoming.price,
+            OrderType::Sell => self.price < incoming.price,
+        };
+        let same_price_ahead = match (self.hidden, incoming.hidden) {
+            (false, true) => true,
+            (true, false) => false,
+            _ => self.created_at <= incoming.created_at,
+        };
+        better_price || (self.price == incoming.price && same_price_ahead)
+    }
+
+    // The same priority as a comparator: a stable sort by it gives the queue
+    // that inserting the orders one by one with queues_ahead_of would build.
+    pub fn queue_cmp(&self, other: &Order) -> Ordering {
+        let price = match self.order_type {
+            OrderType::Buy => other.price.cmp(&self.price),
+            OrderType::Sell => self.price.cmp(&other.price),
+        };
+        price
+            .then(self.hidden.cmp(&other.hidden))
+            .then(self.created_at.cmp(&other.created_at))
+    }
 }
 
 impl Ord for Order {
@@ -49,12 +226,11 @@ impl Ord for Order {
     }
 }
 
+// Follows Ord, so a better sell (lower price) compares greater like a better
+// buy does. Sells used to compare equal to every other order.
 impl PartialOrd for Order {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match self.order_type {
-            OrderType::Buy => Some(self.price.cmp(&other.price)),
-            _ => Some(other.price.cmp(&other.price)),
-        }
+        Some(self.cmp(other))
     }
 }
 
@@ -68,6 +244,64 @@ mod tests {
         assert_eq!(order.order_status, OrderStatus::Active);
     }
 
+    #[test]
+    fn new_orders_have_unique_ids() {
+        let first = Order::new(10, 30, OrderType::Buy);
+        let second = Order::new(10, 30, OrderType::Buy);
+        assert_ne!(first.id, second.id);
+    }
+
+    #[test]
+    fn with_generator_uses_given_scheme() {
+        let generator = id::SnowflakeIdGenerator::new(9);
+        let order = Order::with_generator(10, 30, OrderType::Buy, &generator);
+        assert_eq!(id::Snowflake::parse(&order.id).map(|s| s.node), Some(9));
+    }
+
+    #[test]
+    fn better_priced_orders_compare_greater_on_both_sides() {
+        let (low_bid, high_bid) = (
+            Order::new(1, 9, OrderType::Buy),
+            Order::new(1, 10, OrderType::Buy),
+        );
+        let (low_ask, high_ask) = (
+            Order::new(1, 9, OrderType::Sell),
+            Order::new(1, 10, OrderType::Sell),
+        );
+
+        assert!(high_bid > low_bid);
+        assert!(low_ask > high_ask);
+        assert_eq!(high_ask.partial_cmp(&low_ask), Some(Ordering::Less));
+        assert_eq!(low_ask.partial_cmp(&low_ask), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn displayed_orders_queue_ahead_of_hidden_at_same_price() {
+        let displayed = Order::new(1, 10, OrderType::Buy);
+        let mut hidden = Order::new(1, 10, OrderType::Buy);
+        hidden.update_hidden(true);
+
+        assert!(displayed.queues_ahead_of(&hidden));
+        assert!(!hidden.queues_ahead_of(&displayed));
+        assert!(hidden.queues_ahead_of(&Order {
+            hidden: true,
+            ..hidden
+        }));
+        assert!(hidden.queues_ahead_of(&Order::new(1, 9, OrderType::Buy)));
+    }
+
+    #[test]
+    fn earlier_orders_queue_ahead_at_same_price() {
+        let mut earlier = Order::new(1, 10, OrderType::Sell);
+        earlier.created_at -= 1_000;
+        let later = Order::new(1, 10, OrderType::Sell);
+
+        assert!(earlier.queues_ahead_of(&later));
+        assert!(!later.queues_ahead_of(&earlier));
+        assert_eq!(earlier.queue_cmp(&later), Ordering::Less);
+        assert!(later.queues_ahead_of(&Order::new(1, 11, OrderType::Sell)));
+    }
+
     #[test]
     fn update_order_type_test() {
         let mut order = Order::new(10, 30, OrderType::Buy);
@@ -76,6 +310,21 @@ mod tests {
         assert_eq!(order.order_type, OrderType::Sell);
     }
 
+    #[test]
+    fn fill_tracks_the_remaining_quantity() {
+        let mut order = Order::new(10, 30, OrderType::Buy);
+        order.update_peg(Some(Peg::parse("bid").unwrap()));
+
+        order.fill(4);
+        assert_eq!(order.order_status, OrderStatus::PartiallyFilled);
+        assert_eq!(order.remaining(), 6);
+        assert!(order.is_open() && order.peg.is_some());
+
+        order.fill(6);
+        assert_eq!(order.order_status, OrderStatus::Filled);
+        assert!(!order.is_open() && order.peg.is_none());
+    }
+
     #[test]
     fn update_order_status_test() {
         let mut order = Order::new(10, 30, OrderType::Sell);
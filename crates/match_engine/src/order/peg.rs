@@ -0,0 +1,86 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum PegReference {
+    BestBid,
+    BestAsk,
+    Midpoint,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Peg {
+    pub reference: PegReference,
+    pub offset: i32,
+}
+
+impl Peg {
+    pub fn new(reference: PegReference, offset: i32) -> Self {
+        Self { reference, offset }
+    }
+
+    // None when the reference side of the book is empty, the order then keeps its last price
+    pub fn price(&self, best_bid: Option<i32>, best_ask: Option<i32>) -> Option<i32> {
+        let reference = match self.reference {
+            PegReference::BestBid => best_bid?,
+            PegReference::BestAsk => best_ask?,
+            PegReference::Midpoint => (best_bid? + best_ask?) / 2,
+        };
+        Some(reference + self.offset)
+    }
+
+    // e.g. "bid", "ask-2", "mid+1"
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let spec = spec.trim();
+        let split = spec.find(['+', '-']).unwrap_or(spec.len());
+        let (reference, offset) = spec.split_at(split);
+        let reference = match reference {
+            "bid" => PegReference::BestBid,
+            "ask" => PegReference::BestAsk,
+            "mid" => PegReference::Midpoint,
+            _ => {
+                return Err(anyhow!(
+                    "Invalid peg reference {}, expected bid, ask or mid",
+                    reference
+                ))
+            }
+        };
+        let offset = match offset {
+            "" => 0,
+            offset => offset.trim_start_matches('+').parse()?,
+        };
+        Ok(Self::new(reference, offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reference_and_offset() {
+        assert_eq!(
+            Peg::parse("bid").unwrap(),
+            Peg::new(PegReference::BestBid, 0)
+        );
+        assert_eq!(
+            Peg::parse("ask-2").unwrap(),
+            Peg::new(PegReference::BestAsk, -2)
+        );
+        assert_eq!(
+            Peg::parse("mid+1").unwrap(),
+            Peg::new(PegReference::Midpoint, 1)
+        );
+        assert!(Peg::parse("last").is_err());
+    }
+
+    #[test]
+    fn price_tracks_reference() {
+        assert_eq!(Peg::parse("bid+1").unwrap().price(Some(10), None), Some(11));
+        assert_eq!(
+            Peg::parse("mid").unwrap().price(Some(10), Some(20)),
+            Some(15)
+        );
+        assert_eq!(Peg::parse("mid").unwrap().price(Some(10), None), None);
+    }
+}
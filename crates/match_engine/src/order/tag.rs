@@ -0,0 +1,86 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+
+pub const MAX_LEN: usize = 16;
+
+// A client's label for a group of orders, e.g. "mm" for the quotes of a
+// market maker, so they can be cancelled together. Stored inline to keep
+// Order Copy.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Tag {
+    len: u8,
+    bytes: [u8; MAX_LEN],
+}
+
+impl Tag {
+    // e.g. "mm" or "grid-2", letters, digits, '-' and '_'
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let raw = raw.trim();
+        let valid = raw
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if raw.is_empty() || raw.len() > MAX_LEN || !valid {
+            return Err(error::validation(format!(
+                "Invalid tag {}, expected up to {} letters, digits, '-' or '_'",
+                raw, MAX_LEN
+            )));
+        }
+        let mut bytes = [0; MAX_LEN];
+        bytes[..raw.len()].copy_from_slice(raw.as_bytes());
+        Ok(Self {
+            len: raw.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize]).expect("tags are ascii")
+    }
+}
+
+impl TryFrom<String> for Tag {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: String) -> anyhow::Result<Self> {
+        Self::parse(&raw)
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> Self {
+        tag.as_str().to_string()
+    }
+}
+
+impl fmt::Debug for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tag({:?})", self.as_str())
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_short_labels_stored_as_strings() {
+        let tag = Tag::parse("grid-2").unwrap();
+        assert_eq!(tag.as_str(), "grid-2");
+        assert_eq!(serde_json::to_string(&tag).unwrap(), "\"grid-2\"");
+        assert_eq!(serde_json::from_str::<Tag>("\"grid-2\"").unwrap(), tag);
+
+        assert!(Tag::parse("").is_err());
+        assert!(Tag::parse("a tag").is_err());
+        assert!(Tag::parse(&"x".repeat(MAX_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum TimeInForce {
+    // rests until filled or cancelled
+    #[default]
+    Gtc,
+    // immediate or cancel, whatever does not fill on arrival is cancelled
+    Ioc,
+    // fill or kill, rejected unless it fills in full on arrival
+    Fok,
+    // good til date, rests until the expiry sweep passes expires_at
+    Gtd {
+        expires_at: u64,
+    },
+}
+
+impl TimeInForce {
+    // Unfilled quantity is cancelled instead of resting.
+    pub fn is_immediate(&self) -> bool {
+        matches!(self, TimeInForce::Ioc | TimeInForce::Fok)
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        match self {
+            TimeInForce::Gtd { expires_at } => Some(*expires_at),
+            _ => None,
+        }
+    }
+
+    // e.g. "gtc", "ioc", "fok", "gtd:1767225600000" (expiry in unix millis)
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        match spec.trim() {
+            "gtc" => Ok(TimeInForce::Gtc),
+            "ioc" => Ok(TimeInForce::Ioc),
+            "fok" => Ok(TimeInForce::Fok),
+            spec => match spec.strip_prefix("gtd:") {
+                Some(expires_at) => Ok(TimeInForce::Gtd {
+                    expires_at: expires_at.parse()?,
+                }),
+                None => Err(anyhow!(
+                    "Invalid time in force {}, expected gtc, ioc, fok or gtd:<expiry millis>",
+                    spec
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_in_force() {
+        assert_eq!(TimeInForce::parse("ioc").unwrap(), TimeInForce::Ioc);
+        assert_eq!(
+            TimeInForce::parse("gtd:1000").unwrap().expires_at(),
+            Some(1000)
+        );
+        assert!(TimeInForce::parse("gtd:soon").is_err());
+        assert!(TimeInForce::parse("day").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorKind;
+use crate::order::{Order, OrderStatus};
+use crate::trade::Trade;
+
+// What became of a placement, with the order as it stands once matched and
+// the trades it took part in. The book reports rejections as errors so `?`
+// stops callers, OrderAck::rejected turns one into an ack for transports
+// that answer every order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum OrderAck {
+    Rested {
+        order: Order,
+    },
+    // the rest is resting, or cancelled for a market order
+    PartiallyFilled {
+        order: Order,
+        fills: Vec<Trade>,
+    },
+    Filled {
+        order: Order,
+        fills: Vec<Trade>,
+    },
+    // an IOC/FOK order that found nothing to fill
+    Cancelled {
+        order: Order,
+    },
+    Rejected {
+        order: Order,
+        kind: ErrorKind,
+        reason: String,
+    },
+}
+
+impl OrderAck {
+    pub(crate) fn placed(order: Order, trades: &[Trade]) -> Self {
+        let fills = trades
+            .iter()
+            .filter(|t| t.buy_order_id == order.id || t.sell_order_id == order.id)
+            .copied()
+            .collect::<Vec<_>>();
+        match order.order_status {
+            OrderStatus::Filled => OrderAck::Filled { order, fills },
+            OrderStatus::Cancelled if fills.is_empty() => OrderAck::Cancelled { order },
+            _ if fills.is_empty() && order.is_open() => OrderAck::Rested { order },
+            _ => OrderAck::PartiallyFilled { order, fills },
+        }
+    }
+
+    pub fn rejected(order: Order, error: &anyhow::Error) -> Self {
+        OrderAck::Rejected {
+            order,
+            kind: ErrorKind::of(error),
+            reason: error.to_string(),
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        match self {
+            OrderAck::Rested { order }
+            | OrderAck::PartiallyFilled { order, .. }
+            | OrderAck::Filled { order, .. }
+            | OrderAck::Cancelled { order }
+            | OrderAck::Rejected { order, .. } => order,
+        }
+    }
+
+    pub fn fills(&self) -> &[Trade] {
+        match self {
+            OrderAck::PartiallyFilled { fills, .. } | OrderAck::Filled { fills, .. } => fills,
+            OrderAck::Rested { .. } | OrderAck::Cancelled { .. } | OrderAck::Rejected { .. } => &[],
+        }
+    }
+}
+
+impl fmt::Display for OrderAck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let filled = self.fills().iter().map(|t| t.quantity).sum::<i32>();
+        match self {
+            OrderAck::Rested { order } => {
+                write!(f, "Rested {} at {}", order.remaining(), order.price)
+            }
+            OrderAck::PartiallyFilled { order, fills } if order.is_open() => write!(
+                f,
+                "Partially filled {} in {} trade(s), {} resting at {}",
+                filled,
+                fills.len(),
+                order.remaining(),
+                order.price
+            ),
+            OrderAck::PartiallyFilled { order, fills } => write!(
+                f,
+                "Partially filled {} in {} trade(s), {} cancelled",
+                filled,
+                fills.len(),
+                order.remaining()
+            ),
+            OrderAck::Filled { fills, .. } => {
+                write!(f, "Filled {} in {} trade(s)", filled, fills.len())
+            }
+            OrderAck::Cancelled { order } => {
+                write!(f, "Cancelled {} unfilled", order.remaining())
+            }
+            OrderAck::Rejected { kind, reason, .. } => write!(f, "Rejected ({}): {}", kind, reason),
+        }
+    }
+}
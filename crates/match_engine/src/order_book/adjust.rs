@@ -0,0 +1,67 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+// A price factor as a fraction, e.g. 1/100 for a redenomination that drops
+// two zeros. Kept exact so replaying the command log gives the same prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceAdjustment {
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl PriceAdjustment {
+    // e.g. "1/100" or "10"
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let (numerator, denominator) = match spec.trim().split_once('/') {
+            Some((numerator, denominator)) => {
+                (numerator.trim().parse()?, denominator.trim().parse()?)
+            }
+            None => (spec.trim().parse()?, 1),
+        };
+        if numerator <= 0 || denominator <= 0 {
+            return Err(anyhow!(
+                "Invalid adjustment factor {}, expected a positive fraction like 1/100",
+                spec
+            ));
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    // Rounded half up to the nearest whole price, None when it overflows.
+    pub fn apply(&self, price: i32) -> Option<i32> {
+        let scaled = price as i64 * self.numerator as i64 * 2 + self.denominator as i64;
+        i32::try_from(scaled / (self.denominator as i64 * 2)).ok()
+    }
+}
+
+impl fmt::Display for PriceAdjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_round() {
+        let adjustment = PriceAdjustment::parse("1/100").unwrap();
+        assert_eq!(adjustment.apply(1_000), Some(10));
+        assert_eq!(adjustment.apply(1_050), Some(11));
+        assert_eq!(adjustment.apply(1_049), Some(10));
+        assert_eq!(adjustment.to_string(), "1/100");
+
+        let adjustment = PriceAdjustment::parse("10").unwrap();
+        assert_eq!(adjustment.apply(7), Some(70));
+        assert_eq!(adjustment.apply(i32::MAX), None);
+        assert!(PriceAdjustment::parse("0").is_err());
+        assert!(PriceAdjustment::parse("-1/2").is_err());
+        assert!(PriceAdjustment::parse("half").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order::Order;
+
+// A resting order's new price and total quantity. The order keeps its place
+// in the queue when only its quantity goes down, otherwise it queues again
+// from requeued_at, which is logged so replays rebuild the same queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amendment {
+    pub id: Uuid,
+    pub price: i32,
+    pub quantity: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requeued_at: Option<u64>,
+}
+
+impl Amendment {
+    pub fn new(id: Uuid, price: i32, quantity: i32) -> Self {
+        Self {
+            id,
+            price,
+            quantity,
+            requeued_at: None,
+        }
+    }
+
+    pub fn keeps_priority(&self, order: &Order) -> bool {
+        self.price == order.price && self.quantity <= order.quantity
+    }
+
+    pub fn apply(&self, order: &mut Order) {
+        order.price = self.price;
+        order.quantity = self.quantity;
+        if let Some(requeued_at) = self.requeued_at {
+            order.created_at = requeued_at;
+        }
+    }
+}
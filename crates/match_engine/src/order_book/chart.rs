@@ -0,0 +1,85 @@
+use super::depth::{DepthLevel, DepthSnapshot};
+
+pub const DEFAULT_WIDTH: usize = 40;
+
+// Cumulative quantity per level as bars scaled to the deepest side, asks
+// over bids with the farthest levels on the outside, e.g.
+//
+// ask 12 |########################################| 8
+// ask 11 |#########################               | 5
+// bid 10 |###############                         | 3
+// bid  9 |###################################     | 7
+pub fn render(depth: &DepthSnapshot, width: usize) -> String {
+    let asks = cumulative(&depth.asks);
+    let bids = cumulative(&depth.bids);
+    let deepest = asks.iter().chain(&bids).map(|(_, total)| *total).max();
+    let Some(deepest) = deepest.filter(|total| *total > 0) else {
+        return "No orders".to_string();
+    };
+    let price_width = asks
+        .iter()
+        .chain(&bids)
+        .map(|(price, _)| price.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let line = |side: &str, (price, total): &(i32, i64)| {
+        // rounded up so every level shows at least one mark
+        let marks = (*total as u64 * width as u64).div_ceil(deepest as u64) as usize;
+        format!(
+            "{} {:>pw$} |{:<width$}| {}",
+            side,
+            price,
+            "#".repeat(marks),
+            total,
+            pw = price_width,
+            width = width
+        )
+    };
+    asks.iter()
+        .rev()
+        .map(|level| line("ask", level))
+        .chain(bids.iter().map(|level| line("bid", level)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// (price, quantity up to and including the level), best level first
+fn cumulative(levels: &[DepthLevel]) -> Vec<(i32, i64)> {
+    levels
+        .iter()
+        .scan(0, |total, level| {
+            *total += level.total_quantity as i64;
+            Some((level.price, *total))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, OrderType};
+    use crate::order_book::OrderBook;
+
+    #[test]
+    fn chart_stacks_cumulative_depth_around_the_spread() {
+        let mut order_book = OrderBook::default();
+        order_book.load_bulk(vec![
+            Order::new(3, 10, OrderType::Buy),
+            Order::new(4, 9, OrderType::Buy),
+            Order::new(5, 11, OrderType::Sell),
+            Order::new(3, 12, OrderType::Sell),
+        ]);
+
+        assert_eq!(
+            render(&order_book.depth(10), 8),
+            [
+                "ask 12 |########| 8",
+                "ask 11 |#####   | 5",
+                "bid 10 |###     | 3",
+                "bid  9 |####### | 7",
+            ]
+            .join("\n")
+        );
+        assert_eq!(render(&DepthSnapshot::default(), 8), "No orders");
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Item, OrderBook};
+use crate::order::{Order, OrderType};
+
+pub const DEFAULT_LEVELS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub price: i32,
+    pub total_quantity: i32,
+    pub order_count: usize,
+}
+
+// Level 2 view of a book, best price first on both sides.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+impl DepthSnapshot {
+    // Only open orders the market may see are counted, hidden ones never
+    // leave the engine.
+    pub fn from_orders<'a>(orders: impl IntoIterator<Item = &'a Order>, levels: usize) -> Self {
+        let mut bids = BTreeMap::<i32, DepthLevel>::new();
+        let mut asks = BTreeMap::<i32, DepthLevel>::new();
+        for order in orders.into_iter().filter(|o| o.is_open() && !o.hidden) {
+            let side = match order.order_type {
+                OrderType::Buy => &mut bids,
+                OrderType::Sell => &mut asks,
+            };
+            let level = side.entry(order.price).or_insert(DepthLevel {
+                price: order.price,
+                total_quantity: 0,
+                order_count: 0,
+            });
+            level.total_quantity += order.remaining();
+            level.order_count += 1;
+        }
+        Self {
+            bids: bids.into_values().rev().take(levels).collect(),
+            asks: asks.into_values().take(levels).collect(),
+        }
+    }
+}
+
+impl OrderBook {
+    pub fn depth(&self, levels: usize) -> DepthSnapshot {
+        let buy_orders = self.buy_orders.lock().unwrap();
+        let sell_orders = self.sell_orders.lock().unwrap();
+        DepthSnapshot::from_orders(buy_orders.iter().chain(sell_orders.iter()), levels)
+    }
+}
+
+impl Item {
+    // For books read from the database or a replica rather than loaded.
+    pub fn depth(&self, levels: usize) -> DepthSnapshot {
+        DepthSnapshot::from_orders(&self.active_orders, levels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_aggregates_visible_orders_per_level() {
+        let mut order_book = OrderBook::default();
+        let mut hidden = Order::new(10, 10, OrderType::Buy);
+        hidden.hidden = true;
+        order_book.load_bulk(vec![
+            Order::new(1, 10, OrderType::Buy),
+            Order::new(2, 10, OrderType::Buy),
+            Order::new(4, 9, OrderType::Buy),
+            Order::new(8, 8, OrderType::Buy),
+            hidden,
+            Order::new(3, 12, OrderType::Sell),
+            Order::new(5, 11, OrderType::Sell),
+        ]);
+
+        let depth = order_book.depth(2);
+
+        assert_eq!(
+            depth.bids,
+            vec![
+                DepthLevel {
+                    price: 10,
+                    total_quantity: 3,
+                    order_count: 2
+                },
+                DepthLevel {
+                    price: 9,
+                    total_quantity: 4,
+                    order_count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            depth.asks.iter().map(|l| l.price).collect::<Vec<_>>(),
+            vec![11, 12]
+        );
+        assert_eq!(order_book.snapshot().depth(2), depth);
+    }
+}
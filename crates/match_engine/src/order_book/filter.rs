@@ -0,0 +1,126 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::accounts::AccountId;
+use crate::order::tag::Tag;
+use crate::order::{Order, OrderType};
+use crate::telemetry;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelFilter {
+    pub side: Option<OrderType>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    pub created_before: Option<u64>,
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    // good-til-date orders whose expiry is at or before this
+    #[serde(default)]
+    pub expired_at: Option<u64>,
+    #[serde(default)]
+    pub tag: Option<Tag>,
+    #[serde(default)]
+    pub account: Option<AccountId>,
+}
+
+impl CancelFilter {
+    pub fn matches(&self, order: &Order) -> bool {
+        self.side.is_none_or(|side| side == order.order_type)
+            && self.min_price.is_none_or(|min| order.price >= min)
+            && self.max_price.is_none_or(|max| order.price <= max)
+            && self
+                .created_before
+                .is_none_or(|before| order.created_at < before)
+            && self.id.is_none_or(|id| order.id == id)
+            && self.expired_at.is_none_or(|at| {
+                order
+                    .time_in_force
+                    .expires_at()
+                    .is_some_and(|expires_at| expires_at <= at)
+            })
+            && self.tag.is_none_or(|tag| order.tag == Some(tag))
+            && self
+                .account
+                .is_none_or(|account| order.account == Some(account))
+    }
+
+    // e.g. "side=buy,min_price=10,max_price=20,older_than=60" (older_than in seconds),
+    // "tag=mm,account=7" or "id=67e55044-10b1-426f-9247-bb680e5fe0c8"
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut filter = CancelFilter::default();
+        for clause in spec.split(',').filter(|c| !c.trim().is_empty()) {
+            let (key, value) = clause
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid filter clause {}, expected key=value", clause))?;
+            match key.trim() {
+                "side" => {
+                    filter.side = Some(match value.trim() {
+                        "buy" => OrderType::Buy,
+                        "sell" => OrderType::Sell,
+                        _ => return Err(anyhow!("Invalid side {}, expected buy or sell", value)),
+                    })
+                }
+                "min_price" => filter.min_price = Some(value.trim().parse()?),
+                "max_price" => filter.max_price = Some(value.trim().parse()?),
+                "older_than" => {
+                    let seconds: u64 = value.trim().parse()?;
+                    filter.created_before =
+                        Some(telemetry::now_millis().saturating_sub(seconds * 1000));
+                }
+                "id" => filter.id = Some(value.trim().parse()?),
+                "tag" => filter.tag = Some(Tag::parse(value)?),
+                "account" => filter.account = Some(value.trim().parse()?),
+                _ => return Err(anyhow!("Unknown filter {}", key)),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match() {
+        let filter = CancelFilter::parse("side=buy,min_price=10,max_price=20").unwrap();
+
+        assert!(filter.matches(&Order::new(1, 15, OrderType::Buy)));
+        assert!(!filter.matches(&Order::new(1, 25, OrderType::Buy)));
+        assert!(!filter.matches(&Order::new(1, 5, OrderType::Buy)));
+        assert!(!filter.matches(&Order::new(1, 15, OrderType::Sell)));
+
+        let order = Order::new(1, 15, OrderType::Buy);
+        let filter = CancelFilter::parse(&format!("id={}", order.id)).unwrap();
+        assert!(filter.matches(&order));
+        assert!(!filter.matches(&Order::new(1, 15, OrderType::Buy)));
+
+        let mut quote = Order::new(1, 15, OrderType::Buy);
+        quote.update_tag(Some(Tag::parse("mm").unwrap()));
+        quote.update_account(Some(7));
+        let filter = CancelFilter::parse("tag=mm,account=7").unwrap();
+        assert!(filter.matches(&quote));
+        assert!(!filter.matches(&order));
+        quote.update_account(Some(8));
+        assert!(!filter.matches(&quote));
+    }
+
+    #[test]
+    fn older_than_uses_creation_time() {
+        let filter = CancelFilter::parse("older_than=60").unwrap();
+        let mut order = Order::new(1, 15, OrderType::Buy);
+        assert!(!filter.matches(&order));
+
+        order.created_at -= 120_000;
+        assert!(filter.matches(&order));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_filters() {
+        assert!(CancelFilter::parse("colour=red").is_err());
+        assert!(CancelFilter::parse("tag=market maker").is_err());
+        assert!(CancelFilter::parse("side=long").is_err());
+        assert!(CancelFilter::parse("min_price").is_err());
+    }
+}
@@ -0,0 +1,313 @@
+// Placements are journalled instead of snapshotted. The command log is the
+// write-ahead journal (placed, cancelled, adjusted) and trades are appended
+// to their own tree, so a placement costs a few appends rather than a
+// rewrite of the whole Item. Books are snapshotted every `interval`
+// journalled commands, and loading replays what was journalled after the
+// snapshot.
+//
+// Once a snapshot is durable the commands it covers are only needed by
+// book_at and recover. Compaction truncates those older than the retention
+// and folds them into a base book, which recovery starts from instead of an
+// empty one.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use super::{Item, OrderBook};
+use crate::command_log::{self, LoggedCommand};
+use crate::error;
+use crate::key::{self, Key};
+use crate::telemetry;
+
+pub const SNAPSHOT_INTERVAL_ENV: &str = "FTX_SNAPSHOT_INTERVAL";
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 100;
+pub const RETENTION_ENV: &str = "FTX_JOURNAL_RETENTION";
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compaction {
+    pub pair: String,
+    // the book after the last truncated command
+    pub base: Item,
+    // timestamp of the last truncated command, book_at cannot go further back
+    pub through: u64,
+    pub truncated: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalStats {
+    pub commands: usize,
+    pub bytes: usize,
+    pub per_pair: BTreeMap<String, usize>,
+    pub truncated: u64,
+}
+
+// e.g. FTX_SNAPSHOT_INTERVAL=1000, None when unset
+pub fn interval_from_env() -> anyhow::Result<Option<u64>> {
+    match std::env::var(SNAPSHOT_INTERVAL_ENV) {
+        Ok(raw) => match raw.trim().parse::<u64>() {
+            Ok(interval) if interval > 0 => Ok(Some(interval)),
+            _ => Err(error::validation(format!(
+                "Invalid snapshot interval {}, expected a positive number of commands",
+                raw
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+// e.g. FTX_JOURNAL_RETENTION=3600 (seconds), None when unset
+pub fn retention_from_env() -> anyhow::Result<Option<Duration>> {
+    match std::env::var(RETENTION_ENV) {
+        Ok(raw) => raw
+            .trim()
+            .parse::<u64>()
+            .map(|secs| Some(Duration::from_secs(secs)))
+            .map_err(|_| {
+                error::validation(format!(
+                    "Invalid journal retention {}, expected a number of seconds",
+                    raw
+                ))
+            }),
+        Err(_) => Ok(None),
+    }
+}
+
+pub(crate) fn snapshot(db: &Database, pair: &str) -> anyhow::Result<Option<Item>> {
+    Key::book(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+// Commands journalled for pair after item was snapshotted. Snapshots written
+// before the journal carry no sequence and are complete, without a snapshot
+// the whole journal is pending.
+pub(crate) fn pending(
+    db: &Database,
+    pair: &str,
+    item: Option<&Item>,
+) -> anyhow::Result<Vec<LoggedCommand>> {
+    match item {
+        Some(Item { sequence: None, .. }) => Ok(Vec::new()),
+        Some(item) => command_log::commands_after(db, Some(pair), item.sequence),
+        None => command_log::commands(db, Some(pair)),
+    }
+}
+
+// The book as a live one would snapshot it now, for readers that go to the
+// database instead of loading the pair. Nothing is written.
+pub fn current(db: &Database, pair: &str) -> anyhow::Result<Option<Item>> {
+    let item = snapshot(db, pair)?;
+    let pending = pending(db, pair, item.as_ref())?;
+    if pending.is_empty() {
+        return Ok(item);
+    }
+
+    let mut scratch = OrderBook::default();
+    if let Some(item) = item {
+        scratch.load_bulk(
+            item.active_orders
+                .into_iter()
+                .chain(item.fulfilled_orders)
+                .collect(),
+        );
+    }
+    for logged in &pending {
+        scratch.apply_scratch(&logged.command);
+        scratch.sequence = Some(logged.sequence);
+    }
+    Ok(Some(scratch.snapshot()))
+}
+
+pub fn compaction(db: &Database, pair: &str) -> anyhow::Result<Option<Compaction>> {
+    Key::compaction(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+// Truncates the pair's commands covered by its durable snapshot and older
+// than retention. Returns how many were removed.
+pub fn compact(db: &Database, pair: &str, retention: Duration) -> anyhow::Result<usize> {
+    let Some(snapshotted) = snapshot(db, pair)?.and_then(|item| item.sequence) else {
+        return Ok(0);
+    };
+    let previous = compaction(db, pair)?;
+    let cutoff = telemetry::now_millis().saturating_sub(retention.as_millis() as u64);
+    let after = previous.as_ref().and_then(|c| c.base.sequence);
+    let truncated = command_log::commands_after(db, Some(pair), after)?
+        .into_iter()
+        .take_while(|c| c.sequence <= snapshotted && c.timestamp <= cutoff)
+        .collect::<Vec<_>>();
+    let Some(last) = truncated.last() else {
+        return Ok(0);
+    };
+
+    let mut scratch = OrderBook::default();
+    if let Some(previous) = &previous {
+        scratch.load_bulk(
+            previous
+                .base
+                .active_orders
+                .iter()
+                .chain(&previous.base.fulfilled_orders)
+                .copied()
+                .collect(),
+        );
+    }
+    for logged in &truncated {
+        scratch.apply_scratch(&logged.command);
+    }
+    scratch.sequence = Some(last.sequence);
+    let compaction = Compaction {
+        pair: pair.to_string(),
+        base: scratch.snapshot(),
+        through: last.timestamp,
+        truncated: previous.map_or(0, |c| c.truncated) + truncated.len() as u64,
+    };
+    Key::compaction(pair).set(db, &compaction)?;
+    // the snapshot and the base outlive the commands they replace
+    db.flush()?;
+    for logged in &truncated {
+        Key::command(pair, logged.sequence).remove(db)?;
+    }
+    Ok(truncated.len())
+}
+
+impl JournalStats {
+    // Totals over several databases, see shard.
+    pub fn add(&mut self, other: JournalStats) {
+        self.commands += other.commands;
+        self.bytes += other.bytes;
+        self.truncated += other.truncated;
+        for (pair, commands) in other.per_pair {
+            *self.per_pair.entry(pair).or_default() += commands;
+        }
+    }
+}
+
+pub fn stats(db: &Database) -> anyhow::Result<JournalStats> {
+    let mut stats = JournalStats::default();
+    for (_, json) in db.entries_in(key::COMMANDS)? {
+        let logged: LoggedCommand = serde_json::from_str(&json)?;
+        stats.commands += 1;
+        stats.bytes += json.len();
+        *stats.per_pair.entry(logged.pair).or_default() += 1;
+    }
+    for (_, json) in db.entries_in(key::COMPACTIONS)? {
+        stats.truncated += serde_json::from_str::<Compaction>(&json)?.truncated;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, OrderType};
+    use crate::symbol::Symbol;
+    use std::sync::{Arc, Mutex};
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn placements_are_journalled_and_snapshotted_periodically() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        let book = |db: &Arc<Mutex<Database>>| {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_pair(pair.clone());
+            order_book_builder.set_db(db.clone());
+            order_book_builder.set_snapshot_interval(3);
+            let mut order_book = order_book_builder.build().unwrap();
+            order_book.load().unwrap();
+            order_book
+        };
+
+        // the first placement is snapshotted, the next ones only journalled
+        let mut order_book = book(&db);
+        order_book
+            .append_buy_order(Order::new(2, 10, OrderType::Buy))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 10, OrderType::Sell))
+            .unwrap();
+        let stale = snapshot(&db.lock().unwrap(), pair.as_str())
+            .unwrap()
+            .unwrap();
+        assert_eq!(stale.active_orders[0].remaining(), 2);
+        assert_eq!(
+            current(&db.lock().unwrap(), pair.as_str()).unwrap(),
+            Some(order_book.snapshot())
+        );
+
+        // a restart replays the journal instead of losing it
+        let mut order_book = book(&db);
+        assert_eq!(order_book.get_active_buy_orders()[0].remaining(), 1);
+        order_book
+            .append_sell_order(Order::new(5, 20, OrderType::Sell))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(5, 30, OrderType::Sell))
+            .unwrap();
+        let snapshotted = snapshot(&db.lock().unwrap(), pair.as_str())
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshotted.active_orders, order_book.join_active_orders());
+        assert!(
+            pending(&db.lock().unwrap(), pair.as_str(), Some(&snapshotted))
+                .unwrap()
+                .is_empty()
+        );
+
+        order_book
+            .append_buy_order(Order::new(1, 5, OrderType::Buy))
+            .unwrap();
+        assert_eq!(
+            book(&db).join_active_orders(),
+            order_book.join_active_orders()
+        );
+    }
+
+    #[test]
+    fn snapshotted_commands_are_compacted_after_retention() {
+        let db = shared_temp_db();
+        let pair = Symbol::parse("BTC/USD").unwrap();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(pair.clone());
+        order_book_builder.set_db(db.clone());
+        order_book_builder.set_snapshot_interval(2);
+        order_book_builder.set_journal_retention(Duration::ZERO);
+        let mut order_book = order_book_builder.build().unwrap();
+        order_book.load().unwrap();
+
+        for price in [10, 11, 12] {
+            order_book
+                .append_buy_order(Order::new(1, price, OrderType::Buy))
+                .unwrap();
+        }
+        order_book
+            .append_sell_order(Order::new(1, 12, OrderType::Sell))
+            .unwrap();
+
+        // the first and third placements are snapshotted, the fourth is
+        // only journalled and stays
+        let stats = stats(&db.lock().unwrap()).unwrap();
+        assert_eq!((stats.commands, stats.truncated), (1, 3));
+        let compacted = compaction(&db.lock().unwrap(), pair.as_str())
+            .unwrap()
+            .unwrap();
+        assert_eq!(compacted.base.active_orders.len(), 3);
+        assert!(order_book.book_at(compacted.through - 1).is_err());
+
+        // recovery starts from the base instead of an empty book
+        let expected = order_book.join_active_orders();
+        assert_eq!(order_book.recover().unwrap(), 1);
+        assert_eq!(order_book.join_active_orders(), expected);
+        assert_eq!(
+            compact(&db.lock().unwrap(), pair.as_str(), Duration::ZERO).unwrap(),
+            0
+        );
+    }
+}
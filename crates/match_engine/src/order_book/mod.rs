@@ -1,30 +1,114 @@
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
+use crossbeam_channel::{Receiver, Sender};
 use db::Database;
-use sorted_insert::SortedInsertByKey;
+use sorted_insert::SortedInsertBy;
+use uuid::Uuid;
 
-use crate::order::{Order, OrderStatus, OrderType};
+pub mod ack;
+pub mod adjust;
+pub mod amend;
+pub mod chart;
+pub mod depth;
+pub mod filter;
+pub mod journal;
+pub mod page;
+pub mod verify;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::accounts;
+use crate::calendar::{self, MarketState};
+use crate::command_log::{self, Command, LoggedCommand};
+use crate::error;
+use crate::event_log::{Event, EventKind, EventLog};
+use crate::events::{EventBus, OrderBookEvent, Overflow, SubscriberStats};
+use crate::fees;
+use crate::instrument;
+use crate::key::Key;
+use crate::latency::{self, LatencyBudget, StageTimings};
+use crate::order::time_in_force::TimeInForce;
+use crate::order::{Execution, Order, OrderKind, OrderStatus, OrderType};
+use crate::pipeline::speed_bump::SpeedBump;
+use crate::quarantine;
+use crate::replica::{self, Role};
+use crate::supervision;
+use crate::symbol::Symbol;
+use crate::sync;
+use crate::telemetry::{self, TelemetrySample};
+use crate::trade::{self, Trade};
+use crate::writer::{Task, Writer};
+use ack::OrderAck;
+use adjust::PriceAdjustment;
+use amend::Amendment;
+use filter::CancelFilter;
+
+pub(crate) type Side = sync::Arc<sync::Mutex<Vec<Order>>>;
+
+fn side(orders: Vec<Order>) -> Side {
+    sync::Arc::new(sync::Mutex::new(orders))
+}
+
+fn top(orders: &[Order]) -> Option<Order> {
+    orders
+        .iter()
+        .find(|o| o.is_open() && !o.hidden && !o.all_or_none)
+        .copied()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Item {
     pub active_orders: Vec<Order>,
     pub fulfilled_orders: Vec<Order>,
+    // the last journalled command folded in, see journal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+}
+
+impl Item {
+    // What the market is allowed to see: hidden orders never leave the engine.
+    pub fn public_view(&self) -> Item {
+        Item {
+            active_orders: self
+                .active_orders
+                .iter()
+                .filter(|o| !o.hidden)
+                .copied()
+                .collect(),
+            fulfilled_orders: self.fulfilled_orders.clone(),
+            sequence: self.sequence,
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct OrderBook {
-    pair: Option<String>,
+    pair: Option<Symbol>,
     db: Option<Arc<Mutex<Database>>>,
-    buy_orders: Arc<Mutex<Vec<Order>>>,
-    sell_orders: Arc<Mutex<Vec<Order>>>,
+    // control records (halts, calendars, instruments) when db is a shard
+    home: Option<Arc<Mutex<Database>>>,
+    buy_orders: Side,
+    sell_orders: Side,
+    read_only: bool,
+    telemetry_retention: Option<Duration>,
+    latency_budget: Option<LatencyBudget>,
+    writer: Option<Arc<Writer>>,
+    event_log: Option<Arc<EventLog>>,
+    events: EventBus,
+    speed_bump: Option<SpeedBump>,
+    halted: bool,
+    snapshot_interval: Option<u64>,
+    journal_retention: Option<Duration>,
+    sequence: Option<u64>,
+    // commands journalled since the last snapshot
+    journalled: u64,
 }
 
 impl OrderBook {
-    pub fn set_pair(&mut self, pair: String) {
+    pub fn set_pair(&mut self, pair: Symbol) {
         self.pair = Some(pair)
     }
 
@@ -32,70 +116,371 @@ impl OrderBook {
         self.db = Some(db);
     }
 
-    pub fn get_pair(&self) -> &String {
+    // Where halts, calendars and instruments are read from when the book's
+    // own database is a shard, see shard.
+    pub fn set_home_db(&mut self, home: Arc<Mutex<Database>>) {
+        self.home = Some(home);
+    }
+
+    pub fn set_telemetry_retention(&mut self, retention: Duration) {
+        self.telemetry_retention = Some(retention);
+    }
+
+    pub fn set_latency_budget(&mut self, budget: LatencyBudget) {
+        self.latency_budget = Some(budget);
+    }
+
+    pub fn set_writer(&mut self, writer: Arc<Writer>) {
+        self.writer = Some(writer);
+    }
+
+    // Only applies to orders submitted through a Pipeline.
+    pub fn set_speed_bump(&mut self, speed_bump: SpeedBump) {
+        self.speed_bump = Some(speed_bump);
+    }
+
+    // Journalled commands between snapshots, see journal.
+    pub fn set_snapshot_interval(&mut self, interval: u64) {
+        self.snapshot_interval = Some(interval);
+    }
+
+    // How long snapshotted commands stay journalled, see journal::compact.
+    pub fn set_journal_retention(&mut self, retention: Duration) {
+        self.journal_retention = Some(retention);
+    }
+
+    pub fn set_event_log(&mut self, event_log: Arc<EventLog>) {
+        self.event_log = Some(event_log);
+    }
+
+    pub fn set_event_sender(&mut self, event_sender: Sender<OrderBookEvent>) {
+        self.events.add_sender(event_sender);
+    }
+
+    // Events from this point on, see OrderBookEvent.
+    pub fn subscribe(&mut self) -> Receiver<OrderBookEvent> {
+        self.events.subscribe()
+    }
+
+    // At most `capacity` events queued, see Overflow for what happens beyond.
+    pub fn subscribe_bounded(
+        &mut self,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> Receiver<OrderBookEvent> {
+        self.events.subscribe_bounded(capacity, overflow)
+    }
+
+    pub fn subscriber_stats(&self) -> Vec<SubscriberStats> {
+        self.events.stats()
+    }
+
+    pub fn add_listener<F>(&mut self, listener: F)
+    where
+        F: Fn(&OrderBookEvent) + Send + Sync + 'static,
+    {
+        self.events.add_listener(Arc::new(listener));
+    }
+
+    pub fn get_pair(&self) -> &Symbol {
         self.pair.as_ref().expect("Pair is not set!")
     }
 
-    pub fn load(&mut self) {
-        let binding = self.db.clone().expect("Database is required!");
-        let guard = &binding.lock().unwrap();
+    // The snapshot with the journal after it replayed. A snapshot that no
+    // longer parses is quarantined and the book is rebuilt from the whole
+    // journal, storage errors are returned.
+    pub fn load(&mut self) -> anyhow::Result<()> {
+        self.replay(None)
+    }
 
-        match guard.get(&self.pair.clone().expect("Pair is required!")) {
-            Ok(value) => match value {
-                Some(item) => {
-                    let item_from_db: Item =
-                        serde_json::from_str(item.as_str()).expect("Failed to deserialize!");
-                    item_from_db
-                        .active_orders
-                        .clone()
-                        .into_iter()
-                        .filter(|o| o.order_type == OrderType::Buy)
-                        .for_each(|o| {
-                            self.buy_orders
-                                .clone()
-                                .lock()
-                                .expect("Failed to get buy orders lock")
-                                .push(o)
-                        });
-
-                    item_from_db
-                        .active_orders
-                        .clone()
-                        .into_iter()
-                        .filter(|o| o.order_type == OrderType::Sell)
-                        .for_each(|o| {
-                            self.sell_orders
-                                .clone()
-                                .lock()
-                                .expect("Failed to get sell orders lock")
-                                .push(o)
-                        });
-                }
-                None => {}
-            },
-            Err(_) => {}
+    fn replay(&mut self, skip: Option<u64>) -> anyhow::Result<()> {
+        let pair = self.pair.clone().expect("Pair is required!");
+        let (item, pending) = {
+            let guard = self.db_guard();
+            let item = match Key::book(pair.as_str()).get(&guard)? {
+                Some(raw) => match serde_json::from_str::<Item>(&raw) {
+                    Ok(item) => Some(item),
+                    Err(e) => {
+                        quarantine::quarantine(&guard, pair.as_str(), &raw, &e.to_string())?;
+                        None
+                    }
+                },
+                None => None,
+            };
+            // without a snapshot the journal starts where it was compacted
+            let item = match item {
+                Some(item) => Some(item),
+                None => journal::compaction(&guard, pair.as_str())?.map(|c| c.base),
+            };
+            let pending = journal::pending(&guard, pair.as_str(), item.as_ref())?;
+            (item, pending)
+        };
+
+        // closed orders are loaded too, the next snapshot would drop them
+        if let Some(item) = item {
+            self.sequence = item.sequence;
+            self.load_bulk(
+                item.active_orders
+                    .into_iter()
+                    .chain(item.fulfilled_orders)
+                    .collect(),
+            );
+        }
+        self.journalled = pending.len() as u64;
+        let settled = accounts::settled(&self.home_guard(), pair.as_str())?;
+        let recorded = trade::recorded(&self.db_guard(), pair.as_str())?;
+        for logged in &pending {
+            self.reapply(logged, settled, recorded, Some(logged.sequence) == skip)?;
+        }
+        Ok(())
+    }
+
+    // Applies a logged command again, or skips it, and settles and records
+    // its trades when its balance changes or trades never made it to the
+    // database, e.g. the process died in between. A skipped command reserves
+    // nothing.
+    fn reapply(
+        &mut self,
+        logged: &LoggedCommand,
+        settled: Option<u64>,
+        recorded: Option<u64>,
+        skip: bool,
+    ) -> anyhow::Result<()> {
+        let unsettled = !self.read_only && settled.is_none_or(|s| logged.sequence > s);
+        let unrecorded = !self.read_only && recorded.is_none_or(|r| logged.sequence > r);
+        let reserved = unsettled.then(|| self.all_orders());
+        let trades = match skip {
+            true => {
+                self.sequence = Some(logged.sequence);
+                Vec::new()
+            }
+            false => self.apply(logged)?,
+        };
+        let unrecorded = unrecorded && !trades.is_empty();
+        if !unsettled && !unrecorded {
+            return Ok(());
+        }
+        let trades = self.charge(trades)?;
+        if let Some(reserved) = reserved {
+            self.settle(&reserved, &trades, logged.sequence)?;
+        }
+        if unrecorded {
+            self.write(vec![Task::Trades {
+                pair: self.get_pair().to_string(),
+                sequence: logged.sequence,
+                trades,
+            }])?;
+        }
+        Ok(())
+    }
+
+    // Replaces both sides with orders in one sort per side instead of an
+    // insert each. Nothing is matched, logged or persisted, the orders are
+    // taken as already accepted; full ties (same price, visibility and
+    // creation millisecond) keep the order they are given in.
+    pub fn load_bulk(&mut self, orders: Vec<Order>) {
+        let (mut buy_orders, mut sell_orders): (Vec<Order>, Vec<Order>) = orders
+            .into_iter()
+            .partition(|o| o.order_type == OrderType::Buy);
+        buy_orders.sort_by(Order::queue_cmp);
+        sell_orders.sort_by(Order::queue_cmp);
+        self.buy_orders = side(buy_orders);
+        self.sell_orders = side(sell_orders);
+    }
+
+    pub fn build(self) -> anyhow::Result<Self> {
+        let db = self.db.expect("Db is required!");
+        let pair = self.pair.expect("Pair is required!");
+        let guard = self
+            .home
+            .as_ref()
+            .unwrap_or(&db)
+            .lock()
+            .expect("could not get db lock");
+        let role = replica::role(&guard)?;
+        let halted = supervision::halted(&guard, pair.as_str())?.is_some();
+        drop(guard);
+
+        Ok(Self {
+            pair: Some(pair),
+            db: Some(db),
+            home: self.home,
+            buy_orders: side(Vec::new()),
+            sell_orders: side(Vec::new()),
+            read_only: role != Role::Primary,
+            telemetry_retention: self.telemetry_retention,
+            latency_budget: self.latency_budget,
+            writer: self.writer,
+            event_log: self.event_log,
+            events: self.events,
+            speed_bump: self.speed_bump,
+            halted,
+            snapshot_interval: self.snapshot_interval,
+            journal_retention: self.journal_retention,
+            sequence: None,
+            journalled: 0,
+        })
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Reloads the pair without the command the matcher panicked on.
+    pub fn restart(&mut self) -> anyhow::Result<()> {
+        let halt = supervision::halted(&self.home_guard(), self.get_pair().as_str())?;
+        let skip = halt.and_then(|halt| halt.sequence);
+        self.buy_orders = side(Vec::new());
+        self.sell_orders = side(Vec::new());
+        self.halted = false;
+        self.replay(skip)?;
+        // so the next load does not replay it either
+        if skip.is_some() {
+            self.checkpoint()?;
+        }
+        supervision::resume(&self.home_guard(), self.get_pair().as_str())
+    }
+
+    fn db_guard(&self) -> MutexGuard<'_, Database> {
+        self.db
+            .as_ref()
+            .expect("Database is not set!")
+            .lock()
+            .expect("could not get db lock")
+    }
+
+    fn home_guard(&self) -> MutexGuard<'_, Database> {
+        self.home
+            .as_ref()
+            .or(self.db.as_ref())
+            .expect("Database is not set!")
+            .lock()
+            .expect("could not get db lock")
+    }
+
+    fn supervised<F, T>(&mut self, order: &Order, sequence: Option<u64>, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&Self) -> T,
+    {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        match result {
+            Ok(value) => Ok(value),
+            Err(payload) => {
+                let reason = supervision::panic_message(payload.as_ref());
+                eprintln!(
+                    "Matcher for {} panicked while processing {:?}: {}. Pair halted until restart.",
+                    self.get_pair(),
+                    order,
+                    reason
+                );
+                supervision::halt(
+                    &self.home_guard(),
+                    self.get_pair().as_str(),
+                    &reason,
+                    sequence,
+                )?;
+                self.halted = true;
+                Err(anyhow!(
+                    "Matcher for {} panicked and the pair was halted: {}",
+                    self.get_pair(),
+                    reason
+                ))
+            }
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn ensure_writable(&self) -> anyhow::Result<()> {
+        if self.halted {
+            return Err(error::state(format!(
+                "Order book for {} is halted, restart the pair to accept orders",
+                self.get_pair()
+            )));
+        }
+        if self.read_only {
+            return Err(error::state(format!(
+                "Order book for {} is read-only, orders must be sent to the primary",
+                self.get_pair()
+            )));
+        }
+        Ok(())
+    }
+
+    // Only placements are gated by the trading calendar, cancels go through
+    // while the market is closed.
+    fn ensure_open(&self) -> anyhow::Result<()> {
+        match calendar::observe(&self.home_guard(), self.get_pair().as_str())? {
+            MarketState::Open => Ok(()),
+            MarketState::Closed => Err(error::state(format!(
+                "Market for {} is closed",
+                self.get_pair()
+            ))),
+        }
+    }
+
+    fn ensure_tradable(&self, order: &Order) -> anyhow::Result<()> {
+        match instrument::get(&self.home_guard(), self.get_pair().as_str())? {
+            Some(instrument) => instrument.validate(order),
+            None => Ok(()),
         }
     }
 
-    pub fn build(self) -> Self {
-        Self {
-            pair: self.pair.map(Some).expect("Pair is required!"),
-            db: self.db.map(Some).expect("Db is required!"),
-            buy_orders: Arc::new(Mutex::new(Vec::new())),
-            sell_orders: Arc::new(Mutex::new(Vec::new())),
+    // Funds are checked before a change is logged. Its reservation is settled
+    // with its trades once the book applied it, from the book before it,
+    // before anything else is written. A replay only settles commands logged
+    // past the pair's settled one, see reapply.
+    fn ensure_funded(&self, from: &[Order], to: &[Order]) -> anyhow::Result<()> {
+        accounts::ensure_funded(&self.home_guard(), self.get_pair(), from, to)
+    }
+
+    fn settle(&self, reserved: &[Order], trades: &[Trade], sequence: u64) -> anyhow::Result<()> {
+        let orders = self.all_orders();
+        accounts::settle(
+            &self.home_guard(),
+            self.get_pair(),
+            sequence,
+            reserved,
+            &orders,
+            trades,
+        )
+    }
+
+    // Fees of the pair's schedule on trades as they execute, see fees.
+    fn charge(&self, mut trades: Vec<Trade>) -> anyhow::Result<Vec<Trade>> {
+        if trades.is_empty() {
+            return Ok(trades);
         }
+        if let Some(schedule) = fees::get(&self.home_guard(), self.get_pair().as_str())? {
+            trades.iter_mut().for_each(|trade| schedule.charge(trade));
+        }
+        Ok(trades)
+    }
+
+    pub fn speed_bump(&self) -> Option<SpeedBump> {
+        self.speed_bump
+    }
+
+    // Shared with pipeline stages that read the book while the match stage owns it.
+    pub(crate) fn sides(&self) -> (Side, Side) {
+        (self.buy_orders.clone(), self.sell_orders.clone())
     }
 
     pub fn get_buy_orders(&self) -> Vec<Order> {
-        let buy_orders = Arc::clone(&self.buy_orders);
-        let orders_vec = buy_orders.lock().unwrap().to_owned();
-        return orders_vec;
+        self.buy_orders.lock().unwrap().to_owned()
     }
 
     pub fn get_sell_orders(&self) -> Vec<Order> {
-        let sell_orders = Arc::clone(&self.sell_orders);
-        let orders_vec = sell_orders.lock().unwrap().to_owned();
-        return orders_vec;
+        self.sell_orders.lock().unwrap().to_owned()
+    }
+
+    fn all_orders(&self) -> Vec<Order> {
+        self.get_buy_orders()
+            .into_iter()
+            .chain(self.get_sell_orders())
+            .collect()
     }
 
     pub fn get_filled_buy_orders(&self) -> Vec<Order> {
@@ -104,7 +489,7 @@ impl OrderBook {
             .into_iter()
             .filter(|o| o.order_status == OrderStatus::Filled)
             .collect();
-        return orders;
+        orders
     }
 
     pub fn get_filled_sell_orders(&self) -> Vec<Order> {
@@ -113,25 +498,46 @@ impl OrderBook {
             .into_iter()
             .filter(|o| o.order_status == OrderStatus::Filled)
             .collect();
-        return orders;
+        orders
     }
 
+    // Includes partially filled orders, which still rest with their remaining quantity.
     pub fn get_active_buy_orders(&self) -> Vec<Order> {
         let orders: Vec<Order> = self
             .get_buy_orders()
             .into_iter()
-            .filter(|o| o.order_status == OrderStatus::Active)
+            .filter(Order::is_open)
             .collect();
-        return orders;
+        orders
     }
 
     pub fn get_active_sell_orders(&self) -> Vec<Order> {
         let orders: Vec<Order> = self
             .get_sell_orders()
             .into_iter()
-            .filter(|o| o.order_status == OrderStatus::Active)
+            .filter(Order::is_open)
             .collect();
-        return orders;
+        orders
+    }
+
+    // Top of book without cloning the sides. They are kept in priority order,
+    // so this only walks the filled and cancelled orders still queued ahead.
+    // Hidden orders are not quoted.
+    pub fn best_bid(&self) -> Option<Order> {
+        top(&self.buy_orders.lock().unwrap())
+    }
+
+    pub fn best_ask(&self) -> Option<Order> {
+        top(&self.sell_orders.lock().unwrap())
+    }
+
+    pub fn spread(&self) -> Option<i32> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    // Rounded down like a mid peg, see Peg::price.
+    pub fn mid_price(&self) -> Option<i32> {
+        Some((self.best_bid()?.price + self.best_ask()?.price) / 2)
     }
 
     pub fn join_active_orders(&self) -> Vec<Order> {
@@ -148,214 +554,2205 @@ impl OrderBook {
             .collect::<Vec<Order>>()
     }
 
-    pub fn append_buy_order(&mut self, order: Order) -> anyhow::Result<()> {
-        match order.order_type {
-            OrderType::Buy => {
-                let mut buy_orders = self.buy_orders.lock().unwrap();
-                buy_orders.sorted_insert_desc_by_key(order, |o| &o.price);
-                drop(buy_orders);
-
-                self.match_orders();
-
-                let db_mutex_guard = self
-                    .db
-                    .as_ref()
-                    .expect("Database is not set!")
-                    .lock()
-                    .expect("could not get db lock");
-                db_mutex_guard
-                    .set(
-                        &self.get_pair(),
-                        &Item {
-                            active_orders: self.join_active_orders(),
-                            fulfilled_orders: self.join_filled_orders(),
-                        },
-                    )
-                    .expect("sam bankman fried");
-                drop(db_mutex_guard);
-                Ok(())
-            }
-            _ => Err(anyhow!(
-                "Invalid order type, expected Buy order type but Sell provided"
-            )),
-        }
+    pub fn join_cancelled_orders(&self) -> Vec<Order> {
+        self.get_buy_orders()
+            .into_iter()
+            .chain(self.get_sell_orders())
+            .filter(|o| o.order_status == OrderStatus::Cancelled)
+            .collect::<Vec<Order>>()
     }
 
-    pub fn append_sell_order(&mut self, order: Order) -> anyhow::Result<()> {
-        match order.order_type {
-            OrderType::Sell => {
-                let mut sell_orders = self.sell_orders.lock().unwrap();
-                sell_orders.sorted_insert_asc_by_key(order, |o| &o.price);
-                drop(sell_orders);
-
-                self.match_orders();
-
-                let db_mutex_guard = self
-                    .db
-                    .as_ref()
-                    .expect("Database is not set!")
-                    .lock()
-                    .expect("could not get db lock");
-                db_mutex_guard
-                    .set(
-                        &self.get_pair(),
-                        &Item {
-                            active_orders: self.join_active_orders(),
-                            fulfilled_orders: self.join_filled_orders(),
-                        },
-                    )
-                    .expect("sam bankman fried");
-                drop(db_mutex_guard);
-                Ok(())
-            }
-            _ => Err(anyhow!(
-                "Invalid order type, expected Sell order type but Buy provided"
-            )),
+    // cancelled orders are kept with the filled ones so history survives
+    pub fn snapshot(&self) -> Item {
+        Item {
+            active_orders: self.join_active_orders(),
+            fulfilled_orders: self
+                .join_filled_orders()
+                .into_iter()
+                .chain(self.join_cancelled_orders())
+                .collect(),
+            sequence: self.sequence,
         }
     }
 
-    fn match_orders(&self) {
-        let stop = AtomicBool::new(false);
+    pub fn cancel_where(&mut self, filter: &CancelFilter) -> anyhow::Result<Vec<Order>> {
+        self.ensure_writable()?;
+        let logged = self.log(&Command::CancelWhere(filter.clone()))?;
 
-        let buy_orders = Arc::clone(&self.buy_orders);
-        let sell_orders = Arc::clone(&self.sell_orders);
+        let before = self.all_orders();
+        let cancelled = self.apply_cancel(filter);
+        if !cancelled.is_empty() {
+            self.settle(&before, &[], logged.sequence)?;
+        }
+        if self.advance(logged.sequence) {
+            self.write(self.snapshot_tasks())?;
+        }
+        self.emit(|| {
+            cancelled
+                .iter()
+                .map(|o| Event::new(self.get_pair().as_str(), EventKind::Cancelled, *o))
+                .collect()
+        });
+        self.publish(|| {
+            cancelled
+                .iter()
+                .map(|o| OrderBookEvent::OrderCancelled {
+                    pair: self.get_pair().to_string(),
+                    order: *o,
+                })
+                .collect()
+        });
+        Ok(cancelled)
+    }
 
-        let t = thread::spawn(move || {
-            let mut index = 0;
-            while !stop.load(Ordering::Relaxed) {
-                let index_len = index + 1;
-                let mut buy_orders = buy_orders.lock().unwrap();
-                let mut sell_orders = sell_orders.lock().unwrap();
+    // Cancels one open order, it stays in the book as Cancelled so snapshots
+    // keep it with the fulfilled orders.
+    pub fn cancel_order(&mut self, id: Uuid) -> anyhow::Result<Order> {
+        self.ensure_writable()?;
+        if !self.all_orders().iter().any(|o| o.id == id && o.is_open()) {
+            return Err(error::not_found(format!(
+                "No open order {} on {}",
+                id,
+                self.get_pair()
+            )));
+        }
+        let filter = CancelFilter {
+            id: Some(id),
+            ..CancelFilter::default()
+        };
+        self.cancel_where(&filter)?
+            .pop()
+            .ok_or_else(|| error::not_found(format!("No open order {} on {}", id, self.get_pair())))
+    }
 
-                if index_len > buy_orders.len() || index_len > sell_orders.len() {
-                    stop.store(true, Ordering::Relaxed);
-                }
+    // Changes the price and total quantity of an open order, see Amendment
+    // for when it keeps its priority. An order that queues again is matched
+    // like a new placement, the ack carries the fills that gives.
+    pub fn amend_order(
+        &mut self,
+        id: Uuid,
+        new_price: i32,
+        new_quantity: i32,
+    ) -> anyhow::Result<OrderAck> {
+        self.ensure_writable()?;
+        self.ensure_open()?;
+        let order = self
+            .all_orders()
+            .into_iter()
+            .find(|o| o.id == id && o.is_open())
+            .ok_or_else(|| {
+                error::not_found(format!("No open order {} on {}", id, self.get_pair()))
+            })?;
+        if order.peg.is_some() && new_price != order.price {
+            return Err(error::validation(format!(
+                "Order {} is pegged, its price follows the reference",
+                id
+            )));
+        }
+        if new_price <= 0 {
+            return Err(error::validation(format!(
+                "Invalid price {}, expected a positive number",
+                new_price
+            )));
+        }
+        if new_quantity <= order.filled_quantity {
+            return Err(error::validation(format!(
+                "Quantity {} leaves nothing to fill, {} of order {} is filled",
+                new_quantity, order.filled_quantity, id
+            )));
+        }
+        let mut amendment = Amendment::new(id, new_price, new_quantity);
+        if !amendment.keeps_priority(&order) {
+            amendment.requeued_at = Some(telemetry::now_millis());
+        }
+        let mut amended = order;
+        amendment.apply(&mut amended);
+        self.ensure_tradable(&amended)?;
+        self.ensure_funded(&[order], &[amended])?;
 
-                if let Some(max_buy_order) = buy_orders.get_mut(index) {
-                    if let Some(min_sell_order) = sell_orders.get_mut(index) {
-                        if max_buy_order.price >= min_sell_order.price
-                            && max_buy_order.order_status == OrderStatus::Active
-                            && min_sell_order.order_status == OrderStatus::Active
-                        {
-                            max_buy_order.update_order_status(OrderStatus::Filled);
-                            min_sell_order.update_order_status(OrderStatus::Filled);
-                        }
-                    }
-                }
-                index += 1;
-            }
-        });
+        let logged = self.log(&Command::Amend(amendment))?;
+        let before = self.all_orders();
+        let (matching, trades) = match self.requeue(&amendment) {
+            Some(order) => self.apply_place(order, Some(logged.sequence))?,
+            None => (Duration::ZERO, Vec::new()),
+        };
+        let trades = self.charge(trades)?;
+        self.settle(&before, &trades, logged.sequence)?;
+        let snapshot = self.advance(logged.sequence);
+        self.persist(matching, logged.sequence, trades.clone(), snapshot)?;
+        let amended = self
+            .all_orders()
+            .into_iter()
+            .find(|o| o.id == id)
+            .unwrap_or(amended);
 
-        t.join().expect("could not join thread");
+        self.emit(|| {
+            let pair = self.get_pair().as_str();
+            std::iter::once(Event::new(pair, EventKind::Amended, amended))
+                .chain(self.filled_since(&before).into_iter().map(|o| {
+                    let kind = match o.order_status {
+                        OrderStatus::Filled => EventKind::Filled,
+                        _ => EventKind::PartiallyFilled,
+                    };
+                    Event::new(pair, kind, o)
+                }))
+                .collect()
+        });
+        self.publish(|| {
+            let pair = self.get_pair().to_string();
+            let mut events = vec![OrderBookEvent::OrderAmended {
+                pair: pair.clone(),
+                order: amended,
+            }];
+            events.extend(trades.iter().map(|trade| OrderBookEvent::TradeExecuted {
+                pair: pair.clone(),
+                trade: *trade,
+            }));
+            events.extend(
+                self.filled_since(&before)
+                    .into_iter()
+                    .map(|o| match o.order_status {
+                        OrderStatus::Filled => OrderBookEvent::OrderFilled {
+                            pair: pair.clone(),
+                            order: o,
+                        },
+                        _ => OrderBookEvent::OrderPartiallyFilled {
+                            pair: pair.clone(),
+                            order: o,
+                        },
+                    }),
+            );
+            events
+        });
+        Ok(OrderAck::placed(amended, &trades))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use lazy_static::lazy_static;
-    use std::fs;
-    use std::path::Path;
-    use std::time::Duration;
+    // Re-prices the whole book, filled and cancelled orders included so
+    // history reads on the new scale. The command log keeps the original
+    // prices, so book_at before the adjustment still shows them. Returns the
+    // open orders at their new prices.
+    pub fn adjust_prices(&mut self, adjustment: PriceAdjustment) -> anyhow::Result<Vec<Order>> {
+        self.ensure_writable()?;
+        let adjusted = |orders: Vec<Order>| {
+            orders
+                .iter()
+                .map(|o| {
+                    adjustment
+                        .apply(o.price)
+                        .filter(|price| *price > 0 || !o.is_open())
+                        .ok_or_else(|| {
+                            error::validation(format!(
+                                "Adjusting {} by {} leaves no valid price",
+                                o.id, adjustment
+                            ))
+                        })
+                })
+                .collect::<anyhow::Result<Vec<i32>>>()
+        };
+        adjusted(self.all_orders())?;
+        let best_bid = adjusted(self.get_active_buy_orders())?.into_iter().max();
+        let best_ask = adjusted(self.get_active_sell_orders())?.into_iter().min();
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask) {
+            if best_bid >= best_ask {
+                return Err(error::risk(format!(
+                    "Adjusting {} by {} would cross the book at bid {} ask {}",
+                    self.get_pair(),
+                    adjustment,
+                    best_bid,
+                    best_ask
+                )));
+            }
+        }
 
-    lazy_static! {
-        static ref PAIR: String = "BTC/ETH".to_string();
+        let logged = self.log(&Command::Adjust(adjustment))?;
+        let before = self.all_orders();
+        self.apply_adjust(adjustment);
+        self.settle(&before, &[], logged.sequence)?;
+        if self.advance(logged.sequence) {
+            self.write(self.snapshot_tasks())?;
+        }
+
+        // every resting order that moved is amended for the feed and L3
+        let prices = before
+            .iter()
+            .map(|o| (o.id, o.price))
+            .collect::<HashMap<_, _>>();
+        let active = self.join_active_orders();
+        let repriced = active
+            .iter()
+            .filter(|o| prices.get(&o.id) != Some(&o.price))
+            .copied()
+            .collect::<Vec<_>>();
+        self.emit(|| {
+            repriced
+                .iter()
+                .map(|o| Event::new(self.get_pair().as_str(), EventKind::Amended, *o))
+                .collect()
+        });
+        self.publish(|| {
+            repriced
+                .iter()
+                .map(|o| OrderBookEvent::OrderAmended {
+                    pair: self.get_pair().to_string(),
+                    order: *o,
+                })
+                .collect()
+        });
+        Ok(active)
     }
 
-    fn cleanup() {
-        if Path::new("mock.db").exists() {
-            fs::remove_dir_all("mock.db").expect("could not delete mock.db")
+    // Cancels good-til-date orders that expired by `now`, through the journal
+    // like any other cancel.
+    pub fn expire(&mut self, now: u64) -> anyhow::Result<Vec<Order>> {
+        let filter = CancelFilter {
+            expired_at: Some(now),
+            ..CancelFilter::default()
+        };
+        // sweeps that find nothing stay out of the journal
+        if !self
+            .all_orders()
+            .iter()
+            .any(|o| o.is_open() && filter.matches(o))
+        {
+            return Ok(Vec::new());
         }
+        self.cancel_where(&filter)
     }
 
-    #[test]
-    fn it_should_load_orders_from_db() {
-        let db = Arc::new(Mutex::new(Database::new(Some("mock.db".to_string()))));
-        let mut order_book_builder = OrderBook::default();
-        order_book_builder.set_pair(PAIR.clone());
-        order_book_builder.set_db(db.clone());
+    pub fn append_buy_order(&mut self, order: Order) -> anyhow::Result<OrderAck> {
+        let started = Instant::now();
+        let result = match order.order_type {
+            OrderType::Buy => self
+                .ensure_writable()
+                .and_then(|_| self.place(order, started)),
+            _ => Err(error::validation(
+                "Invalid order type, expected Buy order type but Sell provided",
+            )),
+        };
+        self.emit_rejection(&order, &result);
+        result
+    }
 
-        let buy = Order::new(1, 10, OrderType::Buy);
-        let sell = Order::new(1, 20, OrderType::Sell);
+    pub fn append_sell_order(&mut self, order: Order) -> anyhow::Result<OrderAck> {
+        let started = Instant::now();
+        let result = match order.order_type {
+            OrderType::Sell => self
+                .ensure_writable()
+                .and_then(|_| self.place(order, started)),
+            _ => Err(error::validation(
+                "Invalid order type, expected Sell order type but Buy provided",
+            )),
+        };
+        self.emit_rejection(&order, &result);
+        result
+    }
 
-        let binding = db.clone();
-        let db_guard = binding.lock().unwrap();
+    fn place(&mut self, order: Order, started: Instant) -> anyhow::Result<OrderAck> {
+        self.ensure_open()?;
+        self.ensure_tradable(&order)?;
+        self.ensure_peg_reference(&order)?;
+        self.ensure_unexpired(&order)?;
+        let order = self.price_market(order)?;
+        self.ensure_fillable(&order)?;
+        self.ensure_funded(&[], &[order])?;
+        let validation = started.elapsed();
 
-        db_guard
-            .set(
-                &PAIR.clone(),
-                &Item {
-                    active_orders: vec![buy.clone(), sell.clone()],
-                    fulfilled_orders: vec![],
-                },
-            )
-            .unwrap();
-        drop(db_guard);
+        let logging = Instant::now();
+        let logged = self.log(&Command::Place(order))?;
+        let logging = logging.elapsed();
 
-        let mut order_book = order_book_builder.build();
-        order_book.load();
+        let before = self.all_orders();
+        let (matching, trades) = self.apply_place(order, Some(logged.sequence))?;
+        let trades = self.charge(trades)?;
+        self.settle(&before, &trades, logged.sequence)?;
+        let snapshot = self.advance(logged.sequence);
 
-        let binding_buy_order = order_book.buy_orders.clone();
-        let buy_orders_guard = binding_buy_order.lock().unwrap();
+        let persisting = Instant::now();
+        self.persist(matching, logged.sequence, trades.clone(), snapshot)?;
+        let persistence = logging + persisting.elapsed();
+        let placed = self
+            .all_orders()
+            .into_iter()
+            .find(|o| o.id == order.id)
+            .unwrap_or(order);
+        let ack = OrderAck::placed(placed, &trades);
 
-        let binding_sell_order = order_book.sell_orders.clone();
-        let sell_order_guard = binding_sell_order.lock().unwrap();
+        self.emit(|| {
+            let pair = self.get_pair().as_str();
+            std::iter::once(Event::new(pair, EventKind::Accepted, order))
+                .chain(self.filled_since(&before).into_iter().map(|o| {
+                    let kind = match o.order_status {
+                        OrderStatus::Filled => EventKind::Filled,
+                        _ => EventKind::PartiallyFilled,
+                    };
+                    Event::new(pair, kind, o)
+                }))
+                .chain(
+                    self.all_orders()
+                        .into_iter()
+                        .filter(|o| o.id == order.id && o.order_status == OrderStatus::Cancelled)
+                        .map(|o| Event::new(pair, EventKind::Cancelled, o)),
+                )
+                .collect()
+        });
+        self.publish(|| {
+            let pair = self.get_pair().to_string();
+            let mut events = vec![OrderBookEvent::OrderAccepted {
+                pair: pair.clone(),
+                order: placed,
+            }];
+            events.extend(
+                trades
+                    .into_iter()
+                    .map(|trade| OrderBookEvent::TradeExecuted {
+                        pair: pair.clone(),
+                        trade,
+                    }),
+            );
+            events.extend(
+                self.filled_since(&before)
+                    .into_iter()
+                    .map(|o| match o.order_status {
+                        OrderStatus::Filled => OrderBookEvent::OrderFilled {
+                            pair: pair.clone(),
+                            order: o,
+                        },
+                        _ => OrderBookEvent::OrderPartiallyFilled {
+                            pair: pair.clone(),
+                            order: o,
+                        },
+                    }),
+            );
+            // the unfilled rest of a market or IOC/FOK order
+            if placed.order_status == OrderStatus::Cancelled {
+                events.push(OrderBookEvent::OrderCancelled {
+                    pair,
+                    order: placed,
+                });
+            }
+            events
+        });
 
-        assert_eq!(*buy_orders_guard, vec![buy]);
-        assert_eq!(*sell_order_guard, vec![sell]);
+        self.check_latency(&order, StageTimings::new(validation, matching, persistence));
+        Ok(ack)
+    }
 
-        cleanup();
+    fn check_latency(&self, order: &Order, timings: StageTimings) {
+        if let Some(budget) = self.latency_budget {
+            let exceeded = budget.exceeded(&timings);
+            if exceeded.is_empty() {
+                return;
+            }
+            // the order is already applied, a lost report must not fail it
+            let report = latency::report(self.get_pair().as_str(), order.id, timings, exceeded);
+            if let Err(e) = self.write(vec![Task::SlowPath(report)]) {
+                eprintln!("could not record slow path report: {e}");
+            }
+        }
     }
 
-    #[test]
-    // Buy | Sell
-    //  5 | 4
-    //  4 | 3
-    //  3 | 9
-    fn match_orders_test() {
-        let db = Arc::new(Mutex::new(Database::new(Some("mock.db".to_string()))));
-        let mut order_book_builder = OrderBook::default();
-        order_book_builder.set_pair(PAIR.clone());
-        order_book_builder.set_db(db.clone());
+    // Rebuilds the book from the command log instead of the persisted snapshot
+    // and overwrites the snapshot with the result. Returns the replayed count.
+    pub fn recover(&mut self) -> anyhow::Result<usize> {
+        if self.read_only {
+            return Err(error::state(format!(
+                "Order book for {} is read-only, recovery must run on the primary",
+                self.get_pair()
+            )));
+        }
+        let (base, commands) = {
+            let guard = self.db_guard();
+            let pair = self.get_pair().as_str();
+            let base = journal::compaction(&guard, pair)?.map(|c| c.base);
+            (base, command_log::commands(&guard, Some(pair))?)
+        };
+        if commands.is_empty() && base.is_none() {
+            return Err(error::not_found(format!(
+                "No logged commands for {}",
+                self.get_pair()
+            )));
+        }
 
-        let mut order_book = order_book_builder.build();
+        self.buy_orders = side(Vec::new());
+        self.sell_orders = side(Vec::new());
+        if let Some(base) = base {
+            self.sequence = base.sequence;
+            self.load_bulk(
+                base.active_orders
+                    .into_iter()
+                    .chain(base.fulfilled_orders)
+                    .collect(),
+            );
+        }
+        let settled = accounts::settled(&self.home_guard(), self.get_pair().as_str())?;
+        let recorded = trade::recorded(&self.db_guard(), self.get_pair().as_str())?;
+        for logged in &commands {
+            self.reapply(logged, settled, recorded, false)?;
+        }
+        self.checkpoint()?;
+        Ok(commands.len())
+    }
 
-        let orders: [Order; 6] = [
-            Order::new(1, 4, OrderType::Sell),
-            Order::new(1, 3, OrderType::Sell),
-            Order::new(1, 9, OrderType::Sell),
-            //
-            Order::new(1, 5, OrderType::Buy),
-            Order::new(1, 4, OrderType::Buy),
-            Order::new(1, 3, OrderType::Buy),
-        ];
+    // Replays the pair's command log up to and including timestamp on a
+    // scratch book, the live book and the database are left untouched.
+    pub fn book_at(&self, timestamp: u64) -> anyhow::Result<Item> {
+        let (compaction, commands) = {
+            let guard = self.db_guard();
+            let pair = self.get_pair().as_str();
+            (
+                journal::compaction(&guard, pair)?,
+                command_log::commands(&guard, Some(pair))?,
+            )
+        };
+        let mut scratch = OrderBook::default();
+        if let Some(compaction) = compaction {
+            if timestamp < compaction.through {
+                return Err(error::not_found(format!(
+                    "Journal for {} is compacted through {}, nothing earlier is kept",
+                    self.get_pair(),
+                    compaction.through
+                )));
+            }
+            scratch.sequence = compaction.base.sequence;
+            scratch.load_bulk(
+                compaction
+                    .base
+                    .active_orders
+                    .into_iter()
+                    .chain(compaction.base.fulfilled_orders)
+                    .collect(),
+            );
+        }
+        for logged in commands.iter().take_while(|c| c.timestamp <= timestamp) {
+            scratch.apply_scratch(&logged.command);
+            scratch.sequence = Some(logged.sequence);
+        }
+        Ok(scratch.snapshot())
+    }
 
-        for order in orders {
-            if order.order_type == OrderType::Buy {
-                order_book
-                    .append_buy_order(order)
-                    .expect("could not append buy order");
-            } else {
-                order_book
-                    .append_sell_order(order)
-                    .expect("could not append sell order");
+    // Events are only built when a log is attached. A failed write is reported
+    // but never fails the order, the command log stays the source of truth.
+    fn emit<F>(&self, events: F)
+    where
+        F: FnOnce() -> Vec<Event>,
+    {
+        if let Some(event_log) = &self.event_log {
+            if let Err(e) = event_log.append(&events()) {
+                eprintln!("could not write event log: {e}");
             }
         }
+    }
 
-        let filled_buy_orders: Vec<i32> = order_book
-            .get_filled_buy_orders()
-            .into_iter()
-            .map(|o| o.price)
-            .collect();
-        let filled_sell_orders: Vec<i32> = order_book
-            .get_filled_sell_orders()
+    // Like emit, events are only built when someone listens.
+    fn publish<F>(&self, events: F)
+    where
+        F: FnOnce() -> Vec<OrderBookEvent>,
+    {
+        if !self.events.is_empty() {
+            self.events.publish(events());
+        }
+    }
+
+    // Orders that filled some quantity since `before`, in book order.
+    fn filled_since(&self, before: &[Order]) -> Vec<Order> {
+        let filled_before = |o: &Order| {
+            before
+                .iter()
+                .find(|b| b.id == o.id)
+                .map_or(0, |b| b.filled_quantity)
+        };
+        self.all_orders()
             .into_iter()
-            .map(|o| o.price)
-            .collect();
+            .filter(|o| o.filled_quantity > filled_before(o))
+            .collect()
+    }
 
-        assert_eq!(filled_buy_orders, vec![5, 4]);
-        assert_eq!(filled_sell_orders, vec![3, 4]);
+    fn emit_rejection<T>(&self, order: &Order, result: &anyhow::Result<T>) {
+        if let Err(e) = result {
+            self.emit(|| {
+                vec![Event {
+                    reason: Some(e.to_string()),
+                    ..Event::new(self.get_pair().as_str(), EventKind::Rejected, *order)
+                }]
+            });
+            self.publish(|| {
+                vec![OrderBookEvent::OrderRejected {
+                    pair: self.get_pair().to_string(),
+                    order: *order,
+                    reason: e.to_string(),
+                }]
+            });
+        }
+    }
 
-        cleanup();
+    fn log(&self, command: &Command) -> anyhow::Result<LoggedCommand> {
+        command_log::append(&self.db_guard(), self.get_pair().as_str(), command)
+    }
+
+    // Returns the trades the command executed, before fees.
+    fn apply(&mut self, logged: &LoggedCommand) -> anyhow::Result<Vec<Trade>> {
+        let trades = match &logged.command {
+            Command::Place(order) => self.apply_place(*order, Some(logged.sequence))?.1,
+            Command::CancelWhere(filter) => {
+                self.apply_cancel(filter);
+                Vec::new()
+            }
+            Command::Adjust(adjustment) => {
+                self.apply_adjust(*adjustment);
+                Vec::new()
+            }
+            Command::Amend(amendment) => match self.requeue(amendment) {
+                Some(order) => self.apply_place(order, Some(logged.sequence))?.1,
+                None => Vec::new(),
+            },
+        };
+        self.sequence = Some(logged.sequence);
+        Ok(trades)
+    }
+
+    // Like apply for books without a database, a panic is not supervised.
+    fn apply_scratch(&self, command: &Command) {
+        match command {
+            Command::Place(order) => self.place_scratch(*order),
+            Command::CancelWhere(filter) => {
+                self.apply_cancel(filter);
+            }
+            Command::Adjust(adjustment) => self.apply_adjust(*adjustment),
+            Command::Amend(amendment) => {
+                if let Some(order) = self.requeue(amendment) {
+                    self.place_scratch(order);
+                }
+            }
+        }
+    }
+
+    fn place_scratch(&self, order: Order) {
+        self.insert(order);
+        self.reprice_pegged();
+        self.match_orders(Some(order.id));
+        self.cancel_unfilled();
+    }
+
+    // Amended in place while it keeps its priority. Otherwise it leaves its
+    // queue and is returned, amended, to be placed again.
+    fn requeue(&self, amendment: &Amendment) -> Option<Order> {
+        for side in [&self.buy_orders, &self.sell_orders] {
+            let mut orders = side.lock().unwrap();
+            if let Some(index) = orders.iter().position(|o| o.id == amendment.id) {
+                if amendment.requeued_at.is_none() {
+                    amendment.apply(&mut orders[index]);
+                    return None;
+                }
+                let mut order = orders.remove(index);
+                amendment.apply(&mut order);
+                return Some(order);
+            }
+        }
+        None
+    }
+
+    fn apply_cancel(&self, filter: &CancelFilter) -> Vec<Order> {
+        let mut cancelled = Vec::new();
+        let mut buy_orders = self.buy_orders.lock().unwrap();
+        let mut sell_orders = self.sell_orders.lock().unwrap();
+        for order in buy_orders.iter_mut().chain(sell_orders.iter_mut()) {
+            if order.is_open() && filter.matches(order) {
+                order.update_order_status(OrderStatus::Cancelled);
+                cancelled.push(*order);
+            }
+        }
+        cancelled
+    }
+
+    // Rounding can bring orders to the same price, so the queues are rebuilt.
+    fn apply_adjust(&self, adjustment: PriceAdjustment) {
+        for side in [&self.buy_orders, &self.sell_orders] {
+            let mut orders = side.lock().unwrap();
+            for order in orders.iter_mut() {
+                order.price = adjustment
+                    .apply(order.price)
+                    .expect("adjustment was validated before it was logged");
+            }
+            orders.sort_by(Order::queue_cmp);
+        }
+    }
+
+    fn insert(&self, order: Order) {
+        let side = match order.order_type {
+            OrderType::Buy => &self.buy_orders,
+            OrderType::Sell => &self.sell_orders,
+        };
+        side.lock()
+            .unwrap()
+            .sorted_insert_by(order, |e, incoming| e.queues_ahead_of(incoming));
+    }
+
+    fn apply_place(
+        &mut self,
+        order: Order,
+        sequence: Option<u64>,
+    ) -> anyhow::Result<(Duration, Vec<Trade>)> {
+        self.insert(order);
+        let started = Instant::now();
+        let trades = self.supervised(&order, sequence, |order_book| {
+            order_book.reprice_pegged();
+            let trades = order_book.match_orders(Some(order.id));
+            order_book.cancel_unfilled();
+            trades
+        })?;
+        Ok((started.elapsed(), trades))
+    }
+
+    // Trades are recorded with the command at `sequence` that executed them.
+    fn persist(
+        &self,
+        match_latency: Duration,
+        sequence: u64,
+        trades: Vec<Trade>,
+        snapshot: bool,
+    ) -> anyhow::Result<()> {
+        let mut tasks = vec![Task::Telemetry {
+            sample: self.telemetry_sample(match_latency),
+            retention: self
+                .telemetry_retention
+                .unwrap_or(telemetry::DEFAULT_RETENTION),
+        }];
+        if snapshot {
+            tasks.extend(self.snapshot_tasks());
+        }
+        if !trades.is_empty() {
+            tasks.push(Task::Trades {
+                pair: self.get_pair().to_string(),
+                sequence,
+                trades,
+            });
+        }
+        self.write(tasks)
+    }
+
+    // Moves the book to the command at `sequence` and returns whether it is
+    // due a snapshot. A book without a journal position is snapshotted
+    // straight away, which also makes new pairs show up in the database.
+    fn advance(&mut self, sequence: u64) -> bool {
+        let snapshot = self.sequence.is_none()
+            || self.journalled + 1
+                >= self
+                    .snapshot_interval
+                    .unwrap_or(journal::DEFAULT_SNAPSHOT_INTERVAL);
+        self.sequence = Some(sequence);
+        self.journalled = match snapshot {
+            true => 0,
+            false => self.journalled + 1,
+        };
+        snapshot
+    }
+
+    fn checkpoint(&mut self) -> anyhow::Result<()> {
+        self.journalled = 0;
+        self.write(self.snapshot_tasks())
+    }
+
+    // Compaction runs behind the snapshot so it only truncates what is covered.
+    fn snapshot_tasks(&self) -> Vec<Task> {
+        vec![
+            Task::Snapshot {
+                pair: self.get_pair().to_string(),
+                item: self.snapshot(),
+            },
+            Task::Compact {
+                pair: self.get_pair().to_string(),
+                retention: self.journal_retention.unwrap_or(journal::DEFAULT_RETENTION),
+            },
+        ]
+    }
+
+    // All writes go through here so queued snapshots never overwrite newer ones.
+    fn write(&self, tasks: Vec<Task>) -> anyhow::Result<()> {
+        match &self.writer {
+            Some(writer) => writer.submit(tasks),
+            None => {
+                let guard = self.db_guard();
+                tasks.iter().try_for_each(|task| task.apply(&guard))
+            }
+        }
+    }
+
+    fn telemetry_sample(&self, match_latency: Duration) -> TelemetrySample {
+        TelemetrySample {
+            timestamp: telemetry::now_millis(),
+            pair: self.get_pair().to_string(),
+            match_latency_micros: match_latency.as_micros() as u64,
+            buy_orders: self.get_active_buy_orders().len(),
+            sell_orders: self.get_active_sell_orders().len(),
+        }
+    }
+
+    // Pegs reference the best firm (non-pegged, displayed) prices so they
+    // cannot chase each other.
+    fn firm_bbo(&self) -> (Option<i32>, Option<i32>) {
+        let firm =
+            |o: &&Order| o.is_open() && o.peg.is_none() && !o.hidden && o.kind == OrderKind::Limit;
+        let best_bid = self
+            .buy_orders
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(firm)
+            .map(|o| o.price)
+            .max();
+        let best_ask = self
+            .sell_orders
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(firm)
+            .map(|o| o.price)
+            .min();
+        (best_bid, best_ask)
+    }
+
+    // A market order takes the price of the deepest opposite level it would
+    // reach, so it matches and replays like a marketable limit order.
+    fn price_market(&self, order: Order) -> anyhow::Result<Order> {
+        if order.kind != OrderKind::Market {
+            return match order.execution {
+                Execution::Sweep => Ok(order),
+                execution => Err(error::validation(format!(
+                    "Execution {:?} only applies to market orders",
+                    execution
+                ))),
+            };
+        }
+        let mut opposite = match order.order_type {
+            OrderType::Buy => self.get_active_sell_orders(),
+            OrderType::Sell => self.get_active_buy_orders(),
+        };
+        opposite.retain(|resting| !resting.all_or_none);
+        // priced at the last level it may reach, matching stops there
+        let price = match order.execution {
+            Execution::BestLevel => opposite.first().map(|resting| resting.price),
+            Execution::Sweep => {
+                let mut remaining = order.quantity;
+                let mut price = None;
+                for resting in &opposite {
+                    if remaining <= 0 {
+                        break;
+                    }
+                    remaining -= resting.remaining();
+                    price = Some(resting.price);
+                }
+                price
+            }
+        };
+        let price = price.ok_or_else(|| {
+            error::risk(format!(
+                "No liquidity for market order on {}",
+                self.get_pair()
+            ))
+        })?;
+        Ok(Order { price, ..order })
+    }
+
+    // Whatever a market or IOC/FOK order could not fill is cancelled instead
+    // of resting.
+    fn cancel_unfilled(&self) {
+        for side in [&self.buy_orders, &self.sell_orders] {
+            for order in side.lock().unwrap().iter_mut() {
+                let immediate =
+                    order.kind == OrderKind::Market || order.time_in_force.is_immediate();
+                if immediate && order.is_open() {
+                    order.update_order_status(OrderStatus::Cancelled);
+                }
+            }
+        }
+    }
+
+    fn ensure_unexpired(&self, order: &Order) -> anyhow::Result<()> {
+        match order.time_in_force.expires_at() {
+            Some(expires_at) if expires_at <= telemetry::now_millis() => {
+                Err(error::validation(format!(
+                    "Order {} expired at {} before it was placed",
+                    order.id, expires_at
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // A FOK order is only placed when the crossing side holds its full
+    // quantity, hidden orders included.
+    fn ensure_fillable(&self, order: &Order) -> anyhow::Result<()> {
+        if let Some(min_qty) = order
+            .min_qty
+            .filter(|min| !(1..=order.quantity).contains(min))
+        {
+            return Err(error::validation(format!(
+                "Invalid min_qty {}, expected 1 to the order's quantity {}",
+                min_qty, order.quantity
+            )));
+        }
+        if order.all_or_none
+            && (order.kind == OrderKind::Market || order.time_in_force.is_immediate())
+        {
+            return Err(error::validation(
+                "All-or-none orders rest until they fill entirely, use fok to fill at once",
+            ));
+        }
+        if order.time_in_force != TimeInForce::Fok {
+            return Ok(());
+        }
+        let available = match order.order_type {
+            OrderType::Buy => self.get_active_sell_orders(),
+            OrderType::Sell => self.get_active_buy_orders(),
+        }
+        .iter()
+        .filter(|resting| !resting.all_or_none)
+        .filter(|resting| match order.order_type {
+            OrderType::Buy => resting.price <= order.price,
+            OrderType::Sell => resting.price >= order.price,
+        })
+        .map(Order::remaining)
+        .sum::<i32>();
+        if available < order.quantity {
+            return Err(error::risk(format!(
+                "Fill or kill order {} for {} can only fill {} on {}",
+                order.id,
+                order.quantity,
+                available,
+                self.get_pair()
+            )));
+        }
+        Ok(())
+    }
+
+    fn ensure_peg_reference(&self, order: &Order) -> anyhow::Result<()> {
+        let (best_bid, best_ask) = self.firm_bbo();
+        match order.peg {
+            Some(peg) if peg.price(best_bid, best_ask).is_none() => Err(error::risk(format!(
+                "No reference price for pegged order {:?} on {}",
+                peg,
+                self.get_pair()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn reprice_pegged(&self) {
+        let (best_bid, best_ask) = self.firm_bbo();
+        let mut buy_orders = self.buy_orders.lock().unwrap();
+        let mut sell_orders = self.sell_orders.lock().unwrap();
+
+        for orders in [&mut *buy_orders, &mut *sell_orders] {
+            let mut repriced = false;
+            for order in orders.iter_mut() {
+                if !order.is_open() {
+                    continue;
+                }
+                if let Some(price) = order.peg.and_then(|peg| peg.price(best_bid, best_ask)) {
+                    repriced |= order.price != price;
+                    order.price = price;
+                }
+            }
+            if repriced {
+                let queued = std::mem::take(orders);
+                for order in queued {
+                    orders.sorted_insert_by(order, |e, incoming| e.queues_ahead_of(incoming));
+                }
+            }
+        }
+    }
+
+    // Runs the crossing loop on the calling thread and returns the fills in
+    // the order they happened. Only the in-memory book changes, nothing is
+    // logged or persisted.
+    pub fn match_now(&self) -> Vec<Trade> {
+        cross(
+            &self.buy_orders,
+            &self.sell_orders,
+            telemetry::now_millis(),
+            None,
+        )
+    }
+
+    // The same loop on a matcher thread, with the order being placed as the
+    // taker. The timestamp is taken here so a clock overridden on the calling
+    // thread still applies.
+    fn match_orders(&self, taker: Option<Uuid>) -> Vec<Trade> {
+        let buy_orders = sync::Arc::clone(&self.buy_orders);
+        let sell_orders = sync::Arc::clone(&self.sell_orders);
+        let timestamp = telemetry::now_millis();
+
+        let t = sync::thread::spawn(move || cross(&buy_orders, &sell_orders, timestamp, taker));
+
+        t.join().expect("could not join thread")
+    }
+}
+
+// Best bid against best ask until the book no longer crosses, each match
+// fills the smaller remaining quantity on both orders. All-or-none orders sit
+// this out and get their own pass afterwards, see fill_all_or_none. A taker
+// with a min_qty its first match falls short of is cancelled untouched, what
+// it has left below min_qty once matching stops is cancelled too.
+fn cross(buy_orders: &Side, sell_orders: &Side, timestamp: u64, taker: Option<Uuid>) -> Vec<Trade> {
+    let mut trades = Vec::new();
+    let mut buy_orders = buy_orders.lock().unwrap();
+    let mut sell_orders = sell_orders.lock().unwrap();
+    let continuous = |o: &&mut Order| o.is_open() && !o.all_or_none;
+    let mut bids = buy_orders.iter_mut().filter(continuous).peekable();
+    let mut asks = sell_orders.iter_mut().filter(continuous).peekable();
+    let short = |o: &Order, quantity: i32| {
+        Some(o.id) == taker && o.min_qty.is_some_and(|min| quantity < min)
+    };
+
+    while let (Some(bid), Some(ask)) = (bids.peek_mut(), asks.peek_mut()) {
+        if bid.price < ask.price {
+            break;
+        }
+        let quantity = bid.remaining().min(ask.remaining());
+        if let Some(first) = [&mut **bid, &mut **ask]
+            .into_iter()
+            .find(|o| o.filled_quantity == 0 && short(o, quantity))
+        {
+            first.update_order_status(OrderStatus::Cancelled);
+            break;
+        }
+        trades.push(Trade::between(bid, ask, quantity, timestamp, taker));
+        bid.fill(quantity);
+        ask.fill(quantity);
+        if !bid.is_open() {
+            bids.next();
+        }
+        if !ask.is_open() {
+            asks.next();
+        }
+    }
+    drop((bids, asks));
+    // bids get the first pick of the other side's orders
+    fill_all_or_none(
+        &mut buy_orders,
+        &mut sell_orders,
+        timestamp,
+        taker,
+        &short,
+        &mut trades,
+    );
+    fill_all_or_none(
+        &mut sell_orders,
+        &mut buy_orders,
+        timestamp,
+        taker,
+        &short,
+        &mut trades,
+    );
+    for order in buy_orders.iter_mut().chain(sell_orders.iter_mut()) {
+        if order.is_open() && short(order, order.remaining()) {
+            order.update_order_status(OrderStatus::Cancelled);
+        }
+    }
+    trades
+}
+
+// Each open all-or-none order, in queue order, trades only when the crossing
+// orders on the other side can fill all of it at once, taken in their own
+// queue order. Another all-or-none order joins only if it fills entirely too,
+// otherwise it is passed over for the next in line.
+fn fill_all_or_none(
+    orders: &mut [Order],
+    opposite: &mut [Order],
+    timestamp: u64,
+    taker: Option<Uuid>,
+    short: &dyn Fn(&Order, i32) -> bool,
+    trades: &mut Vec<Trade>,
+) {
+    for order in orders.iter_mut().filter(|o| o.is_open() && o.all_or_none) {
+        let crosses = |resting: &Order| match order.order_type {
+            OrderType::Buy => order.price >= resting.price,
+            OrderType::Sell => order.price <= resting.price,
+        };
+        let mut needed = order.remaining();
+        let mut fills = Vec::new();
+        for (index, resting) in opposite.iter().enumerate().filter(|(_, o)| o.is_open()) {
+            if needed == 0 || !crosses(resting) {
+                break;
+            }
+            let quantity = resting.remaining().min(needed);
+            let whole = !resting.all_or_none || quantity == resting.remaining();
+            if whole && !(resting.filled_quantity == 0 && short(resting, quantity)) {
+                needed -= quantity;
+                fills.push((index, quantity));
+            }
+        }
+        if needed > 0 {
+            continue;
+        }
+        for (index, quantity) in fills {
+            let resting = &mut opposite[index];
+            trades.push(match order.order_type {
+                OrderType::Buy => Trade::between(order, resting, quantity, timestamp, taker),
+                OrderType::Sell => Trade::between(resting, order, quantity, timestamp, taker),
+            });
+            order.fill(quantity);
+            resting.fill(quantity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{self, Clock};
+    use crate::error::ErrorKind;
+    use crate::order::peg::Peg;
+    use crate::writer::AckMode;
+    use lazy_static::lazy_static;
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+    use test_utils::shared_temp_db;
+
+    lazy_static! {
+        static ref PAIR: Symbol = Symbol::parse("BTC/ETH").unwrap();
+    }
+
+    // The book as a restart would load it, snapshot plus journal.
+    fn loaded(db: &Arc<Mutex<Database>>) -> Item {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        order_book.load().unwrap();
+        order_book.snapshot()
+    }
+
+    #[test]
+    fn it_should_load_orders_from_db() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+
+        let buy = Order::new(1, 10, OrderType::Buy);
+        let sell = Order::new(1, 20, OrderType::Sell);
+
+        let binding = db.clone();
+        let db_guard = binding.lock().unwrap();
+
+        db_guard
+            .set(
+                PAIR.as_str(),
+                &Item {
+                    active_orders: vec![buy, sell],
+                    fulfilled_orders: vec![],
+                    sequence: None,
+                },
+            )
+            .unwrap();
+        drop(db_guard);
+
+        let mut order_book = order_book_builder.build().unwrap();
+        order_book.load().unwrap();
+
+        let binding_buy_order = order_book.buy_orders.clone();
+        let buy_orders_guard = binding_buy_order.lock().unwrap();
+
+        let binding_sell_order = order_book.sell_orders.clone();
+        let sell_order_guard = binding_sell_order.lock().unwrap();
+
+        assert_eq!(*buy_orders_guard, vec![buy]);
+        assert_eq!(*sell_order_guard, vec![sell]);
+    }
+
+    #[test]
+    fn load_bulk_builds_the_same_queues_as_inserting_one_by_one() {
+        let mut orders = Vec::new();
+        for (price, hidden) in [
+            (10, true),
+            (12, false),
+            (10, false),
+            (12, true),
+            (10, false),
+        ] {
+            let mut buy = Order::new(1, price, OrderType::Buy);
+            buy.update_hidden(hidden);
+            let mut sell = Order::new(1, price + 10, OrderType::Sell);
+            sell.update_hidden(hidden);
+            orders.extend([buy, sell]);
+        }
+
+        let inserted = OrderBook::default();
+        orders.iter().for_each(|o| inserted.insert(*o));
+        let mut bulk = OrderBook::default();
+        bulk.load_bulk(orders);
+
+        assert_eq!(bulk.get_buy_orders(), inserted.get_buy_orders());
+        assert_eq!(bulk.get_sell_orders(), inserted.get_sell_orders());
+        assert_eq!(
+            bulk.get_buy_orders()
+                .iter()
+                .map(|o| (o.price, o.hidden))
+                .collect::<Vec<_>>(),
+            vec![
+                (12, false),
+                (12, true),
+                (10, false),
+                (10, false),
+                (10, true)
+            ]
+        );
+    }
+
+    #[test]
+    // Buy | Sell
+    //  5 | 4
+    //  4 | 3
+    //  3 | 9
+    fn match_orders_test() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let orders: [Order; 6] = [
+            Order::new(1, 4, OrderType::Sell),
+            Order::new(1, 3, OrderType::Sell),
+            Order::new(1, 9, OrderType::Sell),
+            //
+            Order::new(1, 5, OrderType::Buy),
+            Order::new(1, 4, OrderType::Buy),
+            Order::new(1, 3, OrderType::Buy),
+        ];
+
+        for order in orders {
+            if order.order_type == OrderType::Buy {
+                order_book
+                    .append_buy_order(order)
+                    .expect("could not append buy order");
+            } else {
+                order_book
+                    .append_sell_order(order)
+                    .expect("could not append sell order");
+            }
+        }
+
+        let filled_buy_orders: Vec<i32> = order_book
+            .get_filled_buy_orders()
+            .into_iter()
+            .map(|o| o.price)
+            .collect();
+        let filled_sell_orders: Vec<i32> = order_book
+            .get_filled_sell_orders()
+            .into_iter()
+            .map(|o| o.price)
+            .collect();
+
+        assert_eq!(filled_buy_orders, vec![5, 4]);
+        assert_eq!(filled_sell_orders, vec![3, 4]);
+    }
+
+    #[test]
+    fn matches_best_price_then_earliest_order() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let worse = Order::new(1, 11, OrderType::Sell);
+        let later = Order::new(1, 10, OrderType::Sell);
+        let mut earlier = Order::new(1, 10, OrderType::Sell);
+        earlier.created_at -= 1_000;
+        for sell in [worse, later, earlier] {
+            order_book.append_sell_order(sell).unwrap();
+        }
+
+        order_book
+            .append_buy_order(Order::new(1, 11, OrderType::Buy))
+            .unwrap();
+        let filled = order_book.get_filled_sell_orders();
+        assert_eq!(
+            filled.iter().map(|o| o.id).collect::<Vec<_>>(),
+            vec![earlier.id]
+        );
+
+        order_book
+            .append_buy_order(Order::new(1, 11, OrderType::Buy))
+            .unwrap();
+        let active = order_book.get_active_sell_orders();
+        assert_eq!(
+            active.iter().map(|o| o.id).collect::<Vec<_>>(),
+            vec![worse.id]
+        );
+    }
+
+    #[test]
+    fn match_now_returns_the_fills_in_order() {
+        let mut asks = [(2, 10), (3, 11)].map(|(q, p)| Order::new(q, p, OrderType::Sell));
+        let mut bid = Order::new(4, 12, OrderType::Buy);
+        asks[1].created_at = asks[0].created_at;
+        bid.created_at = asks[0].created_at + 1;
+        let mut order_book = OrderBook::default();
+        order_book.load_bulk(vec![asks[0], asks[1], bid]);
+
+        let trades = order_book.match_now();
+
+        assert_eq!(
+            trades
+                .iter()
+                .map(|t| (t.sell_order_id, t.price, t.quantity, t.aggressor))
+                .collect::<Vec<_>>(),
+            vec![
+                (asks[0].id, 10, 2, OrderType::Buy),
+                (asks[1].id, 11, 2, OrderType::Buy)
+            ]
+        );
+        assert!(trades.iter().all(|t| t.buy_order_id == bid.id));
+        assert!(order_book.match_now().is_empty());
+        assert_eq!(order_book.get_active_sell_orders()[0].remaining(), 1);
+    }
+
+    #[test]
+    fn subscribers_and_listeners_see_every_change() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+        let events = order_book.subscribe();
+        let heard = Arc::new(Mutex::new(0));
+        let counter = heard.clone();
+        order_book.add_listener(move |_| *counter.lock().unwrap() += 1);
+
+        let ask = Order::new(3, 10, OrderType::Sell);
+        let bid = Order::new(2, 10, OrderType::Buy);
+        order_book.append_sell_order(ask).unwrap();
+        order_book.append_buy_order(bid).unwrap();
+        order_book
+            .append_buy_order(Order::new(1, 12, OrderType::Sell))
+            .unwrap_err();
+        order_book.cancel_order(ask.id).unwrap();
+
+        let events = events.try_iter().collect::<Vec<_>>();
+        let names = events
+            .iter()
+            .map(|event| match event {
+                OrderBookEvent::OrderAccepted { .. } => "accepted",
+                OrderBookEvent::OrderRejected { .. } => "rejected",
+                OrderBookEvent::OrderFilled { .. } => "filled",
+                OrderBookEvent::OrderPartiallyFilled { .. } => "partially filled",
+                OrderBookEvent::OrderCancelled { .. } => "cancelled",
+                OrderBookEvent::OrderAmended { .. } => "amended",
+                OrderBookEvent::TradeExecuted { .. } => "trade",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "accepted",
+                "accepted",
+                "trade",
+                "filled",
+                "partially filled",
+                "rejected",
+                "cancelled"
+            ]
+        );
+        assert!(matches!(
+            &events[4],
+            OrderBookEvent::OrderPartiallyFilled { order, .. } if order.id == ask.id && order.remaining() == 1
+        ));
+        assert_eq!(*heard.lock().unwrap(), events.len());
+    }
+
+    #[test]
+    fn partial_fills_leave_the_remainder_resting() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let buy = Order::new(10, 5, OrderType::Buy);
+        let sell = Order::new(4, 5, OrderType::Sell);
+        order_book.append_buy_order(buy).unwrap();
+        order_book.append_sell_order(sell).unwrap();
+
+        let sells = order_book.get_filled_sell_orders();
+        assert_eq!(sells.len(), 1);
+        assert_eq!(sells[0].filled_quantity, 4);
+
+        let buys = order_book.get_active_buy_orders();
+        assert_eq!(buys.len(), 1);
+        assert_eq!(buys[0].order_status, OrderStatus::PartiallyFilled);
+        assert_eq!(buys[0].remaining(), 6);
+
+        order_book
+            .append_sell_order(Order::new(6, 4, OrderType::Sell))
+            .unwrap();
+        assert!(order_book.get_active_buy_orders().is_empty());
+        assert!(order_book.verify().is_empty());
+    }
+
+    #[test]
+    fn market_orders_sweep_the_book_and_never_rest() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let market = Order::market(1, OrderType::Buy);
+        assert!(order_book.append_buy_order(market).is_err());
+        assert!(order_book.get_buy_orders().is_empty());
+
+        for (quantity, price) in [(2, 10), (2, 11), (5, 12)] {
+            order_book
+                .append_sell_order(Order::new(quantity, price, OrderType::Sell))
+                .unwrap();
+        }
+        let bid = Order::new(1, 9, OrderType::Buy);
+        order_book.append_buy_order(bid).unwrap();
+
+        let market = Order::market(3, OrderType::Buy);
+        order_book.append_buy_order(market).unwrap();
+        let filled = order_book.get_filled_buy_orders();
+        assert_eq!(filled.len(), 1);
+        assert_eq!((filled[0].id, filled[0].price), (market.id, 11));
+        assert_eq!(
+            order_book
+                .get_active_sell_orders()
+                .iter()
+                .map(|o| (o.price, o.remaining()))
+                .collect::<Vec<_>>(),
+            vec![(11, 1), (12, 5)]
+        );
+
+        let market = Order::market(10, OrderType::Buy);
+        order_book.append_buy_order(market).unwrap();
+        let cancelled = order_book.join_cancelled_orders();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(
+            (cancelled[0].id, cancelled[0].filled_quantity),
+            (market.id, 6)
+        );
+        assert!(order_book.get_active_sell_orders().is_empty());
+        assert_eq!(order_book.get_active_buy_orders(), vec![bid]);
+        assert!(order_book.verify().is_empty());
+
+        // a best level order takes the top of the book and no more
+        for (quantity, price) in [(2, 10), (2, 11)] {
+            order_book
+                .append_sell_order(Order::new(quantity, price, OrderType::Sell))
+                .unwrap();
+        }
+        let mut best = Order::market(3, OrderType::Buy);
+        best.update_execution(Execution::BestLevel);
+        let ack = order_book.append_buy_order(best).unwrap();
+        assert_eq!(
+            ack.to_string(),
+            "Partially filled 2 in 1 trade(s), 1 cancelled"
+        );
+        assert_eq!(order_book.best_ask().unwrap().price, 11);
+        let mut limit = Order::new(1, 11, OrderType::Buy);
+        limit.update_execution(Execution::BestLevel);
+        let e = order_book.append_buy_order(limit).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+    }
+
+    #[test]
+    fn min_qty_orders_need_their_first_match_to_fill_the_minimum() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+        for (quantity, price) in [(2, 10), (5, 11)] {
+            order_book
+                .append_sell_order(Order::new(quantity, price, OrderType::Sell))
+                .unwrap();
+        }
+
+        // the best ask only holds 2, so nothing trades
+        let mut bid = Order::new(6, 11, OrderType::Buy);
+        bid.update_min_qty(Some(3));
+        let ack = order_book.append_buy_order(bid).unwrap();
+        assert_eq!(ack.to_string(), "Cancelled 6 unfilled");
+        assert_eq!(order_book.best_ask().unwrap().remaining(), 2);
+
+        // a first match of 2 clears the minimum, the 1 left below it is cancelled
+        let mut bid = Order::new(8, 11, OrderType::Buy);
+        bid.update_min_qty(Some(2));
+        let ack = order_book.append_buy_order(bid).unwrap();
+        assert_eq!(
+            ack.to_string(),
+            "Partially filled 7 in 2 trade(s), 1 cancelled"
+        );
+        assert!(order_book.get_active_buy_orders().is_empty());
+        assert!(order_book.verify().is_empty());
+
+        let mut bid = Order::new(2, 11, OrderType::Buy);
+        bid.update_min_qty(Some(3));
+        let e = order_book.append_buy_order(bid).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+    }
+
+    #[test]
+    fn all_or_none_orders_rest_until_they_fill_entirely() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let mut aon = Order::new(5, 10, OrderType::Buy);
+        aon.update_all_or_none(true);
+        order_book.append_buy_order(aon).unwrap();
+        // 3 can't fill it, so the ask rests through the all-or-none bid
+        let ask = Order::new(3, 9, OrderType::Sell);
+        let ack = order_book.append_sell_order(ask).unwrap();
+        assert_eq!(ack.to_string(), "Rested 3 at 9");
+        assert!(order_book.best_bid().is_none());
+        assert!(order_book.verify().is_empty());
+
+        // another all-or-none ask larger than what is left is passed over
+        let mut big = Order::new(4, 10, OrderType::Sell);
+        big.update_all_or_none(true);
+        order_book.append_sell_order(big).unwrap();
+        let ack = order_book
+            .append_sell_order(Order::new(2, 10, OrderType::Sell))
+            .unwrap();
+        assert_eq!(ack.to_string(), "Filled 2 in 1 trade(s)");
+        let filled = order_book.get_filled_buy_orders();
+        assert_eq!((filled[0].id, filled[0].filled_quantity), (aon.id, 5));
+        assert_eq!(order_book.get_filled_sell_orders().len(), 2);
+        assert_eq!(order_book.get_active_sell_orders(), vec![big]);
+        assert!(order_book.verify().is_empty());
+
+        let mut ioc = Order::new(1, 10, OrderType::Buy);
+        ioc.update_all_or_none(true);
+        ioc.update_time_in_force(TimeInForce::Ioc);
+        let e = order_book.append_buy_order(ioc).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+    }
+
+    #[test]
+    fn time_in_force_cancels_rejects_and_expires() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        let with_tif = |quantity, price, order_type, time_in_force| {
+            let mut order = Order::new(quantity, price, order_type);
+            order.update_time_in_force(time_in_force);
+            order
+        };
+
+        let ask = Order::new(2, 10, OrderType::Sell);
+        order_book.append_sell_order(ask).unwrap();
+
+        let fok = with_tif(3, 10, OrderType::Buy, TimeInForce::Fok);
+        let e = order_book.append_buy_order(fok).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Risk);
+        assert_eq!(order_book.get_active_sell_orders()[0].remaining(), 2);
+
+        let ioc = with_tif(3, 10, OrderType::Buy, TimeInForce::Ioc);
+        let ack = order_book.append_buy_order(ioc).unwrap();
+        assert_eq!(
+            ack.to_string(),
+            "Partially filled 2 in 1 trade(s), 1 cancelled"
+        );
+        assert!(order_book.get_active_buy_orders().is_empty());
+        let ioc = with_tif(1, 10, OrderType::Buy, TimeInForce::Ioc);
+        let ack = order_book.append_buy_order(ioc).unwrap();
+        assert!(matches!(ack, OrderAck::Cancelled { .. }));
+
+        let now = telemetry::now_millis();
+        let expired = with_tif(1, 9, OrderType::Buy, TimeInForce::Gtd { expires_at: now });
+        let e = order_book.append_buy_order(expired).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+        let gtd = TimeInForce::Gtd {
+            expires_at: now + 60_000,
+        };
+        let gtd = with_tif(1, 9, OrderType::Buy, gtd);
+        order_book.append_buy_order(gtd).unwrap();
+
+        assert!(order_book.expire(now + 1).unwrap().is_empty());
+        let expired = order_book.expire(now + 60_000).unwrap();
+        assert_eq!(
+            expired.iter().map(|o| o.id).collect::<Vec<_>>(),
+            vec![gtd.id]
+        );
+        let persisted = loaded(&db);
+        assert!(persisted.active_orders.is_empty());
+        assert!(persisted.fulfilled_orders.iter().any(|o| o.id == gtd.id));
+    }
+
+    #[test]
+    fn top_of_book_skips_closed_and_hidden_orders() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+        assert_eq!((order_book.spread(), order_book.mid_price()), (None, None));
+
+        let mut hidden = Order::new(1, 12, OrderType::Buy);
+        hidden.hidden = true;
+        order_book.append_buy_order(hidden).unwrap();
+        let bid = Order::new(2, 10, OrderType::Buy);
+        order_book.append_buy_order(bid).unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 15, OrderType::Sell))
+            .unwrap();
+        // fills the best ask, which stays queued as filled
+        order_book
+            .append_buy_order(Order::new(1, 15, OrderType::Buy))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 17, OrderType::Sell))
+            .unwrap();
+
+        assert_eq!(order_book.best_bid().map(|o| o.id), Some(bid.id));
+        assert_eq!(order_book.best_ask().map(|o| o.price), Some(17));
+        assert_eq!(order_book.spread(), Some(7));
+        assert_eq!(order_book.mid_price(), Some(13));
+    }
+
+    #[test]
+    fn placements_are_acked_with_their_outcome() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let ask = Order::new(3, 10, OrderType::Sell);
+        let ack = order_book.append_sell_order(ask).unwrap();
+        assert_eq!(ack, OrderAck::Rested { order: ask });
+        assert_eq!(ack.to_string(), "Rested 3 at 10");
+
+        let ack = order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        assert!(matches!(ack, OrderAck::Filled { .. }));
+        assert_eq!(ack.fills()[0].sell_order_id, ask.id);
+
+        let ack = order_book
+            .append_buy_order(Order::new(4, 10, OrderType::Buy))
+            .unwrap();
+        assert_eq!(ack.fills().len(), 1);
+        assert_eq!(
+            ack.to_string(),
+            "Partially filled 2 in 1 trade(s), 2 resting at 10"
+        );
+
+        let wrong_side = Order::new(1, 12, OrderType::Sell);
+        let e = order_book.append_buy_order(wrong_side).unwrap_err();
+        assert!(matches!(
+            OrderAck::rejected(wrong_side, &e),
+            OrderAck::Rejected {
+                kind: ErrorKind::Validation,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn read_replica_rejects_orders() {
+        let db = shared_temp_db();
+        replica::set_role(&db.lock().unwrap(), Role::ReadReplica).unwrap();
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        assert!(order_book.is_read_only());
+        assert!(order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .is_err());
+        assert!(order_book.get_buy_orders().is_empty());
+    }
+
+    #[test]
+    fn append_records_telemetry() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 20, OrderType::Sell))
+            .unwrap();
+
+        let samples = telemetry::history(&db.lock().unwrap(), Some(PAIR.as_str())).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[1].buy_orders, samples[1].sell_orders), (1, 1));
+    }
+
+    #[test]
+    fn panicking_matcher_halts_pair_until_restart() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        let order = Order::new(1, 20, OrderType::Sell);
+        assert!(order_book
+            .supervised(&order, None, |_| panic!("corrupt book"))
+            .is_err());
+
+        assert!(order_book.is_halted());
+        assert!(order_book.append_sell_order(order).is_err());
+        assert!(supervision::halted(&db.lock().unwrap(), PAIR.as_str())
+            .unwrap()
+            .is_some());
+
+        order_book.restart().unwrap();
+        assert!(!order_book.is_halted());
+        assert_eq!(order_book.get_buy_orders().len(), 1);
+        order_book.append_sell_order(order).unwrap();
+    }
+
+    #[test]
+    fn load_quarantines_corrupt_pair() {
+        let db = shared_temp_db();
+        db.lock()
+            .unwrap()
+            .set(PAIR.as_str(), &"not an item")
+            .unwrap();
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+        order_book.load().unwrap();
+
+        assert!(order_book.get_buy_orders().is_empty());
+        assert_eq!(
+            quarantine::quarantined(&db.lock().unwrap()).unwrap().len(),
+            1
+        );
+        assert!(db
+            .lock()
+            .unwrap()
+            .get::<Item>(PAIR.as_str())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn cancel_where_cancels_matching_orders_and_is_journalled() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        for price in [10, 11, 12] {
+            order_book
+                .append_buy_order(Order::new(1, price, OrderType::Buy))
+                .unwrap();
+        }
+        order_book
+            .append_sell_order(Order::new(1, 30, OrderType::Sell))
+            .unwrap();
+
+        let cancelled = order_book
+            .cancel_where(&CancelFilter::parse("side=buy,min_price=11").unwrap())
+            .unwrap();
+
+        assert_eq!(
+            cancelled.iter().map(|o| o.price).collect::<Vec<i32>>(),
+            vec![12, 11]
+        );
+        assert_eq!(order_book.get_active_buy_orders().len(), 1);
+        assert_eq!(order_book.get_active_sell_orders().len(), 1);
+
+        // only the first placement was snapshotted, the cancel is replayed
+        let snapshot: Item = db.lock().unwrap().get(PAIR.as_str()).unwrap().unwrap();
+        assert_eq!(snapshot.active_orders.len(), 1);
+        let persisted = loaded(&db);
+        assert_eq!(persisted.active_orders.len(), 2);
+        assert!(persisted
+            .fulfilled_orders
+            .iter()
+            .all(|o| o.order_status == OrderStatus::Cancelled));
+    }
+
+    #[test]
+    fn restarted_book_keeps_closed_orders_in_its_snapshots() {
+        let db = shared_temp_db();
+        let build = || {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_pair(PAIR.clone());
+            order_book_builder.set_db(db.clone());
+            order_book_builder.build().unwrap()
+        };
+        let mut order_book = build();
+        let resting = Order::new(1, 10, OrderType::Buy);
+        order_book.append_buy_order(resting).unwrap();
+        let cancelled = order_book.cancel_order(resting.id).unwrap();
+        order_book
+            .append_buy_order(Order::new(1, 9, OrderType::Buy))
+            .unwrap();
+
+        let mut restarted = build();
+        restarted.load().unwrap();
+        restarted.checkpoint().unwrap();
+
+        let persisted: Item = db.lock().unwrap().get(PAIR.as_str()).unwrap().unwrap();
+        assert_eq!(persisted.fulfilled_orders, vec![cancelled]);
+        assert_eq!(persisted.active_orders.len(), 1);
+    }
+
+    #[test]
+    fn cancel_order_keeps_history_and_survives_recovery() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let resting = Order::new(1, 10, OrderType::Buy);
+        order_book.append_buy_order(resting).unwrap();
+
+        let cancelled = order_book.cancel_order(resting.id).unwrap();
+        assert_eq!(cancelled.id, resting.id);
+        assert_eq!(cancelled.order_status, OrderStatus::Cancelled);
+        assert!(order_book.cancel_order(resting.id).is_err());
+        assert!(order_book.cancel_order(Uuid::nil()).is_err());
+
+        let persisted = loaded(&db);
+        assert!(persisted.active_orders.is_empty());
+        assert_eq!(persisted.fulfilled_orders, vec![cancelled]);
+
+        assert_eq!(order_book.recover().unwrap(), 2);
+        assert_eq!(order_book.join_cancelled_orders(), vec![cancelled]);
+    }
+
+    #[test]
+    fn amended_orders_keep_priority_only_when_reduced() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let (first, second) = (
+            Order::new(5, 10, OrderType::Buy),
+            Order::new(5, 10, OrderType::Buy),
+        );
+        let ask = Order::new(3, 12, OrderType::Sell);
+        for order in [first, second] {
+            order_book.append_buy_order(order).unwrap();
+        }
+        order_book.append_sell_order(ask).unwrap();
+
+        let reduced = order_book.amend_order(first.id, 10, 3).unwrap();
+        assert!(matches!(reduced, OrderAck::Rested { order } if order.quantity == 3));
+        assert_eq!(order_book.best_bid().unwrap().id, first.id);
+
+        order_book.amend_order(first.id, 10, 4).unwrap();
+        assert_eq!(order_book.best_bid().unwrap().id, second.id);
+
+        // repriced through the ask, it takes what rests there
+        match order_book.amend_order(second.id, 12, 5).unwrap() {
+            OrderAck::PartiallyFilled { order, fills } => {
+                assert_eq!((order.price, order.remaining()), (12, 2));
+                assert_eq!(fills[0].aggressor, OrderType::Buy);
+            }
+            other => panic!("expected a partial fill, got {:?}", other),
+        }
+        let e = order_book.amend_order(ask.id, 12, 5).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::NotFound);
+        let e = order_book.amend_order(second.id, 12, 3).unwrap_err();
+        assert_eq!(ErrorKind::of(&e), ErrorKind::Validation);
+
+        let after = order_book.snapshot();
+        let persisted = loaded(&db);
+        assert_eq!(persisted, after);
+        assert_eq!(order_book.recover().unwrap(), 6);
+        assert_eq!(order_book.snapshot(), after);
+    }
+
+    #[test]
+    fn adjust_prices_reprices_the_book_and_replays() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        order_book
+            .append_sell_order(Order::new(1, 1_000, OrderType::Sell))
+            .unwrap();
+        order_book
+            .append_buy_order(Order::new(1, 1_000, OrderType::Buy))
+            .unwrap();
+        order_book
+            .append_buy_order(Order::new(1, 940, OrderType::Buy))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 960, OrderType::Sell))
+            .unwrap();
+        let before = order_book.snapshot();
+
+        // 940 and 960 would both round to 1
+        let crossing = PriceAdjustment::parse("1/1000").unwrap();
+        assert!(order_book.adjust_prices(crossing).is_err());
+        assert_eq!(order_book.snapshot(), before);
+
+        let events = order_book.subscribe();
+        let later = Arc::new(Clock::manual(telemetry::now_millis() + 1_000));
+        let adjusted = clock::with_clock(later.clone(), || {
+            order_book.adjust_prices(PriceAdjustment::parse("1/100").unwrap())
+        })
+        .unwrap();
+        let prices = |orders: &[Order]| orders.iter().map(|o| o.price).collect::<Vec<_>>();
+        assert_eq!(prices(&adjusted), vec![9, 10]);
+        let amended = events
+            .try_iter()
+            .filter_map(|event| match event {
+                OrderBookEvent::OrderAmended { order, .. } => Some(order),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(amended, adjusted);
+        assert_eq!(prices(&order_book.join_filled_orders()), vec![10, 10]);
+        assert!(order_book.verify().is_empty());
+
+        let after = order_book.snapshot();
+        let persisted = loaded(&db);
+        assert_eq!(persisted, after);
+
+        assert_eq!(order_book.book_at(later.now_millis() - 1).unwrap(), before);
+        assert_eq!(order_book.recover().unwrap(), 5);
+        assert_eq!(order_book.snapshot(), after);
+    }
+
+    #[test]
+    fn hidden_orders_match_but_queue_behind_displayed_and_stay_private() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let mut hidden_sell = Order::new(1, 20, OrderType::Sell);
+        hidden_sell.update_hidden(true);
+        let displayed_sell = Order::new(1, 20, OrderType::Sell);
+        order_book.append_sell_order(hidden_sell).unwrap();
+        order_book.append_sell_order(displayed_sell).unwrap();
+
+        assert_eq!(
+            order_book
+                .get_sell_orders()
+                .iter()
+                .map(|o| o.id)
+                .collect::<Vec<Uuid>>(),
+            vec![displayed_sell.id, hidden_sell.id]
+        );
+        assert_eq!(
+            order_book.snapshot().public_view().active_orders,
+            vec![displayed_sell]
+        );
+
+        let mut hidden_buy = Order::new(1, 25, OrderType::Buy);
+        hidden_buy.update_hidden(true);
+        order_book.append_buy_order(hidden_buy).unwrap();
+
+        assert_eq!(
+            order_book
+                .get_filled_sell_orders()
+                .iter()
+                .map(|o| o.id)
+                .collect::<Vec<Uuid>>(),
+            vec![displayed_sell.id]
+        );
+        assert_eq!(order_book.get_filled_buy_orders()[0].id, hidden_buy.id);
+    }
+
+    #[test]
+    fn pegged_orders_track_the_bbo_and_fill_at_a_firm_price() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let mut pegged = Order::new(1, 0, OrderType::Buy);
+        pegged.update_peg(Some(Peg::parse("bid+1").unwrap()));
+        assert!(order_book.append_buy_order(pegged).is_err());
+
+        order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        order_book.append_buy_order(pegged).unwrap();
+        assert_eq!(order_book.get_buy_orders()[0].id, pegged.id);
+        assert_eq!(order_book.get_buy_orders()[0].price, 11);
+
+        order_book
+            .append_buy_order(Order::new(1, 12, OrderType::Buy))
+            .unwrap();
+        assert_eq!(order_book.get_buy_orders()[0].id, pegged.id);
+        assert_eq!(order_book.get_buy_orders()[0].price, 13);
+
+        order_book
+            .append_sell_order(Order::new(1, 13, OrderType::Sell))
+            .unwrap();
+        let filled = order_book.get_filled_buy_orders();
+        assert_eq!(filled[0].id, pegged.id);
+        assert_eq!(filled[0].price, 13);
+        assert_eq!(filled[0].peg, None);
+    }
+
+    #[test]
+    fn recover_replays_the_command_log_over_a_stale_snapshot() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 30, OrderType::Sell))
+            .unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 9, OrderType::Sell))
+            .unwrap();
+        order_book
+            .cancel_where(&CancelFilter::parse("side=sell").unwrap())
+            .unwrap();
+        let expected = order_book.snapshot();
+
+        // a crash between logging and persisting leaves the snapshot behind
+        db.lock()
+            .unwrap()
+            .set(
+                PAIR.as_str(),
+                &Item {
+                    active_orders: vec![],
+                    fulfilled_orders: vec![],
+                    sequence: None,
+                },
+            )
+            .unwrap();
+
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut recovered = order_book_builder.build().unwrap();
+        assert_eq!(recovered.recover().unwrap(), 4);
+        assert_eq!(recovered.snapshot(), expected);
+
+        let persisted: Item = db.lock().unwrap().get(PAIR.as_str()).unwrap().unwrap();
+        assert_eq!(persisted, expected);
+    }
+
+    #[test]
+    fn book_at_reconstructs_past_states_from_the_command_log() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        let before_match = order_book.snapshot();
+        let commands = command_log::commands(&db.lock().unwrap(), Some(PAIR.as_str())).unwrap();
+        let first = commands[0].timestamp;
+
+        thread::sleep(Duration::from_millis(5));
+        order_book
+            .append_sell_order(Order::new(1, 9, OrderType::Sell))
+            .unwrap();
+
+        assert_eq!(order_book.book_at(first).unwrap(), before_match);
+        assert_eq!(
+            order_book.book_at(telemetry::now_millis()).unwrap(),
+            order_book.snapshot()
+        );
+        assert!(order_book
+            .book_at(first - 1)
+            .unwrap()
+            .active_orders
+            .is_empty());
+    }
+
+    #[test]
+    fn exceeded_latency_budgets_are_reported() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        order_book_builder.set_latency_budget(LatencyBudget::parse("matching=0").unwrap());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let order = Order::new(1, 10, OrderType::Buy);
+        order_book.append_buy_order(order).unwrap();
+
+        let reports = latency::slow_paths(&db.lock().unwrap(), Some(PAIR.as_str())).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].order_id, order.id);
+        assert_eq!(reports[0].exceeded, vec!["matching".to_string()]);
+    }
+
+    #[test]
+    fn order_events_are_written_to_the_event_log() {
+        let dir = Path::new("mock_order_book_events");
+        fs::create_dir_all(dir).expect("could not create mock_order_book_events");
+        let path = dir.join("events.ndjson");
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(shared_temp_db());
+        order_book_builder
+            .set_event_log(Arc::new(EventLog::open(&path, Default::default()).unwrap()));
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let buy = Order::new(1, 10, OrderType::Buy);
+        let sell = Order::new(1, 10, OrderType::Sell);
+        let resting = Order::new(1, 5, OrderType::Buy);
+        order_book.append_buy_order(buy).unwrap();
+        order_book.append_sell_order(sell).unwrap();
+        assert!(order_book.append_sell_order(resting).is_err());
+        order_book.append_buy_order(resting).unwrap();
+        order_book.cancel_where(&CancelFilter::default()).unwrap();
+
+        let events = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Event>(line).unwrap())
+            .map(|e| (e.kind, e.order.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                (EventKind::Accepted, buy.id),
+                (EventKind::Accepted, sell.id),
+                (EventKind::Filled, buy.id),
+                (EventKind::Filled, sell.id),
+                (EventKind::Rejected, resting.id),
+                (EventKind::Accepted, resting.id),
+                (EventKind::Cancelled, resting.id),
+            ]
+        );
+
+        fs::remove_dir_all(dir).expect("could not delete mock_order_book_events");
+    }
+
+    #[test]
+    fn writes_go_through_the_writer_thread() {
+        let db = shared_temp_db();
+        let writer = Arc::new(Writer::spawn(db.clone(), AckMode::Fast));
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(PAIR.clone());
+        order_book_builder.set_db(db.clone());
+        order_book_builder.set_writer(writer.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        order_book
+            .append_buy_order(Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        order_book
+            .cancel_where(&CancelFilter::parse("side=buy").unwrap())
+            .unwrap();
+        let expected = order_book.snapshot();
+        drop(order_book);
+        drop(writer);
+
+        let persisted = loaded(&db);
+        assert_eq!(persisted, expected);
+        assert_eq!(
+            telemetry::history(&db.lock().unwrap(), Some(PAIR.as_str()))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
+
+// RUSTFLAGS="--cfg loom" cargo test -p match_engine --lib --release loom_tests
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use crate::sync::{thread, Arc};
+
+    fn model<F>(f: F)
+    where
+        F: Fn() + Sync + Send + 'static,
+    {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(f);
+    }
+
+    #[test]
+    fn concurrent_placements_are_kept_and_matched() {
+        model(|| {
+            let order_book = Arc::new(OrderBook::default());
+            let handles = [
+                Order::new(1, 10, OrderType::Buy),
+                Order::new(1, 10, OrderType::Sell),
+            ]
+            .map(|order| {
+                let order_book = order_book.clone();
+                thread::spawn(move || {
+                    order_book.insert(order);
+                    order_book.match_orders(Some(order.id));
+                })
+            });
+            for handle in handles {
+                handle.join().unwrap();
+            }
+
+            assert_eq!(order_book.get_filled_buy_orders().len(), 1);
+            assert_eq!(order_book.get_filled_sell_orders().len(), 1);
+            assert!(order_book.verify().is_empty());
+        });
+    }
+
+    #[test]
+    fn cancel_and_match_never_fill_a_cancelled_order() {
+        model(|| {
+            let mut order_book = OrderBook::default();
+            order_book.load_bulk(vec![Order::new(1, 10, OrderType::Buy)]);
+            let order_book = Arc::new(order_book);
+
+            let matcher = {
+                let order_book = order_book.clone();
+                thread::spawn(move || {
+                    order_book.insert(Order::new(1, 10, OrderType::Sell));
+                    order_book.match_orders(None);
+                })
+            };
+            let canceller = {
+                let order_book = order_book.clone();
+                thread::spawn(move || {
+                    order_book.apply_cancel(&CancelFilter {
+                        side: Some(OrderType::Buy),
+                        ..CancelFilter::default()
+                    });
+                })
+            };
+            matcher.join().unwrap();
+            canceller.join().unwrap();
+
+            let buy = order_book.get_buy_orders()[0];
+            let sell = order_book.get_sell_orders()[0];
+            assert!(matches!(
+                (buy.order_status, sell.order_status),
+                (OrderStatus::Filled, OrderStatus::Filled)
+                    | (OrderStatus::Cancelled, OrderStatus::Active)
+            ));
+            assert!(order_book.verify().is_empty());
+        });
     }
 }
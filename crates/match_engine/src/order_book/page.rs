@@ -0,0 +1,146 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::Item;
+use crate::order::Order;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortKey {
+    Price,
+    Time,
+    Size,
+}
+
+impl SortKey {
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        match raw.trim() {
+            "price" => Ok(SortKey::Price),
+            "time" => Ok(SortKey::Time),
+            "size" => Ok(SortKey::Size),
+            _ => Err(anyhow!(
+                "Invalid sort {}, expected price, time or size",
+                raw
+            )),
+        }
+    }
+
+    fn value(&self, order: &Order) -> i64 {
+        match self {
+            SortKey::Price => order.price as i64,
+            SortKey::Time => order.created_at as i64,
+            SortKey::Size => order.quantity as i64,
+        }
+    }
+}
+
+// Sort value and id of the last order on a page. Orders inserted between
+// requests don't shift later pages the way an offset would, so a cursor can be
+// kept and resumed from in a later invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub value: i64,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    // e.g. "15:67e55044-10b1-426f-9247-bb680e5fe0c8"
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let (value, id) = raw
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid cursor {}, expected value:id", raw))?;
+        Ok(Self {
+            value: value.parse()?,
+            id: id.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.value, self.id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page {
+    pub orders: Vec<Order>,
+    // None on the last page
+    pub next: Option<Cursor>,
+}
+
+impl Item {
+    // Active and fulfilled orders ascending by the sort key, ties broken by id.
+    pub fn page(&self, sort: SortKey, after: Option<&Cursor>, limit: usize) -> Page {
+        let key = |o: &Order| (sort.value(o), o.id);
+        let mut orders = self
+            .active_orders
+            .iter()
+            .chain(&self.fulfilled_orders)
+            .filter(|o| after.is_none_or(|c| key(o) > (c.value, c.id)))
+            .copied()
+            .collect::<Vec<Order>>();
+        orders.sort_by_key(key);
+
+        let more = orders.len() > limit;
+        orders.truncate(limit);
+        let next = orders.last().filter(|_| more).map(|o| Cursor {
+            value: sort.value(o),
+            id: o.id,
+        });
+        Page { orders, next }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::OrderType;
+
+    fn item() -> Item {
+        Item {
+            active_orders: vec![
+                Order::new(5, 30, OrderType::Sell),
+                Order::new(1, 10, OrderType::Buy),
+            ],
+            fulfilled_orders: vec![Order::new(3, 20, OrderType::Buy)],
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn pages_follow_the_cursor() {
+        let item = item();
+
+        let first = item.page(SortKey::Price, None, 2);
+        let prices = first.orders.iter().map(|o| o.price).collect::<Vec<_>>();
+        assert_eq!(prices, vec![10, 20]);
+
+        let cursor = Cursor::parse(&first.next.unwrap().to_string()).unwrap();
+        let second = item.page(SortKey::Price, Some(&cursor), 2);
+        assert_eq!(second.orders.len(), 1);
+        assert_eq!(second.orders[0].price, 30);
+        assert_eq!(second.next, None);
+    }
+
+    #[test]
+    fn cursor_is_stable_under_inserts() {
+        let mut item = item();
+        let first = item.page(SortKey::Size, None, 1);
+        assert_eq!(first.orders[0].quantity, 1);
+
+        item.active_orders.push(Order::new(0, 40, OrderType::Buy));
+        let second = item.page(SortKey::Size, first.next.as_ref(), 1);
+        assert_eq!(second.orders[0].quantity, 3);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_sorts_and_cursors() {
+        assert!(SortKey::parse("volume").is_err());
+        assert!(Cursor::parse("15").is_err());
+        assert!(Cursor::parse("x:y").is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::OrderBook;
+use crate::order::{Order, OrderStatus, OrderType};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsistencyViolation {
+    // the order at index queues ahead of the one before it
+    Unsorted { side: OrderType, index: usize },
+    Crossed { best_bid: i32, best_ask: i32 },
+    WrongSide { side: OrderType, order_id: Uuid },
+    DuplicateId(Uuid),
+    // every match fills the same quantity on a buy and a sell
+    UnbalancedFills { buys: i32, sells: i32 },
+    FilledWithPeg(Uuid),
+}
+
+impl fmt::Display for ConsistencyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsorted { side, index } => {
+                write!(
+                    f,
+                    "{:?} side is out of queue order at index {}",
+                    side, index
+                )
+            }
+            Self::Crossed { best_bid, best_ask } => write!(
+                f,
+                "resting book is crossed, best bid {} >= best ask {}",
+                best_bid, best_ask
+            ),
+            Self::WrongSide { side, order_id } => {
+                write!(f, "order {} rests on the {:?} side", order_id, side)
+            }
+            Self::DuplicateId(id) => write!(f, "order {} is in the book more than once", id),
+            Self::UnbalancedFills { buys, sells } => {
+                write!(
+                    f,
+                    "{} filled on buys against {} filled on sells",
+                    buys, sells
+                )
+            }
+            Self::FilledWithPeg(id) => write!(f, "filled order {} is still pegged", id),
+        }
+    }
+}
+
+impl OrderBook {
+    // Empty when the book is consistent. Only reads, so it is safe to run
+    // against a live book between commands.
+    pub fn verify(&self) -> Vec<ConsistencyViolation> {
+        let buy_orders = self.get_buy_orders();
+        let sell_orders = self.get_sell_orders();
+        let mut violations = Vec::new();
+
+        for (side, orders) in [
+            (OrderType::Buy, &buy_orders),
+            (OrderType::Sell, &sell_orders),
+        ] {
+            for (index, pair) in orders.windows(2).enumerate() {
+                if pair[0].queue_cmp(&pair[1]) == Ordering::Greater {
+                    violations.push(ConsistencyViolation::Unsorted {
+                        side,
+                        index: index + 1,
+                    });
+                }
+            }
+            for order in orders.iter().filter(|o| o.order_type != side) {
+                violations.push(ConsistencyViolation::WrongSide {
+                    side,
+                    order_id: order.id,
+                });
+            }
+        }
+
+        // all-or-none orders may rest through the book, see fill_all_or_none
+        let open = |o: &&Order| o.is_open() && !o.all_or_none;
+        let best_bid = buy_orders.iter().filter(open).map(|o| o.price).max();
+        let best_ask = sell_orders.iter().filter(open).map(|o| o.price).min();
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask) {
+            if best_bid >= best_ask {
+                violations.push(ConsistencyViolation::Crossed { best_bid, best_ask });
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for order in buy_orders.iter().chain(&sell_orders) {
+            if !seen.insert(order.id) {
+                violations.push(ConsistencyViolation::DuplicateId(order.id));
+            }
+            if order.order_status == OrderStatus::Filled && order.peg.is_some() {
+                violations.push(ConsistencyViolation::FilledWithPeg(order.id));
+            }
+        }
+
+        let filled = |orders: &[Order]| orders.iter().map(|o| o.filled_quantity).sum::<i32>();
+        let (buys, sells) = (filled(&buy_orders), filled(&sell_orders));
+        if buys != sells {
+            violations.push(ConsistencyViolation::UnbalancedFills { buys, sells });
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::id::SeededIdGenerator;
+    use rand::Rng;
+
+    #[test]
+    fn consistent_book_has_no_violations() {
+        let mut order_book = OrderBook::default();
+        order_book.load_bulk(vec![
+            Order::new(1, 10, OrderType::Buy),
+            Order::new(1, 9, OrderType::Buy),
+            Order::new(1, 11, OrderType::Sell),
+        ]);
+
+        assert!(order_book.verify().is_empty());
+    }
+
+    #[test]
+    fn random_resting_orders_load_consistently() {
+        let mut rng = test_utils::rng();
+        let ids = SeededIdGenerator::new(rng.gen());
+        let orders = (0..200)
+            .map(|_| {
+                let (price, side) = match rng.gen_bool(0.5) {
+                    true => (rng.gen_range(1..50), OrderType::Buy),
+                    false => (rng.gen_range(50..100), OrderType::Sell),
+                };
+                Order::with_generator(rng.gen_range(1..10), price, side, &ids)
+            })
+            .collect();
+
+        let mut order_book = OrderBook::default();
+        order_book.load_bulk(orders);
+
+        assert_eq!(order_book.verify(), vec![]);
+    }
+
+    #[test]
+    fn reports_each_broken_invariant() {
+        let buy = Order::new(1, 12, OrderType::Buy);
+        let mut filled = Order::new(1, 8, OrderType::Sell);
+        filled.fill(1);
+        filled.update_peg(Some(crate::order::peg::Peg::parse("bid").unwrap()));
+        let order_book = OrderBook::default();
+        *order_book.buy_orders.lock().unwrap() = vec![Order::new(1, 9, OrderType::Buy), buy, buy];
+        *order_book.sell_orders.lock().unwrap() = vec![Order::new(1, 11, OrderType::Sell), filled];
+
+        let violations = order_book.verify();
+
+        assert!(violations.contains(&ConsistencyViolation::Unsorted {
+            side: OrderType::Buy,
+            index: 1
+        }));
+        assert!(violations.contains(&ConsistencyViolation::Crossed {
+            best_bid: 12,
+            best_ask: 11
+        }));
+        assert!(violations.contains(&ConsistencyViolation::DuplicateId(buy.id)));
+        assert!(violations.contains(&ConsistencyViolation::FilledWithPeg(filled.id)));
+        assert!(violations.contains(&ConsistencyViolation::UnbalancedFills { buys: 0, sells: 1 }));
+        assert!(!violations
+            .iter()
+            .any(|v| matches!(v, ConsistencyViolation::WrongSide { .. })));
+    }
+}
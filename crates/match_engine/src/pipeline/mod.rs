@@ -0,0 +1,240 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::anyhow;
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+
+use crate::error;
+use crate::order::{Order, OrderKind, OrderType};
+use crate::order_book::ack::OrderAck;
+use crate::order_book::OrderBook;
+
+pub mod speed_bump;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub order: Order,
+    pub rejected: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct StageMetrics {
+    processed: AtomicU64,
+    busy_micros: AtomicU64,
+}
+
+impl StageMetrics {
+    fn record(&self, started: Instant) {
+        self.busy_micros
+            .fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageStats {
+    pub stage: &'static str,
+    pub processed: u64,
+    pub busy_micros: u64,
+}
+
+// ingress -> [speed bump] -> match -> publish. Risk and settlement stages slot in between
+// once accounts and trades exist; each stage owns its thread and the
+// channels between stages are bounded, so a slow stage backs up ingress.
+pub struct Pipeline {
+    ingress: Option<Sender<Order>>,
+    outcomes: Receiver<Outcome>,
+    matcher: Option<JoinHandle<OrderBook>>,
+    handles: Vec<JoinHandle<()>>,
+    metrics: Vec<(&'static str, Arc<StageMetrics>)>,
+}
+
+// Runs until the upstream stage hangs up or the downstream one goes away.
+fn run_stage<I, O, F>(input: Receiver<I>, output: Sender<O>, metrics: &StageMetrics, mut f: F)
+where
+    F: FnMut(I) -> O,
+{
+    for item in input.iter() {
+        let started = Instant::now();
+        let result = f(item);
+        metrics.record(started);
+        if output.send(result).is_err() {
+            break;
+        }
+    }
+}
+
+pub(crate) fn validate(order: &Order) -> anyhow::Result<()> {
+    if order.quantity <= 0 {
+        return Err(error::validation(format!(
+            "Quantity must be positive, got {}",
+            order.quantity
+        )));
+    }
+    if order.price <= 0 && order.peg.is_none() && order.kind == OrderKind::Limit {
+        return Err(error::validation(format!(
+            "Price must be positive, got {}",
+            order.price
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn place(order_book: &mut OrderBook, order: Order) -> anyhow::Result<OrderAck> {
+    match order.order_type {
+        OrderType::Buy => order_book.append_buy_order(order),
+        OrderType::Sell => order_book.append_sell_order(order),
+    }
+}
+
+impl Pipeline {
+    pub fn spawn(mut order_book: OrderBook, capacity: usize) -> Self {
+        let (ingress, ingress_out) = bounded::<Order>(capacity);
+        let (validated, mut validated_out) = bounded::<Outcome>(capacity);
+        let (matched, matched_out) = bounded::<Outcome>(capacity);
+        // unbounded so shutdown never blocks on a consumer that stopped reading
+        let (published, outcomes) = unbounded::<Outcome>();
+        let mut metrics: Vec<(&'static str, Arc<StageMetrics>)> = ["ingress", "match", "publish"]
+            .into_iter()
+            .map(|name| (name, Arc::new(StageMetrics::default())))
+            .collect();
+
+        let ingress_metrics = metrics[0].1.clone();
+        let ingress_stage = thread::spawn(move || {
+            run_stage(ingress_out, validated, &ingress_metrics, |order: Order| {
+                Outcome {
+                    order,
+                    rejected: validate(&order).err().map(|e| e.to_string()),
+                }
+            })
+        });
+
+        let mut handles = vec![ingress_stage];
+        if let Some(bump) = order_book.speed_bump() {
+            let (bumped, bumped_out) = bounded::<Outcome>(capacity);
+            let bump_metrics = Arc::new(StageMetrics::default());
+            metrics.insert(1, ("speed_bump", bump_metrics.clone()));
+            let sides = order_book.sides();
+            let input = std::mem::replace(&mut validated_out, bumped_out);
+            handles.push(thread::spawn(move || {
+                speed_bump::run(input, bumped, &bump_metrics, bump, sides)
+            }));
+        }
+
+        let match_metrics = metrics[metrics.len() - 2].1.clone();
+        let matcher = thread::spawn(move || {
+            run_stage(
+                validated_out,
+                matched,
+                &match_metrics,
+                |outcome: Outcome| {
+                    if outcome.rejected.is_some() {
+                        return outcome;
+                    }
+                    Outcome {
+                        rejected: place(&mut order_book, outcome.order)
+                            .err()
+                            .map(|e| e.to_string()),
+                        ..outcome
+                    }
+                },
+            );
+            order_book
+        });
+
+        let publish_metrics = metrics[metrics.len() - 1].1.clone();
+        handles.push(thread::spawn(move || {
+            run_stage(
+                matched_out,
+                published,
+                &publish_metrics,
+                |outcome: Outcome| outcome,
+            )
+        }));
+
+        Self {
+            ingress: Some(ingress),
+            outcomes,
+            matcher: Some(matcher),
+            handles,
+            metrics,
+        }
+    }
+
+    // Blocks while the ingress queue is full.
+    pub fn submit(&self, order: Order) -> anyhow::Result<()> {
+        self.ingress
+            .as_ref()
+            .expect("pipeline is shut down")
+            .send(order)
+            .map_err(|_| anyhow!("pipeline is shut down"))
+    }
+
+    pub fn outcomes(&self) -> &Receiver<Outcome> {
+        &self.outcomes
+    }
+
+    pub fn metrics(&self) -> Vec<StageStats> {
+        self.metrics
+            .iter()
+            .map(|(stage, metrics)| StageStats {
+                stage,
+                processed: metrics.processed.load(Ordering::Relaxed),
+                busy_micros: metrics.busy_micros.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    // Drains every submitted order through all stages and hands the book back.
+    pub fn shutdown(mut self) -> OrderBook {
+        drop(self.ingress.take());
+        let order_book = self
+            .matcher
+            .take()
+            .expect("pipeline is shut down")
+            .join()
+            .expect("match stage panicked");
+        for handle in self.handles.drain(..) {
+            handle.join().expect("pipeline stage panicked");
+        }
+        order_book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn orders_flow_through_every_stage_in_order() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+        order_book_builder.set_db(db.clone());
+        let pipeline = Pipeline::spawn(order_book_builder.build().unwrap(), 2);
+
+        let buy = Order::new(1, 10, OrderType::Buy);
+        let invalid = Order::new(0, 10, OrderType::Sell);
+        let sell = Order::new(1, 9, OrderType::Sell);
+        for order in [buy, invalid, sell] {
+            pipeline.submit(order).unwrap();
+        }
+
+        let outcomes: Vec<Outcome> = pipeline.outcomes().iter().take(3).collect();
+        assert_eq!(
+            outcomes.iter().map(|o| o.order.id).collect::<Vec<_>>(),
+            vec![buy.id, invalid.id, sell.id]
+        );
+        assert!(outcomes[0].rejected.is_none());
+        assert!(outcomes[1].rejected.is_some());
+        assert!(outcomes[2].rejected.is_none());
+        assert!(pipeline.metrics().iter().all(|stats| stats.processed == 3));
+
+        let order_book = pipeline.shutdown();
+        assert_eq!(order_book.join_filled_orders().len(), 2);
+    }
+}
@@ -0,0 +1,138 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use rand::Rng;
+
+use super::{Outcome, StageMetrics};
+use crate::order::{Order, OrderKind, OrderType};
+use crate::order_book::Side;
+
+// Marketable orders are held for `delay` plus up to `jitter` before they
+// reach the matcher, everything else passes straight through. Resting
+// orders can therefore still be placed ahead of a taker that arrived first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpeedBump {
+    pub delay: Duration,
+    pub jitter: Duration,
+}
+
+impl SpeedBump {
+    pub fn new(delay: Duration, jitter: Duration) -> Self {
+        Self { delay, jitter }
+    }
+
+    fn hold(&self) -> Duration {
+        let jitter = self.jitter.as_micros() as u64;
+        self.delay + Duration::from_micros(rand::thread_rng().gen_range(0..=jitter))
+    }
+}
+
+// Pegged orders price off the book when placed, so they never count as
+// marketable. Market orders always do.
+fn marketable(order: &Order, buy_orders: &Side, sell_orders: &Side) -> bool {
+    let open = |o: &&Order| o.is_open();
+    match (order.order_type, order.peg) {
+        _ if order.kind == OrderKind::Market => true,
+        (_, Some(_)) => false,
+        (OrderType::Buy, None) => sell_orders
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(open)
+            .any(|o| o.price <= order.price),
+        (OrderType::Sell, None) => buy_orders
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(open)
+            .any(|o| o.price >= order.price),
+    }
+}
+
+// Held orders are released in due order. When ingress hangs up the
+// remaining ones still wait out their delay, so shutdown drains them.
+pub(crate) fn run(
+    input: Receiver<Outcome>,
+    output: Sender<Outcome>,
+    metrics: &StageMetrics,
+    speed_bump: SpeedBump,
+    (buy_orders, sell_orders): (Side, Side),
+) {
+    let mut held: Vec<(Instant, Outcome)> = Vec::new();
+    let mut open = true;
+
+    while open || !held.is_empty() {
+        let next_due = held.iter().map(|(due, _)| *due).min();
+        let received = match (open, next_due) {
+            (true, Some(due)) => input.recv_timeout(due.saturating_duration_since(Instant::now())),
+            (true, None) => input.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            (false, Some(due)) => {
+                thread::sleep(due.saturating_duration_since(Instant::now()));
+                Err(RecvTimeoutError::Timeout)
+            }
+            (false, None) => break,
+        };
+
+        match received {
+            Ok(outcome) => {
+                let started = Instant::now();
+                let bumped = outcome.rejected.is_none()
+                    && marketable(&outcome.order, &buy_orders, &sell_orders);
+                metrics.record(started);
+                if bumped {
+                    held.push((Instant::now() + speed_bump.hold(), outcome));
+                } else if output.send(outcome).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => open = false,
+        }
+
+        let now = Instant::now();
+        held.sort_by_key(|(due, _)| *due);
+        let due = held.iter().take_while(|(due, _)| *due <= now).count();
+        for (_, outcome) in held.drain(..due) {
+            if output.send(outcome).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_book::OrderBook;
+    use crate::pipeline::Pipeline;
+    use crate::symbol::Symbol;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn takers_are_held_while_resting_orders_pass() {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+        order_book_builder.set_db(shared_temp_db());
+        order_book_builder
+            .set_speed_bump(SpeedBump::new(Duration::from_millis(50), Duration::ZERO));
+        let pipeline = Pipeline::spawn(order_book_builder.build().unwrap(), 4);
+
+        let ask = Order::new(1, 10, OrderType::Sell);
+        pipeline.submit(ask).unwrap();
+        assert_eq!(pipeline.outcomes().recv().unwrap().order.id, ask.id);
+
+        let started = Instant::now();
+        let taker = Order::new(1, 10, OrderType::Buy);
+        let bid = Order::new(1, 5, OrderType::Buy);
+        pipeline.submit(taker).unwrap();
+        pipeline.submit(bid).unwrap();
+
+        assert_eq!(pipeline.outcomes().recv().unwrap().order.id, bid.id);
+        assert_eq!(pipeline.outcomes().recv().unwrap().order.id, taker.id);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let order_book = pipeline.shutdown();
+        assert_eq!(order_book.join_filled_orders().len(), 2);
+    }
+}
@@ -0,0 +1,78 @@
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::key::{self, Key};
+use crate::order_book::Item;
+use crate::telemetry;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quarantined {
+    pub pair: String,
+    pub raw: String,
+    pub error: String,
+    pub timestamp: u64,
+}
+
+pub fn quarantine(
+    db: &Database,
+    pair: &str,
+    raw: &str,
+    error: &str,
+) -> anyhow::Result<Quarantined> {
+    let quarantined = Quarantined {
+        pair: pair.to_string(),
+        raw: raw.to_string(),
+        error: error.to_string(),
+        timestamp: telemetry::now_millis(),
+    };
+    Key::quarantined(pair).set(db, &quarantined)?;
+    Key::book(pair).remove(db)?;
+    eprintln!("Quarantined corrupt pair {}: {}", pair, error);
+    Ok(quarantined)
+}
+
+pub fn quarantined(db: &Database) -> anyhow::Result<Vec<Quarantined>> {
+    db.entries_in(key::CORRUPT)?
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+pub fn scan(db: &Database) -> anyhow::Result<Vec<Quarantined>> {
+    let mut found = Vec::new();
+    for pair in db.keys()? {
+        if let Some(raw) = Key::book(&pair).get(db)? {
+            if let Err(e) = serde_json::from_str::<Item>(&raw) {
+                found.push(quarantine(db, &pair, &raw, &e.to_string())?);
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, OrderType};
+    use test_utils::temp_db;
+
+    #[test]
+    fn scan_quarantines_only_corrupt_pairs() {
+        let db = temp_db();
+        let healthy = Item {
+            active_orders: vec![Order::new(1, 10, OrderType::Buy)],
+            fulfilled_orders: vec![],
+            sequence: None,
+        };
+        db.set("BTC/USD", &healthy).unwrap();
+        db.set("ETH/USD", &"not an item").unwrap();
+
+        let found = scan(&db).unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].pair, "ETH/USD");
+        assert_eq!(found[0].raw, "\"not an item\"");
+        assert_eq!(db.keys().unwrap(), vec!["BTC/USD".to_string()]);
+        assert_eq!(quarantined(&db).unwrap(), found);
+    }
+}
@@ -0,0 +1,311 @@
+// Filter expressions over the trade history, e.g.
+// "pair=BTC/USD and price>100 and time>=2024-01-01". Time bounds and the
+// pair narrow what is read from the log, the rest is checked per trade.
+use db::Database;
+
+use crate::archive::{self, ObjectStore};
+use crate::calendar;
+use crate::error;
+use crate::order::OrderType;
+use crate::symbol::Symbol;
+use crate::trade::LoggedTrade;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Pair,
+    // the aggressor's side
+    Side,
+    Price,
+    Quantity,
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn holds<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Pair(Symbol),
+    Side(OrderType),
+    Number(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Op,
+    pub value: Value,
+}
+
+impl Condition {
+    // e.g. "price>100", "side=buy" or "time<2024-01-01T12:00:00"
+    pub fn parse(clause: &str) -> anyhow::Result<Self> {
+        let invalid = || {
+            error::validation(format!(
+                "Invalid condition {}, expected e.g. price>100",
+                clause
+            ))
+        };
+        let at = clause.find(['=', '!', '<', '>']).ok_or_else(invalid)?;
+        let (name, rest) = clause.split_at(at);
+        let (op, raw) = [
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ]
+        .into_iter()
+        .find_map(|(token, op)| rest.strip_prefix(token).map(|raw| (op, raw.trim())))
+        .ok_or_else(invalid)?;
+
+        let field = match name.trim().to_lowercase().as_str() {
+            "pair" => Field::Pair,
+            "side" => Field::Side,
+            "price" => Field::Price,
+            "quantity" | "qty" => Field::Quantity,
+            "time" => Field::Time,
+            other => {
+                return Err(error::validation(format!(
+                    "Unknown field {}, expected pair, side, price, quantity or time",
+                    other
+                )))
+            }
+        };
+        let value = match field {
+            Field::Pair => Value::Pair(Symbol::parse(raw)?),
+            Field::Side => Value::Side(match raw.to_lowercase().as_str() {
+                "buy" => OrderType::Buy,
+                "sell" => OrderType::Sell,
+                _ => {
+                    return Err(error::validation(format!(
+                        "Invalid side {}, expected buy or sell",
+                        raw
+                    )))
+                }
+            }),
+            Field::Time => Value::Number(parse_time(raw)? as i64),
+            Field::Price | Field::Quantity => Value::Number(raw.parse().map_err(|_| invalid())?),
+        };
+        if matches!(value, Value::Pair(_) | Value::Side(_)) && !matches!(op, Op::Eq | Op::Ne) {
+            return Err(error::validation(format!(
+                "{} can only be compared with = or !=",
+                name.trim()
+            )));
+        }
+        Ok(Self { field, op, value })
+    }
+
+    pub fn matches(&self, logged: &LoggedTrade) -> bool {
+        let trade = &logged.trade;
+        match (&self.field, &self.value) {
+            (Field::Pair, Value::Pair(pair)) => self.op.holds(logged.pair.as_str(), pair.as_str()),
+            (Field::Side, Value::Side(side)) => self.op.holds(trade.aggressor == *side, true),
+            (Field::Price, Value::Number(n)) => self.op.holds(trade.price as i64, *n),
+            (Field::Quantity, Value::Number(n)) => self.op.holds(trade.quantity as i64, *n),
+            (Field::Time, Value::Number(n)) => self.op.holds(trade.timestamp as i64, *n),
+            _ => false,
+        }
+    }
+}
+
+// Milliseconds since the epoch, or a UTC date as YYYY-MM-DD optionally
+// followed by THH:MM:SS.
+pub fn parse_time(raw: &str) -> anyhow::Result<u64> {
+    if let Ok(millis) = raw.parse() {
+        return Ok(millis);
+    }
+    let invalid = || {
+        error::validation(format!(
+            "Invalid time {}, expected milliseconds or YYYY-MM-DD[THH:MM:SS]",
+            raw
+        ))
+    };
+    let (date, time) = raw.split_once('T').unwrap_or((raw, "00:00:00"));
+    let numbers = |part: &str, sep: char| {
+        part.split(sep)
+            .map(|n| n.parse::<u32>().map_err(|_| invalid()))
+            .collect::<anyhow::Result<Vec<_>>>()
+    };
+    let (date, time) = (numbers(date, '-')?, numbers(time, ':')?);
+    match (date.as_slice(), time.as_slice()) {
+        ([year, month, day], [hour, minute, second])
+            if (1970..=9999).contains(year)
+                && (1..=12).contains(month)
+                && (1..=31).contains(day)
+                && *hour < 24
+                && *minute < 60
+                && *second < 60 =>
+        {
+            let days = calendar::days_from_civil(*year as i64, *month, *day) as u64;
+            let seconds = days * 86_400 + (*hour * 3_600 + *minute * 60 + *second) as u64;
+            Ok(seconds * 1000)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub conditions: Vec<Condition>,
+}
+
+impl Query {
+    // Conditions joined by "and", an empty expression selects everything.
+    pub fn parse(expression: &str) -> anyhow::Result<Self> {
+        let mut clauses = vec![String::new()];
+        for token in expression.split_whitespace() {
+            match token.eq_ignore_ascii_case("and") {
+                true => clauses.push(String::new()),
+                false => clauses.last_mut().expect("never empty").push_str(token),
+            }
+        }
+        if clauses.len() > 1 && clauses.iter().any(String::is_empty) {
+            return Err(error::validation(format!(
+                "Invalid query {}, expected conditions joined by and",
+                expression
+            )));
+        }
+        let conditions = clauses
+            .iter()
+            .filter(|clause| !clause.is_empty())
+            .map(|clause| Condition::parse(clause))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { conditions })
+    }
+
+    // The pair the query is limited to, if any.
+    pub fn pair(&self) -> Option<&Symbol> {
+        self.conditions
+            .iter()
+            .find_map(|c| match (&c.op, &c.value) {
+                (Op::Eq, Value::Pair(pair)) => Some(pair),
+                _ => None,
+            })
+    }
+
+    // Inclusive bounds on the trade time, None when they exclude everything.
+    pub fn time_range(&self) -> Option<(u64, u64)> {
+        let (mut from, mut to) = (0u64, u64::MAX);
+        for condition in self.conditions.iter().filter(|c| c.field == Field::Time) {
+            let Value::Number(n) = condition.value else {
+                continue;
+            };
+            let n = n as u64;
+            match condition.op {
+                Op::Eq => (from, to) = (from.max(n), to.min(n)),
+                Op::Gt => from = from.max(n.checked_add(1)?),
+                Op::Ge => from = from.max(n),
+                Op::Lt => to = to.min(n.checked_sub(1)?),
+                Op::Le => to = to.min(n),
+                Op::Ne => {}
+            }
+        }
+        (from <= to).then_some((from, to))
+    }
+
+    pub fn matches(&self, logged: &LoggedTrade) -> bool {
+        self.conditions.iter().all(|c| c.matches(logged))
+    }
+
+    // Matching trades of one database in the order they executed, archived
+    // ones included when they fall in the time range, see archive::history.
+    pub fn run(
+        &self,
+        db: &Database,
+        store: Option<&dyn ObjectStore>,
+    ) -> anyhow::Result<Vec<LoggedTrade>> {
+        let Some((from, to)) = self.time_range() else {
+            return Ok(Vec::new());
+        };
+        let pair = self.pair().map(Symbol::as_str);
+        Ok(archive::history(db, store, pair, from, to)?
+            .into_iter()
+            .filter(|logged| self.matches(logged))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+    use crate::trade::{self, Trade};
+    use test_utils::temp_db;
+
+    #[test]
+    fn queries_filter_the_trade_log() {
+        let db = temp_db();
+        let day = parse_time("2024-01-02").unwrap();
+        assert_eq!(day, 1_704_153_600_000);
+        assert_eq!(parse_time("2024-01-02T00:00:01").unwrap(), day + 1_000);
+        assert!(parse_time("2024-13-01").is_err());
+
+        let trade = |price, quantity, timestamp| {
+            let bid = Order::new(quantity, price, OrderType::Buy);
+            let ask = Order::new(quantity, price, OrderType::Sell);
+            Trade::between(&bid, &ask, quantity, timestamp, Some(bid.id))
+        };
+        trade::record(
+            &db,
+            "BTC/USD",
+            &[
+                trade(90, 1, day - 1),
+                trade(110, 2, day),
+                trade(120, 3, day + 1),
+            ],
+        )
+        .unwrap();
+        trade::record(&db, "ETH/USD", &[trade(150, 1, day + 2)]).unwrap();
+
+        let query = Query::parse("pair=btc/usd AND price>100 and time>=2024-01-02").unwrap();
+        assert_eq!(query.time_range(), Some((day, u64::MAX)));
+        let prices = |query: &Query| {
+            query
+                .run(&db, None)
+                .unwrap()
+                .iter()
+                .map(|t| t.trade.price)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(prices(&query), vec![110, 120]);
+        assert_eq!(
+            prices(&Query::parse("qty <= 1 and side=buy").unwrap()),
+            vec![90, 150]
+        );
+        assert_eq!(prices(&Query::parse("pair!=BTC/USD").unwrap()), vec![150]);
+        assert!(prices(&Query::parse("time>5 and time<3").unwrap()).is_empty());
+        assert_eq!(prices(&Query::parse("").unwrap()).len(), 4);
+
+        for invalid in [
+            "price>>1",
+            "size=1",
+            "pair>BTC/USD",
+            "price>1 and",
+            "side=long",
+        ] {
+            assert!(Query::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
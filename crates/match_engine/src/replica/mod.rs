@@ -0,0 +1,191 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::handoff::{export_state, import_state, StateExport};
+use crate::key::Key;
+use crate::order_book::{journal, Item};
+
+const ROLE_KEY: &str = "role";
+const APPLIED_HASH_KEY: &str = "applied_state_hash";
+const SNAPSHOT_REQUEST: &str = "SNAPSHOT";
+const BOOK_REQUEST: &str = "BOOK";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Role {
+    Primary,
+    Standby,
+    ReadReplica,
+}
+
+pub fn role(db: &Database) -> anyhow::Result<Role> {
+    match Key::replica(ROLE_KEY).get(db)? {
+        Some(role) => Ok(serde_json::from_str(&role)?),
+        None => Ok(Role::Primary),
+    }
+}
+
+pub fn set_role(db: &Database, role: Role) -> anyhow::Result<()> {
+    Key::replica(ROLE_KEY).set(db, &role)?;
+    Ok(())
+}
+
+pub fn serve(listener: TcpListener, db: Arc<Mutex<Database>>) -> anyhow::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        if let Err(e) = handle(stream, &db) {
+            eprintln!("replica: failed to ship snapshot: {e}");
+        }
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, db: &Arc<Mutex<Database>>) -> anyhow::Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    let guard = db.lock().expect("could not get db lock");
+    let mut payload = match request.split_whitespace().collect::<Vec<&str>>()[..] {
+        [SNAPSHOT_REQUEST] => serde_json::to_string(&export_state(&guard)?)?,
+        [BOOK_REQUEST, pair] => {
+            let item: Option<Item> = journal::current(&guard, pair)?.map(|item| item.public_view());
+            serde_json::to_string(&item)?
+        }
+        _ => return Err(anyhow!("Unknown replication request {}", request.trim())),
+    };
+    drop(guard);
+    payload.push('\n');
+    stream.write_all(payload.as_bytes())?;
+    Ok(())
+}
+
+fn request(addr: &str, request: &str) -> anyhow::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_all(format!("{request}\n").as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    Ok(response)
+}
+
+pub fn fetch_snapshot(primary: &str) -> anyhow::Result<StateExport> {
+    Ok(serde_json::from_str(&request(primary, SNAPSHOT_REQUEST)?)?)
+}
+
+pub fn fetch_book(replica: &str, pair: &str) -> anyhow::Result<Option<Item>> {
+    Ok(serde_json::from_str(&request(
+        replica,
+        &format!("{BOOK_REQUEST} {pair}"),
+    )?)?)
+}
+
+pub fn check_divergence(db: &Database) -> anyhow::Result<()> {
+    let applied: Option<String> = Key::replica(APPLIED_HASH_KEY)
+        .get(db)?
+        .map(|hash| serde_json::from_str(&hash))
+        .transpose()?;
+    let local = export_state(db)?.state_hash;
+
+    match applied {
+        Some(applied) if applied != local => Err(anyhow!(
+            "Standby diverged from primary: local state_hash={} last applied state_hash={}",
+            local,
+            applied
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn sync_from(db: &Database, primary: &str) -> anyhow::Result<String> {
+    if role(db)? == Role::Primary {
+        return Err(anyhow!("A primary cannot sync from another primary"));
+    }
+    check_divergence(db)?;
+
+    let state = fetch_snapshot(primary)?;
+    import_state(db, &state)?;
+    Key::replica(APPLIED_HASH_KEY).set(db, &state.state_hash)?;
+    Ok(state.state_hash)
+}
+
+pub fn promote(db: &Database) -> anyhow::Result<()> {
+    if role(db)? != Role::Standby {
+        return Err(anyhow!("Only a standby can be promoted"));
+    }
+    check_divergence(db)?;
+    set_role(db, Role::Primary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, OrderType};
+    use test_utils::{shared_temp_db, temp_db};
+
+    use std::thread;
+
+    fn item(price: i32) -> Item {
+        Item {
+            active_orders: vec![Order::new(1, price, OrderType::Buy)],
+            fulfilled_orders: vec![],
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn standby_follows_primary_and_promotes() {
+        let primary = shared_temp_db();
+        let standby = temp_db();
+        set_role(&standby, Role::Standby).unwrap();
+
+        primary.lock().unwrap().set("BTC/USD", &item(10)).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let serving = primary.clone();
+        thread::spawn(move || serve(listener, serving));
+
+        let hash = sync_from(&standby, &addr).unwrap();
+        assert_eq!(
+            hash,
+            export_state(&primary.lock().unwrap()).unwrap().state_hash
+        );
+
+        promote(&standby).unwrap();
+        assert_eq!(role(&standby).unwrap(), Role::Primary);
+    }
+
+    #[test]
+    fn local_writes_on_standby_are_detected_as_divergence() {
+        let standby = temp_db();
+        set_role(&standby, Role::Standby).unwrap();
+        Key::replica(APPLIED_HASH_KEY)
+            .set(&standby, &export_state(&standby).unwrap().state_hash)
+            .unwrap();
+
+        standby.set("BTC/USD", &item(10)).unwrap();
+
+        assert!(check_divergence(&standby).is_err());
+        assert!(promote(&standby).is_err());
+    }
+
+    #[test]
+    fn read_replica_serves_books() {
+        let replica = shared_temp_db();
+        set_role(&replica.lock().unwrap(), Role::ReadReplica).unwrap();
+        let btc = item(10);
+        replica.lock().unwrap().set("BTC/USD", &btc).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let serving = replica.clone();
+        thread::spawn(move || serve(listener, serving));
+
+        assert_eq!(fetch_book(&addr, "BTC/USD").unwrap(), Some(btc));
+        assert_eq!(fetch_book(&addr, "ETH/USD").unwrap(), None);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use db::codec::Codec;
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::order::peg::Peg;
+use crate::order::{Order, OrderType};
+use crate::order_book::OrderBook;
+use crate::pipeline;
+use crate::symbol::Symbol;
+
+// A matching behaviour written down as data: orders placed in sequence on an
+// empty book and the book they must leave behind. Golden files live in
+// crates/match_engine/scenarios and are all run by this module's tests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub pair: String,
+    pub orders: Vec<ScenarioOrder>,
+    pub expected: BookState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScenarioOrder {
+    pub side: OrderType,
+    #[serde(default)]
+    pub price: i32,
+    #[serde(default = "one")]
+    pub quantity: i32,
+    #[serde(default)]
+    pub hidden: bool,
+    // e.g. "bid+1", the price is then ignored
+    #[serde(default)]
+    pub peg: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderState {
+    pub side: OrderType,
+    pub price: i32,
+    #[serde(default = "one")]
+    pub quantity: i32,
+    #[serde(default)]
+    pub filled_quantity: i32,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+// Active and filled orders in book order, rejected holds input indexes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookState {
+    #[serde(default)]
+    pub rejected: Vec<usize>,
+    #[serde(default)]
+    pub active: Vec<OrderState>,
+    #[serde(default)]
+    pub filled: Vec<OrderState>,
+}
+
+fn one() -> i32 {
+    1
+}
+
+impl ScenarioOrder {
+    fn order(&self) -> anyhow::Result<Order> {
+        let mut order = Order::new(self.quantity, self.price, self.side);
+        order.update_hidden(self.hidden);
+        order.update_peg(self.peg.as_deref().map(Peg::parse).transpose()?);
+        Ok(order)
+    }
+}
+
+impl From<&Order> for OrderState {
+    fn from(order: &Order) -> Self {
+        Self {
+            side: order.order_type,
+            price: order.price,
+            quantity: order.quantity,
+            filled_quantity: order.filled_quantity,
+            hidden: order.hidden,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    // Plays the orders against a fresh book over a temporary database.
+    pub fn run(&self) -> anyhow::Result<BookState> {
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(Symbol::parse(&self.pair)?);
+        order_book_builder.set_db(Arc::new(Mutex::new(Database::temporary(Codec::default())?)));
+        let mut order_book = order_book_builder.build()?;
+
+        let mut rejected = Vec::new();
+        for (index, input) in self.orders.iter().enumerate() {
+            if pipeline::place(&mut order_book, input.order()?).is_err() {
+                rejected.push(index);
+            }
+        }
+        Ok(BookState {
+            rejected,
+            active: order_book
+                .join_active_orders()
+                .iter()
+                .map(OrderState::from)
+                .collect(),
+            filled: order_book
+                .join_filled_orders()
+                .iter()
+                .map(OrderState::from)
+                .collect(),
+        })
+    }
+
+    pub fn verify(&self) -> anyhow::Result<()> {
+        let actual = self.run()?;
+        if actual != self.expected {
+            return Err(anyhow!(
+                "Scenario {} diverged\nexpected: {}\nactual: {}",
+                self.name,
+                serde_json::to_string(&self.expected)?,
+                serde_json::to_string(&actual)?
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_scenarios() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        let mut paths = fs::read_dir(&dir)
+            .expect("could not read scenarios")
+            .map(|entry| entry.expect("could not read scenario").path())
+            .filter(|path| path.extension().is_some_and(|e| e == "json"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        let failures = paths
+            .iter()
+            .filter_map(|path| {
+                Scenario::load(path)
+                    .and_then(|scenario| scenario.verify())
+                    .err()
+                    .map(|e| format!("{}: {}", path.display(), e))
+            })
+            .collect::<Vec<_>>();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
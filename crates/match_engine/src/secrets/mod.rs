@@ -0,0 +1,212 @@
+use anyhow::anyhow;
+use db::Database;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::error;
+use crate::key::{self, Key};
+use crate::telemetry;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum SecretKind {
+    ApiKey,
+    WebhookSigningKey,
+}
+
+impl SecretKind {
+    pub fn parse(kind: &str) -> anyhow::Result<Self> {
+        match kind {
+            "api" => Ok(SecretKind::ApiKey),
+            "webhook" => Ok(SecretKind::WebhookSigningKey),
+            _ => Err(anyhow!(
+                "Unknown key kind {}, expected api or webhook",
+                kind
+            )),
+        }
+    }
+}
+
+// API secrets are only ever verified, so only a salted hash is kept. Webhook
+// signing keys have to be recoverable to sign payloads, they are encrypted
+// with the database's cipher and stay so in raw reads, exports and replicas.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredSecret {
+    pub name: String,
+    pub kind: SecretKind,
+    pub version: u32,
+    pub created_at: u64,
+    pub rotated_at: Option<u64>,
+    salt: String,
+    hash: Option<String>,
+    // hex of the encrypted key
+    signing_key: Option<String>,
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid hex {}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| Ok(u8::from_str_radix(&hex[index..index + 2], 16)?))
+        .collect()
+}
+
+fn cipher(db: &Database) -> anyhow::Result<&db::cipher::Cipher> {
+    db.cipher().ok_or_else(|| {
+        error::state(format!(
+            "Webhook signing keys are kept encrypted, set {} to open the database with a key",
+            db::cipher::KEY_ENV
+        ))
+    })
+}
+
+fn seal(db: &Database, secret: &str) -> anyhow::Result<String> {
+    Ok(to_hex(&cipher(db)?.encrypt(secret.as_bytes())?))
+}
+
+fn unseal(db: &Database, sealed: &str) -> anyhow::Result<String> {
+    Ok(String::from_utf8(cipher(db)?.decrypt(&from_hex(sealed)?)?)?)
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+fn hash(salt: &str, secret: &str) -> String {
+    format!("{:x}", Sha256::digest(format!("{salt}{secret}")))
+}
+
+fn get(db: &Database, name: &str) -> anyhow::Result<Option<StoredSecret>> {
+    Key::secret(name)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+fn store(db: &Database, stored: &mut StoredSecret) -> anyhow::Result<String> {
+    let secret = random_hex();
+    stored.salt = random_hex();
+    match stored.kind {
+        SecretKind::ApiKey => stored.hash = Some(hash(&stored.salt, &secret)),
+        SecretKind::WebhookSigningKey => stored.signing_key = Some(seal(db, &secret)?),
+    }
+    Key::secret(&stored.name).set(db, stored)?;
+    Ok(secret)
+}
+
+pub fn create(db: &Database, name: &str, kind: SecretKind) -> anyhow::Result<String> {
+    if get(db, name)?.is_some() {
+        return Err(anyhow!("Key {} already exists, rotate it instead", name));
+    }
+
+    store(
+        db,
+        &mut StoredSecret {
+            name: name.to_string(),
+            kind,
+            version: 1,
+            created_at: telemetry::now_millis(),
+            rotated_at: None,
+            salt: String::new(),
+            hash: None,
+            signing_key: None,
+        },
+    )
+}
+
+pub fn rotate(db: &Database, name: &str) -> anyhow::Result<String> {
+    let mut stored = get(db, name)?.ok_or_else(|| anyhow!("Key {} does not exist", name))?;
+    stored.version += 1;
+    stored.rotated_at = Some(telemetry::now_millis());
+    store(db, &mut stored)
+}
+
+// Compared in constant time, so timing does not leak how much matched.
+pub fn verify(db: &Database, name: &str, secret: &str) -> anyhow::Result<bool> {
+    let Some(stored) = get(db, name)? else {
+        return Ok(false);
+    };
+    Ok(match (stored.kind, &stored.hash, &stored.signing_key) {
+        (SecretKind::ApiKey, Some(expected), _) => same(expected, &hash(&stored.salt, secret)),
+        (SecretKind::WebhookSigningKey, _, Some(sealed)) => same(&unseal(db, sealed)?, secret),
+        _ => false,
+    })
+}
+
+// Like verify, for API keys only, a webhook signing key never logs anyone in.
+pub fn authenticate(db: &Database, name: &str, secret: &str) -> anyhow::Result<bool> {
+    match get(db, name)? {
+        Some(stored) if stored.kind == SecretKind::ApiKey => verify(db, name, secret),
+        _ => Ok(false),
+    }
+}
+
+pub fn signing_key(db: &Database, name: &str) -> anyhow::Result<String> {
+    let sealed = get(db, name)?
+        .and_then(|stored| stored.signing_key)
+        .ok_or_else(|| anyhow!("No webhook signing key named {}", name))?;
+    unseal(db, &sealed)
+}
+
+pub fn list(db: &Database) -> anyhow::Result<Vec<StoredSecret>> {
+    db.entries_in(key::SECRETS)?
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use db::cipher::Cipher;
+    use test_utils::temp_db;
+
+    #[test]
+    fn api_keys_are_hashed_and_rotatable() {
+        let db = temp_db();
+
+        let secret = create(&db, "bot", SecretKind::ApiKey).unwrap();
+        assert!(create(&db, "bot", SecretKind::ApiKey).is_err());
+        assert!(verify(&db, "bot", &secret).unwrap());
+        assert!(!db
+            .get_raw_in(key::SECRETS, "bot")
+            .unwrap()
+            .unwrap()
+            .contains(&secret));
+
+        let rotated = rotate(&db, "bot").unwrap();
+        assert!(!verify(&db, "bot", &secret).unwrap());
+        assert!(verify(&db, "bot", &rotated).unwrap());
+        assert_eq!(list(&db).unwrap()[0].version, 2);
+    }
+
+    #[test]
+    fn webhook_signing_keys_are_encrypted_and_recoverable() {
+        assert!(create(&temp_db(), "hooks", SecretKind::WebhookSigningKey).is_err());
+        let db = temp_db().with_cipher(Cipher::new([7; 32]));
+
+        let secret = create(&db, "hooks", SecretKind::WebhookSigningKey).unwrap();
+        assert_eq!(signing_key(&db, "hooks").unwrap(), secret);
+        assert!(verify(&db, "hooks", &secret).unwrap());
+        assert!(!verify(&db, "hooks", "wrong").unwrap());
+        assert!(!db
+            .get_raw_in(key::SECRETS, "hooks")
+            .unwrap()
+            .unwrap()
+            .contains(&secret));
+        assert!(signing_key(&db, "missing").is_err());
+    }
+}
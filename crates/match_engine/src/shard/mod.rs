@@ -0,0 +1,426 @@
+// Pairs can be spread over several databases for deployments one sled
+// directory cannot keep up with. What a book writes for itself (snapshot,
+// journal, trades, telemetry) lives on its pair's shard, everything else
+// (calendars, instruments, fees, halts, aliases, roles, accounts, audit,
+// replica state)
+// stays on the home database, shard 0.
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::anyhow;
+use db::Database;
+use serde_json::Value;
+
+use crate::command_log;
+use crate::error;
+use crate::key::{self, Key};
+use crate::order_book::{journal, OrderBook};
+use crate::symbol::Symbol;
+use crate::trade::{self, LoggedTrade};
+use crate::writer::{AckMode, Writer};
+
+// e.g. FTX_SHARDS=order_book.1.db,order_book.2.db, shards besides the home one
+pub const SHARDS_ENV: &str = "FTX_SHARDS";
+// e.g. FTX_SHARD_STRATEGY=prefix:BTC=1,ETH=2, hash when unset
+pub const STRATEGY_ENV: &str = "FTX_SHARD_STRATEGY";
+
+// Trees a book writes records naming their pair to, see key.
+const BOOK_RECORDS: [&str; 7] = [
+    key::TRADES,
+    key::ORDER_TRADES,
+    key::ACCOUNT_TRADES,
+    key::TELEMETRY,
+    key::SLOW_PATH,
+    key::CORRUPT,
+    key::ARCHIVES,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    // FNV-1a of the canonical pair, stable across processes and builds
+    Hash,
+    // pairs whose base asset starts with a prefix go to its shard, the longest
+    // prefix wins and unmatched pairs stay home
+    Prefix(Vec<(String, usize)>),
+}
+
+impl Strategy {
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let raw = raw.trim();
+        if raw == "hash" {
+            return Ok(Strategy::Hash);
+        }
+        let invalid = || {
+            anyhow!(
+                "Invalid shard strategy {}, expected hash or prefix:BTC=1,ETH=2",
+                raw
+            )
+        };
+        let prefixes = raw.strip_prefix("prefix:").ok_or_else(invalid)?;
+        prefixes
+            .split(',')
+            .map(|rule| {
+                let (prefix, shard) = rule.split_once('=').ok_or_else(invalid)?;
+                let shard = shard.trim().parse().map_err(|_| invalid())?;
+                Ok((prefix.trim().to_uppercase(), shard))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map(Strategy::Prefix)
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var(STRATEGY_ENV) {
+            Ok(raw) => Self::parse(&raw),
+            Err(_) => Ok(Strategy::Hash),
+        }
+    }
+
+    pub fn shard_of(&self, pair: &Symbol, shards: usize) -> usize {
+        match self {
+            Strategy::Hash => {
+                let hash = pair
+                    .as_str()
+                    .bytes()
+                    .fold(0xcbf29ce484222325u64, |hash, b| {
+                        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+                    });
+                (hash % shards as u64) as usize
+            }
+            Strategy::Prefix(prefixes) => prefixes
+                .iter()
+                .filter(|(prefix, _)| pair.base().starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len())
+                .map_or(0, |(_, shard)| *shard),
+        }
+    }
+}
+
+pub fn paths_from_env() -> Vec<String> {
+    std::env::var(SHARDS_ENV)
+        .map(|raw| {
+            raw.split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rebalance {
+    // (pair, from shard, to shard)
+    pub moved: Vec<(String, usize, usize)>,
+    pub records: usize,
+}
+
+#[derive(Clone)]
+pub struct Shards {
+    dbs: Vec<Arc<Mutex<Database>>>,
+    strategy: Strategy,
+    writers: Vec<Arc<Writer>>,
+}
+
+impl Shards {
+    // A single home database, nothing is sharded.
+    pub fn home_only(home: Arc<Mutex<Database>>) -> Self {
+        Self {
+            dbs: vec![home],
+            strategy: Strategy::Hash,
+            writers: Vec::new(),
+        }
+    }
+
+    pub fn new(
+        home: Arc<Mutex<Database>>,
+        shards: Vec<Arc<Mutex<Database>>>,
+        strategy: Strategy,
+    ) -> anyhow::Result<Self> {
+        let dbs = std::iter::once(home).chain(shards).collect::<Vec<_>>();
+        if let Strategy::Prefix(prefixes) = &strategy {
+            if let Some((prefix, shard)) = prefixes.iter().find(|(_, shard)| *shard >= dbs.len()) {
+                return Err(anyhow!(
+                    "Prefix {} is sent to shard {}, only {} shards are open",
+                    prefix,
+                    shard,
+                    dbs.len()
+                ));
+            }
+        }
+        Ok(Self {
+            dbs,
+            strategy,
+            writers: Vec::new(),
+        })
+    }
+
+    // One writer per shard, books are given the one of their pair's shard.
+    pub fn spawn_writers(&mut self, mode: AckMode) {
+        self.writers = self
+            .dbs
+            .iter()
+            .map(|db| Arc::new(Writer::spawn(db.clone(), mode)))
+            .collect();
+    }
+
+    pub fn home(&self) -> &Arc<Mutex<Database>> {
+        &self.dbs[0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.dbs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dbs.is_empty()
+    }
+
+    pub fn all(&self) -> &[Arc<Mutex<Database>>] {
+        &self.dbs
+    }
+
+    pub fn writer(&self, shard: usize) -> Option<&Arc<Writer>> {
+        self.writers.get(shard)
+    }
+
+    pub fn shard_of(&self, pair: &Symbol) -> usize {
+        self.strategy.shard_of(pair, self.dbs.len())
+    }
+
+    pub fn db_for(&self, pair: &Symbol) -> &Arc<Mutex<Database>> {
+        &self.dbs[self.shard_of(pair)]
+    }
+
+    // Points a builder at its pair's shard, control records are read from home.
+    // A pair still stored on another shard, e.g. after shards were added, is
+    // refused rather than opened empty where it now belongs.
+    pub fn configure(
+        &self,
+        order_book_builder: &mut OrderBook,
+        pair: &Symbol,
+    ) -> anyhow::Result<()> {
+        let shard = self.shard_of(pair);
+        for (stored, db) in self.dbs.iter().enumerate() {
+            if stored != shard && Key::book(pair.as_str()).get(&lock(db))?.is_some() {
+                return Err(error::state(format!(
+                    "{} is stored on shard {} but belongs on shard {}, run shards rebalance",
+                    pair, stored, shard
+                )));
+            }
+        }
+        order_book_builder.set_db(self.dbs[shard].clone());
+        order_book_builder.set_home_db(self.home().clone());
+        if let Some(writer) = self.writer(shard) {
+            order_book_builder.set_writer(writer.clone());
+        }
+        Ok(())
+    }
+
+    // Persisted pairs of every shard, sorted.
+    pub fn pairs(&self) -> anyhow::Result<Vec<(Symbol, usize)>> {
+        let mut pairs = Vec::new();
+        for (shard, db) in self.dbs.iter().enumerate() {
+            for pair in lock(db).keys()? {
+                if let Ok(pair) = Symbol::parse(&pair) {
+                    pairs.push((pair, shard));
+                }
+            }
+        }
+        pairs.sort();
+        Ok(pairs)
+    }
+
+    // Moves every pair stored away from the shard the strategy gives it, e.g.
+    // after a shard was added. Books must not be open while this runs.
+    pub fn rebalance(&self) -> anyhow::Result<Rebalance> {
+        let mut rebalance = Rebalance::default();
+        for (pair, from) in self.pairs()? {
+            let to = self.shard_of(&pair);
+            if from != to {
+                rebalance.records += move_pair(&self.dbs[from], &self.dbs[to], pair.as_str())?;
+                // numbered by the source, the target's commands start over
+                Key::settlement(pair.as_str()).remove(&lock(self.home()))?;
+                rebalance.moved.push((pair.to_string(), from, to));
+            }
+        }
+        Ok(rebalance)
+    }
+}
+
+fn lock(db: &Arc<Mutex<Database>>) -> MutexGuard<'_, Database> {
+    db.lock().expect("could not get db lock")
+}
+
+fn pair_of(json: &str) -> anyhow::Result<Option<String>> {
+    let value: Value = serde_json::from_str(json)?;
+    Ok(value
+        .get("pair")
+        .and_then(Value::as_str)
+        .map(str::to_string))
+}
+
+// The journal is folded into the moved snapshot rather than copied, sequences
+// come from each database's own counter and would not line up on the target.
+// For the same reason records keyed by sequence are numbered again by the
+// target, trades are recorded anew with their index entries.
+fn move_pair(
+    from: &Arc<Mutex<Database>>,
+    to: &Arc<Mutex<Database>>,
+    pair: &str,
+) -> anyhow::Result<usize> {
+    let source = lock(from);
+    let target = lock(to);
+    let mut moved = 0;
+
+    if let Some(mut item) = journal::current(&source, pair)? {
+        item.sequence = None;
+        Key::book(pair).set(&target, &item)?;
+        moved += 1;
+    }
+    let mut removed = Vec::new();
+    let mut trades = Vec::new();
+    for tree in BOOK_RECORDS {
+        for (id, json) in source.entries_in(tree)? {
+            if pair_of(&json)?.as_deref() != Some(pair) {
+                continue;
+            }
+            match tree {
+                key::TRADES => trades.push(serde_json::from_str::<LoggedTrade>(&json)?.trade),
+                key::ORDER_TRADES | key::ACCOUNT_TRADES => {}
+                key::CORRUPT => target.set_in(tree, &id, &serde_json::from_str::<Value>(&json)?)?,
+                _ => {
+                    // archives are grouped under their pair, see key
+                    let timestamp = id
+                        .rsplit('/')
+                        .next()
+                        .and_then(|id| id.split('-').next())
+                        .and_then(|timestamp| timestamp.parse().ok())
+                        .ok_or_else(|| anyhow!("Invalid {} key {}", tree, id))?;
+                    let key = match tree {
+                        key::ARCHIVES => Key::archive(pair, timestamp, target.generate_id()?),
+                        _ => Key::chronological(tree, timestamp, target.generate_id()?),
+                    };
+                    key.set(&target, &serde_json::from_str::<Value>(&json)?)?;
+                }
+            }
+            removed.push((tree, id));
+            moved += 1;
+        }
+    }
+    trade::record(&target, pair, &trades)?;
+    target.flush()?;
+
+    for logged in command_log::commands(&source, Some(pair))? {
+        removed.push((
+            key::COMMANDS,
+            Key::command(pair, logged.sequence).id().to_string(),
+        ));
+    }
+    for (tree, key) in removed {
+        source.remove_in(tree, &key)?;
+    }
+    Key::compaction(pair).remove(&source)?;
+    Key::recorded(pair).remove(&source)?;
+    Key::book(pair).remove(&source)?;
+    source.flush()?;
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::Exchange;
+    use crate::order::{Order, OrderType};
+    use crate::trade::Trade;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn pairs_follow_their_shard_through_a_rebalance() {
+        let (home, shard) = (shared_temp_db(), shared_temp_db());
+        let btc = Symbol::parse("BTC/USD").unwrap();
+        let eth = Symbol::parse("ETH/USD").unwrap();
+
+        let single = Shards::home_only(home.clone());
+        let mut exchange = Exchange::with_shards(single, OrderBook::default);
+        exchange
+            .submit("BTC/USD", Order::new(2, 10, OrderType::Buy))
+            .unwrap();
+        exchange
+            .submit("BTC/USD", Order::new(1, 10, OrderType::Sell))
+            .unwrap();
+        exchange
+            .submit("ETH/USD", Order::new(1, 5, OrderType::Buy))
+            .unwrap();
+        drop(exchange);
+
+        let strategy = Strategy::parse("prefix:BTC=1").unwrap();
+        assert_eq!(strategy.shard_of(&eth, 2), 0);
+        let shards = Shards::new(home.clone(), vec![shard.clone()], strategy).unwrap();
+        let rebalance = shards.rebalance().unwrap();
+        assert_eq!(rebalance.moved, vec![("BTC/USD".to_string(), 0, 1)]);
+        assert_eq!(lock(&home).keys().unwrap(), vec!["ETH/USD".to_string()]);
+        assert_eq!(lock(&shard).keys().unwrap(), vec!["BTC/USD".to_string()]);
+
+        // the exchange sees the same books wherever they live
+        let mut exchange = Exchange::with_shards(shards.clone(), OrderBook::default);
+        assert_eq!(exchange.list_pairs().unwrap(), vec![btc.clone(), eth]);
+        let book = exchange.book(&btc).unwrap();
+        assert_eq!(book.get_active_buy_orders()[0].remaining(), 1);
+        book.append_buy_order(Order::new(1, 9, OrderType::Buy))
+            .unwrap();
+        assert!(lock(&home).keys().unwrap().len() == 1);
+        assert!(shards.rebalance().unwrap().moved.is_empty());
+
+        // a shard added without a rebalance leaves BTC/USD where it was
+        let (home, shard) = (shared_temp_db(), shared_temp_db());
+        let single = Shards::home_only(home.clone());
+        Exchange::with_shards(single, OrderBook::default)
+            .submit("BTC/USD", Order::new(1, 10, OrderType::Buy))
+            .unwrap();
+        let unbalanced = Shards::new(
+            home.clone(),
+            vec![shard],
+            Strategy::parse("prefix:BTC=1").unwrap(),
+        )
+        .unwrap();
+        let e = Exchange::with_shards(unbalanced.clone(), OrderBook::default)
+            .book(&btc)
+            .err()
+            .unwrap();
+        assert_eq!(error::ErrorKind::of(&e), error::ErrorKind::State);
+        unbalanced.rebalance().unwrap();
+        assert!(Exchange::with_shards(unbalanced, OrderBook::default)
+            .book(&btc)
+            .is_ok());
+        assert!(Shards::new(home, vec![], Strategy::parse("prefix:BTC=1").unwrap()).is_err());
+    }
+
+    #[test]
+    fn moved_trades_are_numbered_by_the_target() {
+        let (source, target) = (shared_temp_db(), shared_temp_db());
+        let trade_at = |timestamp| {
+            let (bid, ask) = (
+                Order::new(1, 10, OrderType::Buy),
+                Order::new(1, 10, OrderType::Sell),
+            );
+            Trade::between(&bid, &ask, 1, timestamp, None)
+        };
+        let (btc, eth) = (trade_at(1_000), trade_at(1_000));
+        // both databases hand out the same first sequence
+        trade::record(&lock(&source), "BTC/USD", &[btc]).unwrap();
+        trade::record(&lock(&target), "ETH/USD", &[eth]).unwrap();
+        let sequence = |db| trade::trades(&lock(db), None).unwrap()[0].sequence;
+        assert_eq!(sequence(&source), sequence(&target));
+
+        move_pair(&source, &target, "BTC/USD").unwrap();
+
+        let target = lock(&target);
+        assert_eq!(trade::trades(&target, None).unwrap().len(), 2);
+        let moved = trade::trades_of_order(&target, btc.buy_order_id).unwrap();
+        assert_eq!((moved[0].pair.as_str(), moved[0].trade), ("BTC/USD", btc));
+        assert_eq!(
+            trade::trades_of_order(&target, eth.buy_order_id).unwrap()[0].trade,
+            eth
+        );
+        assert!(trade::trades(&lock(&source), None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use std::any::Any;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::key::{self, Key};
+use crate::telemetry;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Halt {
+    pub pair: String,
+    pub reason: String,
+    pub timestamp: u64,
+    // the journalled command the matcher panicked on, skipped on restart
+    #[serde(default)]
+    pub sequence: Option<u64>,
+}
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+pub fn halt(
+    db: &Database,
+    pair: &str,
+    reason: &str,
+    sequence: Option<u64>,
+) -> anyhow::Result<Halt> {
+    let halt = Halt {
+        pair: pair.to_string(),
+        reason: reason.to_string(),
+        timestamp: telemetry::now_millis(),
+        sequence,
+    };
+    Key::halt(pair).set(db, &halt)?;
+    Ok(halt)
+}
+
+pub fn halted(db: &Database, pair: &str) -> anyhow::Result<Option<Halt>> {
+    Key::halt(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+pub fn halted_pairs(db: &Database) -> anyhow::Result<Vec<Halt>> {
+    db.entries_in(key::HALTED)?
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect()
+}
+
+pub fn resume(db: &Database, pair: &str) -> anyhow::Result<()> {
+    Key::halt(pair).remove(db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    use std::panic;
+
+    #[test]
+    fn halt_and_resume_pair() {
+        let db = temp_db();
+
+        halt(&db, "BTC/USD", "matcher panicked", None).unwrap();
+        assert_eq!(
+            halted(&db, "BTC/USD").unwrap().map(|h| h.reason),
+            Some("matcher panicked".to_string())
+        );
+        assert!(halted(&db, "ETH/USD").unwrap().is_none());
+        assert_eq!(halted_pairs(&db).unwrap().len(), 1);
+
+        resume(&db, "BTC/USD").unwrap();
+        assert!(halted_pairs(&db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn panic_message_from_payload() {
+        let payload = panic::catch_unwind(|| panic!("boom {}", 1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "boom 1");
+    }
+}
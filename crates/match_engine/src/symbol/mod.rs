@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::key::{self, Key};
+
+const MAX_ASSET_LEN: usize = 10;
+
+// Normalized "BASE/QUOTE" pair, e.g. "btc/usd" -> "BTC/USD".
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Symbol(String);
+
+impl Symbol {
+    pub fn parse(raw: &str) -> anyhow::Result<Self> {
+        let normalized = raw.trim().to_uppercase();
+        let (base, quote) = normalized.split_once('/').ok_or_else(|| {
+            error::validation(format!(
+                "Invalid pair {}, expected BASE/QUOTE e.g. BTC/USD",
+                raw
+            ))
+        })?;
+
+        for asset in [base, quote] {
+            if asset.is_empty()
+                || asset.len() > MAX_ASSET_LEN
+                || !asset.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                return Err(error::validation(format!(
+                    "Invalid pair {}, assets must be 1-{} alphanumeric characters",
+                    raw, MAX_ASSET_LEN
+                )));
+            }
+        }
+        if base == quote {
+            return Err(error::validation(format!(
+                "Invalid pair {}, base and quote are the same",
+                raw
+            )));
+        }
+
+        Ok(Self(normalized))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn base(&self) -> &str {
+        self.0
+            .split_once('/')
+            .map(|(base, _)| base)
+            .unwrap_or_default()
+    }
+
+    pub fn quote(&self) -> &str {
+        self.0
+            .split_once('/')
+            .map(|(_, quote)| quote)
+            .unwrap_or_default()
+    }
+}
+
+// Aliases let clients using other naming conventions (e.g. "XBT/USD") reach
+// the canonical book ("BTC/USD").
+pub fn add_alias(db: &Database, alias: &Symbol, canonical: &Symbol) -> anyhow::Result<()> {
+    if alias == canonical {
+        return Err(anyhow!("{} cannot be an alias of itself", alias));
+    }
+    if Key::alias(canonical.as_str()).get(db)?.is_some() {
+        return Err(anyhow!(
+            "{} is itself an alias, point {} at its canonical symbol",
+            canonical,
+            alias
+        ));
+    }
+
+    Key::alias(alias.as_str()).set(db, canonical)?;
+    Ok(())
+}
+
+pub fn remove_alias(db: &Database, alias: &Symbol) -> anyhow::Result<()> {
+    Key::alias(alias.as_str()).remove(db)?;
+    Ok(())
+}
+
+pub fn aliases(db: &Database) -> anyhow::Result<Vec<(Symbol, Symbol)>> {
+    db.entries_in(key::ALIASES)?
+        .into_iter()
+        .map(|(alias, canonical)| Ok((Symbol::parse(&alias)?, serde_json::from_str(&canonical)?)))
+        .collect()
+}
+
+pub fn resolve(db: &Database, raw: &str) -> anyhow::Result<Symbol> {
+    let symbol = Symbol::parse(raw)?;
+    match Key::alias(symbol.as_str()).get(db)? {
+        Some(canonical) => Ok(serde_json::from_str(&canonical)?),
+        None => Ok(symbol),
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        Self::parse(raw)
+    }
+}
+
+impl TryFrom<String> for Symbol {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        Self::parse(&raw)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    #[test]
+    fn normalizes_case_and_whitespace() {
+        let symbol = Symbol::parse(" btc/Usd ").unwrap();
+        assert_eq!(symbol.as_str(), "BTC/USD");
+        assert_eq!((symbol.base(), symbol.quote()), ("BTC", "USD"));
+        assert_eq!(symbol, "BTC/USD".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_pairs() {
+        for raw in [
+            "btcusd",
+            "btc/",
+            "/usd",
+            "btc/usd/eth",
+            "b-tc/usd",
+            "btc/btc",
+        ] {
+            assert!(Symbol::parse(raw).is_err(), "{raw} should be rejected");
+        }
+    }
+
+    #[test]
+    fn aliases_resolve_to_canonical_symbol() {
+        let db = temp_db();
+        let xbt = Symbol::parse("XBT/USD").unwrap();
+        let btc = Symbol::parse("BTC/USD").unwrap();
+
+        add_alias(&db, &xbt, &btc).unwrap();
+        assert_eq!(resolve(&db, "xbt/usd").unwrap(), btc);
+        assert_eq!(resolve(&db, "eth/usd").unwrap().as_str(), "ETH/USD");
+        assert!(add_alias(&db, &btc, &btc).is_err());
+        assert!(add_alias(&db, &Symbol::parse("XXBT/USD").unwrap(), &xbt).is_err());
+        assert_eq!(aliases(&db).unwrap(), vec![(xbt.clone(), btc)]);
+
+        remove_alias(&db, &xbt).unwrap();
+        assert_eq!(resolve(&db, "xbt/usd").unwrap(), xbt);
+    }
+
+    #[test]
+    fn serde_validates() {
+        let symbol: Symbol = serde_json::from_str("\"eth/usdc\"").unwrap();
+        assert_eq!(serde_json::to_string(&symbol).unwrap(), "\"ETH/USDC\"");
+        assert!(serde_json::from_str::<Symbol>("\"eth\"").is_err());
+    }
+}
@@ -0,0 +1,13 @@
+// What the order book's sides and matcher thread are built on. Under
+// RUSTFLAGS="--cfg loom" these are loom's model-checked versions, see the
+// loom tests in order_book.
+#[cfg(loom)]
+pub(crate) use loom::{
+    sync::{Arc, Mutex},
+    thread,
+};
+#[cfg(not(loom))]
+pub(crate) use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
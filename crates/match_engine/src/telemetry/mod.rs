@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use db::Database;
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::key::{self, Key};
+
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TelemetrySample {
+    pub timestamp: u64,
+    pub pair: String,
+    pub match_latency_micros: u64,
+    pub buy_orders: usize,
+    pub sell_orders: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetrySummary {
+    pub samples: usize,
+    pub orders_per_sec: f64,
+    pub avg_match_latency_micros: u64,
+    pub max_match_latency_micros: u64,
+}
+
+// Unix millis from the system clock, or the simulation clock when one is installed.
+pub fn now_millis() -> u64 {
+    clock::now_millis()
+}
+
+pub fn record(db: &Database, sample: &TelemetrySample, retention: Duration) -> anyhow::Result<()> {
+    Key::telemetry(sample.timestamp, db.generate_id()?).set(db, sample)?;
+    prune(
+        db,
+        sample
+            .timestamp
+            .saturating_sub(retention.as_millis() as u64),
+    )
+}
+
+pub fn prune(db: &Database, older_than: u64) -> anyhow::Result<()> {
+    for (id, value) in db.entries_in(key::TELEMETRY)? {
+        let sample: TelemetrySample = serde_json::from_str(&value)?;
+        if sample.timestamp >= older_than {
+            break;
+        }
+        db.remove_in(key::TELEMETRY, &id)?;
+    }
+    Ok(())
+}
+
+pub fn history(db: &Database, pair: Option<&str>) -> anyhow::Result<Vec<TelemetrySample>> {
+    let mut samples = Vec::new();
+    for (_, value) in db.entries_in(key::TELEMETRY)? {
+        let sample: TelemetrySample = serde_json::from_str(&value)?;
+        if pair.is_none_or(|pair| pair == sample.pair) {
+            samples.push(sample);
+        }
+    }
+    Ok(samples)
+}
+
+pub fn summarize(samples: &[TelemetrySample]) -> TelemetrySummary {
+    let latencies: Vec<u64> = samples.iter().map(|s| s.match_latency_micros).collect();
+    let span_millis = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => last.timestamp - first.timestamp,
+        _ => 0,
+    };
+
+    TelemetrySummary {
+        samples: samples.len(),
+        orders_per_sec: if span_millis == 0 {
+            samples.len() as f64
+        } else {
+            samples.len() as f64 * 1000.0 / span_millis as f64
+        },
+        avg_match_latency_micros: if latencies.is_empty() {
+            0
+        } else {
+            latencies.iter().sum::<u64>() / latencies.len() as u64
+        },
+        max_match_latency_micros: latencies.into_iter().max().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::temp_db;
+
+    fn sample(timestamp: u64, pair: &str, latency: u64) -> TelemetrySample {
+        TelemetrySample {
+            timestamp,
+            pair: pair.to_string(),
+            match_latency_micros: latency,
+            buy_orders: 1,
+            sell_orders: 1,
+        }
+    }
+
+    #[test]
+    fn record_prunes_samples_outside_retention() {
+        let db = temp_db();
+        let retention = Duration::from_millis(1_000);
+
+        record(&db, &sample(1_000, "BTC/USD", 5), retention).unwrap();
+        record(&db, &sample(1_500, "BTC/USD", 5), retention).unwrap();
+        record(&db, &sample(2_200, "BTC/USD", 5), retention).unwrap();
+
+        let timestamps: Vec<u64> = history(&db, None)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![1_500, 2_200]);
+    }
+
+    #[test]
+    fn history_filters_by_pair_and_summarizes() {
+        let db = temp_db();
+
+        record(&db, &sample(1_000, "BTC/USD", 10), DEFAULT_RETENTION).unwrap();
+        record(&db, &sample(1_500, "ETH/USD", 90), DEFAULT_RETENTION).unwrap();
+        record(&db, &sample(2_000, "BTC/USD", 30), DEFAULT_RETENTION).unwrap();
+
+        let btc = history(&db, Some("BTC/USD")).unwrap();
+        let summary = summarize(&btc);
+
+        assert_eq!(summary.samples, 2);
+        assert_eq!(summary.orders_per_sec, 2.0);
+        assert_eq!(summary.avg_match_latency_micros, 20);
+        assert_eq!(summary.max_match_latency_micros, 30);
+    }
+}
@@ -0,0 +1,82 @@
+use super::LoggedTrade;
+use crate::calendar;
+use crate::order::OrderType;
+
+// FIX-flavored execution reports, one line of tag=value fields per order in
+// a trade, separated by '|' like the order files `ingest` reads:
+//
+// 35 MsgType (8), 17 ExecID, 37 OrderID, 55 Symbol, 54 Side (1 buy, 2 sell),
+// 150 ExecType (F trade), 31 LastPx, 32 LastQty, 60 TransactTime (UTC),
+// 1057 AggressorIndicator (Y or N)
+pub fn execution_reports(logged: &LoggedTrade) -> [String; 2] {
+    let trade = &logged.trade;
+    let report = |side: OrderType| {
+        let (order_id, tag) = match side {
+            OrderType::Buy => (trade.buy_order_id, 1),
+            OrderType::Sell => (trade.sell_order_id, 2),
+        };
+        let aggressor = if trade.aggressor == side { "Y" } else { "N" };
+        format!(
+            "35=8|17={}-{}|37={}|55={}|54={}|150=F|31={}|32={}|60={}|1057={}|",
+            logged.sequence,
+            tag,
+            order_id,
+            logged.pair,
+            tag,
+            trade.price,
+            trade.quantity,
+            transact_time(trade.timestamp),
+            aggressor
+        )
+    };
+    [report(OrderType::Buy), report(OrderType::Sell)]
+}
+
+// YYYYMMDD-HH:MM:SS.sss
+fn transact_time(timestamp: u64) -> String {
+    let millis_per_day = 24 * 60 * 60 * 1000;
+    let (year, month, day) = calendar::civil_from_days((timestamp / millis_per_day) as i64);
+    let millis = timestamp % millis_per_day;
+    format!(
+        "{:04}{:02}{:02}-{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1_000 % 60,
+        millis % 1_000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+    use crate::trade::Trade;
+
+    #[test]
+    fn one_report_per_side() {
+        let ask = Order::new(3, 10, OrderType::Sell);
+        let mut bid = Order::new(2, 11, OrderType::Buy);
+        bid.created_at = ask.created_at + 1;
+        let logged = LoggedTrade {
+            sequence: 7,
+            pair: "BTC/USD".to_string(),
+            // 2026-01-02 03:04:05.006 UTC
+            trade: Trade::between(&bid, &ask, 2, 1_767_323_045_006, None),
+        };
+
+        let [buy, sell] = execution_reports(&logged);
+
+        assert_eq!(
+            buy,
+            format!(
+                "35=8|17=7-1|37={}|55=BTC/USD|54=1|150=F|31=10|32=2|60=20260102-03:04:05.006|1057=Y|",
+                bid.id
+            )
+        );
+        assert!(sell.starts_with(&format!("35=8|17=7-2|37={}|", ask.id)));
+        assert!(sell.ends_with("|1057=N|"));
+    }
+}
@@ -0,0 +1,336 @@
+use db::{Batch, Database};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod fix;
+
+use crate::accounts::AccountId;
+use crate::key::{self, Key};
+use crate::order::{Order, OrderType};
+
+// One fill between a resting order and the order that crossed it. The price
+// is the resting order's, the aggressor is the side that arrived later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trade {
+    pub buy_order_id: Uuid,
+    pub sell_order_id: Uuid,
+    pub price: i32,
+    pub quantity: i32,
+    pub aggressor: OrderType,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buy_account: Option<AccountId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sell_account: Option<AccountId>,
+    // charged on what each side receives, the buyer's in the base asset and
+    // the seller's in the quote asset, see fees
+    #[serde(default)]
+    pub buy_fee: i64,
+    #[serde(default)]
+    pub sell_fee: i64,
+}
+
+impl Trade {
+    // The taker is the order being placed when the caller knows it, otherwise
+    // the later created one. Orders created in the same millisecond then count
+    // the sell as the aggressor.
+    pub fn between(
+        bid: &Order,
+        ask: &Order,
+        quantity: i32,
+        timestamp: u64,
+        taker: Option<Uuid>,
+    ) -> Self {
+        let buy_takes = match taker {
+            Some(id) if id == bid.id => true,
+            Some(id) if id == ask.id => false,
+            _ => bid.created_at > ask.created_at,
+        };
+        let (aggressor, price) = match buy_takes {
+            true => (OrderType::Buy, ask.price),
+            false => (OrderType::Sell, bid.price),
+        };
+        Self {
+            buy_order_id: bid.id,
+            sell_order_id: ask.id,
+            price,
+            quantity,
+            aggressor,
+            timestamp,
+            buy_account: bid.account,
+            sell_account: ask.account,
+            buy_fee: 0,
+            sell_fee: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedTrade {
+    pub sequence: u64,
+    pub pair: String,
+    pub trade: Trade,
+}
+
+// Index entry of a trade under each of its orders and accounts, pointing at
+// the trade's key in the trades tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeRef {
+    pub pair: String,
+    pub trade: String,
+}
+
+// Trades and their index entries are written in one batch, the index never
+// points at a trade that is not there.
+pub fn record(db: &Database, pair: &str, trades: &[Trade]) -> anyhow::Result<()> {
+    let mut batch = db.batch();
+    record_in_batch(db, &mut batch, pair, trades)?;
+    Ok(batch.commit()?)
+}
+
+// Like record, for the trades of the pair's command at `sequence`, which is
+// remembered in the same batch. Commands recorded before are skipped, so a
+// replay can record again whatever may have been lost. Returns whether the
+// trades were written.
+pub fn record_command(
+    db: &Database,
+    pair: &str,
+    sequence: u64,
+    trades: &[Trade],
+) -> anyhow::Result<bool> {
+    if recorded(db, pair)?.is_some_and(|recorded| recorded >= sequence) {
+        return Ok(false);
+    }
+    let mut batch = db.batch();
+    record_in_batch(db, &mut batch, pair, trades)?;
+    Key::recorded(pair).set_in_batch(&mut batch, &sequence)?;
+    batch.commit()?;
+    Ok(true)
+}
+
+// The last command of the pair whose trades were recorded, None before any.
+pub fn recorded(db: &Database, pair: &str) -> anyhow::Result<Option<u64>> {
+    Key::recorded(pair)
+        .get(db)?
+        .map(|json| Ok(serde_json::from_str(&json)?))
+        .transpose()
+}
+
+fn record_in_batch(
+    db: &Database,
+    batch: &mut Batch,
+    pair: &str,
+    trades: &[Trade],
+) -> anyhow::Result<()> {
+    for trade in trades {
+        let logged = LoggedTrade {
+            sequence: db.generate_id()?,
+            pair: pair.to_string(),
+            trade: *trade,
+        };
+        let key = Key::trade(pair, trade.timestamp, logged.sequence);
+        key.set_in_batch(batch, &logged)?;
+        let trade_ref = TradeRef {
+            pair: pair.to_string(),
+            trade: key.id().to_string(),
+        };
+        for key in index_keys(trade, logged.sequence) {
+            key.set_in_batch(batch, &trade_ref)?;
+        }
+    }
+    Ok(())
+}
+
+// Indexes trades recorded before the index existed, returns the entries added.
+pub fn reindex(db: &Database) -> anyhow::Result<usize> {
+    let mut indexed = 0;
+    for logged in trades(db, None)? {
+        let trade = &logged.trade;
+        let trade_ref = TradeRef {
+            pair: logged.pair.clone(),
+            trade: Key::trade(&logged.pair, trade.timestamp, logged.sequence)
+                .id()
+                .to_string(),
+        };
+        for key in index_keys(trade, logged.sequence) {
+            if key.get(db)?.is_none() {
+                key.set(db, &trade_ref)?;
+                indexed += 1;
+            }
+        }
+    }
+    db.flush()?;
+    Ok(indexed)
+}
+
+// Drops a trade and its index entries, e.g. once it is archived.
+pub fn remove(db: &Database, logged: &LoggedTrade) -> anyhow::Result<()> {
+    let (timestamp, sequence) = (logged.trade.timestamp, logged.sequence);
+    let mut batch = db.batch();
+    Key::trade(&logged.pair, timestamp, sequence).remove_in_batch(&mut batch);
+    for key in index_keys(&logged.trade, sequence) {
+        key.remove_in_batch(&mut batch);
+    }
+    Ok(batch.commit()?)
+}
+
+// Both orders of a trade, and the accounts behind them when there are any.
+fn index_keys(trade: &Trade, sequence: u64) -> Vec<Key> {
+    let orders = [trade.buy_order_id, trade.sell_order_id]
+        .into_iter()
+        .map(|order| Key::order_trade(order, trade.timestamp, sequence));
+    let accounts = [trade.buy_account, trade.sell_account]
+        .into_iter()
+        .flatten()
+        .map(|account| Key::account_trade(account, trade.timestamp, sequence));
+    orders.chain(accounts).collect()
+}
+
+// Trades in the order they executed, optionally for a single pair.
+pub fn trades(db: &Database, pair: Option<&str>) -> anyhow::Result<Vec<LoggedTrade>> {
+    trades_between(db, pair, 0, u64::MAX)
+}
+
+// Like trades, only those executed between from and to (inclusive). A single
+// pair is read off its own time ordered keys, all pairs are merged.
+pub fn trades_between(
+    db: &Database,
+    pair: Option<&str>,
+    from: u64,
+    to: u64,
+) -> anyhow::Result<Vec<LoggedTrade>> {
+    let entries = match pair {
+        Some(pair) => db.entries_in_range(
+            key::TRADES,
+            Key::trade(pair, from, 0).id(),
+            Key::trade(pair, to, u64::MAX).id(),
+        )?,
+        None => db.entries_in(key::TRADES)?,
+    };
+    let mut logged = entries
+        .into_iter()
+        .map(|(_, json)| Ok(serde_json::from_str(&json)?))
+        .collect::<anyhow::Result<Vec<LoggedTrade>>>()?;
+    if pair.is_none() {
+        logged.retain(|t| (from..=to).contains(&t.trade.timestamp));
+        logged.sort_by_key(|t| (t.trade.timestamp, t.sequence));
+    }
+    Ok(logged)
+}
+
+// Trades an order took part in as they executed, found through the index
+// rather than a scan of the log. Archived trades are not indexed.
+pub fn trades_of_order(db: &Database, order: Uuid) -> anyhow::Result<Vec<LoggedTrade>> {
+    indexed(db, key::ORDER_TRADES, &order.to_string())
+}
+
+// Like trades_of_order, for both sides of every order of an account. A
+// database only indexes the trades of its own pairs.
+pub fn trades_of_account(db: &Database, account: AccountId) -> anyhow::Result<Vec<LoggedTrade>> {
+    indexed(db, key::ACCOUNT_TRADES, &format!("{:020}", account))
+}
+
+fn indexed(db: &Database, tree: &str, prefix: &str) -> anyhow::Result<Vec<LoggedTrade>> {
+    let mut logged = Vec::new();
+    for (_, json) in db.entries_in_range(tree, &format!("{}/", prefix), &format!("{}/~", prefix))? {
+        let trade_ref: TradeRef = serde_json::from_str(&json)?;
+        if let Some(json) = db.get_raw_in(key::TRADES, &trade_ref.trade)? {
+            logged.push(serde_json::from_str(&json)?);
+        }
+    }
+    Ok(logged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_log::{self, Command};
+    use crate::order_book::OrderBook;
+    use crate::symbol::Symbol;
+    use test_utils::shared_temp_db;
+
+    #[test]
+    fn the_taker_gets_the_resting_price() {
+        let ask = Order::new(1, 10, OrderType::Sell);
+        let mut bid = Order::new(1, 12, OrderType::Buy);
+        bid.created_at = ask.created_at + 1;
+
+        let trade = Trade::between(&bid, &ask, 1, 0, None);
+        assert_eq!((trade.aggressor, trade.price), (OrderType::Buy, 10));
+
+        bid.created_at = ask.created_at - 1;
+        let trade = Trade::between(&bid, &ask, 1, 0, None);
+        assert_eq!((trade.aggressor, trade.price), (OrderType::Sell, 12));
+
+        let trade = Trade::between(&bid, &ask, 1, 0, Some(bid.id));
+        assert_eq!((trade.aggressor, trade.price), (OrderType::Buy, 10));
+    }
+
+    #[test]
+    fn placed_orders_record_their_trades() {
+        let db = shared_temp_db();
+        let mut order_book_builder = OrderBook::default();
+        order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+        order_book_builder.set_db(db.clone());
+        let mut order_book = order_book_builder.build().unwrap();
+
+        let ask = Order::new(2, 10, OrderType::Sell);
+        let bid = Order::new(3, 10, OrderType::Buy);
+        order_book.append_sell_order(ask).unwrap();
+        order_book.append_buy_order(bid).unwrap();
+        order_book
+            .append_sell_order(Order::new(1, 9, OrderType::Sell))
+            .unwrap();
+
+        let logged = trades(&db.lock().unwrap(), Some("BTC/USD")).unwrap();
+        assert_eq!(
+            logged
+                .iter()
+                .map(|t| (t.trade.price, t.trade.quantity, t.trade.aggressor))
+                .collect::<Vec<_>>(),
+            vec![(10, 2, OrderType::Buy), (10, 1, OrderType::Sell)]
+        );
+        assert_eq!(logged[0].trade.sell_order_id, ask.id);
+        let db = db.lock().unwrap();
+        assert_eq!(trades_of_order(&db, bid.id).unwrap(), logged);
+        assert_eq!(trades_of_order(&db, ask.id).unwrap(), logged[..1]);
+        remove(&db, &logged[0]).unwrap();
+        assert_eq!(trades_of_order(&db, bid.id).unwrap(), logged[1..]);
+        assert!(trades_of_order(&db, ask.id).unwrap().is_empty());
+        assert!(trades(&db, Some("ETH/USD")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn trades_lost_before_a_crash_are_recorded_once_on_load() {
+        let db = shared_temp_db();
+        let build = || {
+            let mut order_book_builder = OrderBook::default();
+            order_book_builder.set_pair(Symbol::parse("BTC/USD").unwrap());
+            order_book_builder.set_db(db.clone());
+            let mut order_book = order_book_builder.build().unwrap();
+            order_book.load().unwrap();
+            order_book
+        };
+        build()
+            .append_sell_order(Order::new(2, 10, OrderType::Sell))
+            .unwrap();
+
+        // the bid is logged, then the process dies before its trade is written
+        let bid = Order::new(1, 10, OrderType::Buy);
+        command_log::append(&db.lock().unwrap(), "BTC/USD", &Command::Place(bid)).unwrap();
+
+        for _ in 0..2 {
+            build();
+            let logged = trades(&db.lock().unwrap(), Some("BTC/USD")).unwrap();
+            assert_eq!(logged.len(), 1);
+            assert_eq!(logged[0].trade.buy_order_id, bid.id);
+        }
+        let ask = Order::new(1, 10, OrderType::Sell);
+        build().append_sell_order(ask).unwrap();
+        build();
+        assert_eq!(
+            trades(&db.lock().unwrap(), Some("BTC/USD")).unwrap().len(),
+            1
+        );
+    }
+}
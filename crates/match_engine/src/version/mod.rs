@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+// Set by build.rs, "unknown" when built outside a git checkout.
+pub const GIT_HASH: &str = env!("FTX_GIT_HASH");
+// Bump when stored records change in a way older engines cannot read, see key.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// What an engine was built from, for bug reports and for replicas checking
+// they can read what the primary writes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub crates: BTreeMap<String, String>,
+    pub git_hash: String,
+    pub schema_version: u32,
+    pub features: Vec<String>,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        let crates = [("match_engine", VERSION), ("db", db::VERSION)]
+            .into_iter()
+            .map(|(name, version)| (name.to_string(), version.to_string()))
+            .collect();
+        let features = [("loom", cfg!(loom)), ("debug", cfg!(debug_assertions))]
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature.to_string())
+            .collect();
+        Self {
+            crates,
+            git_hash: GIT_HASH.to_string(),
+            schema_version: SCHEMA_VERSION,
+            features,
+        }
+    }
+
+    // Builds may differ, the storage schema may not.
+    pub fn is_compatible(&self, other: &VersionInfo) -> bool {
+        self.schema_version == other.schema_version
+    }
+}
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, schema {})",
+            self.crates
+                .get("match_engine")
+                .map_or("unknown", String::as_str),
+            self.git_hash,
+            self.schema_version
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engines_are_compatible_across_builds_of_one_schema() {
+        let current = VersionInfo::current();
+        assert_eq!(current.crates["match_engine"], VERSION);
+        assert!(!current.git_hash.is_empty());
+
+        let other_build = VersionInfo {
+            git_hash: "0123456789ab".to_string(),
+            ..current.clone()
+        };
+        let other_schema = VersionInfo {
+            schema_version: SCHEMA_VERSION + 1,
+            ..current.clone()
+        };
+        assert!(current.is_compatible(&other_build));
+        assert!(!current.is_compatible(&other_schema));
+    }
+}
@@ -0,0 +1,226 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use db::Database;
+
+use crate::key::Key;
+use crate::latency::{self, SlowPathReport};
+use crate::order_book::{journal, Item};
+use crate::telemetry::{self, TelemetrySample};
+use crate::trade::{self, Trade};
+
+pub const ACK_MODE_ENV: &str = "FTX_ACK_MODE";
+
+const MAX_BATCH: usize = 256;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AckMode {
+    // acked once queued, durability comes from the command log
+    Fast,
+    // acked once the batch holding the write is flushed
+    Durable,
+}
+
+impl AckMode {
+    pub fn parse(mode: &str) -> anyhow::Result<Self> {
+        match mode {
+            "fast" => Ok(AckMode::Fast),
+            "durable" => Ok(AckMode::Durable),
+            _ => Err(anyhow!(
+                "Unknown ack mode {}, expected fast or durable",
+                mode
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Task {
+    Snapshot {
+        pair: String,
+        item: Item,
+    },
+    Telemetry {
+        sample: TelemetrySample,
+        retention: Duration,
+    },
+    SlowPath(SlowPathReport),
+    // of the command at sequence
+    Trades {
+        pair: String,
+        sequence: u64,
+        trades: Vec<Trade>,
+    },
+    // queued behind a snapshot of the pair, see journal::compact
+    Compact {
+        pair: String,
+        retention: Duration,
+    },
+}
+
+impl Task {
+    pub fn apply(&self, db: &Database) -> anyhow::Result<()> {
+        match self {
+            Task::Snapshot { pair, item } => Ok(Key::book(pair).set(db, item)?),
+            Task::Telemetry { sample, retention } => telemetry::record(db, sample, *retention),
+            Task::SlowPath(report) => latency::record(db, report),
+            Task::Trades {
+                pair,
+                sequence,
+                trades,
+            } => trade::record_command(db, pair, *sequence, trades).map(|_| ()),
+            Task::Compact { pair, retention } => journal::compact(db, pair, *retention).map(|_| ()),
+        }
+    }
+}
+
+struct Request {
+    tasks: Vec<Task>,
+    ack: Option<Sender<anyhow::Result<()>>>,
+}
+
+pub struct Writer {
+    sender: Option<Sender<Request>>,
+    handle: Option<JoinHandle<()>>,
+    mode: AckMode,
+}
+
+impl Writer {
+    pub fn spawn(db: Arc<Mutex<Database>>, mode: AckMode) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || run(db, receiver));
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            mode,
+        }
+    }
+
+    pub fn mode(&self) -> AckMode {
+        self.mode
+    }
+
+    // Tasks of one submission are applied in order within a single batch.
+    pub fn submit(&self, tasks: Vec<Task>) -> anyhow::Result<()> {
+        let sender = self.sender.as_ref().expect("writer is stopped");
+        match self.mode {
+            AckMode::Fast => {
+                sender
+                    .send(Request { tasks, ack: None })
+                    .map_err(|_| anyhow!("writer thread is gone"))?;
+                Ok(())
+            }
+            AckMode::Durable => {
+                let (ack, acked) = mpsc::channel();
+                sender
+                    .send(Request {
+                        tasks,
+                        ack: Some(ack),
+                    })
+                    .map_err(|_| anyhow!("writer thread is gone"))?;
+                acked.recv().map_err(|_| anyhow!("writer thread is gone"))?
+            }
+        }
+    }
+}
+
+// Drains the queue before returning so fast-acked writes are not lost on shutdown.
+impl Drop for Writer {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("writer thread panicked");
+        }
+    }
+}
+
+fn run(db: Arc<Mutex<Database>>, receiver: Receiver<Request>) {
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH {
+            match receiver.try_recv() {
+                Ok(request) => batch.push(request),
+                Err(_) => break,
+            }
+        }
+
+        let guard = db.lock().expect("could not get db lock");
+        let mut results: Vec<anyhow::Result<()>> = batch
+            .iter()
+            .map(|request| request.tasks.iter().try_for_each(|task| task.apply(&guard)))
+            .collect();
+        if batch.iter().any(|request| request.ack.is_some()) {
+            if let Err(e) = guard.flush() {
+                for result in results.iter_mut().filter(|r| r.is_ok()) {
+                    *result = Err(anyhow!("could not flush batch: {}", e));
+                }
+            }
+        }
+        drop(guard);
+
+        for (request, result) in batch.into_iter().zip(results) {
+            match request.ack {
+                Some(ack) => {
+                    let _ = ack.send(result);
+                }
+                None => {
+                    if let Err(e) = result {
+                        eprintln!("Writer could not persist fast-acked tasks: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::shared_temp_db;
+
+    fn snapshot(pair: &str) -> Task {
+        Task::Snapshot {
+            pair: pair.to_string(),
+            item: Item {
+                active_orders: vec![],
+                fulfilled_orders: vec![],
+                sequence: None,
+            },
+        }
+    }
+
+    #[test]
+    fn durable_writes_are_visible_once_acked() {
+        let db = shared_temp_db();
+        let writer = Writer::spawn(db.clone(), AckMode::Durable);
+
+        writer.submit(vec![snapshot("BTC/USD")]).unwrap();
+        assert!(Key::book("BTC/USD")
+            .get(&db.lock().unwrap())
+            .unwrap()
+            .is_some());
+
+        drop(writer);
+    }
+
+    #[test]
+    fn fast_writes_are_drained_on_drop() {
+        let db = shared_temp_db();
+        let writer = Writer::spawn(db.clone(), AckMode::Fast);
+
+        for pair in ["BTC/USD", "ETH/USD", "SOL/USD"] {
+            writer.submit(vec![snapshot(pair)]).unwrap();
+        }
+        drop(writer);
+        assert_eq!(db.lock().unwrap().keys().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn parse_ack_mode() {
+        assert_eq!(AckMode::parse("durable").unwrap(), AckMode::Durable);
+        assert!(AckMode::parse("eventual").is_err());
+    }
+}
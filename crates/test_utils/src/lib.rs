@@ -0,0 +1,13 @@
+// Shared fixtures for tests across the workspace. match_engine's own unit
+// tests can only use the temp db helpers: the builders return the Order of
+// the match_engine build this crate links, which is a different type from
+// `crate::order::Order` inside that crate's test binary.
+pub mod order;
+pub mod scenario;
+pub mod seed;
+pub mod temp_db;
+
+pub use order::order;
+pub use scenario::{book, orders};
+pub use seed::{rng, seed};
+pub use temp_db::{shared_temp_db, temp_db};
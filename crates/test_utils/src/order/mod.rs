@@ -0,0 +1,105 @@
+use match_engine::order::peg::Peg;
+use match_engine::order::{Order, OrderStatus, OrderType};
+
+// e.g. order().sell().price(10).qty(3).hidden().build()
+pub fn order() -> OrderBuilder {
+    OrderBuilder::default()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderBuilder {
+    order_type: OrderType,
+    price: i32,
+    quantity: i32,
+    status: OrderStatus,
+    hidden: bool,
+    peg: Option<Peg>,
+}
+
+impl Default for OrderBuilder {
+    fn default() -> Self {
+        Self {
+            order_type: OrderType::Buy,
+            price: 1,
+            quantity: 1,
+            status: OrderStatus::Active,
+            hidden: false,
+            peg: None,
+        }
+    }
+}
+
+impl OrderBuilder {
+    pub fn buy(mut self) -> Self {
+        self.order_type = OrderType::Buy;
+        self
+    }
+
+    pub fn sell(mut self) -> Self {
+        self.order_type = OrderType::Sell;
+        self
+    }
+
+    pub fn price(mut self, price: i32) -> Self {
+        self.price = price;
+        self
+    }
+
+    pub fn qty(mut self, quantity: i32) -> Self {
+        self.quantity = quantity;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    // e.g. "bid", "ask-2", "mid+1"
+    pub fn peg(mut self, spec: &str) -> Self {
+        self.peg = Some(Peg::parse(spec).expect("Invalid peg"));
+        self
+    }
+
+    pub fn filled(mut self) -> Self {
+        self.status = OrderStatus::Filled;
+        self
+    }
+
+    pub fn cancelled(mut self) -> Self {
+        self.status = OrderStatus::Cancelled;
+        self
+    }
+
+    pub fn build(self) -> Order {
+        let mut order = Order::new(self.quantity, self.price, self.order_type);
+        match self.status {
+            OrderStatus::Filled => order.fill(self.quantity),
+            status => order.update_order_status(status),
+        }
+        order.update_hidden(self.hidden);
+        order.update_peg(self.peg);
+        order
+    }
+}
+
+impl From<OrderBuilder> for Order {
+    fn from(builder: OrderBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_described_order() {
+        let order = order().sell().price(10).qty(3).hidden().build();
+
+        assert_eq!(order.order_type, OrderType::Sell);
+        assert_eq!((order.price, order.quantity), (10, 3));
+        assert!(order.hidden);
+        assert_eq!(order.order_status, OrderStatus::Active);
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::anyhow;
+use match_engine::order::Order;
+use match_engine::order_book::OrderBook;
+use match_engine::symbol::Symbol;
+
+use crate::order::order;
+use crate::temp_db::shared_temp_db;
+
+// One order per line, "<side> <price>[x<quantity>] [hidden] [filled]",
+// e.g. "buy 10x3" or "sell 12 hidden". Blank lines and # comments are skipped.
+pub fn orders(scenario: &str) -> anyhow::Result<Vec<Order>> {
+    scenario
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut words = line.split_whitespace();
+            let mut builder = match words.next() {
+                Some("buy") => order().buy(),
+                Some("sell") => order().sell(),
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid scenario line {}, expected buy or sell",
+                        line
+                    ))
+                }
+            };
+            let size = words
+                .next()
+                .ok_or_else(|| anyhow!("Invalid scenario line {}, price is missing", line))?;
+            let (price, quantity) = size.split_once('x').unwrap_or((size, "1"));
+            builder = builder.price(price.parse()?).qty(quantity.parse()?);
+            for flag in words {
+                builder = match flag {
+                    "hidden" => builder.hidden(),
+                    "filled" => builder.filled(),
+                    _ => return Err(anyhow!("Invalid scenario flag {}", flag)),
+                };
+            }
+            Ok(builder.build())
+        })
+        .collect()
+}
+
+// A built order book for pair over a temp db, resting the scenario's orders
+// as they are without matching them.
+pub fn book(pair: &str, scenario: &str) -> OrderBook {
+    let mut order_book_builder = OrderBook::default();
+    order_book_builder.set_pair(Symbol::parse(pair).expect("Invalid pair"));
+    order_book_builder.set_db(shared_temp_db());
+    let mut order_book = order_book_builder
+        .build()
+        .expect("could not build order book");
+    order_book.load_bulk(orders(scenario).expect("Invalid scenario"));
+    order_book
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_a_resting_book() {
+        let order_book = book(
+            "BTC/USD",
+            "
+            # bids
+            buy 10x3
+            buy 11 hidden
+            sell 12x2
+            ",
+        );
+
+        assert_eq!(
+            order_book
+                .get_buy_orders()
+                .iter()
+                .map(|o| (o.price, o.quantity, o.hidden))
+                .collect::<Vec<_>>(),
+            vec![(11, 1, true), (10, 3, false)]
+        );
+        assert_eq!(order_book.get_sell_orders().len(), 1);
+        assert!(order_book.verify().is_empty());
+        assert!(orders("hold 10").is_err());
+    }
+}
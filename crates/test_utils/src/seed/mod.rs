@@ -0,0 +1,21 @@
+use std::env;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+pub const SEED_ENV: &str = "FTX_SEED";
+
+// FTX_SEED when set, otherwise a fresh one. The seed goes to stderr, which
+// cargo test only shows for failing tests, so a failure can be replayed with
+// FTX_SEED=<seed> cargo test <name>.
+pub fn seed() -> u64 {
+    let seed = env::var(SEED_ENV)
+        .map(|s| s.parse().expect("FTX_SEED must be a number"))
+        .unwrap_or_else(|_| rand::random());
+    eprintln!("{SEED_ENV}={seed}");
+    seed
+}
+
+pub fn rng() -> StdRng {
+    StdRng::seed_from_u64(seed())
+}
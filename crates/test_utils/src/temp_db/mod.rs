@@ -0,0 +1,14 @@
+use std::sync::{Arc, Mutex};
+
+use db::codec::Codec;
+use db::Database;
+
+// Deleted when the last handle drops, so there is nothing to clean up and
+// parallel tests never share a directory.
+pub fn temp_db() -> Database {
+    Database::temporary(Codec::default()).expect("could not open a temporary database")
+}
+
+pub fn shared_temp_db() -> Arc<Mutex<Database>> {
+    Arc::new(Mutex::new(temp_db()))
+}